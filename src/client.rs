@@ -14,6 +14,63 @@ use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+/// Comma-separated hosts the wrapper may connect to, replacing the defaults
+const ALLOWED_HOSTS_VAR: &str = "TOOLMAN_ALLOWED_HOSTS";
+
+/// Hosts allowed when `TOOLMAN_ALLOWED_HOSTS` is unset: this machine and in-cluster
+/// service names, which covers the default server URL
+const DEFAULT_ALLOWED_HOSTS: &[&str] = &["localhost", "127.0.0.1", "::1", "*.svc.cluster.local"];
+
+/// Hosts the wrapper may connect to, from `TOOLMAN_ALLOWED_HOSTS` or the defaults
+fn allowed_server_hosts() -> Vec<String> {
+    match std::env::var(ALLOWED_HOSTS_VAR) {
+        Ok(hosts) => hosts
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect(),
+        Err(_) => DEFAULT_ALLOWED_HOSTS
+            .iter()
+            .map(|host| host.to_string())
+            .collect(),
+    }
+}
+
+/// Refuse a server URL that isn't http(s) to one of `allowed_hosts`. An entry is a host
+/// name or address, `*.suffix` for any host under `suffix`, or `*` for any host.
+fn check_server_url(url: &str, allowed_hosts: &[String]) -> Result<()> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| anyhow::anyhow!("Invalid server URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!(
+            "Refusing to connect to {}: only http and https URLs are allowed",
+            url
+        );
+    }
+    let host = parsed
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_lowercase();
+    let allowed = allowed_hosts
+        .iter()
+        .any(|pattern| match pattern.strip_prefix("*.") {
+            Some(suffix) => host.ends_with(&format!(".{suffix}")),
+            None => pattern == "*" || *pattern == host,
+        });
+    if !allowed {
+        anyhow::bail!(
+            "Refusing to connect to {}: host '{}' is not allowed (allowed: {}). Set {} to a comma-separated list of hosts to allow it.",
+            url,
+            host,
+            allowed_hosts.join(", "),
+            ALLOWED_HOSTS_VAR
+        );
+    }
+    Ok(())
+}
+
 /// Local server process handle
 #[derive(Debug)]
 pub struct LocalServerProcess {
@@ -37,6 +94,8 @@ pub struct McpClient {
 
 impl McpClient {
     pub fn new(http_base_url: String, working_dir: Option<String>) -> Result<Self> {
+        check_server_url(&http_base_url, &allowed_server_hosts())?;
+
         let client = reqwest::Client::new();
         let rt = Runtime::new()?;
 
@@ -124,7 +183,11 @@ impl McpClient {
 
     pub fn run(&self) -> Result<()> {
         let stdin = io::stdin();
-        let mut stdout = io::stdout();
+        self.run_with(stdin.lock(), &mut io::stdout())
+    }
+
+    fn run_with(&self, input: impl BufRead, stdout: &mut impl Write) -> Result<()> {
+        let mut lines = input.lines();
 
         // Spawn local servers on startup
         if let Err(e) = self.rt.block_on(async { self.spawn_local_servers().await }) {
@@ -139,8 +202,15 @@ impl McpClient {
             eprintln!("[Bridge] Warning: Failed to handshake with some local servers: {e}");
         }
 
-        // Send initial capabilities
-        self.send_capabilities(&mut stdout)?;
+        // Send initial capabilities. If the HTTP server can't be reached, tell the IDE why
+        // instead of just exiting
+        if let Err(e) = self.send_capabilities(stdout) {
+            let pending = lines.next().transpose()?;
+            self.report_unreachable_server(&e, pending.as_deref(), stdout)?;
+            self.rt
+                .block_on(async { self.cleanup_local_servers().await })?;
+            return Err(e);
+        }
 
         // Set up cleanup handler with static reference
         let cleanup_servers = self.local_servers.clone();
@@ -164,7 +234,7 @@ impl McpClient {
             .expect("Failed to set cleanup handler");
         });
 
-        for line in stdin.lock().lines() {
+        for line in lines {
             let line = line?;
             if line.trim().is_empty() {
                 continue;
@@ -201,7 +271,60 @@ impl McpClient {
         Ok(())
     }
 
-    fn send_capabilities(&self, stdout: &mut io::Stdout) -> Result<()> {
+    /// Answer the in-flight request (if it expects a response) with a JSON-RPC error and
+    /// follow it with an error log notification describing the connection failure
+    fn report_unreachable_server(
+        &self,
+        error: &anyhow::Error,
+        pending: Option<&str>,
+        stdout: &mut impl Write,
+    ) -> Result<()> {
+        eprintln!(
+            "[Bridge] ❌ Cannot reach toolman server at {}: {error:#}",
+            self.http_base_url
+        );
+
+        let message = format!("Cannot reach toolman server at {}", self.http_base_url);
+        let details = json!({
+            "httpServerUrl": self.http_base_url,
+            "reason": format!("{error:#}")
+        });
+
+        let pending_id = pending
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .and_then(|request| request.get("id").cloned())
+            .filter(|id| !id.is_null());
+        if let Some(id) = pending_id {
+            let error_response = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": -32000,
+                    "message": message,
+                    "data": details
+                }
+            });
+            writeln!(stdout, "{}", serde_json::to_string(&error_response)?)?;
+        }
+
+        let diagnostic = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {
+                "level": "error",
+                "logger": "toolman-client",
+                "data": {
+                    "message": message,
+                    "details": details
+                }
+            }
+        });
+        writeln!(stdout, "{}", serde_json::to_string(&diagnostic)?)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn send_capabilities(&self, stdout: &mut impl Write) -> Result<()> {
         // Get current tools from HTTP server
         let tools = self
             .rt
@@ -1071,3 +1194,71 @@ impl McpClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unreachable_server_answers_pending_request_with_error() {
+        // A port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let dir = tempfile::tempdir().unwrap();
+        let client = McpClient::new(
+            format!("http://127.0.0.1:{port}/mcp"),
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+
+        let input = r#"{"jsonrpc":"2.0","id":7,"method":"initialize","params":{}}"#;
+        let mut output = Vec::new();
+        let result = client.run_with(io::Cursor::new(input), &mut output);
+        assert!(result.is_err());
+
+        let messages: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(messages.len(), 2);
+
+        let response = &messages[0];
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["error"]["code"], -32000);
+        let message = response["error"]["message"].as_str().unwrap();
+        assert!(message.contains(&format!("127.0.0.1:{port}")), "{message}");
+        assert!(response.get("result").is_none());
+
+        let diagnostic = &messages[1];
+        assert_eq!(diagnostic["method"], "notifications/message");
+        assert_eq!(diagnostic["params"]["level"], "error");
+    }
+
+    #[test]
+    fn test_server_url_must_match_allowed_hosts() {
+        let defaults: Vec<String> = DEFAULT_ALLOWED_HOSTS
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        check_server_url("http://localhost:3000/mcp", &defaults).unwrap();
+        check_server_url("http://[::1]:3000/mcp", &defaults).unwrap();
+        check_server_url("http://toolman.mcp.svc.cluster.local:3000/mcp", &defaults).unwrap();
+        let error = check_server_url("file:///etc/passwd", &defaults).unwrap_err();
+        assert!(error.to_string().contains("only http and https"), "{error}");
+
+        let restrictive = vec!["localhost".to_string()];
+        check_server_url("http://localhost:3000/mcp", &restrictive).unwrap();
+        let error = check_server_url("https://attacker.example.com/mcp", &restrictive).unwrap_err();
+        let message = error.to_string();
+        assert!(
+            message.contains("'attacker.example.com' is not allowed"),
+            "{message}"
+        );
+        assert!(message.contains(ALLOWED_HOSTS_VAR), "{message}");
+    }
+}
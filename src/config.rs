@@ -1,7 +1,7 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
@@ -10,6 +10,67 @@ fn default_transport() -> String {
     "stdio".to_string()
 }
 
+/// Environment variable selecting which per-environment value of a field to use
+const ENVIRONMENT_VAR: &str = "TOOLMAN_ENV";
+
+/// A field that is either a plain string or one value per deployment environment,
+/// e.g. `{ "dev": "http://...", "prod": "https://...", "default": "http://..." }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum EnvSelected {
+    Plain(String),
+    PerEnvironment(HashMap<String, String>),
+}
+
+impl EnvSelected {
+    /// Pick the value for `environment`, falling back to the `default` key
+    fn resolve(self, environment: Option<&str>) -> Result<String, String> {
+        match self {
+            EnvSelected::Plain(value) => Ok(value),
+            EnvSelected::PerEnvironment(mut values) => environment
+                .and_then(|env| values.remove(env))
+                .or_else(|| values.remove("default"))
+                .ok_or_else(|| {
+                    format!(
+                        "no value for environment '{}' and no 'default' (have: {})",
+                        environment.unwrap_or(""),
+                        values.keys().cloned().collect::<Vec<_>>().join(", ")
+                    )
+                }),
+        }
+    }
+}
+
+/// Fields of a server's config that may be given per environment
+const ENVIRONMENT_SELECTED_FIELDS: [&str; 3] = ["url", "workingDirectory", "user_agent"];
+
+/// The environment per-environment fields are resolved against, from `TOOLMAN_ENV`
+pub fn selected_environment() -> Option<String> {
+    std::env::var(ENVIRONMENT_VAR).ok()
+}
+
+/// Replace the per-environment fields of a server's JSON config with their value for
+/// `environment`
+fn select_environment(
+    server: &mut serde_json::Value,
+    environment: Option<&str>,
+) -> Result<(), String> {
+    let Some(server) = server.as_object_mut() else {
+        return Ok(());
+    };
+    for field in ENVIRONMENT_SELECTED_FIELDS {
+        if let Some(value) = server.get_mut(field).filter(|value| value.is_object()) {
+            let selected: EnvSelected =
+                serde_json::from_value(value.take()).map_err(|e| format!("{}: {}", field, e))?;
+            *value = selected
+                .resolve(environment)
+                .map_err(|e| format!("{}: {}", field, e))?
+                .into();
+        }
+    }
+    Ok(())
+}
+
 /// New simplified client-side configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
@@ -43,7 +104,7 @@ pub struct LocalServerConfig {
 pub struct ServerConfig {
     pub name: Option<String>,
     pub description: Option<String>,
-    /// Transport type: "stdio" (default) or "http"
+    /// Transport type: "stdio" (default), "http", "sse" or "websocket"
     #[serde(default = "default_transport")]
     pub transport: String,
     /// For stdio: command to execute
@@ -52,20 +113,620 @@ pub struct ServerConfig {
     /// For stdio: command arguments
     #[serde(default)]
     pub args: Vec<String>,
-    /// For http: URL to connect to
+    /// For http, sse and websocket: URL to connect to. May be a per-environment map resolved against `TOOLMAN_ENV`.
+    #[serde(default)]
     pub url: Option<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// A `.env` file of `KEY=value` lines loaded into the server's environment, relative to
+    /// the project directory. `env` (and `global_env`) take precedence over it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_file: Option<String>,
     /// Working directory for the server process (optional, defaults to project directory)
     /// Supports: "project_root", absolute paths like "/usr/local/bin", or relative paths
+    /// May be a per-environment map like `url`.
     #[serde(rename = "workingDirectory", default)]
     pub working_directory: Option<String>,
+    /// Mirror every tool call to another configured server and log result differences.
+    /// Only the primary's result is returned to the caller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<String>,
+    /// For http/sse: User-Agent sent to the server (defaults to `toolman/<version>`).
+    /// May be a per-environment map like `url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Capabilities declared in the `initialize` handshake with this server (all transports)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<serde_json::Value>,
+    /// Per-tool overrides, keyed by the tool's original (unprefixed) name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tools: HashMap<String, ToolConfig>,
+    /// For stdio: priority and resource limits applied to the server process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<SpawnLimits>,
+    /// JSON type of the request ids sent to this server ("integer" or "string")
+    #[serde(default)]
+    pub request_id_type: RequestIdType,
+    /// For SSE: the highest request id sent on a session before the ids wrap around.
+    /// Defaults to the largest integer a JavaScript number holds exactly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_request_id: Option<u64>,
+    /// For stdio: how many calls may wait for the server while it is busy with another.
+    /// Further calls fail with a "server busy" error. Unbounded if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queued_calls: Option<usize>,
+    /// How many tool calls may be forwarded to this server at once. Further calls wait
+    /// for a free slot, within their call timeout. Unlimited if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_calls: Option<usize>,
+    /// For stdio: what a call does when it finds the server still starting up
+    #[serde(default)]
+    pub while_initializing: WhileInitializing,
+    /// Servers that must be started and discovered before this one. It isn't started
+    /// unless they came up ready.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// How long tool discovery may take for this server, overriding the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_timeout_secs: Option<u64>,
+    /// Rediscover this server's tools in the background this often, so changes to a
+    /// long-running backend's tool list are picked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_ttl_secs: Option<u64>,
+    /// JSON pointer (e.g. `/data`) to the meaningful payload inside this server's tool
+    /// results, for backends that wrap every result in an envelope of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unwrap_path: Option<String>,
+    /// For sse: how many initialized sessions to keep open for reuse by tool calls
+    #[serde(default = "default_sse_pool_size")]
+    pub sse_pool_size: usize,
+    /// For stdio/sse: how long a tool call may wait for the server's response. Defaults to
+    /// 30s for stdio (counted from the server's last line of output) and 120s for sse.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_timeout_secs: Option<u64>,
+    /// Fraction of calls to this server (0.0 to 1.0) logged at info with their arguments,
+    /// result and duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_log_sample_rate: Option<f64>,
+    /// For http/sse: credentials attached to every request sent to the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<ServerAuth>,
+    /// Operator-defined labels (team, risk level, cost, ...) given to all of this server's
+    /// tools, for filtering `tools/list`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Arguments added to every call to this server's tools, keyed by argument name,
+    /// e.g. `{"projectRoot": "working_dir"}`. Nothing is injected when empty.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub inject_params: HashMap<String, InjectSource>,
+}
+
+/// Credentials for an http/sse server. Secrets are read from environment variables so
+/// they stay out of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerAuth {
+    /// `Authorization: Bearer <token>`, with the token read from `token_env`
+    Bearer { token_env: String },
+    /// An arbitrary header, with its value given inline or read from `value_env`
+    Header {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value_env: Option<String>,
+    },
+}
+
+fn default_sse_pool_size() -> usize {
+    1
+}
+
+impl ServerConfig {
+    /// Deserialize a server's config, resolving its per-environment fields against
+    /// `environment`
+    pub fn from_json(
+        mut value: serde_json::Value,
+        environment: Option<&str>,
+    ) -> Result<Self, serde_json::Error> {
+        select_environment(&mut value, environment).map_err(serde::de::Error::custom)?;
+        serde_json::from_value(value)
+    }
+
+    /// Overrides configured for a single tool, if any
+    pub fn tool_config(&self, tool_name: &str) -> Option<&ToolConfig> {
+        self.tools.get(tool_name)
+    }
+
+    /// Server to shadow calls of `tool_name` to; a per-tool setting wins over the server-wide one
+    pub fn shadow_for(&self, tool_name: &str) -> Option<&str> {
+        self.tool_config(tool_name)
+            .and_then(|t| t.shadow.as_deref())
+            .or(self.shadow.as_deref())
+    }
+
+    /// JSON pointer to unwrap `tool_name`'s results at; a per-tool setting wins over the
+    /// server-wide one
+    pub fn unwrap_path_for(&self, tool_name: &str) -> Option<&str> {
+        self.tool_config(tool_name)
+            .and_then(|t| t.unwrap_path.as_deref())
+            .or(self.unwrap_path.as_deref())
+    }
+
+    /// The `inject_params` values for a call, sorted by argument name. Arguments sourced
+    /// from the working directory are left out when there is none.
+    pub fn injected_params(
+        &self,
+        working_dir: Option<&Path>,
+        project_dir: &Path,
+    ) -> Vec<(String, serde_json::Value)> {
+        let mut params: Vec<(String, serde_json::Value)> = self
+            .inject_params
+            .iter()
+            .filter_map(|(name, source)| {
+                let value = match source {
+                    InjectSource::WorkingDir => working_dir?.to_string_lossy().into(),
+                    InjectSource::ProjectDir => project_dir.to_string_lossy().into(),
+                    InjectSource::Literal(value) => value.clone(),
+                };
+                Some((name.clone(), value))
+            })
+            .collect();
+        params.sort_by(|a, b| a.0.cmp(&b.0));
+        params
+    }
+
+    /// Labels of `tool_name`: the server's labels followed by the tool's own
+    pub fn labels_for(&self, tool_name: &str) -> Vec<String> {
+        let mut labels = self.labels.clone();
+        for label in self.tool_config(tool_name).map_or(&[][..], |t| &t.labels) {
+            if !labels.contains(label) {
+                labels.push(label.clone());
+            }
+        }
+        labels
+    }
+
+    /// Why this server can't be connected to as configured, if it can't: a missing field
+    /// its transport requires, or a transport Toolman doesn't know
+    pub fn transport_problem(&self) -> Option<String> {
+        match self.transport.as_str() {
+            "stdio" if self.command.trim().is_empty() => {
+                Some("stdio server requires a non-empty 'command'".to_string())
+            }
+            "http" | "sse" | "websocket" if self.url.is_none() => {
+                Some(format!("{} server requires a 'url'", self.transport))
+            }
+            "stdio" | "http" | "sse" | "websocket" => None,
+            other => Some(format!(
+                "unknown transport '{other}' (expected stdio, http, sse or websocket)"
+            )),
+        }
+    }
+}
+
+/// Per-tool configuration overrides
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolConfig {
+    /// Shadow calls of this tool to another server (overrides the server-level `shadow`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<String>,
+    /// Split results with more content items than this into pages fetched via `nextCursor`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<usize>,
+    /// The tool is idempotent, so calls that fail in transport are retried
+    /// (`tool_call_retries` times)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retryable: bool,
+    /// Argument values filled in when a call omits them
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub defaults: serde_json::Map<String, serde_json::Value>,
+    /// Calls to tools sharing a lock key (on any server) run one at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_key: Option<String>,
+    /// Unwrap this tool's results at a JSON pointer (overrides the server-level `unwrap_path`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unwrap_path: Option<String>,
+    /// Category in the tool catalog, instead of one inferred from the name and description.
+    /// Also picks this tool's rung of `call_timeout_ladder`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Use cases in the tool catalog, instead of ones inferred from the name and description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_cases: Option<Vec<String>>,
+    /// For stdio/sse: how long a call to this tool may wait for its response, overriding
+    /// `call_timeout_ladder` and the server's `call_timeout_secs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_timeout_secs: Option<u64>,
+    /// For stdio: send this tool's results to the client as they are read from the server
+    /// instead of buffering them whole. The result is passed through unchanged.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub streaming: bool,
+    /// Answer calls with an empty result as soon as they are accepted, and forward them
+    /// to the server in the background
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fire_and_forget: bool,
+    /// Labels of this tool, in addition to the server's `labels`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+/// JSON type used for the ids of requests sent to a backend. Responses to the client always
+/// carry the client's own id, whatever the backend was sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestIdType {
+    #[default]
+    Integer,
+    String,
+}
+
+impl RequestIdType {
+    /// Request id `n` in this representation
+    pub fn id(self, n: u64) -> serde_json::Value {
+        match self {
+            RequestIdType::Integer => serde_json::Value::from(n),
+            RequestIdType::String => serde_json::Value::from(n.to_string()),
+        }
+    }
+}
+
+/// What happens to a call for a stdio server that is still being started and initialized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhileInitializing {
+    /// Wait for the handshake to finish, within the call's timeout
+    #[default]
+    Wait,
+    /// Fail at once with a "server initializing" error
+    Reject,
+}
+
+/// Limits applied to a spawned stdio server. Enforced on Linux only; ignored elsewhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpawnLimits {
+    /// Niceness of the process (raising priority, i.e. negative values, needs privileges)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// Address space limit (`RLIMIT_AS`) in megabytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u64>,
+    /// CPU time limit (`RLIMIT_CPU`) in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_seconds: Option<u64>,
 }
 
 /// Root configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServersConfig {
     pub servers: HashMap<String, ServerConfig>,
+    /// Timeout for the TCP reachability check done before discovering an http/sse server,
+    /// in milliseconds. Unreachable servers are skipped instead of waiting out request timeouts.
+    /// 0 disables the check.
+    #[serde(default = "default_reachability_timeout_ms")]
+    pub reachability_timeout_ms: u64,
+    /// How long a server restart (e.g. on reload) waits for in-flight calls to it to finish
+    #[serde(default = "default_reload_drain_timeout_secs")]
+    pub reload_drain_timeout_secs: u64,
+    /// Trace-log every chunk and line read from SSE backends (very verbose; for debugging)
+    #[serde(default)]
+    pub sse_chunk_logging: bool,
+    /// Largest SSE message, in bytes, read from a backend's stream. A stream whose current
+    /// message grows past it is closed with an error instead of buffering without bound.
+    #[serde(default = "default_max_sse_message_bytes")]
+    pub max_sse_message_bytes: usize,
+    /// How many Docker-command servers may be discovered at once at startup. Other servers
+    /// are all discovered in parallel.
+    #[serde(default = "default_docker_discovery_concurrency")]
+    pub docker_discovery_concurrency: usize,
+    /// How long a stopped server may take to exit after SIGTERM before it is SIGKILLed
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// On SIGTERM/Ctrl-C, how long stdio servers get to stop before the ones still running
+    /// are SIGKILLed
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// `/health` reports unhealthy once the internal watchdog hasn't completed a round for
+    /// this long, e.g. because a shared lock is stuck. 0 disables the check.
+    #[serde(default = "default_health_heartbeat_timeout_secs")]
+    pub health_heartbeat_timeout_secs: u64,
+    /// How many Toolman proxies a request may already have passed through (counted in the
+    /// `X-Toolman-Hops` header) before it is rejected as a proxy loop. 0 disables the check.
+    #[serde(default = "default_max_hops")]
+    pub max_hops: u32,
+    /// Add `_meta.toolman` (backend server and transport) to each `tools/list` entry
+    #[serde(default)]
+    pub include_tool_meta: bool,
+    /// Leave toolman's built-in tools (`toolman_*`) out of `tools/list` for strict clients.
+    /// They can still be called directly.
+    #[serde(default)]
+    pub hide_builtin_tools: bool,
+    /// Which `clientInfo` backends see in their `initialize` handshake. A backend handshakes
+    /// once for every client sharing it, so it sees the session whose call connected it.
+    #[serde(default)]
+    pub client_info_passthrough: ClientInfoPassthrough,
+    /// Retries of a `retryable` tool's call, or of a server's discovery, after a transport
+    /// failure. Defaults to `TOOLMAN_CALL_RETRIES`, else 2.
+    #[serde(default = "default_tool_call_retries")]
+    pub tool_call_retries: u32,
+    /// Delay before the first retry, doubled for each further one
+    #[serde(default = "default_tool_call_retry_backoff_ms")]
+    pub tool_call_retry_backoff_ms: u64,
+    /// Add `_meta.toolman_error` (`kind`, `server`, `tool`, `detail`) to the content of
+    /// tool-level errors, for agents that branch on the kind of failure
+    #[serde(default)]
+    pub structured_tool_errors: bool,
+    /// Wrap tool results that lack a `content` array (a bare string or object) in a single
+    /// text content item, for clients that expect every result to carry `content`
+    #[serde(default)]
+    pub normalize_tool_results: bool,
+    /// Rediscover every server's tools this often and republish the tool catalog if they
+    /// changed. Off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_refresh_interval_secs: Option<u64>,
+    /// Prefixed tool called instead of failing when a requested tool name can't be
+    /// resolved. It receives `{"tool": <requested name>, "arguments": <requested arguments>}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown_tool_fallback: Option<String>,
+    /// Environment variables set for every spawned server, before (and overridden by)
+    /// each server's own `env`. Templated the same way.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub global_env: HashMap<String, String>,
+    /// How server names are turned into the prefix of their tools' names
+    #[serde(default)]
+    pub server_name_normalization: ServerNameNormalization,
+    /// Explicit tool-name prefixes by server name, taking precedence over
+    /// `server_name_normalization`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub server_name_map: HashMap<String, String>,
+    /// Call timeouts in seconds by kind of tool: a tool's configured `category`, `read_only`
+    /// or `destructive` (from its `readOnlyHint`/`destructiveHint` annotations), or `default`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub call_timeout_ladder: HashMap<String, u64>,
+    /// Whether injected context (`projectRoot`) replaces or defers to a value the client
+    /// passed itself
+    #[serde(default)]
+    pub injection_precedence: InjectionPrecedence,
+    /// Schema advertised for discovered tools that don't declare an `inputSchema`
+    #[serde(default)]
+    pub missing_input_schema: MissingInputSchema,
+}
+
+impl ServersConfig {
+    /// Parse a servers-config.json, resolving each server's per-environment fields against
+    /// `environment`
+    pub fn from_json_str(
+        content: &str,
+        environment: Option<&str>,
+    ) -> Result<Self, serde_json::Error> {
+        let mut value: serde_json::Value = serde_json::from_str(content)?;
+        if let Some(servers) = value.get_mut("servers").and_then(|s| s.as_object_mut()) {
+            for server in servers.values_mut() {
+                select_environment(server, environment).map_err(serde::de::Error::custom)?;
+            }
+        }
+        serde_json::from_value(value)
+    }
+
+    /// Prefix a server's tools are advertised under: its `server_name_map` entry, or its name
+    /// normalized per `server_name_normalization`
+    pub fn server_prefix(&self, server_name: &str) -> String {
+        if let Some(prefix) = self.server_name_map.get(server_name) {
+            return prefix.clone();
+        }
+        match self.server_name_normalization {
+            ServerNameNormalization::Sanitize => sanitize_name(server_name),
+            ServerNameNormalization::Lowercase => sanitize_name(&server_name.to_lowercase()),
+            ServerNameNormalization::Keep => server_name.to_string(),
+        }
+    }
+
+    /// Environment for a server's process: `global_env` with the server's `env` on top
+    pub fn env_for(&self, server: &ServerConfig) -> HashMap<String, String> {
+        let mut env = self.global_env.clone();
+        env.extend(server.env.clone());
+        env
+    }
+
+    /// How long a call to `tool_name` may wait for its response, in seconds: the tool's own
+    /// `call_timeout_secs`, else the ladder rung for its category or annotations, else the
+    /// server's `call_timeout_secs`, else the ladder's `default`. None leaves it to the
+    /// transport's default.
+    pub fn call_timeout_for(
+        &self,
+        server: &ServerConfig,
+        tool_name: &str,
+        annotations: Option<&serde_json::Value>,
+    ) -> Option<u64> {
+        let tool = server.tool_config(tool_name);
+        if let Some(secs) = tool.and_then(|t| t.call_timeout_secs) {
+            return Some(secs);
+        }
+        let hint = |name: &str| {
+            annotations
+                .and_then(|a| a.get(name))
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false)
+        };
+        let rung = tool
+            .and_then(|t| t.category.as_deref())
+            .and_then(|category| self.call_timeout_ladder.get(category))
+            .or_else(|| {
+                hint("readOnlyHint")
+                    .then(|| self.call_timeout_ladder.get("read_only"))
+                    .flatten()
+            })
+            .or_else(|| {
+                hint("destructiveHint")
+                    .then(|| self.call_timeout_ladder.get("destructive"))
+                    .flatten()
+            });
+        rung.copied()
+            .or(server.call_timeout_secs)
+            .or_else(|| self.call_timeout_ladder.get("default").copied())
+    }
+}
+
+/// Normalize a server or tool name the way clients sanitize tool names: every character
+/// other than ASCII letters, digits and `_` (hyphens, dots, spaces, ...) becomes `_`
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// How a server's name becomes the prefix of its tools' names (`{prefix}_{tool}`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerNameNormalization {
+    /// Characters other than ASCII letters, digits and `_` become `_` (`task-master` → `task_master`)
+    #[default]
+    Sanitize,
+    /// Sanitized and lowercased (`TaskMaster` → `taskmaster`)
+    Lowercase,
+    /// The server name as configured
+    Keep,
+}
+
+/// Which value wins when a call's arguments already hold a key Toolman injects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionPrecedence {
+    /// The injected value replaces the client's
+    #[default]
+    Proxy,
+    /// The client's value is kept; Toolman only fills the key in when it is missing
+    Client,
+}
+
+/// Where the value of an argument in `inject_params` comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectSource {
+    /// The client's working directory; nothing is injected until one is known
+    WorkingDir,
+    /// The directory holding the servers config
+    ProjectDir,
+    /// A fixed value, given as `{"literal": <value>}`
+    Literal(serde_json::Value),
+}
+
+/// The `inputSchema` given to a discovered tool that doesn't declare one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingInputSchema {
+    /// `{}`
+    #[default]
+    Empty,
+    /// `{"type": "object", "additionalProperties": true}`, for clients that read `{}` as
+    /// taking no arguments
+    Permissive,
+}
+
+impl MissingInputSchema {
+    pub fn schema(self) -> serde_json::Value {
+        match self {
+            MissingInputSchema::Empty => serde_json::json!({}),
+            MissingInputSchema::Permissive => {
+                serde_json::json!({ "type": "object", "additionalProperties": true })
+            }
+        }
+    }
+}
+
+/// How the connecting client's identity is passed on to backend handshakes.
+/// Only affects backend connections opened after the client has initialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientInfoPassthrough {
+    /// Backends see `toolman`
+    #[default]
+    Off,
+    /// Backends see the client's own `name` and `version`
+    Forward,
+    /// Backends see `toolman-via-<client name>`
+    Via,
+}
+
+fn default_reachability_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_reload_drain_timeout_secs() -> u64 {
+    30
+}
+
+fn default_docker_discovery_concurrency() -> usize {
+    2
+}
+
+fn default_max_sse_message_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    5
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+fn default_health_heartbeat_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_hops() -> u32 {
+    8
+}
+
+fn default_tool_call_retries() -> u32 {
+    std::env::var("TOOLMAN_CALL_RETRIES")
+        .ok()
+        .and_then(|retries| retries.trim().parse().ok())
+        .unwrap_or(2)
+}
+
+fn default_tool_call_retry_backoff_ms() -> u64 {
+    200
+}
+
+impl Default for ServersConfig {
+    fn default() -> Self {
+        Self {
+            servers: HashMap::new(),
+            reachability_timeout_ms: default_reachability_timeout_ms(),
+            reload_drain_timeout_secs: default_reload_drain_timeout_secs(),
+            docker_discovery_concurrency: default_docker_discovery_concurrency(),
+            sse_chunk_logging: false,
+            max_sse_message_bytes: default_max_sse_message_bytes(),
+            shutdown_grace_secs: default_shutdown_grace_secs(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            health_heartbeat_timeout_secs: default_health_heartbeat_timeout_secs(),
+            max_hops: default_max_hops(),
+            include_tool_meta: false,
+            hide_builtin_tools: false,
+            client_info_passthrough: ClientInfoPassthrough::default(),
+            tool_call_retries: default_tool_call_retries(),
+            tool_call_retry_backoff_ms: default_tool_call_retry_backoff_ms(),
+            structured_tool_errors: false,
+            catalog_refresh_interval_secs: None,
+            normalize_tool_results: false,
+            unknown_tool_fallback: None,
+            global_env: HashMap::new(),
+            server_name_normalization: ServerNameNormalization::default(),
+            server_name_map: HashMap::new(),
+            call_timeout_ladder: HashMap::new(),
+            injection_precedence: InjectionPrecedence::default(),
+            missing_input_schema: MissingInputSchema::default(),
+        }
+    }
 }
 
 /// Session-based configuration sent during MCP initialization
@@ -114,6 +775,75 @@ fn default_auto_start() -> bool {
     true
 }
 
+/// A mistake in servers-config.json found by `SystemConfigManager::validate`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigError {
+    #[error("server '{server}': {reason}")]
+    InvalidServer { server: String, reason: String },
+
+    #[error(
+        "servers {} all advertise their tools under the prefix '{prefix}'; rename one or set `server_name_map`",
+        servers.iter().map(|s| format!("'{s}'")).collect::<Vec<_>>().join(", ")
+    )]
+    DuplicatePrefix {
+        prefix: String,
+        servers: Vec<String>,
+    },
+
+    #[error("server '{server}' depends on '{dependency}', which is not configured")]
+    UnknownDependency { server: String, dependency: String },
+
+    #[error("servers depend on each other in a cycle: {}", servers.join(" -> "))]
+    DependencyCycle { servers: Vec<String> },
+}
+
+/// The order to start `servers` in, following their `depends_on`: a list of waves, each
+/// holding servers whose dependencies are all in earlier waves. Servers within a wave may
+/// start together. Dependencies on servers outside `servers` are taken to be running
+/// already. Fails if some servers depend on each other in a cycle.
+pub fn startup_waves(
+    servers: &HashMap<String, ServerConfig>,
+) -> Result<Vec<Vec<String>>, ConfigError> {
+    let mut remaining: std::collections::BTreeSet<&String> = servers.keys().collect();
+    // Dependencies of `name` that haven't been placed in a wave yet
+    let waiting_on = |name: &String, remaining: &std::collections::BTreeSet<&String>| {
+        servers[name]
+            .depends_on
+            .iter()
+            .filter(|dependency| remaining.contains(dependency))
+            .min()
+            .cloned()
+    };
+
+    let mut waves = Vec::new();
+    while !remaining.is_empty() {
+        let wave: Vec<String> = remaining
+            .iter()
+            .filter(|name| waiting_on(name, &remaining).is_none())
+            .map(|name| (*name).clone())
+            .collect();
+        if wave.is_empty() {
+            // Every server left waits on another one left: follow the waits until one
+            // repeats
+            let mut path: Vec<String> = Vec::new();
+            let mut current = (*remaining.iter().next().unwrap()).clone();
+            while !path.contains(&current) {
+                let next = waiting_on(&current, &remaining).unwrap_or_default();
+                path.push(std::mem::replace(&mut current, next));
+            }
+            let start = path.iter().position(|name| *name == current).unwrap_or(0);
+            let mut cycle = path.split_off(start);
+            cycle.push(current);
+            return Err(ConfigError::DependencyCycle { servers: cycle });
+        }
+        for name in &wave {
+            remaining.remove(name);
+        }
+        waves.push(wave);
+    }
+    Ok(waves)
+}
+
 /// Configuration manager for loading and managing server configs
 #[derive(Debug, Clone)]
 pub struct SystemConfigManager {
@@ -124,6 +854,23 @@ pub struct SystemConfigManager {
 impl SystemConfigManager {
     pub fn new(project_dir: Option<PathBuf>) -> Result<Self> {
         let config_path = if let Some(dir) = project_dir {
+            // A common mistake is passing the config file itself rather than its directory
+            let dir = match dir.parent() {
+                Some(parent) if dir.is_file() => {
+                    let parent = if parent.as_os_str().is_empty() {
+                        Path::new(".")
+                    } else {
+                        parent
+                    };
+                    tracing::warn!(
+                        "Project directory {} is a file, using its directory {} instead",
+                        dir.display(),
+                        parent.display()
+                    );
+                    parent.to_path_buf()
+                }
+                _ => dir,
+            };
             dir.join("servers-config.json")
         } else {
             PathBuf::from("servers-config.json")
@@ -131,11 +878,9 @@ impl SystemConfigManager {
 
         let config = if config_path.exists() {
             let config_content = std::fs::read_to_string(&config_path)?;
-            serde_json::from_str(&config_content)?
+            ServersConfig::from_json_str(&config_content, selected_environment().as_deref())?
         } else {
-            ServersConfig {
-                servers: HashMap::new(),
-            }
+            ServersConfig::default()
         };
 
         Ok(Self {
@@ -152,6 +897,73 @@ impl SystemConfigManager {
         self.config.servers.get(name)
     }
 
+    /// Check the loaded config for mistakes that would otherwise only show up when a tool
+    /// is called: servers missing a field their transport requires, unknown transports,
+    /// and servers whose tool prefixes collide once sanitized (`task-master` and
+    /// `task_master`). Every problem found is returned, sorted by server name.
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors: Vec<ConfigError> = self
+            .config
+            .servers
+            .iter()
+            .filter_map(|(name, server)| {
+                server
+                    .transport_problem()
+                    .map(|reason| ConfigError::InvalidServer {
+                        server: name.clone(),
+                        reason,
+                    })
+            })
+            .collect();
+
+        let mut by_prefix: HashMap<String, Vec<String>> = HashMap::new();
+        for name in self.config.servers.keys() {
+            by_prefix
+                .entry(sanitize_name(&self.config.server_prefix(name)))
+                .or_default()
+                .push(name.clone());
+        }
+        errors.extend(
+            by_prefix
+                .into_iter()
+                .filter(|(_, servers)| servers.len() > 1)
+                .map(|(prefix, mut servers)| {
+                    servers.sort();
+                    ConfigError::DuplicatePrefix { prefix, servers }
+                }),
+        );
+
+        for (name, server) in &self.config.servers {
+            for dependency in &server.depends_on {
+                if !self.config.servers.contains_key(dependency) {
+                    errors.push(ConfigError::UnknownDependency {
+                        server: name.clone(),
+                        dependency: dependency.clone(),
+                    });
+                }
+            }
+        }
+        if let Err(cycle) = startup_waves(&self.config.servers) {
+            errors.push(cycle);
+        }
+
+        errors.sort_by_key(|error| error.to_string());
+        errors
+    }
+
+    /// Read this manager's config file again, e.g. after it was edited. Unlike `new`, a
+    /// missing file is an error rather than an empty config.
+    pub fn reread(&self) -> Result<Self> {
+        let config_content = std::fs::read_to_string(&self.config_path)?;
+        Ok(Self {
+            config_path: self.config_path.clone(),
+            config: ServersConfig::from_json_str(
+                &config_content,
+                selected_environment().as_deref(),
+            )?,
+        })
+    }
+
     /// Save the configuration to file
     pub fn save(&self) -> Result<()> {
         let config_json = serde_json::to_string_pretty(&self.config)?;
@@ -159,6 +971,11 @@ impl SystemConfigManager {
         Ok(())
     }
 
+    /// Get the full configuration, including proxy-wide settings
+    pub fn get_config(&self) -> &ServersConfig {
+        &self.config
+    }
+
     /// Get a mutable reference to the config for bulk updates
     pub fn get_config_mut(&mut self) -> &mut ServersConfig {
         &mut self.config
@@ -181,7 +998,10 @@ impl SystemConfigManager {
             Err(e) => {
                 // Attempt to restore from backup on failure
                 if let Err(restore_err) = self.restore_from_backup(&backup_path) {
-                    eprintln!("Failed to restore from backup after save failure: {restore_err}");
+                    tracing::error!(
+                        "Failed to restore from backup after save failure: {}",
+                        restore_err
+                    );
                 }
                 Err(e)
             }
@@ -251,7 +1071,8 @@ impl SystemConfigManager {
         let content = std::fs::read_to_string(file_path)?;
 
         // Parse JSON to validate syntax
-        let parsed_config: ServersConfig = serde_json::from_str(&content)?;
+        let parsed_config =
+            ServersConfig::from_json_str(&content, selected_environment().as_deref())?;
 
         // Basic structure validation
         if parsed_config.servers.is_empty() {
@@ -327,12 +1148,12 @@ impl SystemConfigManager {
         }
 
         // Sort by modification time (newest first)
-        backup_files.sort_by(|a, b| b.1.cmp(&a.1));
+        backup_files.sort_by_key(|b| std::cmp::Reverse(b.1));
 
         // Remove files beyond the 5 most recent
         for (path, _) in backup_files.iter().skip(5) {
             if let Err(e) = std::fs::remove_file(path) {
-                eprintln!("Warning: Failed to remove old backup file {path:?}: {e}");
+                tracing::warn!("Failed to remove old backup file {:?}: {}", path, e);
             }
         }
 
@@ -363,8 +1184,8 @@ impl SystemConfigManager {
                         if age > std::time::Duration::from_secs(3600) {
                             // 1 hour
                             if let Err(e) = std::fs::remove_file(entry.path()) {
-                                eprintln!(
-                                    "Warning: Failed to remove temp file {:?}: {}",
+                                tracing::warn!(
+                                    "Failed to remove temp file {:?}: {}",
                                     entry.path(),
                                     e
                                 );
@@ -419,6 +1240,31 @@ pub fn substitute_template_variables(template: &str, context: &TemplateContext)
     result
 }
 
+/// Parse the contents of a `.env` file: `KEY=value` lines, optionally prefixed with
+/// `export`. Blank lines and `#` comments are skipped, as are lines without `=`. Values
+/// wrapped in matching single or double quotes are unquoted.
+pub fn parse_env_file(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| {
+                    value
+                        .strip_prefix(*quote)
+                        .and_then(|rest| rest.strip_suffix(*quote))
+                })
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
 /// Process environment variables with template substitution
 pub fn process_env_templates(
     env: &HashMap<String, String>,
@@ -432,3 +1278,200 @@ pub fn process_env_templates(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_dir_pointing_at_config_file_uses_its_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("servers-config.json");
+        std::fs::write(
+            &config_file,
+            r#"{"servers": {"memory": {"command": "npx"}}}"#,
+        )
+        .unwrap();
+
+        let manager = SystemConfigManager::new(Some(config_file.clone())).unwrap();
+        assert_eq!(manager.get_config_path(), config_file.as_path());
+        assert!(manager.get_server("memory").is_some());
+    }
+
+    #[test]
+    fn test_env_selected_resolution() {
+        let per_env = || {
+            EnvSelected::PerEnvironment(HashMap::from([
+                ("dev".to_string(), "http://dev".to_string()),
+                ("prod".to_string(), "https://prod".to_string()),
+                ("default".to_string(), "http://default".to_string()),
+            ]))
+        };
+        assert_eq!(per_env().resolve(Some("prod")).unwrap(), "https://prod");
+        assert_eq!(per_env().resolve(Some("qa")).unwrap(), "http://default");
+        assert_eq!(per_env().resolve(None).unwrap(), "http://default");
+
+        let no_default = EnvSelected::PerEnvironment(HashMap::from([(
+            "dev".to_string(),
+            "http://dev".to_string(),
+        )]));
+        assert!(no_default.resolve(Some("prod")).is_err());
+    }
+
+    #[test]
+    fn test_server_url_follows_toolman_env() {
+        let server = serde_json::json!({
+            "transport": "http",
+            "url": { "prod": "https://mcp.example.com/mcp", "default": "http://localhost:8080/mcp" }
+        });
+
+        let prod = ServerConfig::from_json(server.clone(), Some("prod")).unwrap();
+        let default = ServerConfig::from_json(server, None).unwrap();
+
+        assert_eq!(prod.url.as_deref(), Some("https://mcp.example.com/mcp"));
+        assert_eq!(default.url.as_deref(), Some("http://localhost:8080/mcp"));
+
+        // Plain strings are unaffected
+        let plain: ServerConfig = ServerConfig::from_json(
+            serde_json::json!({ "url": "http://plain/mcp" }),
+            Some("prod"),
+        )
+        .unwrap();
+        assert_eq!(plain.url.as_deref(), Some("http://plain/mcp"));
+    }
+
+    /// A manager for a servers-config.json holding `servers`
+    fn manager_with_servers(
+        servers: serde_json::Value,
+    ) -> (tempfile::TempDir, SystemConfigManager) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("servers-config.json"),
+            serde_json::json!({ "servers": servers }).to_string(),
+        )
+        .unwrap();
+        let manager = SystemConfigManager::new(Some(dir.path().to_path_buf())).unwrap();
+        (dir, manager)
+    }
+
+    #[test]
+    fn test_validate_reports_missing_transport_fields() {
+        for (server, reason) in [
+            (
+                serde_json::json!({ "transport": "http" }),
+                "http server requires a 'url'",
+            ),
+            (
+                serde_json::json!({ "transport": "sse" }),
+                "sse server requires a 'url'",
+            ),
+            (
+                serde_json::json!({ "transport": "websocket" }),
+                "websocket server requires a 'url'",
+            ),
+            (
+                serde_json::json!({ "transport": "stdio", "command": " " }),
+                "stdio server requires a non-empty 'command'",
+            ),
+            (
+                serde_json::json!({ "args": ["server.js"] }),
+                "stdio server requires a non-empty 'command'",
+            ),
+            (
+                serde_json::json!({ "transport": "grpc", "url": "http://localhost:1" }),
+                "unknown transport 'grpc' (expected stdio, http, sse or websocket)",
+            ),
+        ] {
+            let (_dir, manager) = manager_with_servers(serde_json::json!({
+                "broken": server,
+                "fine": { "command": "npx" },
+            }));
+            assert_eq!(
+                manager.validate(),
+                vec![ConfigError::InvalidServer {
+                    server: "broken".to_string(),
+                    reason: reason.to_string(),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_colliding_server_prefixes() {
+        let (_dir, manager) = manager_with_servers(serde_json::json!({
+            "task-master": { "command": "npx" },
+            "task_master": { "command": "npx" },
+            "memory": { "transport": "http", "url": "http://localhost:1/mcp" },
+        }));
+        let errors = manager.validate();
+        assert_eq!(
+            errors,
+            vec![ConfigError::DuplicatePrefix {
+                prefix: "task_master".to_string(),
+                servers: vec!["task-master".to_string(), "task_master".to_string()],
+            }]
+        );
+        assert!(errors[0]
+            .to_string()
+            .contains("'task-master', 'task_master'"));
+
+        // Mapping one of them to a prefix of its own resolves the collision
+        let mut manager = manager;
+        manager
+            .get_config_mut()
+            .server_name_map
+            .insert("task-master".to_string(), "tm".to_string());
+        assert!(manager.validate().is_empty());
+    }
+
+    #[test]
+    fn test_startup_waves_follow_depends_on() {
+        let (_dir, manager) = manager_with_servers(serde_json::json!({
+            "gateway": { "command": "gw", "depends_on": ["search", "files"] },
+            "search": { "command": "search", "depends_on": ["files"] },
+            "files": { "command": "files" },
+            "memory": { "command": "memory" },
+        }));
+        assert!(manager.validate().is_empty());
+        assert_eq!(
+            startup_waves(manager.get_servers()).unwrap(),
+            vec![
+                vec!["files".to_string(), "memory".to_string()],
+                vec!["search".to_string()],
+                vec!["gateway".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_dependency_cycles() {
+        let (_dir, manager) = manager_with_servers(serde_json::json!({
+            "gateway": { "command": "gw", "depends_on": ["search"] },
+            "search": { "command": "search", "depends_on": ["files"] },
+            "files": { "command": "files", "depends_on": ["gateway"] },
+            "memory": { "command": "memory", "depends_on": ["missing"] },
+        }));
+        let errors = manager.validate();
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError::UnknownDependency {
+                    server: "memory".to_string(),
+                    dependency: "missing".to_string(),
+                },
+                ConfigError::DependencyCycle {
+                    servers: vec![
+                        "files".to_string(),
+                        "gateway".to_string(),
+                        "search".to_string(),
+                        "files".to_string(),
+                    ],
+                },
+            ]
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "servers depend on each other in a cycle: files -> gateway -> search -> files"
+        );
+    }
+}
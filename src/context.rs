@@ -84,18 +84,47 @@ pub struct ContextManager {
 impl ContextManager {
     /// Create a new context manager
     pub fn new() -> Result<Self> {
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Unable to determine home directory"))?;
-
-        let contexts_dir = home_dir.join(".mcp-bridge-proxy").join("contexts");
+        let contexts_dir = Self::default_contexts_dir()?;
 
         // Create contexts directory if it doesn't exist
         std::fs::create_dir_all(&contexts_dir)?;
 
-        Ok(Self {
+        Ok(Self::with_contexts_dir(contexts_dir))
+    }
+
+    /// Create a context manager reading and writing contexts in `contexts_dir`
+    pub fn with_contexts_dir(contexts_dir: PathBuf) -> Self {
+        Self {
             contexts_dir,
             current_context: None,
-        })
+        }
+    }
+
+    /// Where contexts are kept by default: `~/.mcp-bridge-proxy/contexts`
+    pub fn default_contexts_dir() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| anyhow::anyhow!("Unable to determine home directory"))?;
+        Ok(home_dir.join(".mcp-bridge-proxy").join("contexts"))
+    }
+
+    /// Look up the saved context for a project and user, without creating one
+    pub fn find_context(
+        &self,
+        project_path: &str,
+        user_id: Option<&str>,
+    ) -> Result<Option<ContextConfig>> {
+        let context_key = match user_id {
+            Some(uid) => format!("{project_path}+{uid}"),
+            None => project_path.to_string(),
+        };
+        let context_id = ContextConfig::hash_context(&context_key);
+        let context_file = self.contexts_dir.join(format!("{context_id}.json"));
+
+        if !context_file.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&context_file)?;
+        Ok(Some(serde_json::from_str(&content)?))
     }
 
     /// Load or create a context for the given project and user
@@ -202,8 +231,10 @@ impl ContextManager {
                     {
                         if last_updated.with_timezone(&chrono::Utc) < thirty_days_ago {
                             if let Err(e) = std::fs::remove_file(&path) {
-                                eprintln!(
-                                    "Warning: Failed to remove old context file {path:?}: {e}"
+                                tracing::warn!(
+                                    "Failed to remove old context file {:?}: {}",
+                                    path,
+                                    e
                                 );
                             }
                         }
@@ -198,7 +198,7 @@ impl HealthMonitor {
         let mut tasks = self.monitoring_tasks.lock().await;
         tasks.insert(server_name.clone(), task);
 
-        println!("🔍 Started health monitoring for server: {server_name}");
+        tracing::debug!(server = %server_name, "Started health monitoring");
         Ok(())
     }
 
@@ -207,7 +207,7 @@ impl HealthMonitor {
         let mut tasks = self.monitoring_tasks.lock().await;
         if let Some(task) = tasks.remove(server_name) {
             task.abort();
-            println!("🛑 Stopped health monitoring for server: {server_name}");
+            tracing::debug!(server = %server_name, "Stopped health monitoring");
         }
 
         // Remove from health map
@@ -241,7 +241,7 @@ impl HealthMonitor {
                     ServerHealth::Degraded { .. } | ServerHealth::Unresponsive { .. } => {
                         status.health = ServerHealth::Healthy;
                         status.uptime_start = Some(Instant::now());
-                        println!("✅ Server '{server_name}' has recovered and is now healthy");
+                        tracing::info!(server = %server_name, "Recovered and healthy again");
                     }
                     _ => {}
                 }
@@ -257,8 +257,9 @@ impl HealthMonitor {
 
             // Check if we need to trigger recovery
             if status.consecutive_failures >= self.config.failure_threshold {
-                println!(
-                    "⚠️ Server '{server_name}' marked as unhealthy after {} consecutive failures",
+                tracing::warn!(
+                    server = %server_name,
+                    "Marked unhealthy after {} consecutive failures",
                     status.consecutive_failures
                 );
             }
@@ -290,7 +291,7 @@ impl HealthMonitor {
         if let Some(status) = health_map.get_mut(server_name) {
             status.health = ServerHealth::Crashed { exit_code };
             status.uptime_start = None;
-            println!("💥 Server '{server_name}' marked as crashed (exit code: {exit_code:?})");
+            tracing::error!(server = %server_name, "Marked as crashed (exit code: {:?})", exit_code);
         }
     }
 
@@ -302,8 +303,9 @@ impl HealthMonitor {
             status.health = ServerHealth::Restarting {
                 attempt: status.restart_attempts,
             };
-            println!(
-                "🔄 Server '{server_name}' restart attempt #{}",
+            tracing::info!(
+                server = %server_name,
+                "Restart attempt #{}",
                 status.restart_attempts
             );
         }
@@ -317,7 +319,7 @@ impl HealthMonitor {
             status.consecutive_failures = 0;
             status.consecutive_successes = 1;
             status.uptime_start = Some(Instant::now());
-            println!("✅ Server '{server_name}' marked as healthy after restart");
+            tracing::info!(server = %server_name, "Healthy after restart");
         }
     }
 
@@ -372,7 +374,7 @@ impl HealthMonitor {
                     }
                 }
                 _ = shutdown_rx.recv() => {
-                    println!("🛑 Shutting down health monitor for server: {server_name}");
+                    tracing::debug!(server = %server_name, "Shutting down health monitor");
                     break;
                 }
             }
@@ -429,7 +431,7 @@ impl HealthMonitor {
         for (server_name, task) in tasks.drain() {
             task.abort();
             let _ = task.await;
-            println!("🛑 Shutdown monitoring for server: {server_name}");
+            tracing::debug!(server = %server_name, "Shut down health monitoring");
         }
     }
 }
@@ -136,7 +136,7 @@ impl ServerRecoveryManager {
             circuit_breakers.insert(server_name.clone(), false);
         }
 
-        println!("📋 Registered server '{server_name}' for monitoring and recovery");
+        tracing::debug!(server = %server_name, "Registered for monitoring and recovery");
         Ok(())
     }
 
@@ -168,7 +168,7 @@ impl ServerRecoveryManager {
             }
         }
 
-        println!("📋 Unregistered server '{server_name}' from monitoring");
+        tracing::debug!(server = %server_name, "Unregistered from monitoring");
         Ok(())
     }
 
@@ -256,7 +256,7 @@ impl ServerRecoveryManager {
             }
 
             RecoveryAction::RetryWithDelay { delay } => {
-                println!("⏱️ Delaying retry for {delay:?}");
+                tracing::debug!("Delaying retry for {:?}", delay);
                 tokio::time::sleep(delay).await;
                 Ok(())
             }
@@ -275,7 +275,7 @@ impl ServerRecoveryManager {
             } => self.mark_server_as_failed(&server_name, &reason).await,
 
             RecoveryAction::RequireManualIntervention { message } => {
-                println!("🚨 Manual intervention required: {message}");
+                tracing::error!("Manual intervention required: {}", message);
                 Ok(())
             }
         }
@@ -320,11 +320,11 @@ impl ServerRecoveryManager {
         );
 
         if delay > Duration::from_millis(0) {
-            println!(
-                "⏱️ Waiting {:?} before restart attempt #{} for server '{}'",
+            tracing::info!(
+                server = %server_name,
+                "Waiting {:?} before restart attempt #{}",
                 delay,
-                restart_attempt + 1,
-                server_name
+                restart_attempt + 1
             );
             tokio::time::sleep(delay).await;
         }
@@ -335,7 +335,7 @@ impl ServerRecoveryManager {
             health_monitor.mark_server_restarting(server_name).await;
         }
 
-        println!("🔄 Attempting to restart server: {server_name}");
+        tracing::info!(server = %server_name, "Attempting restart");
 
         // Spawn the new process
         let mut cmd = Command::new(&connection_info.command);
@@ -367,9 +367,9 @@ impl ServerRecoveryManager {
                     health_monitor.mark_server_healthy(server_name).await;
                 }
 
-                println!(
-                    "✅ Successfully restarted server '{}' (PID: {:?})",
-                    server_name,
+                tracing::info!(
+                    server = %server_name,
+                    "Restarted (PID: {:?})",
                     process.id()
                 );
                 Ok(())
@@ -390,7 +390,11 @@ impl ServerRecoveryManager {
 
     /// Switch to a fallback server
     async fn switch_to_fallback(&self, primary: &str, fallback: &str) -> BridgeResult<()> {
-        println!("🔄 Switching from primary server '{primary}' to fallback '{fallback}'");
+        tracing::warn!(
+            "Switching from primary server '{}' to fallback '{}'",
+            primary,
+            fallback
+        );
 
         // Update fallback mappings
         {
@@ -403,13 +407,13 @@ impl ServerRecoveryManager {
 
         // For now, this is a placeholder - in a real implementation,
         // you'd need to reroute requests to the fallback server
-        println!("✅ Configured fallback: {primary} -> {fallback}");
+        tracing::info!("Configured fallback: {} -> {}", primary, fallback);
         Ok(())
     }
 
     /// Mark a server as permanently failed
     async fn mark_server_as_failed(&self, server_name: &str, reason: &str) -> BridgeResult<()> {
-        println!("💀 Marking server '{server_name}' as failed: {reason}");
+        tracing::error!(server = %server_name, "Marking as failed: {}", reason);
 
         // Open circuit breaker permanently
         {
@@ -460,9 +464,10 @@ impl ServerRecoveryManager {
                 let mut circuit_breakers = self.circuit_breakers.write().await;
                 circuit_breakers.insert(server_name.to_string(), true);
 
-                println!(
-                    "🔴 Circuit breaker opened for server '{}' after {} failures",
-                    server_name, connection_info.circuit_breaker_trips
+                tracing::warn!(
+                    server = %server_name,
+                    "Circuit breaker opened after {} failures",
+                    connection_info.circuit_breaker_trips
                 );
             }
         }
@@ -531,14 +536,14 @@ impl ServerRecoveryManager {
 
     /// Shutdown the recovery manager
     pub async fn shutdown(&mut self) {
-        println!("🛑 Shutting down server recovery manager");
+        tracing::debug!("Shutting down server recovery manager");
 
         // Stop all recovery tasks
         let mut tasks = self.recovery_tasks.lock().await;
         for (server_name, task) in tasks.drain() {
             task.abort();
             let _ = task.await;
-            println!("🛑 Stopped recovery task for server: {server_name}");
+            tracing::debug!(server = %server_name, "Stopped recovery task");
         }
 
         // Shutdown health monitor
@@ -5,9 +5,9 @@
 
 use anyhow::Result;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
@@ -16,13 +16,21 @@ use clap::Parser;
 use futures::future;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use toolman::config::{process_env_templates, TemplateContext};
-use toolman::config::{ServerConfig, SystemConfigManager as ConfigManager};
+use toolman::config::{
+    parse_env_file, process_env_templates, sanitize_name, selected_environment, startup_waves,
+    TemplateContext,
+};
+use toolman::config::{
+    ClientInfo, ClientInfoPassthrough, InjectionPrecedence, RequestIdType, ServerAuth,
+    ServerConfig, ServersConfig, SpawnLimits, SystemConfigManager as ConfigManager,
+    WhileInitializing,
+};
 use toolman::resolve_working_directory;
+use toolman::{ContextConfig, ContextManager};
 use tower_http::cors::CorsLayer;
 
 // Kubernetes imports
@@ -46,6 +54,665 @@ fn get_current_namespace() -> String {
     }
 }
 
+/// Whether discovery should publish the tool catalog ConfigMap (`TOOLMAN_WRITE_TOOL_CATALOG`, default on)
+fn tool_catalog_writes_enabled() -> bool {
+    !matches!(
+        std::env::var("TOOLMAN_WRITE_TOOL_CATALOG")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "false" | "0" | "no" | "off"
+    )
+}
+
+/// Whether tools/call arguments are checked against the tool's `inputSchema` before being
+/// forwarded (`TOOLMAN_VALIDATE_ARGUMENTS`, default off)
+fn argument_validation_enabled() -> bool {
+    matches!(
+        std::env::var("TOOLMAN_VALIDATE_ARGUMENTS")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "true" | "1" | "yes" | "on"
+    )
+}
+
+/// Where `arguments` violate `schema`, as `{path, message}` objects. The `injected`
+/// arguments are left out unless the schema declares them, since Toolman adds them to
+/// calls whatever the tool. A schema that doesn't compile is reported by no violations.
+fn argument_violations(schema: &Value, arguments: &Value, injected: &[String]) -> Vec<Value> {
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(validator) => validator,
+        Err(e) => {
+            tracing::debug!(target: LOG_SERVER, "Not validating arguments against an invalid inputSchema: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut arguments = arguments.clone();
+    if let Some(args) = arguments.as_object_mut() {
+        for name in injected {
+            if schema["properties"].get(name).is_none() {
+                args.remove(name);
+            }
+        }
+    }
+    validator
+        .iter_errors(&arguments)
+        .map(|error| json!({ "path": error.instance_path.to_string(), "message": error.to_string() }))
+        .collect()
+}
+
+/// How long a stdio server may go without a tool call before it is stopped
+/// (`TOOLMAN_IDLE_TIMEOUT_SECS`, default 600). `0` keeps servers running forever.
+fn idle_timeout() -> Option<std::time::Duration> {
+    let secs = std::env::var("TOOLMAN_IDLE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(600);
+    (secs > 0).then(|| std::time::Duration::from_secs(secs))
+}
+
+/// Check that the service account may write the tool catalog ConfigMap.
+///
+/// Uses a dry-run server-side apply of the catalog so nothing is persisted. Returns an
+/// actionable message when the write would be rejected.
+async fn probe_catalog_permissions(client: Client, namespace: &str) -> Result<(), String> {
+    let cm = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some("toolman-tool-catalog".to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    api.patch(
+        "toolman-tool-catalog",
+        &PatchParams::apply("toolman").force().dry_run(),
+        &Patch::Apply(&cm),
+    )
+    .await
+    .map(|_| ())
+    .map_err(|e| catalog_permission_message(&e, namespace))
+}
+
+/// Turn a failed catalog write into a single message naming the missing RBAC permission
+fn catalog_permission_message(error: &kube::Error, namespace: &str) -> String {
+    match error {
+        kube::Error::Api(response) if response.code == 403 => {
+            // Kubernetes reports e.g. `... cannot patch resource "configmaps" ...`
+            let verb = response
+                .message
+                .split("cannot ")
+                .nth(1)
+                .and_then(|rest| rest.split_whitespace().next())
+                .unwrap_or("patch");
+            format!(
+                "Service account is missing RBAC verb '{}' on configmaps in namespace '{}'. \
+                 Grant a Role with verbs [get, create, patch] on configmaps (or set \
+                 TOOLMAN_WRITE_TOOL_CATALOG=false). The tool catalog ConfigMap will not be written.",
+                verb, namespace
+            )
+        }
+        other => format!(
+            "Cannot write the tool catalog ConfigMap in namespace '{}': {}. \
+             The tool catalog ConfigMap will not be written.",
+            namespace, other
+        ),
+    }
+}
+
+// Log targets, so `RUST_LOG` can set levels per area, e.g.
+// `RUST_LOG=info,toolman::backend=debug` or `RUST_LOG=info,toolman::backend[{server=memory}]=debug`
+/// Request handling in the HTTP server
+const LOG_SERVER: &str = "toolman::server";
+/// Tool discovery across configured servers
+const LOG_DISCOVERY: &str = "toolman::discovery";
+/// Output of backend MCP servers (stderr of stdio servers), inside a `backend{server=<name>}` span
+const LOG_BACKEND: &str = "toolman::backend";
+
+/// Build the log filter from `RUST_LOG`-style directives, defaulting to `info`
+fn log_filter(directives: Option<&str>) -> tracing_subscriber::EnvFilter {
+    let directives = directives.unwrap_or("info");
+    tracing_subscriber::EnvFilter::try_new(directives).unwrap_or_else(|e| {
+        eprintln!("⚠️ Invalid RUST_LOG '{}' ({}), using 'info'", directives, e);
+        tracing_subscriber::EnvFilter::new("info")
+    })
+}
+
+fn init_tracing() {
+    tracing_subscriber::fmt()
+        .with_env_filter(log_filter(std::env::var("RUST_LOG").ok().as_deref()))
+        .init();
+}
+
+/// Whether a key looks like it names a secret (an API key, token, password or secret),
+/// whatever its case or separators: `API_KEY`, `apiKey` and `api-key` all do
+fn is_sensitive_key(key: &str) -> bool {
+    let key: String = key
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    ["APIKEY", "TOKEN", "PASSWORD", "SECRET"]
+        .iter()
+        .any(|pattern| key.contains(pattern))
+}
+
+/// An environment variable's value as it may be logged: values of keys that look like
+/// secrets (API keys, tokens, passwords) are masked
+fn masked_env_value(key: &str, value: &str) -> String {
+    if is_sensitive_key(key) {
+        if value.len() > 8 {
+            format!("{}...{}", &value[..4], &value[value.len() - 4..])
+        } else {
+            "***".to_string()
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+/// Tool call arguments as they may be written to the audit log: the values of keys that
+/// look like secrets are replaced, at any depth
+fn redact_arguments(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let value = if is_sensitive_key(key) {
+                        json!("[REDACTED]")
+                    } else {
+                        redact_arguments(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_arguments).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Audit trail of forwarded tool calls (`TOOLMAN_AUDIT_LOG`): one JSON line per call,
+/// in a file that rolls over daily. Lines are queued for a background thread, so calls
+/// never wait on the disk. Should the disk fall so far behind that the queue fills up,
+/// further lines are dropped and counted (`toolman_audit_log_dropped_lines`).
+#[derive(Clone)]
+struct AuditLog {
+    writer: tracing_appender::non_blocking::NonBlocking,
+    // Flushes lines still buffered once the last clone is dropped
+    _guard: Arc<tracing_appender::non_blocking::WorkerGuard>,
+}
+
+impl AuditLog {
+    /// Audit log writing to `<path>.<YYYY-MM-DD>`, starting a new file each day
+    fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("'{}' is not a file path", path.display()))?;
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| std::path::Path::new("."));
+        let appender = tracing_appender::rolling::RollingFileAppender::builder()
+            .rotation(tracing_appender::rolling::Rotation::DAILY)
+            .filename_prefix(file_name.to_string_lossy())
+            .build(dir)?;
+        Ok(Self::with_writer(
+            appender,
+            tracing_appender::non_blocking::DEFAULT_BUFFERED_LINES_LIMIT,
+        ))
+    }
+
+    /// Audit log handing its lines to `writer` on a background thread, with up to
+    /// `buffered_lines` waiting to be written
+    fn with_writer(writer: impl std::io::Write + Send + 'static, buffered_lines: usize) -> Self {
+        let (writer, guard) = tracing_appender::non_blocking::NonBlockingBuilder::default()
+            .buffered_lines_limit(buffered_lines)
+            .finish(writer);
+        Self {
+            writer,
+            _guard: Arc::new(guard),
+        }
+    }
+
+    /// Lines dropped so far because the writer had fallen behind
+    fn dropped_lines(&self) -> usize {
+        self.writer.error_counter().dropped_lines()
+    }
+
+    /// The audit log named by `TOOLMAN_AUDIT_LOG`, if set
+    fn from_env() -> Option<Self> {
+        let path = std::env::var("TOOLMAN_AUDIT_LOG")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        match Self::open(std::path::Path::new(&path)) {
+            Ok(audit_log) => {
+                tracing::info!(target: LOG_SERVER, "Writing an audit log of tool calls to {}", path);
+                Some(audit_log)
+            }
+            Err(e) => {
+                tracing::warn!(target: LOG_SERVER, "Not writing an audit log to {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn record(&self, entry: &Value) {
+        use std::io::Write;
+        let mut line = entry.to_string();
+        line.push('\n');
+        if let Err(e) = self.writer.clone().write_all(line.as_bytes()) {
+            tracing::warn!(target: LOG_SERVER, "Failed to write an audit log entry: {}", e);
+        }
+    }
+}
+
+/// Span wrapping a backend's output; its `server` field is what per-server directives match on
+fn backend_span(server_name: &str) -> tracing::Span {
+    tracing::info_span!(target: LOG_BACKEND, "backend", server = %server_name)
+}
+
+/// Forward each stderr line of a backend process to the `toolman::backend` target at debug level
+fn forward_backend_stderr(server_name: String, stderr: tokio::process::ChildStderr) {
+    let span = backend_span(&server_name);
+    let drain = async move {
+        let mut stderr_reader = BufReader::new(stderr);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match stderr_reader.read_line(&mut line).await {
+                Ok(0) => break, // EOF
+                Ok(_) => {
+                    if !line.trim().is_empty() {
+                        tracing::debug!(target: LOG_BACKEND, "{}", line.trim());
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(target: LOG_BACKEND, "Error reading stderr from {}: {}", server_name, e);
+                    break;
+                }
+            }
+        }
+    };
+    tokio::spawn(tracing::Instrument::instrument(drain, span));
+}
+
+/// User-Agent sent to http/sse servers that don't configure their own
+const DEFAULT_USER_AGENT: &str = concat!("toolman/", env!("CARGO_PKG_VERSION"));
+
+/// Header counting the Toolman proxies a request has passed through, to catch proxy loops
+const HOPS_HEADER: &str = "X-Toolman-Hops";
+
+/// Header asking for `tools/call` to describe the call it would forward instead of making it
+const DRY_RUN_HEADER: &str = "X-Toolman-Dry-Run";
+
+/// Whether the client request asked for a dry run with `X-Toolman-Dry-Run: true`
+fn is_dry_run(headers: Option<&axum::http::HeaderMap>) -> bool {
+    headers
+        .and_then(|h| h.get(DRY_RUN_HEADER))
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true") || v.trim() == "1")
+}
+
+tokio::task_local! {
+    /// `X-Toolman-Hops` of the client request being handled
+    static REQUEST_HOPS: u32;
+}
+
+/// Build the HTTP client for a remote server, honouring its `user_agent` override.
+/// Requests carry `X-Toolman-Hops`, one more than the client request being handled.
+fn http_client_for(server_name: &str, config: &ServerConfig) -> reqwest::Client {
+    let user_agent = config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    let hops = REQUEST_HOPS.try_with(|hops| *hops).unwrap_or(0) + 1;
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(HOPS_HEADER, reqwest::header::HeaderValue::from(hops));
+    if let Some(auth) = &config.auth {
+        match auth_header(auth) {
+            Ok((name, value)) => {
+                headers.insert(name, value);
+            }
+            Err(e) => tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Sending requests without auth: {}",
+                e
+            ),
+        }
+    }
+    reqwest::Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers)
+        .build()
+        .unwrap_or_else(|e| {
+            tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Invalid user_agent '{}' ({}), using default client",
+                user_agent,
+                e
+            );
+            reqwest::Client::new()
+        })
+}
+
+/// The header a server's `auth` adds to its requests, marked sensitive so it isn't logged
+fn auth_header(
+    auth: &ServerAuth,
+) -> anyhow::Result<(reqwest::header::HeaderName, reqwest::header::HeaderValue)> {
+    let from_env = |var: &str| {
+        std::env::var(var).map_err(|_| anyhow::anyhow!("environment variable {} is not set", var))
+    };
+    let (name, value) = match auth {
+        ServerAuth::Bearer { token_env } => (
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", from_env(token_env)?),
+        ),
+        ServerAuth::Header {
+            name,
+            value,
+            value_env,
+        } => {
+            let value = match (value, value_env) {
+                (_, Some(var)) => from_env(var)?,
+                (Some(value), None) => value.clone(),
+                (None, None) => anyhow::bail!("header '{}' has no value or value_env", name),
+            };
+            (reqwest::header::HeaderName::try_from(name.as_str())?, value)
+        }
+    };
+    let mut value = reqwest::header::HeaderValue::from_str(&value)?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Capabilities declared when no `capabilities` are configured for a server
+fn default_handshake_capabilities() -> Value {
+    json!({
+        "tools": {
+            "listChanged": true
+        }
+    })
+}
+
+/// The `clientInfo` a backend sees, depending on `client_info_passthrough` and the
+/// identity of the client session the handshake is made for (if any)
+fn backend_client_info(passthrough: ClientInfoPassthrough, client: Option<&ClientInfo>) -> Value {
+    match (passthrough, client) {
+        (ClientInfoPassthrough::Forward, Some(client)) => json!({
+            "name": client.name,
+            "version": client.version
+        }),
+        (ClientInfoPassthrough::Via, Some(client)) => json!({
+            "name": format!("toolman-via-{}", client.name),
+            "version": env!("CARGO_PKG_VERSION")
+        }),
+        _ => json!({
+            "name": "toolman",
+            "version": env!("CARGO_PKG_VERSION")
+        }),
+    }
+}
+
+/// Build the `initialize` request sent to a backend. Every transport uses this, so all
+/// backends see the same handshake; `capabilities` can be overridden per server for
+/// backends that reject the default (e.g. ones requiring `roots` or `sampling`).
+fn initialize_request(config: &ServerConfig, client_info: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": config.request_id_type.id(1),
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2024-11-05",
+            "capabilities": config
+                .capabilities
+                .clone()
+                .unwrap_or_else(default_handshake_capabilities),
+            "clientInfo": client_info
+        }
+    })
+}
+
+/// Apply a stdio server's configured niceness and rlimits to the child before it execs
+#[cfg(target_os = "linux")]
+fn apply_spawn_limits(cmd: &mut Command, server_name: &str, limits: &SpawnLimits) {
+    tracing::debug!(
+        target: LOG_BACKEND,
+        server = %server_name,
+        "Applying spawn limits: {:?}",
+        limits
+    );
+    let limits = limits.clone();
+    let set_rlimit = |resource, value: u64| {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: setrlimit only reads the struct passed by pointer
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+
+    // SAFETY: the hook runs in the forked child and only makes async-signal-safe syscalls
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(nice) = limits.nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(memory_mb) = limits.memory_mb {
+                set_rlimit(libc::RLIMIT_AS, memory_mb.saturating_mul(1024 * 1024))?;
+            }
+            if let Some(cpu_seconds) = limits.cpu_seconds {
+                set_rlimit(libc::RLIMIT_CPU, cpu_seconds)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_spawn_limits(_cmd: &mut Command, server_name: &str, _limits: &SpawnLimits) {
+    tracing::warn!(
+        target: LOG_BACKEND,
+        server = %server_name,
+        "Spawn limits are only supported on Linux, ignoring them"
+    );
+}
+
+/// Whether a server is started with `docker` (by name or path)
+fn is_docker_command(command: &str) -> bool {
+    std::path::Path::new(command)
+        .file_name()
+        .is_some_and(|name| name == "docker")
+}
+
+/// Stop a backend process: SIGTERM first so it can flush and release what it holds, then
+/// SIGKILL if it is still running after `grace`. A zero grace period kills it at once.
+async fn terminate_gracefully(
+    server_name: &str,
+    process: &mut Child,
+    grace: tokio::time::Duration,
+) -> std::io::Result<std::process::ExitStatus> {
+    #[cfg(unix)]
+    if let (Some(pid), false) = (process.id(), grace.is_zero()) {
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } == 0 {
+            match tokio::time::timeout(grace, process.wait()).await {
+                Ok(status) => return status,
+                Err(_) => tracing::warn!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Still running {}s after SIGTERM, sending SIGKILL",
+                    grace.as_secs_f32()
+                ),
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = server_name;
+
+    process.kill().await?;
+    process.wait().await
+}
+
+/// Quick TCP connect to the host behind `url`, bounded by `timeout`
+async fn check_reachable(url: &str, timeout: tokio::time::Duration) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL '{}': {}", url, e))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("URL '{}' has no host", url))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("URL '{}' has no port", url))?;
+
+    match tokio::time::timeout(timeout, tokio::net::TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("cannot connect to {}:{}: {}", host, port, e)),
+        Err(_) => Err(format!(
+            "no connection to {}:{} within {}ms",
+            host,
+            port,
+            timeout.as_millis()
+        )),
+    }
+}
+
+/// Local tool servers that passed validation, and `(name, reason)` for each rejected entry
+type LocalToolsConfig = (HashMap<String, ServerConfig>, Vec<(String, String)>);
+
+/// Parse `local-tools-config.json` (same shape as servers-config.json).
+///
+/// Returns the valid servers plus `(name, reason)` for every entry that was rejected, so
+/// mis-edited entries are reported instead of silently disappearing. Fails only if the
+/// document itself is not JSON or has no `servers` object.
+fn parse_local_tools_config(config_json: &str) -> anyhow::Result<LocalToolsConfig> {
+    let config: Value = serde_json::from_str(config_json)
+        .map_err(|e| anyhow::anyhow!("local-tools-config.json is not valid JSON: {}", e))?;
+    let servers_obj = config
+        .get("servers")
+        .and_then(|s| s.as_object())
+        .ok_or_else(|| anyhow::anyhow!("local-tools-config.json has no 'servers' object"))?;
+
+    let mut servers = HashMap::new();
+    let mut rejected = Vec::new();
+    for (name, value) in servers_obj {
+        let server_config =
+            match ServerConfig::from_json(value.clone(), selected_environment().as_deref()) {
+                Ok(server_config) => server_config,
+                Err(e) => {
+                    rejected.push((name.clone(), e.to_string()));
+                    continue;
+                }
+            };
+
+        match server_config.transport_problem() {
+            Some(reason) => rejected.push((name.clone(), reason)),
+            None => {
+                servers.insert(name.clone(), server_config);
+            }
+        }
+    }
+
+    rejected.sort();
+    Ok((servers, rejected))
+}
+
+/// Labels asked for in `tools/list`: an array, or a comma-separated string as given in
+/// `/mcp?labels=safe,read`
+fn requested_labels(labels: &Value) -> Vec<String> {
+    match labels {
+        Value::Array(labels) => labels
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Value::String(labels) => labels
+            .split(',')
+            .map(str::trim)
+            .filter(|label| !label.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Drop repeated names from one server's `tools/list`, keeping the first definition.
+/// A misbehaving backend listing a tool twice would otherwise have the last copy
+/// silently win when the tools are registered by name.
+fn dedupe_tools(server_name: &str, tools: Vec<Tool>) -> Vec<Tool> {
+    let mut seen = HashSet::new();
+    tools
+        .into_iter()
+        .filter(|tool| {
+            let first = seen.insert(tool.name.clone());
+            if !first {
+                tracing::warn!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Ignoring duplicate tool '{}' in tools/list",
+                    tool.name
+                );
+            }
+            first
+        })
+        .collect()
+}
+
+/// Maximum number of characters of an unparseable body quoted in errors
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// Parse the body of a JSON-RPC response received over HTTP.
+///
+/// Tries the body as plain JSON first, then the `data:` payload of each SSE event (some
+/// servers answer POSTs with an SSE stream). Fails with a snippet of the body otherwise.
+fn parse_http_jsonrpc_body(body: &str) -> anyhow::Result<Value> {
+    if let Ok(value) = serde_json::from_str::<Value>(body.trim()) {
+        return Ok(value);
+    }
+
+    if let Some(value) = sse_event_payloads(body)
+        .iter()
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .find(|value| value.is_object())
+    {
+        return Ok(value);
+    }
+
+    let mut snippet: String = body.chars().take(BODY_SNIPPET_CHARS).collect();
+    if body.chars().count() > BODY_SNIPPET_CHARS {
+        snippet.push('…');
+    }
+    Err(anyhow::anyhow!(
+        "response is neither JSON nor SSE-framed JSON: {:?}",
+        snippet
+    ))
+}
+
+/// The `data` of each event in an SSE body (multi-line data joined with newlines)
+fn sse_event_payloads(body: &str) -> Vec<String> {
+    let mut events = Vec::new();
+    let mut data_lines: Vec<&str> = Vec::new();
+    for line in body.lines().map(|l| l.trim_end_matches('\r')) {
+        if line.is_empty() {
+            if !data_lines.is_empty() {
+                events.push(data_lines.join("\n"));
+                data_lines.clear();
+            }
+        } else if let Some(data) = line.strip_prefix("data:") {
+            data_lines.push(data.strip_prefix(' ').unwrap_or(data));
+        }
+    }
+    if !data_lines.is_empty() {
+        events.push(data_lines.join("\n"));
+    }
+    events
+}
+
 /// Toolman HTTP MCP Server
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -74,6 +741,12 @@ struct Tool {
     /// This is used for forwarding tool calls to preserve the exact name the server expects
     #[serde(skip_serializing)]
     original_tool_name: String,
+    /// MCP annotations (`readOnlyHint`, `destructiveHint`, ...) as reported by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotations: Option<Value>,
+    /// Operator-defined labels from the server's and the tool's config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
 }
 
 // Tool catalog structures for ConfigMap
@@ -90,6 +763,9 @@ struct LocalServerInfo {
     command: String,
     args: Vec<String>,
     working_directory: String,
+    /// `serverInfo.version` reported by the server's initialize response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_version: Option<String>,
     tools: Vec<ToolInfo>,
 }
 
@@ -97,6 +773,8 @@ struct LocalServerInfo {
 struct RemoteServerInfo {
     description: String,
     endpoint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server_version: Option<String>,
     tools: Vec<ToolInfo>,
 }
 
@@ -107,6 +785,8 @@ struct ToolInfo {
     category: String,
     use_cases: Vec<String>,
     input_schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
 }
 
 // Tool name parsing structures and functions
@@ -124,11 +804,73 @@ enum ToolParseError {
     EmptyToolName,
 }
 
+/// Variables from a server's `env_file`, or none (with a warning) if it can't be read
+fn read_env_file(server_name: &str, path: &std::path::Path) -> HashMap<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => parse_env_file(&content),
+        Err(e) => {
+            tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Ignoring env_file {}: {}",
+                path.display(),
+                e
+            );
+            HashMap::new()
+        }
+    }
+}
+
+/// One server's tools as they would be listed, keyed by prefixed name, for spotting changes
+fn server_tools_snapshot(tools: &HashMap<String, Tool>, server_name: &str) -> Value {
+    tools
+        .iter()
+        .filter(|(_, tool)| tool.server_name == server_name)
+        .map(|(name, tool)| (name.clone(), json!(tool)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Fail with every problem `validate` finds in the config, one per line
+fn check_config(config_manager: &ConfigManager) -> Result<()> {
+    let config_errors = config_manager.validate();
+    if !config_errors.is_empty() {
+        anyhow::bail!(
+            "Invalid {}:\n  {}",
+            config_manager.get_config_path().display(),
+            config_errors
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Tool-name prefix of every configured server, keyed by server name
+fn server_prefixes(config: &ServersConfig) -> HashMap<String, String> {
+    config
+        .servers
+        .keys()
+        .map(|name| (name.clone(), config.server_prefix(name)))
+        .collect()
+}
+
+/// Key a discovered tool is registered under and advertised as: `{prefix}_{tool}`, with the
+/// tool name sanitized
+fn prefixed_tool_name(prefix: &str, tool_name: &str) -> String {
+    format!("{}_{}", prefix, sanitize_name(tool_name))
+}
+
 /// Parse a prefixed tool name into server and tool components
 ///
 /// This function handles the Context7 routing bug by looking up the original tool name
-/// from the available_tools HashMap. When Cursor sanitizes tool names (converting hyphens
-/// to underscores), we need to restore the original tool name for forwarding.
+/// from the available_tools HashMap. Clients sanitize tool names differently (Cursor turns
+/// hyphens into underscores, others also rewrite dots or spaces), so an incoming name that
+/// isn't registered as-is is normalized with `sanitize_name` and matched against the
+/// sanitized registered keys. A registered tool is routed to the server and exact tool name
+/// stored at discovery; only unregistered names are split using `server_prefixes`.
 ///
 /// Examples:
 /// - "memory_delete_entities" → ParsedTool { server_name: "memory", tool_name: "delete_entities" }
@@ -136,19 +878,29 @@ enum ToolParseError {
 /// - "filesystem_read_file" → ParsedTool { server_name: "filesystem", tool_name: "read_file" }
 fn parse_tool_name_with_servers(
     tool_name: &str,
-    available_servers: &[String],
+    server_prefixes: &HashMap<String, String>,
     available_tools: &HashMap<String, Tool>,
 ) -> Result<ParsedTool, ToolParseError> {
     if tool_name.is_empty() {
         return Err(ToolParseError::EmptyToolName);
     }
 
-    // Convert server names to underscore format for matching
-    // e.g., "task-master-ai" -> "task_master_ai"
-    let underscore_servers: Vec<String> = available_servers
-        .iter()
-        .map(|s| s.replace('-', "_"))
-        .collect();
+    // Undo any client-side sanitization by normalizing the incoming name the same way
+    let raw_tool_name = tool_name;
+    let tool_name = &sanitize_name(tool_name);
+
+    let registered = available_tools.get(raw_tool_name).or_else(|| {
+        available_tools
+            .iter()
+            .find(|(key, _)| sanitize_name(key) == *tool_name)
+            .map(|(_, tool)| tool)
+    });
+    if let Some(tool) = registered {
+        return Ok(ParsedTool {
+            server_name: tool.server_name.clone(),
+            tool_name: tool.original_tool_name.clone(),
+        });
+    }
 
     // Find underscore positions
     let underscore_positions: Vec<usize> = tool_name
@@ -164,44 +916,33 @@ fn parse_tool_name_with_servers(
     // Try each underscore position to find a match with known servers
     for &underscore_pos in &underscore_positions {
         let potential_server_underscore = &tool_name[..underscore_pos];
-        let _potential_tool = &tool_name[underscore_pos + 1..];
+        // Unsanitized remainder, used when the tool isn't registered (sanitize_name is per-char)
+        let server_chars = tool_name[..underscore_pos].chars().count();
+        let _potential_tool: String = raw_tool_name.chars().skip(server_chars + 1).collect();
 
         if !potential_server_underscore.is_empty() {
-            // Check if this matches any of our known servers (in underscore format)
-            if underscore_servers
+            // Check if this matches the (sanitized) prefix of any of our known servers
+            if let Some((original_server, _)) = server_prefixes
                 .iter()
-                .any(|s| s == potential_server_underscore)
+                .find(|(_, prefix)| sanitize_name(prefix) == potential_server_underscore)
             {
-                // Find the original server name (with hyphens)
-                let original_server = available_servers
-                    .iter()
-                    .find(|s| s.replace('-', "_") == potential_server_underscore)
-                    .unwrap(); // Safe because we just found it above
-
-                // 🔧 FIX: Look up the original tool name from available_tools HashMap
-                // This is critical for Context7 and any other MCP servers that use hyphens
-                if let Some(tool) = available_tools.get(tool_name) {
-                    // Use the original tool name stored during registration
-                    return Ok(ParsedTool {
-                        server_name: original_server.clone(),
-                        tool_name: tool.original_tool_name.clone(),
-                    });
-                } else {
-                    // Fallback: If tool not found in HashMap, log a warning and use parsed name
-                    // This should not happen in normal operation
-                    eprintln!(
-                        "⚠️ Tool '{}' not found in available_tools HashMap. Using parsed name as fallback.",
-                        tool_name
-                    );
-                    return Ok(ParsedTool {
-                        server_name: original_server.clone(),
-                        tool_name: _potential_tool.to_string(),
-                    });
-                }
+                // Fallback: the tool isn't registered, so log a warning and use the parsed name
+                // This should not happen in normal operation
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    "Tool '{}' not found in available_tools HashMap. Using parsed name as fallback.",
+                    tool_name
+                );
+                return Ok(ParsedTool {
+                    server_name: original_server.clone(),
+                    tool_name: _potential_tool,
+                });
             }
         }
     }
 
+    let mut available_servers: Vec<&str> = server_prefixes.keys().map(String::as_str).collect();
+    available_servers.sort_unstable();
     Err(ToolParseError::InvalidFormat(format!(
         "{} (no matching server found in: {})",
         tool_name,
@@ -256,12 +997,57 @@ struct McpServerConnection {
     stdout_reader: BufReader<tokio::process::ChildStdout>,
     server_name: String,
     next_request_id: Arc<Mutex<u64>>,
+    // When a tool call was last sent or answered, for the idle reaper
+    last_used: std::time::Instant,
 }
 
 #[derive(Debug)]
 struct ServerConnectionPool {
     connections: Arc<RwLock<HashMap<String, Arc<Mutex<McpServerConnection>>>>>,
     config_manager: Arc<RwLock<ConfigManager>>,
+    // Tool calls currently being forwarded, per server
+    in_flight: Arc<std::sync::Mutex<HashMap<String, usize>>>,
+    // `serverInfo.version` from each backend's most recent initialize response
+    server_versions: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    // `capabilities` from each backend's most recent initialize response
+    server_capabilities: Arc<std::sync::Mutex<HashMap<String, Value>>>,
+    // Process id of each running stdio server, so shutdown can kill ones stuck stopping
+    process_ids: Arc<std::sync::Mutex<HashMap<String, u32>>>,
+    // One mutex per configured `lock_key`, serializing calls to the tools that share it
+    tool_locks: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    // Call slots of each server with `max_concurrent_calls`, and the limit they were sized for
+    call_slots: Arc<std::sync::Mutex<HashMap<String, CallSlots>>>,
+    // One mutex per server, held while it is started so concurrent starts spawn it only once
+    start_locks: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    // One mutex per stdio server, held from sending a request until its response is read
+    exchange_locks: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    // Open sessions with SSE servers, up to `sse_pool_size` per server
+    sse_sessions: Arc<std::sync::Mutex<HashMap<String, Vec<Arc<SseSession>>>>>,
+    // Open connections to WebSocket servers, one per server
+    websocket_connections: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<WebSocketConnection>>>>>,
+    // `Mcp-Session-Id` issued by each streamable HTTP server that uses sessions
+    http_sessions: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    // Annotations of each server's tools (by original name) as last discovered, which
+    // pick their rung of `call_timeout_ladder`
+    tool_annotations: Arc<std::sync::Mutex<HashMap<String, HashMap<String, Value>>>>,
+}
+
+/// Marks a tool call as in flight for its server until dropped
+struct InFlightGuard {
+    in_flight: Arc<std::sync::Mutex<HashMap<String, usize>>>,
+    server_name: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.server_name) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                in_flight.remove(&self.server_name);
+            }
+        }
+    }
 }
 
 impl ServerConnectionPool {
@@ -269,47 +1055,754 @@ impl ServerConnectionPool {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
             config_manager,
+            in_flight: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            server_versions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            server_capabilities: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            process_ids: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            tool_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            call_slots: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            start_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            exchange_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            sse_sessions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            websocket_connections: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            http_sessions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            tool_annotations: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
     }
 
-    /// Check if Docker is available and ready
-    async fn is_docker_ready(&self) -> bool {
-        use tokio::process::Command;
+    /// Remember the annotations of a server's freshly discovered tools
+    fn record_tool_annotations(&self, server_name: &str, tools: &[Tool]) {
+        let annotations = tools
+            .iter()
+            .filter_map(|tool| {
+                let annotations = tool.annotations.clone()?;
+                Some((tool.original_tool_name.clone(), annotations))
+            })
+            .collect();
+        self.tool_annotations
+            .lock()
+            .unwrap()
+            .insert(server_name.to_string(), annotations);
+    }
 
-        // Try to run docker version command
-        match Command::new("docker").arg("version").output().await {
-            Ok(output) => {
-                if output.status.success() {
-                    println!("✅ Docker is available and ready");
-                    true
-                } else {
-                    println!("❌ Docker command failed with status: {}", output.status);
-                    false
-                }
-            }
-            Err(e) => {
-                println!("❌ Docker command error: {}", e);
-                false
-            }
+    /// How long a call to `tool_name` may wait for its response, falling back to
+    /// `default_secs` when neither the tool, the ladder nor the server set a timeout
+    fn call_timeout(
+        &self,
+        config: &ServersConfig,
+        server_name: &str,
+        server_config: &ServerConfig,
+        tool_name: &str,
+        default_secs: u64,
+    ) -> std::time::Duration {
+        let tool_annotations = self.tool_annotations.lock().unwrap();
+        let annotations = tool_annotations
+            .get(server_name)
+            .and_then(|tools| tools.get(tool_name));
+        std::time::Duration::from_secs(
+            config
+                .call_timeout_for(server_config, tool_name, annotations)
+                .unwrap_or(default_secs),
+        )
+    }
+
+    /// An open session to reuse for a call to an SSE server: an idle one if there is one,
+    /// else the least contended once the pool is full. None means a new one should be opened.
+    fn pooled_sse_session(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+    ) -> Option<Arc<SseSession>> {
+        let sessions = self.sse_sessions.lock().unwrap();
+        let sessions = sessions.get(server_name)?;
+        if let Some(idle) = sessions.iter().find(|s| s.is_idle()) {
+            return Some(idle.clone());
         }
+        if sessions.len() >= config.sse_pool_size.max(1) {
+            return sessions
+                .iter()
+                .min_by_key(|s| Arc::strong_count(s))
+                .cloned();
+        }
+        None
     }
 
-    /// Wait for Docker to be ready with timeout and retry logic
-    async fn wait_for_docker(&self, timeout_secs: u64) -> anyhow::Result<()> {
+    fn add_sse_session(&self, server_name: &str, config: &ServerConfig, session: Arc<SseSession>) {
+        let mut sessions = self.sse_sessions.lock().unwrap();
+        let sessions = sessions.entry(server_name.to_string()).or_default();
+        if sessions.len() < config.sse_pool_size.max(1) {
+            sessions.push(session);
+        }
+    }
+
+    fn remove_sse_session(&self, server_name: &str, session: &Arc<SseSession>) {
+        if let Some(sessions) = self.sse_sessions.lock().unwrap().get_mut(server_name) {
+            sessions.retain(|s| !Arc::ptr_eq(s, session));
+        }
+    }
+
+    /// The mutex shared by all tools configured with `lock_key`
+    fn tool_lock(&self, lock_key: &str) -> Arc<Mutex<()>> {
+        self.tool_locks
+            .lock()
+            .unwrap()
+            .entry(lock_key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Wait up to `timeout` for one of the server's `max_concurrent_calls` slots. The slot
+    /// is given back when the returned permit is dropped.
+    async fn acquire_call_slot(
+        &self,
+        server_name: &str,
+        limit: usize,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<tokio::sync::OwnedSemaphorePermit> {
+        let slots = {
+            let mut call_slots = self.call_slots.lock().unwrap();
+            match call_slots.get(server_name) {
+                Some((sized_for, slots)) if *sized_for == limit => slots.clone(),
+                // New server, or its limit changed on reload
+                _ => {
+                    let slots = Arc::new(tokio::sync::Semaphore::new(limit.max(1)));
+                    call_slots.insert(server_name.to_string(), (limit, slots.clone()));
+                    slots
+                }
+            }
+        };
+        let permit = tokio::time::timeout(timeout, slots.acquire_owned())
+            .await
+            .map_err(|_| ResponseTimedOut {
+                server: server_name.to_string(),
+                what: "a free call slot (max_concurrent_calls)".to_string(),
+                secs: timeout.as_secs(),
+            })??;
+        Ok(permit)
+    }
+
+    /// Open connections across all backends: stdio processes, SSE sessions and WebSockets
+    async fn live_connections(&self) -> usize {
+        let stdio = self.connections.read().await.len();
+        let sse: usize = self
+            .sse_sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(Vec::len)
+            .sum();
+        let websocket = self.websocket_connections.lock().unwrap().len();
+        stdio + sse + websocket
+    }
+
+    /// Whether the pool holds a live process, session or socket for `server_name`
+    async fn is_connected(&self, server_name: &str) -> bool {
+        self.connections.read().await.contains_key(server_name)
+            || self
+                .sse_sessions
+                .lock()
+                .unwrap()
+                .get(server_name)
+                .is_some_and(|sessions| !sessions.is_empty())
+            || self
+                .websocket_connections
+                .lock()
+                .unwrap()
+                .contains_key(server_name)
+            || self.http_sessions.lock().unwrap().contains_key(server_name)
+    }
+
+    /// The mutex held while `server_name` is being started or connected to
+    fn start_lock(&self, server_name: &str) -> Arc<Mutex<()>> {
+        self.start_locks
+            .lock()
+            .unwrap()
+            .entry(server_name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Hold a call until a start of stdio server `server_name` already under way has
+    /// finished its handshake, for up to `timeout`. With `WhileInitializing::Reject` the
+    /// call fails instead.
+    async fn await_initialization(
+        &self,
+        server_name: &str,
+        while_initializing: WhileInitializing,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<()> {
+        // The start lock is held from spawning the process until it is initialized and
+        // in the pool, so a server is ready whenever the lock is free
+        let start_lock = self.start_lock(server_name);
+        if start_lock.try_lock().is_ok() {
+            return Ok(());
+        }
+        match while_initializing {
+            WhileInitializing::Reject => Err(ServerInitializing {
+                server: server_name.to_string(),
+            }
+            .into()),
+            WhileInitializing::Wait => {
+                tracing::debug!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Call waiting for the server to finish initializing"
+                );
+                tokio::time::timeout(timeout, start_lock.lock())
+                    .await
+                    .map(drop)
+                    .map_err(|_| {
+                        ResponseTimedOut {
+                            server: server_name.to_string(),
+                            what: "initialize".to_string(),
+                            secs: timeout.as_secs(),
+                        }
+                        .into()
+                    })
+            }
+        }
+    }
+
+    /// The mutex serializing request/response exchanges with stdio server `server_name`
+    fn exchange_lock(&self, server_name: &str) -> Arc<Mutex<()>> {
+        self.exchange_locks
+            .lock()
+            .unwrap()
+            .entry(server_name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Send a request to a started stdio server and read its response. Requests take the
+    /// next id from the connection's counter, and only a response carrying that id is
+    /// returned. Exchanges are serialized per server, so concurrent callers can't read each
+    /// other's responses; a late answer to an earlier, timed-out request is discarded.
+    async fn stdio_request(
+        &self,
+        server_name: &str,
+        method: &str,
+        params: Value,
+        backend_request_id: Option<&BackendRequestId>,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<Value> {
+        let exchange_lock = self.exchange_lock(server_name);
+        let _exchange = exchange_lock.lock().await;
+        let (connection, request_id) = self
+            .send_stdio_request(server_name, method, params, backend_request_id)
+            .await?;
+        let response = match self
+            .read_response(connection.clone(), &request_id, method, timeout)
+            .await
+        {
+            Err(e) if e.is::<ServerExited>() => {
+                self.forget_exited_connection(server_name, &connection)
+                    .await;
+                return Err(e);
+            }
+            result => result?,
+        };
+        connection.lock().await.last_used = std::time::Instant::now();
+        Ok(response)
+    }
+
+    /// Drop a stdio server's pooled connection once its process has gone away, unless it
+    /// was already replaced, so the next start spawns a fresh process
+    async fn forget_exited_connection(
+        &self,
+        server_name: &str,
+        connection: &Arc<Mutex<McpServerConnection>>,
+    ) {
+        {
+            let mut connections = self.connections.write().await;
+            if !connections
+                .get(server_name)
+                .is_some_and(|c| Arc::ptr_eq(c, connection))
+            {
+                return;
+            }
+            connections.remove(server_name);
+        }
+        self.process_ids.lock().unwrap().remove(server_name);
+        // Its pipes are closed but it may not have exited yet
+        let _ = connection.lock().await.process.start_kill();
+    }
+
+    /// Like `stdio_request` for a `tools/call`, but the response is relayed to `tx` as it is
+    /// read, rewritten to answer the client's request `client_id`, instead of being returned.
+    /// Also returns whether any of the response was relayed before a failure.
+    async fn stdio_request_streaming(
+        &self,
+        server_name: &str,
+        params: Value,
+        timeout: std::time::Duration,
+        client_id: Value,
+        tx: &tokio::sync::mpsc::Sender<std::io::Result<axum::body::Bytes>>,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> (anyhow::Result<()>, bool) {
+        let exchange_lock = self.exchange_lock(server_name);
+        let _exchange = exchange_lock.lock().await;
+        let (connection, request_id) = match self
+            .send_stdio_request(server_name, "tools/call", params, backend_request_id)
+            .await
+        {
+            Ok(sent) => sent,
+            Err(e) => return (Err(e), false),
+        };
+
+        let mut relay = JsonRpcRelay::new(request_id, client_id);
+        let result = async {
+            let mut conn = connection.lock().await;
+            while !relay.done() {
+                let buffer = tokio::time::timeout(timeout, conn.stdout_reader.fill_buf())
+                    .await
+                    .map_err(|_| ResponseTimedOut {
+                        server: server_name.to_string(),
+                        what: "tools/call".to_string(),
+                        secs: timeout.as_secs(),
+                    })?
+                    .map_err(|e| anyhow::anyhow!("Failed to read response: {}", e))?;
+                if buffer.is_empty() {
+                    return Err(ServerExited {
+                        server: server_name.to_string(),
+                        pipe: "stdout",
+                    }
+                    .into());
+                }
+                let (consumed, out) = relay.push(buffer);
+                conn.stdout_reader.consume(consumed);
+                if !out.is_empty() && tx.send(Ok(out.into())).await.is_err() {
+                    return Err(anyhow::anyhow!(
+                        "Client disconnected before the response was complete"
+                    ));
+                }
+            }
+            conn.last_used = std::time::Instant::now();
+            Ok(())
+        }
+        .await;
+        (result, relay.started())
+    }
+
+    /// Send a request to a running stdio server, returning its connection and the id the
+    /// request was sent with. Callers hold the server's exchange lock until they have read
+    /// the response.
+    async fn send_stdio_request(
+        &self,
+        server_name: &str,
+        method: &str,
+        params: Value,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<(Arc<Mutex<McpServerConnection>>, Value)> {
+        let connection = self
+            .connections
+            .read()
+            .await
+            .get(server_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Server '{}' connection not found", server_name))?;
+        let id_type = self
+            .config_manager
+            .read()
+            .await
+            .get_server(server_name)
+            .map(|c| c.request_id_type)
+            .unwrap_or_default();
+
+        let request_id = {
+            let mut conn = connection.lock().await;
+            conn.last_used = std::time::Instant::now();
+            let mut id = conn.next_request_id.lock().await;
+            let current_id = *id;
+            *id += 1;
+            id_type.id(current_id)
+        };
+        if let Some(slot) = backend_request_id {
+            *slot.lock().unwrap() = Some(request_id.clone());
+        }
+
+        if let Err(e) = self
+            .send_request(
+                connection.clone(),
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": request_id,
+                    "method": method,
+                    "params": params
+                }),
+            )
+            .await
+        {
+            if e.is::<ServerExited>() {
+                self.forget_exited_connection(server_name, &connection)
+                    .await;
+            }
+            return Err(e);
+        }
+        Ok((connection, request_id))
+    }
+
+    /// Initialize a streamable HTTP server unless a session with it is already open.
+    /// Holds the server's start lock, so concurrent discoveries and calls run one handshake.
+    async fn ensure_http_session(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        client: &reqwest::Client,
+        url: &str,
+        client_info: Option<&ClientInfo>,
+    ) -> anyhow::Result<()> {
+        let start_lock = self.start_lock(server_name);
+        let _initializing = start_lock.lock().await;
+        if self.http_sessions.lock().unwrap().contains_key(server_name) {
+            return Ok(());
+        }
+        self.initialize_http_session(server_name, config, client, url, client_info)
+            .await?;
+        Ok(())
+    }
+
+    /// Initialize a streamable HTTP server, keeping the `Mcp-Session-Id` it issues (if any)
+    /// for later requests to it. Returns the parsed initialize response, if it parsed.
+    /// Callers hold the server's start lock.
+    async fn initialize_http_session(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        client: &reqwest::Client,
+        url: &str,
+        client_info: Option<&ClientInfo>,
+    ) -> anyhow::Result<Option<Value>> {
+        let init_request = self.handshake_request(config, client_info).await;
+        let response = client
+            .post(url)
+            .header("Accept", "application/json,text/event-stream")
+            .json(&init_request)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("HTTP init request failed: {}", e))?;
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Initialize response status: {}",
+            response.status()
+        );
+
+        let session_id = response
+            .headers()
+            .get(MCP_SESSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let init_response = response
+            .text()
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|body| parse_http_jsonrpc_body(&body))
+            .ok();
+        if let Some(init_response) = &init_response {
+            self.record_server_info(server_name, init_response);
+        }
+
+        let mut initialized = client
+            .post(url)
+            .header("Accept", "application/json,text/event-stream")
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/initialized"
+            }));
+        {
+            let mut sessions = self.http_sessions.lock().unwrap();
+            match &session_id {
+                Some(session_id) => {
+                    tracing::debug!(
+                        target: LOG_BACKEND,
+                        server = %server_name,
+                        "Using HTTP session {}",
+                        session_id
+                    );
+                    initialized = initialized.header(MCP_SESSION_HEADER, session_id);
+                    sessions.insert(server_name.to_string(), session_id.clone());
+                }
+                None => {
+                    sessions.remove(server_name);
+                }
+            }
+        }
+        initialized
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send initialized: {}", e))?;
+        Ok(init_response)
+    }
+
+    /// POST a JSON-RPC message to a streamable HTTP server, in its session if it issued one.
+    /// If the server no longer knows the session (404), a new session is initialized (once,
+    /// however many requests found it expired) and the message is sent once more, the new
+    /// session carrying `client_info`.
+    async fn http_post(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        client: &reqwest::Client,
+        url: &str,
+        message: &Value,
+        client_info: Option<&ClientInfo>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let post = |session_id: Option<String>| {
+            let request = client
+                .post(url)
+                .header("Accept", "application/json,text/event-stream")
+                .json(message);
+            match session_id {
+                Some(session_id) => request.header(MCP_SESSION_HEADER, session_id),
+                None => request,
+            }
+        };
+
+        let session_id = self.http_sessions.lock().unwrap().get(server_name).cloned();
+        let expired = session_id.clone();
+        let response = post(session_id).send().await?;
+        if expired.is_none() || response.status() != reqwest::StatusCode::NOT_FOUND {
+            return Ok(response);
+        }
+
+        // Renew the session unless a concurrent request already has
+        {
+            let start_lock = self.start_lock(server_name);
+            let _initializing = start_lock.lock().await;
+            let current = self.http_sessions.lock().unwrap().get(server_name).cloned();
+            if current == expired {
+                tracing::info!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "HTTP session expired, initializing a new one"
+                );
+                self.http_sessions.lock().unwrap().remove(server_name);
+                if let Err(e) = self
+                    .initialize_http_session(server_name, config, client, url, client_info)
+                    .await
+                {
+                    tracing::warn!(
+                        target: LOG_BACKEND,
+                        server = %server_name,
+                        "Failed to re-initialize HTTP session: {}",
+                        e
+                    );
+                    return Ok(response);
+                }
+            }
+        }
+        let session_id = self.http_sessions.lock().unwrap().get(server_name).cloned();
+        post(session_id).send().await
+    }
+
+    /// The pooled connection to a WebSocket server, opened and initialized if there is none
+    async fn websocket_connection(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        init_request: Value,
+    ) -> anyhow::Result<Arc<Mutex<WebSocketConnection>>> {
+        let start_lock = self.start_lock(server_name);
+        let _connecting = start_lock.lock().await;
+        if let Some(connection) = self.websocket_connections.lock().unwrap().get(server_name) {
+            return Ok(connection.clone());
+        }
+
+        let url = config
+            .url
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("WebSocket transport requires 'url' field"))?;
+        let (connection, init_response) =
+            WebSocketConnection::open(server_name, url, init_request, config.request_id_type)
+                .await?;
+        self.record_server_info(server_name, &init_response);
+        let connection = Arc::new(Mutex::new(connection));
+        self.websocket_connections
+            .lock()
+            .unwrap()
+            .insert(server_name.to_string(), connection.clone());
+        Ok(connection)
+    }
+
+    /// Send a request to a WebSocket server over its pooled connection. A socket found
+    /// closed is dropped from the pool, and the request is sent once more on a new one.
+    async fn websocket_request(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        init_request: Value,
+        method: &str,
+        params: Value,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<Value> {
+        let mut reconnected = false;
+        loop {
+            let connection = self
+                .websocket_connection(server_name, config, init_request.clone())
+                .await?;
+            let result = connection
+                .lock()
+                .await
+                .request(server_name, method, params.clone(), backend_request_id)
+                .await;
+            match result {
+                Err(e) if e.is::<WebSocketClosed>() => {
+                    let mut connections = self.websocket_connections.lock().unwrap();
+                    if connections
+                        .get(server_name)
+                        .is_some_and(|c| Arc::ptr_eq(c, &connection))
+                    {
+                        connections.remove(server_name);
+                    }
+                    if reconnected {
+                        return Err(e);
+                    }
+                    tracing::warn!(
+                        target: LOG_BACKEND,
+                        server = %server_name,
+                        "{}, reconnecting",
+                        e
+                    );
+                    reconnected = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Remember the version and capabilities a backend reported in its initialize response
+    fn record_server_info(&self, server_name: &str, init_response: &Value) {
+        if let Some(version) = init_response["result"]["serverInfo"]["version"].as_str() {
+            self.server_versions
+                .lock()
+                .unwrap()
+                .insert(server_name.to_string(), version.to_string());
+        }
+        if let Some(capabilities) = init_response["result"].get("capabilities") {
+            self.server_capabilities
+                .lock()
+                .unwrap()
+                .insert(server_name.to_string(), capabilities.clone());
+        }
+    }
+
+    /// Whether a backend declared the `tools` capability when it was last initialized
+    fn declares_tools(&self, server_name: &str) -> bool {
+        self.server_capabilities
+            .lock()
+            .unwrap()
+            .get(server_name)
+            .is_some_and(|capabilities| capabilities.get("tools").is_some())
+    }
+
+    fn server_version(&self, server_name: &str) -> Option<String> {
+        self.server_versions
+            .lock()
+            .unwrap()
+            .get(server_name)
+            .cloned()
+    }
+
+    /// The `initialize` request for a backend, carrying the configured `clientInfo` for
+    /// `client`, the session the backend is being connected for
+    async fn handshake_request(&self, config: &ServerConfig, client: Option<&ClientInfo>) -> Value {
+        let passthrough = self
+            .config_manager
+            .read()
+            .await
+            .get_config()
+            .client_info_passthrough;
+        initialize_request(config, backend_client_info(passthrough, client))
+    }
+
+    /// Count a call to `server_name` as in flight for as long as the guard lives
+    fn track_call(&self, server_name: &str) -> InFlightGuard {
+        *self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(server_name.to_string())
+            .or_insert(0) += 1;
+        InFlightGuard {
+            in_flight: self.in_flight.clone(),
+            server_name: server_name.to_string(),
+        }
+    }
+
+    /// Calls to a stdio server waiting behind the one it is serving (stdio calls are serialized)
+    fn queued_calls(&self, server_name: &str) -> usize {
+        self.in_flight_calls(server_name).saturating_sub(1)
+    }
+
+    fn in_flight_calls(&self, server_name: &str) -> usize {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .get(server_name)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Wait for in-flight calls to `server_name` to finish, giving up after `timeout`.
+    /// Returns the number of calls still running.
+    async fn drain_calls(&self, server_name: &str, timeout: tokio::time::Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let remaining = self.in_flight_calls(server_name);
+            if remaining == 0 || tokio::time::Instant::now() >= deadline {
+                return remaining;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Check if Docker is available and ready
+    async fn is_docker_ready(&self) -> bool {
+        use tokio::process::Command;
+
+        // Try to run docker version command
+        match Command::new("docker").arg("version").output().await {
+            Ok(output) => {
+                if output.status.success() {
+                    tracing::info!(target: LOG_BACKEND, "Docker is available and ready");
+                    true
+                } else {
+                    tracing::error!(
+                        target: LOG_BACKEND,
+                        "Docker command failed with status: {}",
+                        output.status
+                    );
+                    false
+                }
+            }
+            Err(e) => {
+                tracing::error!(target: LOG_BACKEND, "Docker command error: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Wait for Docker to be ready with timeout and retry logic
+    async fn wait_for_docker(&self, timeout_secs: u64) -> anyhow::Result<()> {
         use tokio::time::{sleep, Duration, Instant};
 
         let start_time = Instant::now();
         let timeout = Duration::from_secs(timeout_secs);
         let retry_interval = Duration::from_secs(2);
 
-        println!(
-            "🐳 Waiting for Docker to be ready (timeout: {}s)...",
+        tracing::info!(
+            target: LOG_BACKEND,
+            "Waiting for Docker to be ready (timeout: {}s)...",
             timeout_secs
         );
 
         loop {
             if self.is_docker_ready().await {
-                println!("✅ Docker is ready (elapsed: {:?})", start_time.elapsed());
+                tracing::info!(
+                    target: LOG_BACKEND,
+                    "Docker is ready (elapsed: {:?})",
+                    start_time.elapsed()
+                );
                 return Ok(());
             }
 
@@ -320,8 +1813,9 @@ impl ServerConnectionPool {
                 ));
             }
 
-            println!(
-                "⏳ Docker not ready yet, retrying in {}s...",
+            tracing::debug!(
+                target: LOG_BACKEND,
+                "Docker not ready yet, retrying in {}s...",
                 retry_interval.as_secs()
             );
             sleep(retry_interval).await;
@@ -330,26 +1824,38 @@ impl ServerConnectionPool {
 
     /// Start an MCP server and establish a connection
     async fn start_server(&self, server_name: &str) -> anyhow::Result<()> {
-        self.start_server_with_context(server_name, None).await
+        self.start_server_with_context(server_name, None, None)
+            .await
     }
 
-    /// Start an MCP server with optional user working directory context
+    /// Start an MCP server with optional user working directory context, handshaking with
+    /// the identity of `client`, the session the server is started for
     async fn start_server_with_context(
         &self,
         server_name: &str,
         _user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
     ) -> anyhow::Result<()> {
+        // Whoever starts the server first holds this until it is in the pool; later callers
+        // wait here and then find it connected
+        let start_lock = self.start_lock(server_name);
+        let _starting = start_lock.lock().await;
+
         // Check if server is already connected (scoped read lock)
         {
             let connections = self.connections.read().await;
             if connections.contains_key(server_name) {
-                println!("🔗 Server '{}' is already connected", server_name);
+                tracing::debug!(
+                    target: LOG_BACKEND,
+                    "Server '{}' is already connected",
+                    server_name
+                );
                 return Ok(());
             }
         } // Read lock automatically dropped here
 
-        // Get server config and project directory (scoped read lock)
-        let (config, project_dir) = {
+        // Get server config, its environment and project directory (scoped read lock)
+        let (config, env, project_dir) = {
             let servers = self.config_manager.read().await;
             let config = servers
                 .get_servers()
@@ -358,17 +1864,18 @@ impl ServerConnectionPool {
                     anyhow::anyhow!("Server '{}' not found in configuration", server_name)
                 })?
                 .clone(); // Clone to avoid borrowing across await points
+            let env = servers.get_config().env_for(&config);
             let project_dir = servers
                 .get_config_path()
                 .parent()
                 .unwrap_or_else(|| std::path::Path::new("."))
                 .to_path_buf();
-            (config, project_dir)
+            (config, env, project_dir)
         }; // Read lock automatically dropped here
 
         // Docker readiness is now checked once at startup, so we can proceed directly
 
-        println!("🚀 Starting MCP server: {}", server_name);
+        tracing::info!(target: LOG_BACKEND, "Starting MCP server: {}", server_name);
 
         // Spawn the server process
         let mut cmd = Command::new(&config.command);
@@ -387,34 +1894,59 @@ impl ServerConnectionPool {
             .map(|wd| resolve_working_directory(wd, &project_dir))
             .unwrap_or_else(|| project_dir.clone());
         cmd.current_dir(&working_dir);
-        println!(
-            "🔍 [{}] Setting working directory: {}",
-            server_name,
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Setting working directory: {}",
             working_dir.display()
         );
 
         // Inherit all environment variables from parent process
         cmd.envs(std::env::vars());
 
+        // Values from `env_file` sit under the configured env
+        let mut env_vars = config
+            .env_file
+            .as_deref()
+            .map(|env_file| {
+                read_env_file(
+                    server_name,
+                    &resolve_working_directory(env_file, &project_dir),
+                )
+            })
+            .unwrap_or_default();
+        env_vars.extend(env);
+
         // Process environment variables with template substitution
         let template_context = TemplateContext::new(
             project_dir.clone(),
             working_dir.clone(),
             server_name.to_string(),
         );
-        let processed_env = process_env_templates(&config.env, &template_context);
+        let processed_env = process_env_templates(&env_vars, &template_context);
 
-        // Add/override with server-specific environment variables
+        // Add/override with global and server-specific environment variables
         for (key, value) in &processed_env {
             cmd.env(key, value);
             if !value.is_empty() {
-                println!("🔧 [{}] Setting env {}={}", server_name, key, value);
+                tracing::debug!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Setting env {}={}",
+                    key,
+                    masked_env_value(key, value)
+                );
             }
         }
 
+        if let Some(limits) = &config.limits {
+            apply_spawn_limits(&mut cmd, server_name, limits);
+        }
+
         let mut process = cmd
             .spawn()
             .map_err(|e| anyhow::anyhow!("Failed to spawn server '{}': {}", server_name, e))?;
+        let pid = process.id();
 
         let stdin = process
             .stdin
@@ -427,45 +1959,66 @@ impl ServerConnectionPool {
 
         let stdout_reader = BufReader::new(stdout);
 
+        // Drain stderr into the logs so a chatty server can't block on a full pipe
+        if let Some(stderr) = process.stderr.take() {
+            forward_backend_stderr(server_name.to_string(), stderr);
+        }
+
         // Create connection object
         let connection = McpServerConnection {
             process,
             stdin,
             stdout_reader,
             server_name: server_name.to_string(),
-            next_request_id: Arc::new(Mutex::new(1)),
+            // The initialize request uses id 1
+            next_request_id: Arc::new(Mutex::new(2)),
+            last_used: std::time::Instant::now(),
         };
 
         let connection_arc = Arc::new(Mutex::new(connection));
 
         // Initialize the MCP server
-        println!("🔄 [{}] About to call initialize_server", server_name);
-        self.initialize_server(connection_arc.clone()).await?;
-        println!(
-            "✅ [{}] initialize_server completed successfully",
-            server_name
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "About to call initialize_server"
+        );
+        let init_request = self.handshake_request(&config, client).await;
+        self.initialize_server(connection_arc.clone(), init_request)
+            .await?;
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "initialize_server completed successfully"
         );
 
         // Store the connection
-        println!("🔄 [{}] About to store connection in pool", server_name);
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "About to store connection in pool"
+        );
         {
-            println!(
-                "🔄 [{}] Attempting to acquire write lock on connections...",
-                server_name
+            tracing::debug!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Attempting to acquire write lock on connections..."
             );
 
             // Check if there are any active read locks by trying a try_read first
             if let Ok(read_guard) = self.connections.try_read() {
-                println!(
-                    "🔍 [{}] No read locks detected, {} connections exist",
-                    server_name,
+                tracing::debug!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "No read locks detected, {} connections exist",
                     read_guard.len()
                 );
                 drop(read_guard);
             } else {
-                println!(
-                    "⚠️ [{}] Read locks are active - this will cause write lock to block!",
-                    server_name
+                tracing::warn!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Read locks are active - this will cause write lock to block!"
                 );
             }
 
@@ -478,18 +2031,34 @@ impl ServerConnectionPool {
                 anyhow::anyhow!("DEADLOCK: Timeout acquiring write lock on connections after 5s - read locks may be blocking")
             })?;
 
-            println!("🔄 [{}] Acquired write lock on connections", server_name);
+            tracing::debug!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Acquired write lock on connections"
+            );
             connections.insert(server_name.to_string(), connection_arc);
-            println!("✅ [{}] Connection stored successfully", server_name);
+            if let Some(pid) = pid {
+                self.process_ids
+                    .lock()
+                    .unwrap()
+                    .insert(server_name.to_string(), pid);
+            }
+            tracing::debug!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Connection stored successfully"
+            );
         }
 
-        println!(
-            "✅ Successfully started and initialized server: {}",
+        tracing::info!(
+            target: LOG_BACKEND,
+            "Successfully started and initialized server: {}",
             server_name
         );
-        println!(
-            "🔄 [{}] Returning from start_server_with_context",
-            server_name
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Returning from start_server_with_context"
         );
         Ok(())
     }
@@ -498,46 +2067,44 @@ impl ServerConnectionPool {
     async fn initialize_server(
         &self,
         connection: Arc<Mutex<McpServerConnection>>,
+        init_request: Value,
     ) -> anyhow::Result<()> {
         let server_name = {
             let conn = connection.lock().await;
             conn.server_name.clone()
         };
 
-        println!("🔄 Initializing MCP server: {}", server_name);
+        tracing::debug!(target: LOG_BACKEND, "Initializing MCP server: {}", server_name);
 
         // Send initialize request
-        let init_request = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "initialize",
-            "params": {
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {
-                        "listChanged": true
-                    }
-                },
-                "clientInfo": {
-                    "name": "toolman-http",
-                    "version": "1.0.0"
-                }
-            }
-        });
-
-        println!("🔄 [{}] Sending initialize request", server_name);
+        tracing::debug!(target: LOG_BACKEND, server = %server_name, "Sending initialize request");
+        let init_id = init_request["id"].clone();
         self.send_request(connection.clone(), init_request).await?;
-        println!("✅ [{}] Initialize request sent successfully", server_name);
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Initialize request sent successfully"
+        );
 
         // Read initialization response
-        println!(
-            "🔄 [{}] About to read initialize response (THIS MIGHT HANG)",
-            server_name
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "About to read initialize response (THIS MIGHT HANG)"
         );
-        let _init_response = self.read_response(connection.clone()).await?;
-        println!(
-            "✅ [{}] Initialize response received successfully",
-            server_name
+        let init_response = self
+            .read_response(
+                connection.clone(),
+                &init_id,
+                "initialize",
+                std::time::Duration::from_secs(DEFAULT_STDIO_CALL_TIMEOUT_SECS),
+            )
+            .await?;
+        self.record_server_info(&server_name, &init_response);
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Initialize response received successfully"
         );
 
         // Send initialized notification
@@ -552,7 +2119,7 @@ impl ServerConnectionPool {
         // Give server time to initialize
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
-        println!("✅ Server '{}' initialized successfully", server_name);
+        tracing::info!(target: LOG_BACKEND, "Server '{}' initialized successfully", server_name);
         Ok(())
     }
 
@@ -565,16 +2132,17 @@ impl ServerConnectionPool {
         let request_msg = format!("{}\n", serde_json::to_string(&request)?);
 
         let mut conn = connection.lock().await;
+        let server_name = conn.server_name.clone();
         conn.stdin
             .write_all(request_msg.as_bytes())
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to send request: {}", e))?;
+            .map_err(|e| stdin_error(&server_name, "send request", e))?;
 
         // GROK'S FIX: Flush after write to ensure data is sent
         conn.stdin
             .flush()
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to flush stdin: {}", e))?;
+            .map_err(|e| stdin_error(&server_name, "flush stdin", e))?;
 
         Ok(())
     }
@@ -588,24 +2156,29 @@ impl ServerConnectionPool {
         let notification_msg = format!("{}\n", serde_json::to_string(&notification)?);
 
         let mut conn = connection.lock().await;
+        let server_name = conn.server_name.clone();
         conn.stdin
             .write_all(notification_msg.as_bytes())
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to send notification: {}", e))?;
+            .map_err(|e| stdin_error(&server_name, "send notification", e))?;
 
         // GROK'S FIX: Flush after write to ensure data is sent
         conn.stdin
             .flush()
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to flush stdin: {}", e))?;
+            .map_err(|e| stdin_error(&server_name, "flush stdin", e))?;
 
         Ok(())
     }
 
-    /// Read a response from a server
+    /// Read the response to request `request_id` from a server, waiting at most `timeout`
+    /// for each line of output. Responses to other requests are skipped.
     async fn read_response(
         &self,
         connection: Arc<Mutex<McpServerConnection>>,
+        request_id: &Value,
+        what: &str,
+        timeout: std::time::Duration,
     ) -> anyhow::Result<Value> {
         let mut conn = connection.lock().await;
 
@@ -622,16 +2195,21 @@ impl ServerConnectionPool {
                 ));
             }
             line.clear();
-            let bytes_read = tokio::time::timeout(
-                tokio::time::Duration::from_secs(30), // Increased timeout for large responses
-                conn.stdout_reader.read_line(&mut line),
-            )
-            .await
-            .map_err(|_| anyhow::anyhow!("Timeout reading response"))?
-            .map_err(|e| anyhow::anyhow!("Failed to read response: {}", e))?;
+            let bytes_read = tokio::time::timeout(timeout, conn.stdout_reader.read_line(&mut line))
+                .await
+                .map_err(|_| ResponseTimedOut {
+                    server: conn.server_name.clone(),
+                    what: what.to_string(),
+                    secs: timeout.as_secs(),
+                })?
+                .map_err(|e| anyhow::anyhow!("Failed to read response: {}", e))?;
 
             if bytes_read == 0 {
-                return Err(anyhow::anyhow!("Server connection closed"));
+                return Err(ServerExited {
+                    server: conn.server_name.clone(),
+                    pipe: "stdout",
+                }
+                .into());
             }
 
             // Try to parse as JSON
@@ -646,8 +2224,17 @@ impl ServerConnectionPool {
                 if response.get("id").is_some()
                     && (response.get("result").is_some() || response.get("error").is_some())
                 {
-                    // This is the actual response we want
-                    return Ok(response);
+                    if response.get("id") == Some(request_id) {
+                        // This is the actual response we want
+                        return Ok(response);
+                    }
+                    // A late answer to an earlier request that timed out
+                    tracing::debug!(
+                        target: LOG_BACKEND,
+                        server = %conn.server_name,
+                        "Skipping response to another request: {}",
+                        response["id"]
+                    );
                 }
 
                 // If it's JSON but doesn't match our criteria, continue reading
@@ -665,74 +2252,272 @@ impl ServerConnectionPool {
         tool_name: &str,
         arguments: Value,
         user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+    ) -> anyhow::Result<Value> {
+        self.forward_tool_call_tracked(
+            server_name,
+            tool_name,
+            arguments,
+            None,
+            user_working_dir,
+            client,
+            None,
+        )
+        .await
+    }
+
+    /// Forward a tool call with the client's `_meta`, storing the id the backend was sent
+    /// in `backend_request_id` so the call can be cancelled (not set for SSE), and
+    /// extracting the configured `unwrap_path` from the result. A backend connected for
+    /// the call handshakes with `client`'s identity.
+    async fn forward_tool_call_tracked(
+        &self,
+        server_name: &str,
+        tool_name: &str,
+        arguments: Value,
+        meta: Option<&Value>,
+        user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+        backend_request_id: Option<&BackendRequestId>,
     ) -> anyhow::Result<Value> {
-        // Check if this is an HTTP transport server
-        let config_manager = self.config_manager.read().await;
-        let server_config = config_manager
+        let unwrap_path = self
+            .config_manager
+            .read()
+            .await
             .get_servers()
             .get(server_name)
-            .ok_or_else(|| anyhow::anyhow!("Server '{}' not found", server_name))?;
+            .and_then(|config| config.unwrap_path_for(tool_name))
+            .map(str::to_string);
+
+        let response = self
+            .send_tool_call(
+                server_name,
+                tool_name,
+                tool_call_params(tool_name, arguments, meta),
+                user_working_dir,
+                client,
+                backend_request_id,
+            )
+            .await?;
+        Ok(match unwrap_path {
+            Some(path) => unwrap_result(server_name, tool_name, response, &path),
+            None => response,
+        })
+    }
+
+    async fn send_tool_call(
+        &self,
+        server_name: &str,
+        tool_name: &str,
+        params: Value,
+        user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<Value> {
+        let span = tracing::info_span!(
+            target: LOG_BACKEND,
+            "tool_call",
+            server = %server_name,
+            tool = %tool_name
+        );
+        tracing::Instrument::instrument(
+            self.send_tool_call_in_span(
+                server_name,
+                tool_name,
+                params,
+                user_working_dir,
+                client,
+                backend_request_id,
+            ),
+            span,
+        )
+        .await
+    }
+
+    async fn send_tool_call_in_span(
+        &self,
+        server_name: &str,
+        tool_name: &str,
+        params: Value,
+        user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<Value> {
+        let _in_flight = self.track_call(server_name);
+
+        // Take what the call needs from the config up front; the read lock must not be
+        // held across backend I/O, or a reload waiting to write would block on this call
+        let (server_config, call_timeout, client_info_passthrough, sse_settings) = {
+            let config_manager = self.config_manager.read().await;
+            let server_config = config_manager
+                .get_servers()
+                .get(server_name)
+                .ok_or_else(|| anyhow::anyhow!("Server '{}' not found", server_name))?
+                .clone();
+            let default_timeout = if server_config.transport == "stdio" {
+                DEFAULT_STDIO_CALL_TIMEOUT_SECS
+            } else {
+                DEFAULT_SSE_CALL_TIMEOUT_SECS
+            };
+            let call_timeout = self.call_timeout(
+                config_manager.get_config(),
+                server_name,
+                &server_config,
+                tool_name,
+                default_timeout,
+            );
+            let config = config_manager.get_config();
+            (
+                server_config,
+                call_timeout,
+                config.client_info_passthrough,
+                SseStreamSettings::from_config(config),
+            )
+        }; // Read lock dropped before any backend I/O
+
+        // Tools that must not run concurrently share a lock key; hold it for the whole call
+        let lock_key = server_config
+            .tool_config(tool_name)
+            .and_then(|t| t.lock_key.as_deref());
+        let _tool_lock = match lock_key {
+            Some(lock_key) => Some(self.tool_lock(lock_key).lock_owned().await),
+            None => None,
+        };
+
+        // Reject rather than queue without bound behind a busy stdio server. The count
+        // includes this call, so anything over the limit is one call too many.
+        if server_config.transport == "stdio" {
+            if let Some(max_queued) = server_config.max_queued_calls {
+                let queued = self.queued_calls(server_name);
+                if queued > max_queued {
+                    return Err(ServerBusy {
+                        server: server_name.to_string(),
+                        queued: queued - 1,
+                        max_queued,
+                    }
+                    .into());
+                }
+            }
+        }
+
+        // Hold one of the server's call slots for the whole call. The wait for a free
+        // slot is bounded by the call's timeout.
+        let _call_slot = match server_config.max_concurrent_calls {
+            Some(limit) => Some(
+                self.acquire_call_slot(server_name, limit, call_timeout)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        if server_config.transport == "websocket" {
+            let init_request = initialize_request(
+                &server_config,
+                backend_client_info(client_info_passthrough, client),
+            );
+            tracing::debug!(
+                target: LOG_BACKEND,
+                "Forwarding tool call: {} to WebSocket server {}",
+                tool_name,
+                server_name
+            );
+            return self
+                .websocket_request(
+                    server_name,
+                    &server_config,
+                    init_request,
+                    "tools/call",
+                    params,
+                    backend_request_id,
+                )
+                .await
+                .map_err(transport_failure);
+        }
 
         // Handle HTTP and SSE transports
         if server_config.transport == "http" || server_config.transport == "sse" {
             if let Some(url) = &server_config.url {
-                println!("🌐 Forwarding HTTP request to: {}", url);
+                tracing::debug!(target: LOG_BACKEND, "Forwarding HTTP request to: {}", url);
 
-                // Create HTTP client if not exists
-                let client = reqwest::Client::new();
+                let http_client = http_client_for(server_name, &server_config);
 
                 // Use transport type to determine communication method
                 if server_config.transport == "sse" {
-                    // Use SSE bidirectional communication
-                    return call_tool_via_sse(&client, server_name, url, tool_name, arguments)
+                    // Use SSE bidirectional communication, reusing an open session if possible
+                    let session = match self.pooled_sse_session(server_name, &server_config) {
+                        Some(session) => session,
+                        None => {
+                            let init_request = initialize_request(
+                                &server_config,
+                                backend_client_info(client_info_passthrough, client),
+                            );
+                            let session = Arc::new(
+                                SseSession::open(
+                                    &http_client,
+                                    server_name,
+                                    url,
+                                    init_request,
+                                    sse_settings,
+                                    server_config
+                                        .max_request_id
+                                        .unwrap_or(DEFAULT_MAX_REQUEST_ID),
+                                )
+                                .await
+                                .map_err(transport_failure)?,
+                            );
+                            self.add_sse_session(server_name, &server_config, session.clone());
+                            session
+                        }
+                    };
+                    let result = session
+                        .call_tool(
+                            &http_client,
+                            server_name,
+                            tool_name,
+                            params,
+                            server_config.request_id_type,
+                            call_timeout,
+                        )
                         .await;
+                    if result.is_err() {
+                        // Don't hand a broken session to the next call
+                        self.remove_sse_session(server_name, &session);
+                    }
+                    return result.map_err(transport_failure);
                 } else {
                     // Direct HTTP endpoint (like Solana)
+                    let request_id = server_config.request_id_type.id(1);
+                    if let Some(slot) = backend_request_id {
+                        *slot.lock().unwrap() = Some(request_id.clone());
+                    }
                     let request_body = json!({
                         "jsonrpc": "2.0",
-                        "id": 1,
+                        "id": request_id,
                         "method": "tools/call",
-                        "params": {
-                            "name": tool_name,
-                            "arguments": arguments
-                        }
+                        "params": params
                     });
 
-                    // Send HTTP POST request with proper Accept headers
-                    let response = client
-                        .post(url)
-                        .header("Accept", "application/json,text/event-stream")
-                        .json(&request_body)
-                        .send()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("HTTP request failed: {}", e))?;
-
-                    // Parse response - handle both JSON and SSE formats
-                    let response_text = response
-                        .text()
+                    // Send HTTP POST request with proper Accept headers, in the server's session
+                    let response = self
+                        .http_post(
+                            server_name,
+                            &server_config,
+                            &http_client,
+                            url,
+                            &request_body,
+                            client,
+                        )
                         .await
-                        .map_err(|e| anyhow::anyhow!("Failed to read HTTP response: {}", e))?;
-
-                    // Check if response is SSE format (like Solana)
-                    let response_json: Value = if response_text.starts_with("event:") {
-                        // SSE format: extract JSON from "data:" line
-                        println!("📡 [{}] Detected SSE response format", server_name);
-                        for line in response_text.lines() {
-                            if let Some(data) = line.strip_prefix("data: ") {
-                                return serde_json::from_str(data).map_err(|e| {
-                                    anyhow::anyhow!("Failed to parse SSE data as JSON: {}", e)
-                                });
-                            }
-                        }
-                        return Err(anyhow::anyhow!("No data line found in SSE response"));
-                    } else {
-                        // Direct JSON format
-                        println!("📡 [{}] Detected JSON response format", server_name);
-                        serde_json::from_str(&response_text)
-                            .map_err(|e| anyhow::anyhow!("Failed to parse JSON response: {}", e))?
-                    };
-
-                    println!("📨 Received HTTP response from server {}", server_name);
+                        .map_err(|e| {
+                            transport_failure(anyhow::anyhow!("HTTP request failed: {}", e))
+                        })?;
+                    let response_json = read_http_jsonrpc(response).await?;
+
+                    tracing::debug!(
+                        target: LOG_BACKEND,
+                        "Received HTTP response from server {}",
+                        server_name
+                    );
                     return Ok(response_json);
                 }
             } else {
@@ -741,59 +2526,210 @@ impl ServerConnectionPool {
         }
 
         // Original stdio logic
+        // Don't send anything to a server until its handshake is complete
+        self.await_initialization(server_name, server_config.while_initializing, call_timeout)
+            .await?;
+
         // Start server if not already started
-        if user_working_dir.is_some() {
-            self.start_server_with_context(server_name, user_working_dir)
-                .await?;
-        } else {
-            self.start_server(server_name).await?;
+        self.start_server_with_context(server_name, user_working_dir, client)
+            .await?;
+
+        tracing::debug!(
+            target: LOG_BACKEND,
+            "Forwarding tool call: {} to server {}",
+            tool_name,
+            server_name
+        );
+
+        // Send request and read response. If the server's process died since it was
+        // started, it is restarted once and the call retried.
+        let response = match self
+            .stdio_request(
+                server_name,
+                "tools/call",
+                params.clone(),
+                backend_request_id,
+                call_timeout,
+            )
+            .await
+        {
+            Err(e) if e.is::<ServerExited>() => {
+                tracing::warn!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "{}, restarting it and retrying the call",
+                    e
+                );
+                self.start_server_with_context(server_name, user_working_dir, client)
+                    .await
+                    .map_err(|e| {
+                        transport_failure(
+                            ServerCrashed {
+                                server: server_name.to_string(),
+                                reason: e.to_string(),
+                            }
+                            .into(),
+                        )
+                    })?;
+                self.stdio_request(
+                    server_name,
+                    "tools/call",
+                    params,
+                    backend_request_id,
+                    call_timeout,
+                )
+                .await
+            }
+            result => result,
         }
+        .map_err(transport_failure)?;
 
-        let connection = {
-            let connections = self.connections.read().await;
-            connections
-                .get(server_name)
-                .ok_or_else(|| anyhow::anyhow!("Server '{}' connection not found", server_name))?
-                .clone()
-        };
+        tracing::debug!(target: LOG_BACKEND, "Received response from server {}", server_name);
 
-        // Get next request ID
-        let request_id = {
-            let conn = connection.lock().await;
-            let mut id = conn.next_request_id.lock().await;
-            let current_id = *id;
-            *id += 1;
-            current_id
+        Ok(response)
+    }
+
+    /// Call a `streaming` tool on a stdio server, relaying its response to `tx` as it is read.
+    /// Also returns whether any of the response was relayed before a failure.
+    async fn stream_tool_call(
+        &self,
+        server_name: &str,
+        tool_name: &str,
+        params: Value,
+        user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+        client_id: Value,
+        tx: &tokio::sync::mpsc::Sender<std::io::Result<axum::body::Bytes>>,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> (anyhow::Result<()>, bool) {
+        let _in_flight = self.track_call(server_name);
+        let (lock_key, call_timeout, while_initializing) = {
+            let config_manager = self.config_manager.read().await;
+            let Some(server_config) = config_manager.get_server(server_name) else {
+                return (
+                    Err(anyhow::anyhow!("Server '{}' not found", server_name)),
+                    false,
+                );
+            };
+            (
+                server_config
+                    .tool_config(tool_name)
+                    .and_then(|t| t.lock_key.clone()),
+                self.call_timeout(
+                    config_manager.get_config(),
+                    server_name,
+                    server_config,
+                    tool_name,
+                    DEFAULT_STDIO_CALL_TIMEOUT_SECS,
+                ),
+                server_config.while_initializing,
+            )
+        };
+        let _tool_lock = match &lock_key {
+            Some(lock_key) => Some(self.tool_lock(lock_key).lock_owned().await),
+            None => None,
         };
 
-        // Create tools/call request
-        let tool_request = json!({
+        if let Err(e) = self
+            .await_initialization(server_name, while_initializing, call_timeout)
+            .await
+        {
+            return (Err(e), false);
+        }
+        if let Err(e) = self
+            .start_server_with_context(server_name, user_working_dir, client)
+            .await
+        {
+            return (Err(e), false);
+        }
+        self.stdio_request_streaming(
+            server_name,
+            params,
+            call_timeout,
+            client_id,
+            tx,
+            backend_request_id,
+        )
+        .await
+    }
+
+    /// Tell a backend that a request it was sent has been cancelled by the client
+    async fn cancel_backend_request(
+        &self,
+        server_name: &str,
+        request_id: Value,
+        reason: Option<String>,
+    ) -> anyhow::Result<()> {
+        let notification = json!({
             "jsonrpc": "2.0",
-            "id": request_id,
-            "method": "tools/call",
+            "method": "notifications/cancelled",
             "params": {
-                "name": tool_name,
-                "arguments": arguments
+                "requestId": request_id,
+                "reason": reason
             }
         });
 
-        println!(
-            "🔧 Forwarding tool call: {} to server {}",
-            tool_name, server_name
-        );
-
-        // Send request and read response
-        self.send_request(connection.clone(), tool_request).await?;
-        let response = self.read_response(connection).await?;
-
-        println!("📨 Received response from server {}", server_name);
-
-        Ok(response)
+        let config = self
+            .config_manager
+            .read()
+            .await
+            .get_server(server_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Server '{}' not found", server_name))?;
+        if config.transport == "http" {
+            let url = config
+                .url
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("HTTP transport requires 'url' field"))?;
+            let mut request = http_client_for(server_name, &config)
+                .post(url)
+                .header("Accept", "application/json,text/event-stream")
+                .json(&notification);
+            let session_id = self.http_sessions.lock().unwrap().get(server_name).cloned();
+            if let Some(session_id) = session_id {
+                request = request.header(MCP_SESSION_HEADER, session_id);
+            }
+            request
+                .send()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to send cancellation: {}", e))?;
+        } else {
+            let connection = self.connections.read().await.get(server_name).cloned();
+            if let Some(connection) = connection {
+                self.send_notification(connection, notification).await?;
+            }
+        }
+        Ok(())
     }
 
-    /// Stop a server connection
-    #[allow(dead_code)]
+    /// Stop a server connection, first letting in-flight calls to it finish
+    /// (up to `reload_drain_timeout_secs`), then terminating it gracefully
+    /// (SIGKILL only after `shutdown_grace_secs`)
     async fn stop_server(&self, server_name: &str) -> anyhow::Result<()> {
+        let (drain_timeout, shutdown_grace) = {
+            let config_manager = self.config_manager.read().await;
+            let config = config_manager.get_config();
+            (
+                tokio::time::Duration::from_secs(config.reload_drain_timeout_secs),
+                tokio::time::Duration::from_secs(config.shutdown_grace_secs),
+            )
+        };
+        let remaining = self.drain_calls(server_name, drain_timeout).await;
+        if remaining > 0 {
+            tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Stopping with {} call(s) still in flight after {}s",
+                remaining,
+                drain_timeout.as_secs()
+            );
+        }
+
+        self.websocket_connections
+            .lock()
+            .unwrap()
+            .remove(server_name);
+        self.http_sessions.lock().unwrap().remove(server_name);
         let connection = {
             let mut connections = self.connections.write().await;
             connections.remove(server_name)
@@ -801,12 +2737,87 @@ impl ServerConnectionPool {
 
         if let Some(connection) = connection {
             let mut conn = connection.lock().await;
-            let _ = conn.process.kill().await;
-            println!("🛑 Stopped server: {}", server_name);
+            let _ = terminate_gracefully(server_name, &mut conn.process, shutdown_grace).await;
+            self.process_ids.lock().unwrap().remove(server_name);
+            tracing::info!(target: LOG_BACKEND, "Stopped server: {}", server_name);
         }
 
         Ok(())
     }
+
+    /// Stop every stdio server when toolman itself shuts down, each as `stop_server` would.
+    /// Servers still running after `timeout` are SIGKILLed so none outlive the proxy.
+    async fn stop_all_servers(&self, timeout: std::time::Duration) {
+        let server_names: Vec<String> = self.connections.read().await.keys().cloned().collect();
+        tracing::info!(
+            target: LOG_BACKEND,
+            "Stopping {} stdio server(s)",
+            server_names.len()
+        );
+        let stops = future::join_all(server_names.iter().map(|name| self.stop_server(name)));
+        if tokio::time::timeout(timeout, stops).await.is_ok() {
+            return;
+        }
+
+        let remaining: Vec<(String, u32)> = self.process_ids.lock().unwrap().drain().collect();
+        for (server_name, pid) in remaining {
+            tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Still running {}s into shutdown, sending SIGKILL",
+                timeout.as_secs_f32()
+            );
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGKILL);
+            }
+            #[cfg(not(unix))]
+            let _ = pid;
+        }
+    }
+
+    /// Stop every stdio server that hasn't serviced a tool call in `idle_timeout`, returning
+    /// their names. A connection that is locked or has calls in flight is left alone.
+    async fn reap_idle_connections(&self, idle_timeout: std::time::Duration) -> Vec<String> {
+        let connections: Vec<_> = self
+            .connections
+            .read()
+            .await
+            .iter()
+            .map(|(name, connection)| (name.clone(), connection.clone()))
+            .collect();
+
+        let mut idle = Vec::new();
+        for (server_name, connection) in connections {
+            if self.in_flight_calls(&server_name) > 0 {
+                continue;
+            }
+            let Ok(conn) = connection.try_lock() else {
+                continue;
+            };
+            if conn.last_used.elapsed() >= idle_timeout {
+                idle.push(server_name);
+            }
+        }
+
+        for server_name in &idle {
+            tracing::info!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Idle for over {}s, stopping",
+                idle_timeout.as_secs()
+            );
+            if let Err(e) = self.stop_server(server_name).await {
+                tracing::warn!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Failed to stop idle server: {}",
+                    e
+                );
+            }
+        }
+        idle
+    }
 }
 
 #[derive(Clone)]
@@ -817,549 +2828,2324 @@ pub struct BridgeState {
     available_tools: Arc<RwLock<HashMap<String, Tool>>>,
     // Connection pool for active MCP servers
     connection_pool: Arc<ServerConnectionPool>,
-    // Current working directory for user context (per-request)
-    current_working_dir: Arc<RwLock<Option<std::path::PathBuf>>>,
+    // Whether the tool catalog ConfigMap is written after discovery (cleared by a failed permission probe)
+    catalog_writes_enabled: Arc<std::sync::atomic::AtomicBool>,
+    // Remaining pages of paginated tool results, keyed by cursor
+    result_pages: Arc<std::sync::Mutex<HashMap<String, PendingPages>>>,
+    // Prefixed tool names enabled via POST /client-config (None = every tool is listed)
+    enabled_tools: Arc<RwLock<Option<HashSet<String>>>>,
+    // Outcome of the last discovery attempt per server
+    discovery_status: Arc<RwLock<HashMap<String, DiscoveryStatus>>>,
+    // How long the last discovery attempt per server took, by phase
+    discovery_timings: Arc<RwLock<HashMap<String, DiscoveryTiming>>>,
+    // Running `discovery_ttl_secs` refresh loops per server, so a reload can replace them
+    refresh_tasks: Arc<std::sync::Mutex<HashMap<String, tokio::task::AbortHandle>>>,
+    // Aborts for client tools/calls in progress, keyed per `pending_call_key`
+    pending_calls: Arc<std::sync::Mutex<HashMap<String, PendingCall>>>,
+    // The `clientInfo` each client session sent in `initialize`, keyed by `X-Session-ID`.
+    // Backends started or initialized for a session's call handshake with it.
+    client_infos: Arc<std::sync::Mutex<HashMap<String, ClientInfo>>>,
+    // When the watchdog last got through all shared locks; a stale heartbeat fails /health
+    heartbeat: Arc<std::sync::Mutex<std::time::Instant>>,
+    // Per-project (and per-user) tool selections that narrow tools/list
+    context_manager: Option<ContextManager>,
+    // Sessions (`X-Session-ID`) whose initialize declared
+    // `capabilities.experimental.textOnlyContent`; non-text content in their tool results is
+    // replaced by text summaries
+    text_only_sessions: Arc<std::sync::Mutex<HashSet<String>>>,
+    // The latest tool-call and discovery failures per server, for `/servers/{name}/errors`
+    recent_errors: Arc<std::sync::Mutex<HashMap<String, std::collections::VecDeque<ServerError>>>>,
+    // Tool-call counters and latencies exported on `/metrics`
+    metrics: Metrics,
+    // Held for the duration of a `/reload` so two reloads don't interleave
+    reload_lock: Arc<Mutex<()>>,
+    // Check tools/call arguments against the tool's inputSchema before forwarding
+    validate_arguments: bool,
+    // Server-initiated messages (`notifications/tools/list_changed`) for clients
+    // listening on `GET /mcp`
+    notifications: tokio::sync::broadcast::Sender<Value>,
+    // Where forwarded tool calls are recorded for audit (`TOOLMAN_AUDIT_LOG`)
+    audit_log: Option<AuditLog>,
 }
 
-// JSON-RPC 2.0 message types
-#[derive(Debug, Deserialize)]
-struct JsonRpcRequest {
-    #[allow(dead_code)]
-    jsonrpc: String,
-    id: Option<Value>,
-    method: String,
-    params: Option<Value>,
+/// What a `/reload` did with each server, by name
+#[derive(Debug, Default, Serialize)]
+struct ReloadSummary {
+    added: Vec<String>,
+    removed: Vec<String>,
+    // Restarted and rediscovered because their config differs
+    changed: Vec<String>,
+    unchanged: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    id: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    result: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<JsonRpcError>,
-}
+/// How often the watchdog refreshes the heartbeat checked by `/health`
+const HEARTBEAT_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(5);
 
-#[derive(Debug, Serialize)]
-struct JsonRpcError {
-    code: i32,
-    message: String,
+/// A client's in-progress tools/call, aborted with the reason given in its cancellation.
+/// `token` tells it apart from a later call that reuses its key once it was cancelled.
+struct PendingCall {
+    token: u64,
+    cancel: tokio::sync::oneshot::Sender<Option<String>>,
 }
 
-impl BridgeState {
-    pub fn new(project_dir: Option<std::path::PathBuf>) -> Result<Self> {
-        // Determine system config path from environment variable or fallback to project
-        let system_config_path = std::env::var("SYSTEM_CONFIG_PATH")
-            .ok()
-            .map(std::path::PathBuf::from)
-            .or_else(|| project_dir.clone());
-
-        println!("🔧 System config path: {:?}", system_config_path);
-        println!("🔧 Project directory: {:?}", project_dir);
+/// Where the id a backend was sent for a call is recorded, for forwarding cancellations
+type BackendRequestId = Arc<std::sync::Mutex<Option<Value>>>;
+
+/// Tidies up after a client's tools/call. If it is dropped without `finish`, the request
+/// was dropped mid-call, which is what axum does when the client disconnects. Dropping
+/// the call already stopped the wait for the server's response and released its locks;
+/// the guard also tells the server the call is cancelled. A stdio server's late response
+/// is skipped by id when the next call reads its output.
+struct PendingCallGuard {
+    state: BridgeState,
+    // Key and token of the call in `pending_calls`, if it was registered there
+    pending: Option<(String, u64)>,
+    server_name: String,
+    backend_request_id: BackendRequestId,
+    finished: bool,
+}
 
-        // Create system-level config manager (for server discovery and startup)
-        let system_config_manager_instance = ConfigManager::new(system_config_path)?;
+impl PendingCallGuard {
+    /// The call ran to completion (or was cancelled by the client)
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
 
-        // Cleanup orphaned temporary files from previous runs
-        if let Err(e) = system_config_manager_instance.cleanup_temp_files() {
-            eprintln!("Warning: Failed to cleanup temporary files: {}", e);
+impl Drop for PendingCallGuard {
+    fn drop(&mut self) {
+        if let Some((key, token)) = &self.pending {
+            let mut pending_calls = self.state.pending_calls.lock().unwrap();
+            // The entry may already be cancelled and its key taken by a newer call
+            if pending_calls
+                .get(key)
+                .is_some_and(|call| call.token == *token)
+            {
+                pending_calls.remove(key);
+            }
         }
+        if self.finished {
+            return;
+        }
+        tracing::info!(
+            target: LOG_SERVER,
+            server = %self.server_name,
+            "Client disconnected during a tool call"
+        );
+        let state = self.state.clone();
+        let server_name = self.server_name.clone();
+        let backend_request_id = self.backend_request_id.clone();
+        tokio::spawn(async move {
+            state
+                .forward_cancellation(
+                    &server_name,
+                    &backend_request_id,
+                    Some("client disconnected".to_string()),
+                )
+                .await;
+        });
+    }
+}
 
-        let system_config_manager = Arc::new(RwLock::new(system_config_manager_instance));
-        let connection_pool = Arc::new(ServerConnectionPool::new(system_config_manager.clone()));
+/// The client session a request belongs to, from its `X-Session-ID` header. Requests
+/// without one share the empty session.
+fn request_session_id(headers: Option<&axum::http::HeaderMap>) -> &str {
+    headers
+        .and_then(|h| h.get("x-session-id"))
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
 
-        // Create the state
-        let state = Self {
-            system_config_manager,
-            available_tools: Arc::new(RwLock::new(HashMap::new())),
-            connection_pool,
-            current_working_dir: Arc::new(RwLock::new(None)),
-        };
+/// Key of a client's tools/call in `pending_calls`: its JSON-RPC id within the client's
+/// `X-Session-ID`, as every client numbers its requests from 1
+fn pending_call_key(headers: Option<&axum::http::HeaderMap>, request_id: &Value) -> String {
+    format!("{}/{}", request_session_id(headers), request_id)
+}
 
-        Ok(state)
-    }
+/// A server's `max_concurrent_calls` slots, with the limit they were sized for
+type CallSlots = (usize, Arc<tokio::sync::Semaphore>);
+
+/// Outcome of the most recent discovery attempt for a server, reported by `/ready`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum DiscoveryStatus {
+    Ready {
+        tools: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        server_version: Option<String>,
+    },
+    Unreachable {
+        reason: String,
+    },
+    TimedOut {
+        after_secs: u64,
+    },
+    Failed {
+        reason: String,
+    },
+    /// Not started, because servers in its `depends_on` aren't ready
+    DependencyNotReady {
+        dependencies: Vec<String>,
+    },
+}
 
-    /// Discover and cache available tools from all configured servers
-    async fn discover_all_tools(&self) -> Result<()> {
-        println!("🔍 Starting tool discovery for all configured servers...");
-        let init_start = std::time::Instant::now();
+/// How long each phase of a server's most recent discovery took, reported by `/servers`
+/// and `/metrics`. `init_secs` is the stdio handshake; other transports handshake as
+/// part of listing their tools, so theirs is counted in `tools_list_secs`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct DiscoveryTiming {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    init_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools_list_secs: Option<f64>,
+}
 
-        // Wait for Docker to be ready BEFORE initializing any servers
-        println!("🐳 Ensuring Docker daemon is ready before starting any servers...");
-        let docker_start = std::time::Instant::now();
-        if let Err(e) = self.connection_pool.wait_for_docker(60).await {
-            eprintln!("⚠️ Docker readiness check failed: {}", e);
-            eprintln!("⚠️ Continuing anyway, but Docker-based servers may fail");
+/// Per-server discovery timeout unless `discovery_timeout_secs` overrides it. Generous to
+/// allow for first-time uvx installations from git repos.
+const DEFAULT_DISCOVERY_TIMEOUT_SECS: u64 = 180;
+
+/// How many more times `tools/list` is asked when a backend that declared the tools
+/// capability answers with none, and how long to wait before each retry. Some backends
+/// only fill in their tools shortly after `notifications/initialized`.
+const EMPTY_TOOLS_LIST_RETRIES: u32 = 2;
+const EMPTY_TOOLS_LIST_RETRY_DELAY_MS: u64 = 500;
+
+/// How advertised tool names are built, for clients that construct names themselves:
+/// `{prefix}{separator}{tool}`, with the prefix from the server name and the tool's name
+/// sanitized to ASCII letters, digits and `_`
+fn tool_naming_rules(config: &ServersConfig) -> Value {
+    json!({
+        "separator": "_",
+        "server_name_normalization": config.server_name_normalization,
+        "server_prefixes": server_prefixes(config),
+        "tool_name_normalization": "sanitize",
+        "builtin_prefix": "toolman_",
+    })
+}
+
+/// Names of the boolean features that are on, the compact form of `/capabilities`
+/// included in `initialize`'s `serverInfo`
+fn enabled_features(capabilities: &Value) -> Vec<String> {
+    capabilities["features"]
+        .as_object()
+        .map(|features| {
+            features
+                .iter()
+                .filter(|(_, enabled)| enabled.as_bool() == Some(true))
+                .map(|(name, _)| name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Replace every non-text item in a tool result's `content` with a text summary
+/// (e.g. `[image: 12KB png]`), for clients that can only render text.
+/// Embedded text resources keep their text under a `[resource: <uri>]` header.
+fn coerce_content_to_text(mut result: Value) -> Value {
+    // Approximate decoded size of base64 data
+    let size = |data: &Value| {
+        let bytes = data.as_str().map_or(0, |d| d.len() * 3 / 4);
+        if bytes < 1024 {
+            format!("{}B", bytes)
         } else {
-            let docker_elapsed = docker_start.elapsed();
-            println!(
-                "✅ Docker is ready (took {:.2}s)",
-                docker_elapsed.as_secs_f64()
-            );
+            format!("{}KB", bytes.div_ceil(1024))
         }
+    };
+    let mime_subtype = |mime: &Value| {
+        mime.as_str()
+            .map(|m| m.rsplit('/').next().unwrap_or(m).to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    };
 
-        // Get local tool servers from ConfigMap if available
-        let local_servers = match Client::try_default().await {
-            Ok(client) => self
-                .read_local_tools_config(&client)
-                .await
-                .unwrap_or_default(),
-            Err(_) => HashMap::new(),
-        };
-
-        // Add local servers to system configuration temporarily for discovery
-        if !local_servers.is_empty() {
-            let mut config_manager = self.system_config_manager.write().await;
-            let config = config_manager.get_config_mut();
-            for (name, config_data) in &local_servers {
-                println!("📝 Adding local server '{}' to configuration", name);
-                config.servers.insert(name.clone(), config_data.clone());
-            }
+    if let Some(items) = result.get_mut("content").and_then(Value::as_array_mut) {
+        for item in items.iter_mut() {
+            let text = match item["type"].as_str().unwrap_or_default() {
+                "text" => continue,
+                kind @ ("image" | "audio") => format!(
+                    "[{}: {} {}]",
+                    kind,
+                    size(&item["data"]),
+                    mime_subtype(&item["mimeType"])
+                ),
+                "resource" => {
+                    let resource = &item["resource"];
+                    let uri = resource["uri"].as_str().unwrap_or("unknown");
+                    match resource["text"].as_str() {
+                        Some(text) => format!("[resource: {}]\n{}", uri, text),
+                        None => format!(
+                            "[resource: {}, {} {}]",
+                            uri,
+                            size(&resource["blob"]),
+                            resource["mimeType"].as_str().unwrap_or("unknown")
+                        ),
+                    }
+                }
+                "resource_link" => format!(
+                    "[resource link: {}]",
+                    item["uri"].as_str().unwrap_or("unknown")
+                ),
+                other => format!("[{} content]", other),
+            };
+            *item = json!({ "type": "text", "text": text });
         }
+    }
+    result
+}
 
-        // Get all configured servers (including the newly added local ones)
-        let all_servers = {
-            let config_manager = self.system_config_manager.read().await;
-            config_manager.get_servers().clone()
-        };
+/// Call rejected because too many calls are already waiting for a stdio server
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "server '{server}' is busy: {queued} call(s) already queued (max_queued_calls = {max_queued})"
+)]
+struct ServerBusy {
+    server: String,
+    queued: usize,
+    max_queued: usize,
+}
 
-        // Convert to vector for async operations
-        let mut server_list: Vec<_> = all_servers.into_iter().collect();
-        server_list.sort_by_key(|(name, _)| name.clone()); // Deterministic order
+/// Call rejected because its stdio server is still starting up (`while_initializing = reject`)
+#[derive(Debug, thiserror::Error)]
+#[error("server '{server}' is still initializing")]
+struct ServerInitializing {
+    server: String,
+}
 
-        if server_list.is_empty() {
-            println!("⚠️ No servers configured - skipping tool discovery");
-            return Ok(());
-        }
+/// Header carrying the session a streamable HTTP server assigned at initialization
+const MCP_SESSION_HEADER: &str = "Mcp-Session-Id";
 
-        let mut all_tools = HashMap::new();
+/// How long a stdio server may go without output while a request to it is pending,
+/// unless its `call_timeout_secs` says otherwise
+const DEFAULT_STDIO_CALL_TIMEOUT_SECS: u64 = 30;
 
-        // Parallel initialization: spawn tasks for each server to avoid deadlock
-        println!("🚀 Starting parallel server initialization...");
+/// How long an SSE server's tool call may take, unless its `call_timeout_secs` says otherwise
+const DEFAULT_SSE_CALL_TIMEOUT_SECS: u64 = 120;
 
-        let tasks: Vec<_> = server_list
-            .into_iter()
-            .map(|(server_name, config)| {
-                let connection_pool = self.connection_pool.clone();
-                let self_clone = self.clone();
+/// A backend didn't answer a request in the time allowed
+#[derive(Debug, thiserror::Error)]
+#[error("server '{server}' did not answer {what} within {secs}s")]
+struct ResponseTimedOut {
+    server: String,
+    what: String,
+    secs: u64,
+}
 
-                tokio::spawn(async move {
-                    println!(
-                        "🔍 [{}] Starting parallel initialization at {:?}",
-                        server_name,
-                        chrono::Utc::now().format("%H:%M:%S")
-                    );
+/// A stdio server closed one of its pipes, which means its process exited (or is exiting)
+#[derive(Debug, thiserror::Error)]
+#[error("server '{server}' closed its {pipe}")]
+struct ServerExited {
+    server: String,
+    pipe: &'static str,
+}
 
-                    // For stdio servers, initialize them permanently
-                    if config.transport == "stdio" {
-                        println!("🔄 [{}] Initializing stdio server...", server_name);
+/// A stdio server exited during a call and starting it again failed
+#[derive(Debug, thiserror::Error)]
+#[error("server '{server}' crashed and could not be restarted: {reason}")]
+struct ServerCrashed {
+    server: String,
+    reason: String,
+}
 
-                        match connection_pool.start_server(&server_name).await {
-                            Ok(_) => {
-                                println!("✅ [{}] Server initialized successfully", server_name);
+/// Error for a failed write to a stdio server: `ServerExited` if its stdin is closed
+fn stdin_error(server_name: &str, what: &str, error: std::io::Error) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::BrokenPipe {
+        ServerExited {
+            server: server_name.to_string(),
+            pipe: "stdin",
+        }
+        .into()
+    } else {
+        anyhow::anyhow!("Failed to {}: {}", what, error)
+    }
+}
 
-                                // Small delay to ensure connection is stored
-                                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                            }
-                            Err(e) => {
-                                eprintln!(
-                                    "⚠️ [{}] Failed to initialize server: {}",
-                                    server_name, e
-                                );
-                                return Ok::<(String, Vec<Tool>), anyhow::Error>((
-                                    server_name,
-                                    Vec::new(),
-                                ));
-                            }
-                        }
-                    } else {
-                        println!(
-                            "🔄 [{}] Skipping initialization for {} server",
-                            server_name, config.transport
-                        );
-                    }
+/// Wrap a tool result without a `content` array in a single text content item: a string
+/// as it is, anything else as JSON. Results with `content` are returned unchanged.
+fn normalize_tool_result(result: Value) -> Value {
+    if result.get("content").is_some_and(Value::is_array) {
+        return result;
+    }
+    let text = match result {
+        Value::String(text) => text,
+        other => other.to_string(),
+    };
+    json!({ "content": [{ "type": "text", "text": text }] })
+}
 
-                    // Discover tools with timeout
-                    // Increased to 180s to allow for first-time uvx installations from git repos
-                    println!("🔍 [{}] Starting tool discovery...", server_name);
-                    let discovery_start = std::time::Instant::now();
-                    let discovery_timeout = tokio::time::Duration::from_secs(180);
+/// Value of `_meta.toolman_error.kind` for a failed tool call: `server_busy`,
+/// `server_unreachable`, `server_crashed`, `connection_closed`, `timeout` or `call_failed`
+fn tool_error_kind(error: &anyhow::Error) -> &'static str {
+    if error.is::<ServerBusy>() {
+        "server_busy"
+    } else if error.is::<ServerInitializing>() {
+        "server_initializing"
+    } else if error.is::<BackendUnreachable>() {
+        "server_unreachable"
+    } else if error.is::<ServerCrashed>() {
+        "server_crashed"
+    } else if error.is::<WebSocketClosed>() || error.is::<ServerExited>() {
+        "connection_closed"
+    } else if error.is::<ResponseTimedOut>()
+        || error.to_string().to_lowercase().contains("timeout")
+        || error.to_string().contains("timed out")
+    {
+        "timeout"
+    } else {
+        "call_failed"
+    }
+}
 
-                    match tokio::time::timeout(
-                        discovery_timeout,
-                        self_clone.discover_server_tools(&server_name, &config),
-                    )
-                    .await
-                    {
-                        Ok(Ok(tools)) => {
-                            let discovery_duration = discovery_start.elapsed();
-                            println!(
-                                "✅ [{}] Discovered {} tools in {:.2}s",
-                                server_name,
-                                tools.len(),
-                                discovery_duration.as_secs_f64()
-                            );
+/// Text content item reporting a tool-level error. With `structured`
+/// (`structured_tool_errors`) it also carries
+/// `_meta.toolman_error: {kind, server, tool, detail}`, `server` being null when the
+/// tool name didn't resolve to a server.
+fn tool_error_content(
+    text: String,
+    kind: &str,
+    server: Option<&str>,
+    tool: &str,
+    detail: &str,
+    structured: bool,
+) -> Value {
+    let mut content = json!({ "type": "text", "text": text });
+    if structured {
+        content["_meta"] = json!({
+            "toolman_error": {
+                "kind": kind,
+                "server": server,
+                "tool": tool,
+                "detail": detail,
+            }
+        });
+    }
+    content
+}
 
-                            // Log individual tools discovered
-                            for tool in &tools {
-                                println!("  📎 [{}] Tool: {}", server_name, tool.name);
-                            }
+/// Discovery error for an http/sse server that failed the reachability pre-check
+#[derive(Debug, thiserror::Error)]
+#[error("server is unreachable: {0}")]
+struct BackendUnreachable(String);
 
-                            Ok::<(String, Vec<Tool>), anyhow::Error>((server_name, tools))
-                        }
-                        Ok(Err(e)) => {
-                            eprintln!("⚠️ [{}] Tool discovery failed: {}", server_name, e);
-                            Ok::<(String, Vec<Tool>), anyhow::Error>((server_name, Vec::new()))
-                        }
-                        Err(_) => {
-                            eprintln!(
-                                "⚠️ [{}] Tool discovery timed out after {}s",
-                                server_name,
-                                discovery_timeout.as_secs()
-                            );
-                            Ok::<(String, Vec<Tool>), anyhow::Error>((server_name, Vec::new()))
-                        }
-                    }
-                })
-            })
-            .collect();
+/// Marks a request to a backend that failed in transport: the connection failed or
+/// dropped, or the answer was a 5xx or not JSON-RPC at all. Only these are retried; a
+/// JSON-RPC `error` is the backend's answer, and a timed-out call may still be running.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct TransportFailure(String);
 
-        // Wait for all parallel tasks to complete
-        println!("⏳ Waiting for all servers to complete initialization...");
-        let results = future::join_all(tasks).await;
+/// `error`, marked as a transport failure unless it is a timeout
+fn transport_failure(error: anyhow::Error) -> anyhow::Error {
+    if error.is::<ResponseTimedOut>() {
+        return error;
+    }
+    let message = error.to_string();
+    error.context(TransportFailure(message))
+}
 
-        // Collect all tools from successful initializations
-        for task_result in results {
-            match task_result {
-                Ok(Ok((_server_name, tools))) => {
-                    // Add tools to collection with server prefix
-                    for tool in tools {
-                        // Normalize server name: replace hyphens with underscores for consistent parsing
-                        let normalized_server_name = tool.server_name.replace('-', "_");
-                        // 🔧 CRITICAL: Sanitize tool name to match what Cursor sends us
-                        // Cursor converts hyphens to underscores in tool names for compatibility
-                        // We must use the sanitized name as the HashMap key so lookups work
-                        let sanitized_tool_name = tool.name.replace('-', "_");
-                        let prefixed_name =
-                            format!("{}_{}", normalized_server_name, sanitized_tool_name);
-                        all_tools.insert(prefixed_name, tool);
-                    }
-                }
-                Ok(Err(e)) => {
-                    eprintln!("⚠️ Server task failed: {}", e);
-                }
-                Err(e) => {
-                    eprintln!("⚠️ Task join failed: {}", e);
-                }
+/// Run `attempt` until it succeeds, fails other than in transport, or has been retried
+/// `retries` times, waiting `backoff` before the first retry and doubling it for each
+/// further one. Returns the outcome and how many attempts were made.
+async fn retry_transport_failures<T, F, Fut>(
+    server_name: &str,
+    what: &str,
+    retries: u32,
+    backoff: tokio::time::Duration,
+    mut attempt: F,
+) -> (anyhow::Result<T>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
+    let mut retried = 0;
+    loop {
+        match attempt().await {
+            Err(e) if retried < retries && e.is::<TransportFailure>() => {
+                let delay = backoff * 2u32.saturating_pow(retried);
+                retried += 1;
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    server = %server_name,
+                    "{} failed ({}), retry {}/{} in {:?}",
+                    what,
+                    e,
+                    retried,
+                    retries,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
             }
+            result => return (result, retried + 1),
         }
+    }
+}
 
-        // Store discovered tools
-        let mut available_tools = self.available_tools.write().await;
-        *available_tools = all_tools;
-        let total_elapsed = init_start.elapsed();
-        println!(
-            "✅ Tool discovery complete in {:.2}s. Total tools available: {}",
-            total_elapsed.as_secs_f64(),
-            available_tools.len()
-        );
+/// The JSON-RPC message in an HTTP response. A 5xx is a transport failure unless its
+/// body is a JSON-RPC error, which is the backend's answer like any other.
+async fn read_http_jsonrpc(response: reqwest::Response) -> anyhow::Result<Value> {
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| transport_failure(anyhow::anyhow!("Failed to read HTTP response: {}", e)))?;
+    // Plain JSON or SSE-framed JSON (like Solana)
+    let parsed = parse_http_jsonrpc_body(&body).map_err(transport_failure)?;
+    if status.is_server_error() && parsed.get("error").is_none() {
+        return Err(transport_failure(anyhow::anyhow!(
+            "HTTP request failed with status {}",
+            status
+        )));
+    }
+    Ok(parsed)
+}
+
+/// How many recent errors are kept per server
+const RECENT_ERRORS_PER_SERVER: usize = 50;
+
+/// Prometheus metrics exported on `/metrics`
+#[derive(Clone)]
+struct Metrics {
+    registry: prometheus::Registry,
+    tool_calls: prometheus::IntCounterVec,
+    tool_call_errors: prometheus::IntCounterVec,
+    forward_latency: prometheus::HistogramVec,
+    pool_connections: prometheus::IntGauge,
+    discovered_tools: prometheus::IntGauge,
+    discovery_duration: prometheus::GaugeVec,
+    queued_calls: prometheus::IntGaugeVec,
+    audit_log_dropped_lines: prometheus::IntGauge,
+}
 
-        // Create or update the tool catalog ConfigMap
-        if let Err(e) = self.create_tool_catalog_configmap(&available_tools).await {
-            eprintln!("⚠️ Failed to create tool catalog ConfigMap: {}", e);
-            // Don't fail the entire startup if ConfigMap creation fails
+impl Metrics {
+    fn new() -> Self {
+        let tool_calls = prometheus::IntCounterVec::new(
+            prometheus::Opts::new(
+                "toolman_tool_calls_total",
+                "Tool calls forwarded to backends",
+            ),
+            &["server", "tool"],
+        )
+        .unwrap();
+        let tool_call_errors = prometheus::IntCounterVec::new(
+            prometheus::Opts::new(
+                "toolman_tool_call_errors_total",
+                "Tool calls that failed to get a response from their backend",
+            ),
+            &["server", "tool"],
+        )
+        .unwrap();
+        let forward_latency = prometheus::HistogramVec::new(
+            prometheus::HistogramOpts::new(
+                "toolman_tool_call_duration_seconds",
+                "Time taken to forward a tool call and get its response, retries included",
+            )
+            .buckets(vec![
+                0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+            ]),
+            &["server"],
+        )
+        .unwrap();
+        let pool_connections = prometheus::IntGauge::new(
+            "toolman_pool_connections",
+            "Open backend connections: stdio processes, SSE sessions and WebSockets",
+        )
+        .unwrap();
+        let discovered_tools =
+            prometheus::IntGauge::new("toolman_discovered_tools", "Tools discovered from backends")
+                .unwrap();
+        let queued_calls = prometheus::IntGaugeVec::new(
+            prometheus::Opts::new(
+                "toolman_queued_calls",
+                "Calls waiting behind the one a stdio server is serving",
+            ),
+            &["server"],
+        )
+        .unwrap();
+        let audit_log_dropped_lines = prometheus::IntGauge::new(
+            "toolman_audit_log_dropped_lines",
+            "Audit log lines dropped because writing them had fallen behind",
+        )
+        .unwrap();
+        let discovery_duration = prometheus::GaugeVec::new(
+            prometheus::Opts::new(
+                "toolman_discovery_duration_seconds",
+                "Time the last discovery of a server spent per phase: init or tools_list",
+            ),
+            &["server", "phase"],
+        )
+        .unwrap();
+
+        let registry = prometheus::Registry::new();
+        registry.register(Box::new(tool_calls.clone())).unwrap();
+        registry
+            .register(Box::new(tool_call_errors.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(forward_latency.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(pool_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(discovered_tools.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(discovery_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(queued_calls.clone())).unwrap();
+        registry
+            .register(Box::new(audit_log_dropped_lines.clone()))
+            .unwrap();
+        Self {
+            registry,
+            tool_calls,
+            tool_call_errors,
+            forward_latency,
+            pool_connections,
+            discovered_tools,
+            discovery_duration,
+            queued_calls,
+            audit_log_dropped_lines,
         }
+    }
 
-        Ok(())
+    /// Count a forwarded call and how long it took
+    fn record_call(&self, server: &str, tool: &str, elapsed: std::time::Duration, failed: bool) {
+        self.tool_calls.with_label_values(&[server, tool]).inc();
+        if failed {
+            self.tool_call_errors
+                .with_label_values(&[server, tool])
+                .inc();
+        }
+        self.forward_latency
+            .with_label_values(&[server])
+            .observe(elapsed.as_secs_f64());
     }
 
-    /// Create or update the tool catalog ConfigMap
-    async fn create_tool_catalog_configmap(
-        &self,
-        tools: &HashMap<String, Tool>,
-    ) -> anyhow::Result<()> {
-        println!("📋 Creating tool catalog ConfigMap...");
+    /// All metrics in the Prometheus text exposition format
+    fn render(&self) -> String {
+        prometheus::TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
 
-        // Initialize Kubernetes client
-        let client = Client::try_default().await?;
+/// A tool-call or discovery failure, as reported by `/servers/{name}/errors`
+#[derive(Debug, Clone, Serialize)]
+struct ServerError {
+    timestamp: String,
+    /// `tool_call` or `discovery`
+    source: &'static str,
+    message: String,
+}
 
-        // Detect current namespace
-        let namespace = get_current_namespace();
-        println!("📍 Detected namespace: {}", namespace);
+/// How long the remainder of a paginated result is kept for follow-up calls
+const RESULT_PAGE_TTL: std::time::Duration = std::time::Duration::from_secs(300);
 
-        // Get server configurations for descriptions
-        let servers = {
-            let config_manager = self.system_config_manager.read().await;
-            config_manager.get_servers().clone()
-        };
+/// The not-yet-returned part of a paginated tool result
+struct PendingPages {
+    /// The result with its `content` removed (e.g. `isError`), repeated on every page
+    envelope: Value,
+    remaining: Vec<Value>,
+    page_size: usize,
+    expires_at: std::time::Instant,
+}
 
-        // Read local tools configuration from ConfigMap
-        let local_servers = self.read_local_tools_config(&client).await?;
+// JSON-RPC 2.0 message types
+#[derive(Debug, Deserialize)]
+struct JsonRpcRequest {
+    #[allow(dead_code)]
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    params: Option<Value>,
+}
 
-        // Build the tool catalog
-        let catalog = self.build_tool_catalog(tools, &servers, &local_servers);
+#[derive(Debug, Serialize)]
+struct JsonRpcResponse {
+    jsonrpc: String,
+    id: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+}
 
-        // Convert to JSON
-        let catalog_json = serde_json::to_string_pretty(&catalog)?;
+#[derive(Debug, Serialize)]
+struct JsonRpcError {
+    code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
 
-        // Create ConfigMap
-        let mut data = BTreeMap::new();
-        data.insert("tool-catalog.json".to_string(), catalog_json);
+impl BridgeState {
+    pub fn new(project_dir: Option<std::path::PathBuf>) -> Result<Self> {
+        // Determine system config path from environment variable or fallback to project
+        let system_config_path = std::env::var("SYSTEM_CONFIG_PATH")
+            .ok()
+            .map(std::path::PathBuf::from)
+            .or_else(|| project_dir.clone());
 
-        let cm = ConfigMap {
-            metadata: ObjectMeta {
-                name: Some("toolman-tool-catalog".to_string()),
-                namespace: Some(namespace.clone()),
-                ..Default::default()
-            },
-            data: Some(data),
-            ..Default::default()
+        tracing::debug!(target: LOG_SERVER, "System config path: {:?}", system_config_path);
+        tracing::debug!(target: LOG_SERVER, "Project directory: {:?}", project_dir);
+
+        // Create system-level config manager (for server discovery and startup)
+        let system_config_manager_instance = ConfigManager::new(system_config_path)?;
+
+        // Refuse to start on a config that can only fail later, at tool-call time
+        check_config(&system_config_manager_instance)?;
+
+        // Cleanup orphaned temporary files from previous runs
+        if let Err(e) = system_config_manager_instance.cleanup_temp_files() {
+            tracing::warn!(target: LOG_SERVER, "Failed to cleanup temporary files: {}", e);
+        }
+
+        let system_config_manager = Arc::new(RwLock::new(system_config_manager_instance));
+        let connection_pool = Arc::new(ServerConnectionPool::new(system_config_manager.clone()));
+
+        // Create the state
+        let state = Self {
+            system_config_manager,
+            available_tools: Arc::new(RwLock::new(HashMap::new())),
+            connection_pool,
+            catalog_writes_enabled: Arc::new(std::sync::atomic::AtomicBool::new(
+                tool_catalog_writes_enabled(),
+            )),
+            result_pages: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            enabled_tools: Arc::new(RwLock::new(None)),
+            discovery_status: Arc::new(RwLock::new(HashMap::new())),
+            discovery_timings: Arc::new(RwLock::new(HashMap::new())),
+            refresh_tasks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            pending_calls: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            client_infos: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            heartbeat: Arc::new(std::sync::Mutex::new(std::time::Instant::now())),
+            context_manager: ContextManager::default_contexts_dir()
+                .ok()
+                .map(ContextManager::with_contexts_dir),
+            text_only_sessions: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            recent_errors: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            metrics: Metrics::new(),
+            reload_lock: Arc::new(Mutex::new(())),
+            validate_arguments: argument_validation_enabled(),
+            notifications: tokio::sync::broadcast::channel(16).0,
+            audit_log: AuditLog::from_env(),
         };
 
-        let api: Api<ConfigMap> = Api::namespaced(client, &namespace);
+        if let Some(idle_timeout) = idle_timeout() {
+            state.spawn_idle_reaper(idle_timeout);
+        }
 
-        // Use server-side apply which handles both create and update
-        let patch = Patch::Apply(&cm);
-        api.patch(
-            "toolman-tool-catalog",
-            &PatchParams::apply("toolman").force(),
-            &patch,
-        )
-        .await?;
-        println!("✅ Created/Updated tool catalog ConfigMap");
+        Ok(state)
+    }
 
-        Ok(())
+    /// Periodically stop stdio servers left idle for `idle_timeout`; they are started
+    /// again on their next call. Does nothing outside a Tokio runtime.
+    fn spawn_idle_reaper(&self, idle_timeout: std::time::Duration) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        // Hold the pool weakly so the reaper ends once the state is dropped
+        let pool = Arc::downgrade(&self.connection_pool);
+        runtime.spawn(async move {
+            let mut interval =
+                tokio::time::interval(idle_timeout.min(std::time::Duration::from_secs(60)));
+            loop {
+                interval.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                pool.reap_idle_connections(idle_timeout).await;
+            }
+        });
     }
 
-    /// Read local tools configuration from ConfigMap
-    async fn read_local_tools_config(
-        &self,
-        client: &Client,
-    ) -> anyhow::Result<HashMap<String, ServerConfig>> {
-        let namespace = get_current_namespace();
-        let api: Api<ConfigMap> = Api::namespaced(client.clone(), &namespace);
+    /// Rediscover each server that sets `discovery_ttl_secs` every that many seconds, for
+    /// as long as it is configured with one
+    async fn spawn_discovery_refresh(&self) {
+        let server_names: Vec<String> = self
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .iter()
+            .filter(|(_, config)| config.discovery_ttl_secs.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for server_name in server_names {
+            self.spawn_server_refresh(server_name);
+        }
+    }
 
-        match api.get("toolman-local-tools").await {
-            Ok(cm) => {
-                println!("✅ Loaded local tools config from namespace: {}", namespace);
-                if let Some(data) = cm.data {
-                    if let Some(config_json) = data.get("local-tools-config.json") {
-                        // Parse using the same structure as servers config
-                        let config: serde_json::Value = serde_json::from_str(config_json)?;
-                        if let Some(servers_obj) = config.get("servers").and_then(|s| s.as_object())
-                        {
-                            let mut servers = HashMap::new();
-                            for (name, value) in servers_obj {
-                                if let Ok(server_config) =
-                                    serde_json::from_value::<ServerConfig>(value.clone())
-                                {
-                                    servers.insert(name.clone(), server_config);
-                                }
-                            }
-                            println!(
-                                "✅ Loaded {} local tool servers from ConfigMap",
-                                servers.len()
-                            );
-                            return Ok(servers);
-                        }
-                    }
-                }
-                println!("⚠️ Local tools ConfigMap found but no valid data");
-                Ok(HashMap::new())
-            }
-            Err(e) => {
-                println!(
-                    "⚠️ Local tools ConfigMap not found: {}. No local tools will be included.",
-                    e
-                );
-                Ok(HashMap::new())
+    /// Rediscover one server every `discovery_ttl_secs`, until it no longer sets one.
+    /// Replaces the server's previous refresh loop, if any.
+    fn spawn_server_refresh(&self, server_name: String) {
+        let state = self.clone();
+        let name = server_name.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let ttl = state
+                    .system_config_manager
+                    .read()
+                    .await
+                    .get_server(&server_name)
+                    .and_then(|config| config.discovery_ttl_secs);
+                let Some(ttl) = ttl else {
+                    return;
+                };
+                tokio::time::sleep(std::time::Duration::from_secs(ttl.max(1))).await;
+                state.refresh_server_tools(&server_name).await;
             }
+        });
+        if let Some(previous) = self
+            .refresh_tasks
+            .lock()
+            .unwrap()
+            .insert(name, task.abort_handle())
+        {
+            previous.abort();
         }
     }
 
-    /// Build the tool catalog structure
-    fn build_tool_catalog(
-        &self,
-        tools: &HashMap<String, Tool>,
-        servers: &HashMap<String, ServerConfig>,
-        local_servers: &HashMap<String, ServerConfig>,
-    ) -> ToolCatalog {
-        let mut local = HashMap::new();
-        let mut remote = HashMap::new();
-
-        // Group tools by server
-        let mut server_tools: HashMap<String, Vec<&Tool>> = HashMap::new();
-        for tool in tools.values() {
-            server_tools
-                .entry(tool.server_name.clone())
-                .or_default()
-                .push(tool);
+    /// Stop a server's `discovery_ttl_secs` refresh loop, if it has one
+    fn stop_server_refresh(&self, server_name: &str) {
+        if let Some(task) = self.refresh_tasks.lock().unwrap().remove(server_name) {
+            task.abort();
         }
+    }
 
-        // Build local server info from discovered tools
-        for (server_name, server_config) in local_servers {
-            if let Some(tools) = server_tools.get(server_name) {
-                let tool_infos: Vec<ToolInfo> = tools
-                    .iter()
-                    .map(|tool| ToolInfo {
-                        name: tool.name.clone(),
-                        description: tool.description.clone(),
-                        category: self.infer_category(&tool.name, &tool.description),
-                        use_cases: self.infer_use_cases(&tool.name, &tool.description),
-                        input_schema: Some(tool.input_schema.clone()),
-                    })
-                    .collect();
-
-                if !tool_infos.is_empty() {
-                    local.insert(
-                        server_name.clone(),
-                        LocalServerInfo {
-                            description: server_config.description.clone().unwrap_or_else(|| {
-                                server_config
-                                    .name
-                                    .clone()
-                                    .unwrap_or_else(|| server_name.clone())
-                            }),
-                            command: server_config.command.clone(),
-                            args: server_config.args.clone(),
-                            working_directory: server_config
-                                .working_directory
-                                .clone()
-                                .unwrap_or_else(|| "project_root".to_string()),
-                            tools: tool_infos,
-                        },
-                    );
+    /// Re-read the config file and apply it. Added servers are discovered, deleted ones
+    /// are stopped and their tools dropped, and servers whose config changed are restarted
+    /// and rediscovered; unchanged servers are left running. The new config and
+    /// `available_tools` are swapped together once discovery is done, so a concurrent
+    /// tools/call sees either the old or the new set, and can still reach a removed server
+    /// until then. A changed server that fails rediscovery keeps its known tools.
+    async fn reload_config(&self) -> Result<ReloadSummary> {
+        let _reload = self.reload_lock.lock().await;
+
+        let mut new_manager = self.system_config_manager.read().await.reread()?;
+        check_config(&new_manager)?;
+        // Local servers come from the ConfigMap rather than the file
+        new_manager
+            .get_config_mut()
+            .servers
+            .extend(self.local_tool_servers().await);
+
+        let mut summary = ReloadSummary::default();
+        let rediscover = {
+            let mut config_manager = self.system_config_manager.write().await;
+            let old_servers = config_manager.get_servers();
+            let new_servers = new_manager.get_servers();
+            for (name, old_config) in old_servers {
+                match new_servers.get(name) {
+                    None => summary.removed.push(name.clone()),
+                    Some(new_config)
+                        if serde_json::to_value(new_config).ok()
+                            != serde_json::to_value(old_config).ok() =>
+                    {
+                        summary.changed.push(name.clone())
+                    }
+                    Some(_) => summary.unchanged.push(name.clone()),
                 }
             }
-        }
+            summary.added = new_servers
+                .keys()
+                .filter(|name| !old_servers.contains_key(*name))
+                .cloned()
+                .collect();
+            for names in [
+                &mut summary.added,
+                &mut summary.removed,
+                &mut summary.changed,
+                &mut summary.unchanged,
+            ] {
+                names.sort();
+            }
 
-        // Build remote server info
-        for (server_name, server_config) in servers {
-            // Skip if this is also in local servers (avoid duplicates)
-            if local_servers.contains_key(server_name) {
-                continue;
+            let rediscover: Vec<(String, ServerConfig)> = summary
+                .added
+                .iter()
+                .chain(&summary.changed)
+                .map(|name| (name.clone(), new_servers[name].clone()))
+                .collect();
+            // Until the swap, added and changed servers start under their new config while
+            // removed servers keep theirs
+            let servers = &mut config_manager.get_config_mut().servers;
+            for (name, config) in &rediscover {
+                servers.insert(name.clone(), config.clone());
             }
+            rediscover
+        };
+        tracing::info!(
+            target: LOG_DISCOVERY,
+            "Reloading config: {} added, {} removed, {} changed, {} unchanged",
+            summary.added.len(),
+            summary.removed.len(),
+            summary.changed.len(),
+            summary.unchanged.len()
+        );
 
-            if let Some(tools) = server_tools.get(server_name) {
-                let tool_infos: Vec<ToolInfo> = tools
-                    .iter()
-                    .map(|tool| ToolInfo {
-                        name: tool.name.clone(),
-                        description: tool.description.clone(),
-                        category: self.infer_category(&tool.name, &tool.description),
-                        use_cases: self.infer_use_cases(&tool.name, &tool.description),
-                        input_schema: Some(tool.input_schema.clone()),
-                    })
-                    .collect();
+        self.stop_servers(&summary.changed).await;
+        let discovered = self.discover_servers(rediscover).await;
+        let rediscovered: HashSet<&String> = {
+            let discovery_status = self.discovery_status.read().await;
+            summary
+                .changed
+                .iter()
+                .filter(|name| {
+                    matches!(
+                        discovery_status.get(*name),
+                        Some(DiscoveryStatus::Ready { .. })
+                    )
+                })
+                .collect()
+        };
+        for name in summary.changed.iter().filter(|n| !rediscovered.contains(n)) {
+            tracing::warn!(
+                target: LOG_DISCOVERY,
+                server = %name,
+                "Rediscovery after reload failed, keeping the known tools"
+            );
+        }
 
-                let endpoint = server_config.url.clone().unwrap_or_else(|| {
-                    if server_config.transport == "stdio" {
-                        "stdio".to_string()
-                    } else {
-                        "unknown".to_string()
-                    }
-                });
+        let prefixes = server_prefixes(new_manager.get_config());
+        let mut available_tools = self.available_tools.write().await;
+        let mut config_manager = self.system_config_manager.write().await;
+        // Tools of unchanged servers (and of changed ones that failed rediscovery) are kept,
+        // and every tool is re-keyed in case the naming settings changed
+        let kept: Vec<Tool> = std::mem::take(&mut *available_tools)
+            .into_values()
+            .filter(|tool| {
+                !summary.removed.contains(&tool.server_name)
+                    && !rediscovered.contains(&tool.server_name)
+            })
+            .collect();
+        for tool in kept.into_iter().chain(discovered.into_values()) {
+            let prefix = prefixes
+                .get(&tool.server_name)
+                .cloned()
+                .unwrap_or_else(|| sanitize_name(&tool.server_name));
+            available_tools.insert(prefixed_tool_name(&prefix, &tool.name), tool);
+        }
+        *config_manager = new_manager;
+        drop(config_manager);
+        tracing::info!(
+            target: LOG_DISCOVERY,
+            "Reload complete. Total tools available: {}",
+            available_tools.len()
+        );
+        self.update_tool_catalog(&available_tools).await;
+        drop(available_tools);
+        if !(summary.added.is_empty() && summary.removed.is_empty() && summary.changed.is_empty()) {
+            self.notify_tools_changed();
+        }
 
-                remote.insert(
-                    server_name.clone(),
-                    RemoteServerInfo {
-                        description: server_config.description.clone().unwrap_or_else(|| {
-                            server_config
-                                .name
-                                .clone()
-                                .unwrap_or_else(|| server_name.clone())
-                        }),
-                        endpoint,
-                        tools: tool_infos,
-                    },
-                );
+        self.stop_servers(&summary.removed).await;
+        {
+            let mut discovery_status = self.discovery_status.write().await;
+            let mut discovery_timings = self.discovery_timings.write().await;
+            for name in &summary.removed {
+                discovery_status.remove(name);
+                discovery_timings.remove(name);
             }
         }
 
-        ToolCatalog {
-            last_updated: chrono::Utc::now().to_rfc3339(),
-            local,
-            remote,
+        // Refresh loops follow the new config: started for servers that now set
+        // `discovery_ttl_secs`, stopped for those that no longer do or were removed
+        let ttl_servers: Vec<String> = {
+            let config_manager = self.system_config_manager.read().await;
+            summary
+                .added
+                .iter()
+                .chain(&summary.changed)
+                .filter(|name| {
+                    config_manager
+                        .get_server(name)
+                        .is_some_and(|config| config.discovery_ttl_secs.is_some())
+                })
+                .cloned()
+                .collect()
+        };
+        for name in summary.removed.iter().chain(&summary.changed) {
+            self.stop_server_refresh(name);
         }
+        for name in ttl_servers {
+            self.spawn_server_refresh(name);
+        }
+        Ok(summary)
     }
 
-    /// Infer tool category from name and description
-    fn infer_category(&self, name: &str, description: &str) -> String {
-        let combined = format!("{} {}", name.to_lowercase(), description.to_lowercase());
-
-        if combined.contains("kubernetes") || combined.contains("k8s") || combined.contains("helm")
-        {
-            "kubernetes".to_string()
-        } else if combined.contains("database")
-            || combined.contains("sql")
-            || combined.contains("postgres")
-            || combined.contains("mysql")
-        {
-            "database".to_string()
-        } else if combined.contains("search")
-            || combined.contains("web")
-            || combined.contains("brave")
-        {
-            "search".to_string()
-        } else if combined.contains("memory")
-            || combined.contains("knowledge")
-            || combined.contains("graph")
+    /// Stop the given servers, each draining its in-flight calls first
+    async fn stop_servers(&self, server_names: &[String]) {
+        for result in future::join_all(
+            server_names
+                .iter()
+                .map(|name| self.connection_pool.stop_server(name)),
+        )
+        .await
         {
-            "memory".to_string()
-        } else if combined.contains("git") || combined.contains("version") {
-            "version-control".to_string()
-        } else if combined.contains("file") || combined.contains("directory") {
-            "file-operations".to_string()
-        } else {
-            "general".to_string()
+            if let Err(e) = result {
+                tracing::warn!(target: LOG_DISCOVERY, "Failed to stop server: {}", e);
+            }
         }
     }
 
-    /// Infer use cases from tool name and description
-    fn infer_use_cases(&self, name: &str, description: &str) -> Vec<String> {
-        let mut use_cases = Vec::new();
-        let combined = format!("{} {}", name.to_lowercase(), description.to_lowercase());
+    /// Rediscover one server's tools and swap them into `available_tools` in one go. If
+    /// discovery fails, e.g. because the server is unreachable, its known tools are kept.
+    async fn refresh_server_tools(&self, server_name: &str) -> bool {
+        let (config, prefix) = {
+            let config_manager = self.system_config_manager.read().await;
+            let Some(config) = config_manager.get_server(server_name).cloned() else {
+                return false;
+            };
+            (
+                config,
+                config_manager.get_config().server_prefix(server_name),
+            )
+        };
+        let discovery_timeout = tokio::time::Duration::from_secs(
+            config
+                .discovery_timeout_secs
+                .unwrap_or(DEFAULT_DISCOVERY_TIMEOUT_SECS),
+        );
 
-        // Add use cases based on keywords
-        if combined.contains("list") || combined.contains("get") {
-            use_cases.push("retrieving information".to_string());
-        }
-        if combined.contains("create") || combined.contains("add") {
-            use_cases.push("creating resources".to_string());
-        }
-        if combined.contains("update") || combined.contains("modify") {
-            use_cases.push("updating resources".to_string());
-        }
-        if combined.contains("delete") || combined.contains("remove") {
-            use_cases.push("removing resources".to_string());
+        // Discover without holding any lock calls need
+        let tools = match tokio::time::timeout(
+            discovery_timeout,
+            self.discover_server_tools(server_name, &config),
+        )
+        .await
+        {
+            Ok(Ok(tools)) => tools,
+            Ok(Err(e)) => {
+                tracing::warn!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Refresh failed, keeping the known tools: {}",
+                    e
+                );
+                return false;
+            }
+            Err(_) => {
+                tracing::warn!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Refresh timed out after {}s, keeping the known tools",
+                    discovery_timeout.as_secs()
+                );
+                return false;
+            }
+        };
+
+        self.connection_pool
+            .record_tool_annotations(server_name, &tools);
+        let mut available_tools = self.available_tools.write().await;
+        let before = server_tools_snapshot(&available_tools, server_name);
+        available_tools.retain(|_, tool| tool.server_name != server_name);
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Refreshed tools: {}",
+            tools.len()
+        );
+        for tool in tools {
+            available_tools.insert(prefixed_tool_name(&prefix, &tool.name), tool);
         }
-        if combined.contains("search") {
-            use_cases.push("finding information".to_string());
+        if server_tools_snapshot(&available_tools, server_name) != before {
+            tracing::info!(
+                target: LOG_DISCOVERY,
+                server = %server_name,
+                "Tools changed on refresh"
+            );
+            self.notify_tools_changed();
         }
-        if combined.contains("deploy") {
-            use_cases.push("deployment operations".to_string());
+        true
+    }
+
+    /// Tell clients listening on `GET /mcp` to fetch tools/list again
+    fn notify_tools_changed(&self) {
+        // Nobody may be listening
+        let _ = self.notifications.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/tools/list_changed"
+        }));
+    }
+
+    /// Every `catalog_refresh_interval_secs`, rediscover all servers and republish the
+    /// tool catalog if their tools changed. Does nothing while the interval is unset.
+    fn spawn_catalog_refresh(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let interval = state
+                    .system_config_manager
+                    .read()
+                    .await
+                    .get_config()
+                    .catalog_refresh_interval_secs;
+                let Some(interval) = interval else {
+                    return;
+                };
+                tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))).await;
+                state.refresh_catalog().await;
+            }
+        });
+    }
+
+    /// Rediscover every configured server, as `refresh_server_tools` does, and update the
+    /// tool catalog ConfigMap if any tools changed. Returns whether they did.
+    async fn refresh_catalog(&self) -> bool {
+        let server_names: Vec<String> = self
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .keys()
+            .cloned()
+            .collect();
+        let before = serde_json::to_value(&*self.available_tools.read().await).ok();
+        future::join_all(
+            server_names
+                .iter()
+                .map(|server_name| self.refresh_server_tools(server_name)),
+        )
+        .await;
+
+        let available_tools = self.available_tools.read().await;
+        let changed = serde_json::to_value(&*available_tools).ok() != before;
+        if changed {
+            tracing::info!(
+                target: LOG_DISCOVERY,
+                "Tool catalog changed, {} tools available",
+                available_tools.len()
+            );
+            self.update_tool_catalog(&available_tools).await;
         }
+        changed
+    }
 
-        // If no specific use cases found, add a generic one
-        if use_cases.is_empty() {
-            use_cases.push(description.to_lowercase());
+    /// Periodically take each shared lock in turn and record a heartbeat once all of them
+    /// were acquired. A deadlock or a hung task holding a lock stops the heartbeat.
+    fn spawn_watchdog(&self) -> tokio::task::JoinHandle<()> {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                drop(state.system_config_manager.read().await);
+                drop(state.available_tools.read().await);
+                drop(state.connection_pool.connections.read().await);
+                drop(state.discovery_status.read().await);
+                *state.heartbeat.lock().unwrap() = std::time::Instant::now();
+            }
+        })
+    }
+
+    /// How long ago the watchdog last completed a round
+    fn heartbeat_age(&self) -> std::time::Duration {
+        self.heartbeat.lock().unwrap().elapsed()
+    }
+
+    /// Verify up front that the tool catalog can be written, so a missing RBAC grant is
+    /// reported once at startup instead of as a late failure after discovery.
+    async fn check_catalog_permissions(&self) {
+        use std::sync::atomic::Ordering;
+
+        if !self.catalog_writes_enabled.load(Ordering::Relaxed) {
+            return;
         }
 
-        use_cases
+        let namespace = get_current_namespace();
+        let outcome = match Client::try_default().await {
+            Ok(client) => probe_catalog_permissions(client, &namespace).await,
+            Err(e) => Err(format!(
+                "No Kubernetes client available ({}). The tool catalog ConfigMap will not be written.",
+                e
+            )),
+        };
+
+        match outcome {
+            Ok(()) => tracing::info!(
+                target: LOG_DISCOVERY,
+                "Tool catalog ConfigMap is writable in namespace: {}",
+                namespace
+            ),
+            Err(message) => {
+                tracing::error!(target: LOG_DISCOVERY, "{}", message);
+                self.catalog_writes_enabled.store(false, Ordering::Relaxed);
+            }
+        }
     }
 
-    /// Parse tools from a JSON-RPC tools/list response
-    fn parse_tools_response(
-        &self,
-        server_name: &str,
-        response: Value,
-    ) -> anyhow::Result<Vec<Tool>> {
-        // Parse tools from response
-        if let Some(result) = response.get("result") {
-            if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
-                let parsed_tools: Vec<Tool> = tools_array
-                    .iter()
+    /// Discover and cache available tools from all configured servers
+    async fn discover_all_tools(&self) -> Result<()> {
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            "Starting tool discovery for all configured servers..."
+        );
+        let init_start = std::time::Instant::now();
+
+        // Wait for Docker to be ready BEFORE initializing any servers
+        tracing::info!(
+            target: LOG_DISCOVERY,
+            "Ensuring Docker daemon is ready before starting any servers..."
+        );
+        let docker_start = std::time::Instant::now();
+        if let Err(e) = self.connection_pool.wait_for_docker(60).await {
+            tracing::warn!(target: LOG_DISCOVERY, "Docker readiness check failed: {}", e);
+            tracing::warn!(
+                target: LOG_DISCOVERY,
+                "Continuing anyway, but Docker-based servers may fail"
+            );
+        } else {
+            let docker_elapsed = docker_start.elapsed();
+            tracing::info!(
+                target: LOG_DISCOVERY,
+                "Docker is ready (took {:.2}s)",
+                docker_elapsed.as_secs_f64()
+            );
+        }
+
+        // Get local tool servers from ConfigMap if available
+        let local_servers = self.local_tool_servers().await;
+
+        // Add local servers to system configuration temporarily for discovery
+        if !local_servers.is_empty() {
+            let mut config_manager = self.system_config_manager.write().await;
+            let config = config_manager.get_config_mut();
+            for (name, config_data) in &local_servers {
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    "Adding local server '{}' to configuration",
+                    name
+                );
+                config.servers.insert(name.clone(), config_data.clone());
+            }
+        }
+
+        // Get all configured servers (including the newly added local ones)
+        let all_servers = {
+            let config_manager = self.system_config_manager.read().await;
+            config_manager.get_servers().clone()
+        };
+
+        // Convert to vector for async operations
+        let mut server_list: Vec<_> = all_servers.into_iter().collect();
+        server_list.sort_by_key(|(name, _)| name.clone()); // Deterministic order
+
+        if server_list.is_empty() {
+            tracing::warn!(
+                target: LOG_DISCOVERY,
+                "No servers configured - skipping tool discovery"
+            );
+            return Ok(());
+        }
+
+        let all_tools = self.discover_servers(server_list).await;
+
+        // Store discovered tools
+        let mut available_tools = self.available_tools.write().await;
+        *available_tools = all_tools;
+        let total_elapsed = init_start.elapsed();
+        tracing::info!(
+            target: LOG_DISCOVERY,
+            "Tool discovery complete in {:.2}s. Total tools available: {}",
+            total_elapsed.as_secs_f64(),
+            available_tools.len()
+        );
+
+        self.update_tool_catalog(&available_tools).await;
+
+        Ok(())
+    }
+
+    /// Local tool servers from the ConfigMap, or none outside a cluster
+    async fn local_tool_servers(&self) -> HashMap<String, ServerConfig> {
+        match Client::try_default().await {
+            Ok(client) => self
+                .read_local_tools_config(&client)
+                .await
+                .unwrap_or_default(),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    /// Create or update the tool catalog ConfigMap, unless catalog writes are disabled
+    async fn update_tool_catalog(&self, available_tools: &HashMap<String, Tool>) {
+        if !self
+            .catalog_writes_enabled
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            tracing::debug!(target: LOG_DISCOVERY, "Tool catalog ConfigMap writing is disabled");
+        } else if let Err(e) = self.create_tool_catalog_configmap(available_tools).await {
+            tracing::warn!(
+                target: LOG_DISCOVERY,
+                "Failed to create tool catalog ConfigMap: {}",
+                e
+            );
+            // Don't fail startup or a reload if ConfigMap creation fails
+        }
+    }
+
+    /// Discover the given servers in parallel, returning their tools keyed by prefixed name.
+    /// Docker-command servers take a permit from a smaller pool
+    /// (`docker_discovery_concurrency`) so their `docker run`s don't all start at once.
+    async fn discover_servers(
+        &self,
+        server_list: Vec<(String, ServerConfig)>,
+    ) -> HashMap<String, Tool> {
+        let mut all_tools = HashMap::new();
+        let (docker_concurrency, prefixes) = {
+            let config_manager = self.system_config_manager.read().await;
+            let config = config_manager.get_config();
+            (config.docker_discovery_concurrency, server_prefixes(config))
+        };
+        let docker_permits = Arc::new(tokio::sync::Semaphore::new(docker_concurrency.max(1)));
+
+        // Parallel initialization: spawn tasks for each server to avoid deadlock
+        tracing::info!(target: LOG_DISCOVERY, "Starting parallel server initialization...");
+
+        // Servers start in waves, each after the servers in its `depends_on`
+        let mut configs: HashMap<String, ServerConfig> = server_list.into_iter().collect();
+        let waves = startup_waves(&configs).unwrap_or_else(|e| {
+            tracing::warn!(target: LOG_DISCOVERY, "Ignoring depends_on: {}", e);
+            vec![configs.keys().cloned().collect()]
+        });
+
+        let mut results = Vec::new();
+        for wave in waves {
+            let mut tasks = Vec::new();
+            for server_name in wave {
+                let Some(config) = configs.remove(&server_name) else {
+                    continue;
+                };
+                let unready = self.unready_dependencies(&config).await;
+                if !unready.is_empty() {
+                    tracing::warn!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "Not starting: depends on {}, which is not ready",
+                        unready.join(", ")
+                    );
+                    self.record_discovery_status(
+                        &server_name,
+                        DiscoveryStatus::DependencyNotReady {
+                            dependencies: unready,
+                        },
+                    )
+                    .await;
+                    continue;
+                }
+                let self_clone = self.clone();
+                let docker_permits = docker_permits.clone();
+                tasks.push(tokio::spawn(async move {
+                    let _docker_permit = if is_docker_command(&config.command) {
+                        Some(docker_permits.acquire_owned().await?)
+                    } else {
+                        None
+                    };
+                    let tools = self_clone.discover_and_record(&server_name, &config).await;
+                    Ok::<(String, Vec<Tool>), anyhow::Error>((server_name, tools))
+                }));
+            }
+
+            // Wait for the wave's parallel tasks to complete
+            tracing::debug!(
+                target: LOG_DISCOVERY,
+                "Waiting for all servers to complete initialization..."
+            );
+            results.extend(future::join_all(tasks).await);
+        }
+
+        // Collect all tools from successful initializations
+        for task_result in results {
+            match task_result {
+                Ok(Ok((_server_name, tools))) => {
+                    // Add tools to collection with server prefix
+                    for tool in tools {
+                        // 🔧 CRITICAL: Key by the server's configured prefix and the sanitized
+                        // tool name; the Tool keeps the exact server and original tool name
+                        // that calls are routed by
+                        let prefix = prefixes
+                            .get(&tool.server_name)
+                            .cloned()
+                            .unwrap_or_else(|| sanitize_name(&tool.server_name));
+                        let prefixed_name = prefixed_tool_name(&prefix, &tool.name);
+                        all_tools.insert(prefixed_name, tool);
+                    }
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!(target: LOG_DISCOVERY, "Server task failed: {}", e);
+                }
+                Err(e) => {
+                    tracing::warn!(target: LOG_DISCOVERY, "Task join failed: {}", e);
+                }
+            }
+        }
+
+        all_tools
+    }
+
+    /// Servers in `config.depends_on` whose latest discovery didn't come up ready
+    async fn unready_dependencies(&self, config: &ServerConfig) -> Vec<String> {
+        let discovery_status = self.discovery_status.read().await;
+        config
+            .depends_on
+            .iter()
+            .filter(|dependency| {
+                !matches!(
+                    discovery_status.get(*dependency),
+                    Some(DiscoveryStatus::Ready { .. })
+                )
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Start (for stdio) and discover a single server, recording the outcome in `discovery_status`
+    async fn discover_and_record(&self, server_name: &str, config: &ServerConfig) -> Vec<Tool> {
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Starting parallel initialization at {:?}",
+            chrono::Utc::now().format("%H:%M:%S")
+        );
+
+        let mut timing = DiscoveryTiming::default();
+
+        // For stdio servers, initialize them permanently
+        if config.transport == "stdio" {
+            tracing::debug!(
+                target: LOG_DISCOVERY,
+                server = %server_name,
+                "Initializing stdio server..."
+            );
+
+            let init_start = std::time::Instant::now();
+            let started = self.connection_pool.start_server(server_name).await;
+            timing.init_secs = Some(init_start.elapsed().as_secs_f64());
+            match started {
+                Ok(_) => {
+                    tracing::info!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "Server initialized successfully"
+                    );
+
+                    // Small delay to ensure connection is stored
+                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "Failed to initialize server: {}",
+                        e
+                    );
+                    self.record_discovery_timing(server_name, timing).await;
+                    self.record_discovery_status(
+                        server_name,
+                        DiscoveryStatus::Failed {
+                            reason: e.to_string(),
+                        },
+                    )
+                    .await;
+                    return Vec::new();
+                }
+            }
+        } else {
+            tracing::debug!(
+                target: LOG_DISCOVERY,
+                server = %server_name,
+                "Skipping initialization for {} server",
+                config.transport
+            );
+        }
+
+        // Discover tools with timeout
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Starting tool discovery..."
+        );
+        let discovery_start = std::time::Instant::now();
+        let discovery_timeout = tokio::time::Duration::from_secs(
+            config
+                .discovery_timeout_secs
+                .unwrap_or(DEFAULT_DISCOVERY_TIMEOUT_SECS),
+        );
+
+        let (status, tools) = match tokio::time::timeout(
+            discovery_timeout,
+            self.discover_server_tools(server_name, config),
+        )
+        .await
+        {
+            Ok(Ok(tools)) => {
+                let discovery_duration = discovery_start.elapsed();
+                tracing::info!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Discovered {} tools in {:.2}s",
+                    tools.len(),
+                    discovery_duration.as_secs_f64()
+                );
+
+                // Log individual tools discovered
+                for tool in &tools {
+                    tracing::debug!(target: LOG_DISCOVERY, server = %server_name, "Tool: {}", tool.name);
+                }
+
+                self.connection_pool
+                    .record_tool_annotations(server_name, &tools);
+                let status = DiscoveryStatus::Ready {
+                    tools: tools.len(),
+                    server_version: self.connection_pool.server_version(server_name),
+                };
+                (status, tools)
+            }
+            Ok(Err(e)) => {
+                tracing::warn!(target: LOG_DISCOVERY, server = %server_name, "Tool discovery failed: {}", e);
+                let status = match e.downcast_ref::<BackendUnreachable>() {
+                    Some(BackendUnreachable(reason)) => DiscoveryStatus::Unreachable {
+                        reason: reason.clone(),
+                    },
+                    None => DiscoveryStatus::Failed {
+                        reason: e.to_string(),
+                    },
+                };
+                (status, Vec::new())
+            }
+            Err(_) => {
+                tracing::warn!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Tool discovery timed out after {}s",
+                    discovery_timeout.as_secs()
+                );
+                let status = DiscoveryStatus::TimedOut {
+                    after_secs: discovery_timeout.as_secs(),
+                };
+                (status, Vec::new())
+            }
+        };
+
+        timing.tools_list_secs = Some(discovery_start.elapsed().as_secs_f64());
+        self.record_discovery_timing(server_name, timing).await;
+        self.record_discovery_status(server_name, status).await;
+        tools
+    }
+
+    async fn record_discovery_timing(&self, server_name: &str, timing: DiscoveryTiming) {
+        for (phase, secs) in [
+            ("init", timing.init_secs),
+            ("tools_list", timing.tools_list_secs),
+        ] {
+            if let Some(secs) = secs {
+                self.metrics
+                    .discovery_duration
+                    .with_label_values(&[server_name, phase])
+                    .set(secs);
+            }
+        }
+        self.discovery_timings
+            .write()
+            .await
+            .insert(server_name.to_string(), timing);
+    }
+
+    async fn record_discovery_status(&self, server_name: &str, status: DiscoveryStatus) {
+        let error = match &status {
+            DiscoveryStatus::Ready { .. } => None,
+            DiscoveryStatus::Unreachable { reason } => Some(format!("unreachable: {}", reason)),
+            DiscoveryStatus::TimedOut { after_secs } => {
+                Some(format!("timed out after {}s", after_secs))
+            }
+            DiscoveryStatus::Failed { reason } => Some(reason.clone()),
+            DiscoveryStatus::DependencyNotReady { dependencies } => Some(format!(
+                "waiting for {} to be ready",
+                dependencies.join(", ")
+            )),
+        };
+        if let Some(error) = error {
+            self.record_server_error(server_name, "discovery", error);
+        }
+        self.discovery_status
+            .write()
+            .await
+            .insert(server_name.to_string(), status);
+    }
+
+    /// Remember a failure for a server, dropping its oldest once the buffer is full
+    fn record_server_error(&self, server_name: &str, source: &'static str, message: String) {
+        let mut recent_errors = self.recent_errors.lock().unwrap();
+        let errors = recent_errors.entry(server_name.to_string()).or_default();
+        if errors.len() == RECENT_ERRORS_PER_SERVER {
+            errors.pop_front();
+        }
+        errors.push_back(ServerError {
+            timestamp: Utc::now().to_rfc3339(),
+            source,
+            message,
+        });
+    }
+
+    /// Create or update the tool catalog ConfigMap
+    async fn create_tool_catalog_configmap(
+        &self,
+        tools: &HashMap<String, Tool>,
+    ) -> anyhow::Result<()> {
+        tracing::info!(target: LOG_DISCOVERY, "Creating tool catalog ConfigMap...");
+
+        // Initialize Kubernetes client
+        let client = Client::try_default().await?;
+
+        // Detect current namespace
+        let namespace = get_current_namespace();
+        tracing::debug!(target: LOG_DISCOVERY, "Detected namespace: {}", namespace);
+
+        // Get server configurations for descriptions
+        let servers = {
+            let config_manager = self.system_config_manager.read().await;
+            config_manager.get_servers().clone()
+        };
+
+        // Read local tools configuration from ConfigMap
+        let local_servers = self.read_local_tools_config(&client).await?;
+
+        // Build the tool catalog
+        let catalog = self.build_tool_catalog(tools, &servers, &local_servers);
+
+        // Convert to JSON
+        let catalog_json = serde_json::to_string_pretty(&catalog)?;
+
+        // Create ConfigMap
+        let mut data = BTreeMap::new();
+        data.insert("tool-catalog.json".to_string(), catalog_json);
+
+        let cm = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("toolman-tool-catalog".to_string()),
+                namespace: Some(namespace.clone()),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        };
+
+        let api: Api<ConfigMap> = Api::namespaced(client, &namespace);
+
+        // Use server-side apply which handles both create and update
+        let patch = Patch::Apply(&cm);
+        api.patch(
+            "toolman-tool-catalog",
+            &PatchParams::apply("toolman").force(),
+            &patch,
+        )
+        .await?;
+        tracing::info!(target: LOG_DISCOVERY, "Created/Updated tool catalog ConfigMap");
+
+        Ok(())
+    }
+
+    /// Read local tools configuration from ConfigMap
+    async fn read_local_tools_config(
+        &self,
+        client: &Client,
+    ) -> anyhow::Result<HashMap<String, ServerConfig>> {
+        let namespace = get_current_namespace();
+        let api: Api<ConfigMap> = Api::namespaced(client.clone(), &namespace);
+
+        match api.get("toolman-local-tools").await {
+            Ok(cm) => {
+                tracing::info!(
+                    target: LOG_SERVER,
+                    "Loaded local tools config from namespace: {}",
+                    namespace
+                );
+                if let Some(data) = cm.data {
+                    if let Some(config_json) = data.get("local-tools-config.json") {
+                        let (servers, rejected) = parse_local_tools_config(config_json)?;
+                        for (name, reason) in &rejected {
+                            tracing::error!(
+                                target: LOG_SERVER,
+                                "Rejected local tool server '{}' in ConfigMap toolman-local-tools: {}",
+                                name,
+                                reason
+                            );
+                        }
+                        tracing::info!(
+                            target: LOG_SERVER,
+                            "Loaded {} local tool servers from ConfigMap ({} rejected)",
+                            servers.len(),
+                            rejected.len()
+                        );
+                        return Ok(servers);
+                    }
+                }
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    "Local tools ConfigMap found but no valid data"
+                );
+                Ok(HashMap::new())
+            }
+            Err(e) => {
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    "Local tools ConfigMap not found: {}. No local tools will be included.",
+                    e
+                );
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    /// Build the tool catalog structure
+    fn build_tool_catalog(
+        &self,
+        tools: &HashMap<String, Tool>,
+        servers: &HashMap<String, ServerConfig>,
+        local_servers: &HashMap<String, ServerConfig>,
+    ) -> ToolCatalog {
+        let mut local = HashMap::new();
+        let mut remote = HashMap::new();
+
+        // Group tools by server
+        let mut server_tools: HashMap<String, Vec<&Tool>> = HashMap::new();
+        for tool in tools.values() {
+            server_tools
+                .entry(tool.server_name.clone())
+                .or_default()
+                .push(tool);
+        }
+
+        // Build local server info from discovered tools
+        for (server_name, server_config) in local_servers {
+            if let Some(tools) = server_tools.get(server_name) {
+                let tool_infos: Vec<ToolInfo> = tools
+                    .iter()
+                    .map(|tool| self.tool_info(tool, server_config))
+                    .collect();
+
+                if !tool_infos.is_empty() {
+                    local.insert(
+                        server_name.clone(),
+                        LocalServerInfo {
+                            description: server_config.description.clone().unwrap_or_else(|| {
+                                server_config
+                                    .name
+                                    .clone()
+                                    .unwrap_or_else(|| server_name.clone())
+                            }),
+                            command: server_config.command.clone(),
+                            args: server_config.args.clone(),
+                            working_directory: server_config
+                                .working_directory
+                                .clone()
+                                .unwrap_or_else(|| "project_root".to_string()),
+                            server_version: self.connection_pool.server_version(server_name),
+                            tools: tool_infos,
+                        },
+                    );
+                }
+            }
+        }
+
+        // Build remote server info
+        for (server_name, server_config) in servers {
+            // Skip if this is also in local servers (avoid duplicates)
+            if local_servers.contains_key(server_name) {
+                continue;
+            }
+
+            if let Some(tools) = server_tools.get(server_name) {
+                let tool_infos: Vec<ToolInfo> = tools
+                    .iter()
+                    .map(|tool| self.tool_info(tool, server_config))
+                    .collect();
+
+                let endpoint = server_config.url.clone().unwrap_or_else(|| {
+                    if server_config.transport == "stdio" {
+                        "stdio".to_string()
+                    } else {
+                        "unknown".to_string()
+                    }
+                });
+
+                remote.insert(
+                    server_name.clone(),
+                    RemoteServerInfo {
+                        description: server_config.description.clone().unwrap_or_else(|| {
+                            server_config
+                                .name
+                                .clone()
+                                .unwrap_or_else(|| server_name.clone())
+                        }),
+                        endpoint,
+                        server_version: self.connection_pool.server_version(server_name),
+                        tools: tool_infos,
+                    },
+                );
+            }
+        }
+
+        ToolCatalog {
+            last_updated: chrono::Utc::now().to_rfc3339(),
+            local,
+            remote,
+        }
+    }
+
+    /// Tools ranked by how well they fit a task described in plain words, best first, at
+    /// most `limit` of them. Tools that match none of the task's words are left out.
+    async fn suggest_tools(&self, query: &str, limit: usize) -> Vec<Value> {
+        let terms = suggestion_terms(query);
+        let available_tools = self.available_tools.read().await;
+        let config_manager = self.system_config_manager.read().await;
+        let mut suggestions: Vec<(f64, &String, ToolInfo, String)> = available_tools
+            .iter()
+            .filter_map(|(prefixed_name, tool)| {
+                let server_config = config_manager.get_server(&tool.server_name)?;
+                let info = self.tool_info(tool, server_config);
+                let (score, reason) = suggestion_score(&terms, prefixed_name, &info);
+                (score > 0.0).then_some((score, prefixed_name, info, reason))
+            })
+            .collect();
+        suggestions.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        suggestions
+            .into_iter()
+            .take(limit)
+            .map(|(score, prefixed_name, info, reason)| {
+                json!({
+                    "tool": prefixed_name,
+                    "score": (score * 100.0).round() / 100.0,
+                    "category": info.category,
+                    "reason": reason,
+                })
+            })
+            .collect()
+    }
+
+    /// Catalog entry for a tool. Category and use cases come from the tool's config when
+    /// set there, and are inferred from its name and description otherwise.
+    fn tool_info(&self, tool: &Tool, server_config: &ServerConfig) -> ToolInfo {
+        let tool_config = server_config.tool_config(&tool.original_tool_name);
+        ToolInfo {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            category: tool_config
+                .and_then(|t| t.category.clone())
+                .unwrap_or_else(|| self.infer_category(&tool.name, &tool.description)),
+            use_cases: tool_config
+                .and_then(|t| t.use_cases.clone())
+                .unwrap_or_else(|| self.infer_use_cases(&tool.name, &tool.description)),
+            input_schema: Some(tool.input_schema.clone()),
+            labels: tool.labels.clone(),
+        }
+    }
+
+    /// Infer tool category from name and description
+    fn infer_category(&self, name: &str, description: &str) -> String {
+        let combined = format!("{} {}", name.to_lowercase(), description.to_lowercase());
+
+        if combined.contains("kubernetes") || combined.contains("k8s") || combined.contains("helm")
+        {
+            "kubernetes".to_string()
+        } else if combined.contains("database")
+            || combined.contains("sql")
+            || combined.contains("postgres")
+            || combined.contains("mysql")
+        {
+            "database".to_string()
+        } else if combined.contains("search")
+            || combined.contains("web")
+            || combined.contains("brave")
+        {
+            "search".to_string()
+        } else if combined.contains("memory")
+            || combined.contains("knowledge")
+            || combined.contains("graph")
+        {
+            "memory".to_string()
+        } else if combined.contains("git") || combined.contains("version") {
+            "version-control".to_string()
+        } else if combined.contains("file") || combined.contains("directory") {
+            "file-operations".to_string()
+        } else {
+            "general".to_string()
+        }
+    }
+
+    /// Infer use cases from tool name and description
+    fn infer_use_cases(&self, name: &str, description: &str) -> Vec<String> {
+        let mut use_cases = Vec::new();
+        let combined = format!("{} {}", name.to_lowercase(), description.to_lowercase());
+
+        // Add use cases based on keywords
+        if combined.contains("list") || combined.contains("get") {
+            use_cases.push("retrieving information".to_string());
+        }
+        if combined.contains("create") || combined.contains("add") {
+            use_cases.push("creating resources".to_string());
+        }
+        if combined.contains("update") || combined.contains("modify") {
+            use_cases.push("updating resources".to_string());
+        }
+        if combined.contains("delete") || combined.contains("remove") {
+            use_cases.push("removing resources".to_string());
+        }
+        if combined.contains("search") {
+            use_cases.push("finding information".to_string());
+        }
+        if combined.contains("deploy") {
+            use_cases.push("deployment operations".to_string());
+        }
+
+        // If no specific use cases found, add a generic one
+        if use_cases.is_empty() {
+            use_cases.push(description.to_lowercase());
+        }
+
+        use_cases
+    }
+
+    /// Parse tools from a JSON-RPC tools/list response
+    fn parse_tools_response(
+        &self,
+        server_name: &str,
+        response: Value,
+    ) -> anyhow::Result<Vec<Tool>> {
+        // Parse tools from response
+        if let Some(result) = response.get("result") {
+            if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
+                let parsed_tools: Vec<Tool> = tools_array
+                    .iter()
+                    .filter_map(|tool| {
+                        if let (Some(name), Some(description)) = (
+                            tool.get("name").and_then(|n| n.as_str()),
+                            tool.get("description").and_then(|d| d.as_str()),
+                        ) {
+                            Some(Tool {
+                                name: name.to_string(),
+                                description: description.to_string(),
+                                // Missing schemas are filled in by `discover_server_tools`
+                                input_schema: tool.get("inputSchema").cloned().unwrap_or_default(),
+                                server_name: server_name.to_string(),
+                                // Preserve the original tool name for accurate forwarding
+                                original_tool_name: name.to_string(),
+                                annotations: tool.get("annotations").cloned(),
+                                labels: Vec::new(),
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+
+                let parsed_tools = dedupe_tools(server_name, parsed_tools);
+                tracing::info!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Discovered {} tools via existing connection",
+                    parsed_tools.len()
+                );
+
+                return Ok(parsed_tools);
+            }
+        }
+
+        // If no tools found or parsing failed
+        tracing::warn!(target: LOG_DISCOVERY, server = %server_name, "No tools found in response");
+        Ok(Vec::new())
+    }
+
+    // Discover tools from a single server (stdio servers are started and kept in the pool)
+    async fn discover_server_tools(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+    ) -> anyhow::Result<Vec<Tool>> {
+        let span = tracing::info_span!(
+            target: LOG_DISCOVERY,
+            "discover",
+            server = %server_name,
+            transport = %config.transport
+        );
+        // Discovery is idempotent, so it is retried after transport failures like a
+        // `retryable` tool
+        let (retries, backoff, missing_input_schema) = {
+            let config_manager = self.system_config_manager.read().await;
+            let proxy = config_manager.get_config();
+            (
+                proxy.tool_call_retries,
+                tokio::time::Duration::from_millis(proxy.tool_call_retry_backoff_ms),
+                proxy.missing_input_schema.schema(),
+            )
+        };
+        let discover = retry_transport_failures(server_name, "Discovery", retries, backoff, || {
+            self.discover_server_tools_in_span(server_name, config)
+        });
+        let mut tools = tracing::Instrument::instrument(discover, span).await.0?;
+        for tool in &mut tools {
+            if tool.input_schema.is_null() {
+                tool.input_schema = missing_input_schema.clone();
+            }
+            tool.labels = config.labels_for(&tool.original_tool_name);
+        }
+        Ok(tools)
+    }
+
+    async fn discover_server_tools_in_span(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+    ) -> anyhow::Result<Vec<Tool>> {
+        let start_time = std::time::Instant::now();
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Starting tool discovery at {:?}",
+            chrono::Utc::now().format("%H:%M:%S")
+        );
+
+        // WebSocket servers are discovered over the connection later tool calls reuse
+        if config.transport == "websocket" {
+            let init_request = self.connection_pool.handshake_request(config, None).await;
+            let tools = self
+                .retry_empty_tools_list(server_name, || async {
+                    let response = self
+                        .connection_pool
+                        .websocket_request(
+                            server_name,
+                            config,
+                            init_request.clone(),
+                            "tools/list",
+                            json!({}),
+                            None,
+                        )
+                        .await
+                        .map_err(transport_failure)?;
+                    self.parse_tools_response(server_name, response)
+                })
+                .await?;
+            tracing::info!(
+                target: LOG_DISCOVERY,
+                server = %server_name,
+                "Discovered {} tools via WebSocket (elapsed: {:?})",
+                tools.len(),
+                start_time.elapsed()
+            );
+            return Ok(tools);
+        }
+
+        // Handle HTTP and SSE transports
+        if config.transport == "http" || config.transport == "sse" {
+            if let Some(url) = &config.url {
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Discovering tools from HTTP server: {}",
+                    url
+                );
+
+                // Fail fast on dead remotes instead of waiting out full request timeouts
+                let reachability_timeout_ms = self
+                    .system_config_manager
+                    .read()
+                    .await
+                    .get_config()
+                    .reachability_timeout_ms;
+                if reachability_timeout_ms > 0 {
+                    check_reachable(
+                        url,
+                        tokio::time::Duration::from_millis(reachability_timeout_ms),
+                    )
+                    .await
+                    .map_err(BackendUnreachable)?;
+                }
+
+                let client = http_client_for(server_name, config);
+
+                // Use transport type to determine communication method
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "URL: {}, transport: {}",
+                    url,
+                    config.transport
+                );
+                let (message_url, session_id) = if config.transport == "sse" {
+                    tracing::debug!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "Detected SSE endpoint, starting SSE handshake"
+                    );
+                    let sse_response = client
+                        .get(url)
+                        .header("Accept", "text/event-stream")
+                        .send()
+                        .await;
+
+                    if let Ok(response) = sse_response {
+                        let content_type = response
+                            .headers()
+                            .get("content-type")
+                            .and_then(|v| v.to_str().ok())
+                            .unwrap_or("");
+
+                        if content_type.contains("text/event-stream") {
+                            // This is an SSE endpoint, parse the session info
+                            // For SSE, we only need to read the initial chunk with session ID
+                            let mut body = response.bytes_stream();
+                            use futures::StreamExt;
+
+                            let first_chunk = match tokio::time::timeout(
+                                tokio::time::Duration::from_secs(10),
+                                body.next(),
+                            )
+                            .await
+                            {
+                                Ok(Some(Ok(chunk))) => String::from_utf8_lossy(&chunk).to_string(),
+                                Ok(Some(Err(e))) => {
+                                    return Err(anyhow::anyhow!("Failed to read SSE chunk: {}", e))
+                                }
+                                Ok(None) => {
+                                    return Err(anyhow::anyhow!(
+                                        "No data received from SSE endpoint"
+                                    ))
+                                }
+                                Err(_) => {
+                                    return Err(anyhow::anyhow!(
+                                        "Timeout waiting for SSE session data (waited 10s)"
+                                    ))
+                                }
+                            };
+
+                            // Parse SSE format: "event: endpoint\ndata: /message?sessionId=xxx"
+                            let session_id = if let Some(data_line) =
+                                first_chunk.lines().find(|line| line.starts_with("data: "))
+                            {
+                                let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
+                                if let Some(session_param) =
+                                    endpoint_path.split("sessionId=").nth(1)
+                                {
+                                    session_param.to_string()
+                                } else {
+                                    return Err(anyhow::anyhow!(
+                                        "No sessionId found in SSE response"
+                                    ));
+                                }
+                            } else {
+                                return Err(anyhow::anyhow!("No data line found in SSE response"));
+                            };
+
+                            // Construct the message URL
+                            let base_url = url.trim_end_matches("/sse").trim_end_matches('/');
+                            let message_url =
+                                format!("{}/message?sessionId={}", base_url, session_id);
+                            tracing::debug!(
+                                target: LOG_DISCOVERY,
+                                server = %server_name,
+                                "SSE session ID: {}",
+                                session_id
+                            );
+                            tracing::debug!(
+                                target: LOG_DISCOVERY,
+                                server = %server_name,
+                                "SSE message URL: {}",
+                                message_url
+                            );
+                            (message_url, session_id)
+                        } else {
+                            // Not SSE, use original direct HTTP approach
+                            (url.to_string(), String::new())
+                        }
+                    } else {
+                        // Failed to GET, try original direct HTTP approach
+                        (url.to_string(), String::new())
+                    }
+                } else {
+                    // URL doesn't end with /sse, use direct HTTP approach
+                    tracing::debug!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "Using direct HTTP approach"
+                    );
+                    (url.to_string(), String::new())
+                };
+
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Final message_url: {}",
+                    message_url
+                );
+
+                // Handle SSE vs HTTP endpoints differently
+                if config.transport == "sse" {
+                    tracing::debug!(
+                        target: LOG_DISCOVERY,
+                        server = %server_name,
+                        "SSE endpoint detected - using SSE transport"
+                    );
+
+                    // For SSE endpoints, we need to handle the full MCP handshake
+                    // with responses coming through the SSE stream
+                    return discover_tools_via_sse(
+                        &client,
+                        server_name,
+                        url,
+                        &session_id,
+                        self.connection_pool.handshake_request(config, None).await,
+                        config.request_id_type,
+                        SseStreamSettings::from_config(
+                            self.system_config_manager.read().await.get_config(),
+                        ),
+                    )
+                    .await;
+                }
+
+                // Non-SSE HTTP endpoint handling
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "HTTP endpoint - sending initialize first"
+                );
+
+                // Initialize the server for non-SSE endpoints, unless a session is open
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Ensuring an initialized session with: {}",
+                    message_url
+                );
+                self.connection_pool
+                    .ensure_http_session(server_name, config, &client, &message_url, None)
+                    .await
+                    .map_err(transport_failure)?;
+
+                // Get tools list, asking again if the server isn't ready with them yet
+                return self
+                    .retry_empty_tools_list(server_name, || {
+                        self.list_http_tools(server_name, config, &client, &message_url, start_time)
+                    })
+                    .await;
+            } else {
+                return Err(anyhow::anyhow!("HTTP transport requires 'url' field"));
+            }
+        }
+
+        // stdio servers are discovered over their pooled connection, started here if needed.
+        // Discovery never spawns a process of its own, so it can't race a start into two.
+        self.connection_pool.start_server(server_name).await?;
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Using pooled stdio connection for tool discovery"
+        );
+
+        let tools = self
+            .retry_empty_tools_list(server_name, || async {
+                let response = self
+                    .connection_pool
+                    .stdio_request(
+                        server_name,
+                        "tools/list",
+                        json!({}),
+                        None,
+                        std::time::Duration::from_secs(DEFAULT_STDIO_CALL_TIMEOUT_SECS),
+                    )
+                    .await
+                    .map_err(|e| {
+                        let message = format!("tools/list request failed: {}", e);
+                        transport_failure(e).context(message)
+                    })?;
+                self.parse_tools_response(server_name, response)
+            })
+            .await?;
+        tracing::info!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Discovered {} tools via stdio (elapsed: {:?})",
+            tools.len(),
+            start_time.elapsed()
+        );
+        Ok(tools)
+    }
+
+    /// Send `tools/list` to a streamable HTTP server in its current session
+    async fn list_http_tools(
+        &self,
+        server_name: &str,
+        config: &ServerConfig,
+        client: &reqwest::Client,
+        message_url: &str,
+        start_time: std::time::Instant,
+    ) -> anyhow::Result<Vec<Tool>> {
+        let tools_request = json!({
+            "jsonrpc": "2.0",
+            "id": config.request_id_type.id(2),
+            "method": "tools/list",
+            "params": {}
+        });
+
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Sending tools/list request to: {}",
+            message_url
+        );
+        let tools_response = self
+            .connection_pool
+            .http_post(
+                server_name,
+                config,
+                client,
+                message_url,
+                &tools_request,
+                None,
+            )
+            .await
+            .map_err(|e| transport_failure(anyhow::anyhow!("HTTP tools request failed: {}", e)))?;
+
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Tools response status: {}",
+            tools_response.status()
+        );
+        if tools_response.status().is_server_error() {
+            return Err(transport_failure(anyhow::anyhow!(
+                "HTTP tools request failed with status {}",
+                tools_response.status()
+            )));
+        }
+        let response_text = tools_response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Raw tools response: {}",
+            response_text
+        );
+
+        // Plain JSON or SSE-framed JSON (direct HTTP endpoints like Solana answer with SSE)
+        let response_json = parse_http_jsonrpc_body(&response_text).map_err(|e| {
+            transport_failure(anyhow::anyhow!("Failed to parse tools response: {}", e))
+        })?;
+
+        tracing::debug!(
+            target: LOG_DISCOVERY,
+            server = %server_name,
+            "Tools response JSON: {}",
+            response_json
+        );
+
+        // Parse tools from response
+        if let Some(result) = response_json.get("result") {
+            if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
+                let parsed_tools: Vec<Tool> = tools_array
+                    .iter()
                     .filter_map(|tool| {
                         if let (Some(name), Some(description)) = (
                             tool.get("name").and_then(|n| n.as_str()),
@@ -1368,2143 +5154,8352 @@ impl BridgeState {
                             Some(Tool {
                                 name: name.to_string(),
                                 description: description.to_string(),
-                                input_schema: tool
-                                    .get("inputSchema")
-                                    .cloned()
-                                    .unwrap_or_else(|| json!({})),
+                                input_schema: tool.get("inputSchema").cloned().unwrap_or_default(),
                                 server_name: server_name.to_string(),
                                 // Preserve the original tool name for accurate forwarding
                                 original_tool_name: name.to_string(),
+                                annotations: tool.get("annotations").cloned(),
+                                labels: Vec::new(),
                             })
                         } else {
                             None
                         }
-                    })
-                    .collect();
+                    })
+                    .collect();
+
+                let parsed_tools = dedupe_tools(server_name, parsed_tools);
+                tracing::info!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "Discovered {} tools via HTTP (elapsed: {:?})",
+                    parsed_tools.len(),
+                    start_time.elapsed()
+                );
+
+                return Ok(parsed_tools);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Run `list` (a server's `tools/list`), asking again a couple of times if the server
+    /// declared the tools capability but listed none, in case it wasn't ready yet
+    async fn retry_empty_tools_list<F, Fut>(
+        &self,
+        server_name: &str,
+        mut list: F,
+    ) -> anyhow::Result<Vec<Tool>>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = anyhow::Result<Vec<Tool>>>,
+    {
+        let mut tools = list().await?;
+        for attempt in 1..=EMPTY_TOOLS_LIST_RETRIES {
+            if !tools.is_empty() || !self.connection_pool.declares_tools(server_name) {
+                break;
+            }
+            tracing::info!(
+                target: LOG_DISCOVERY,
+                server = %server_name,
+                "Server declared tools but listed none; asking again (retry {}/{})",
+                attempt,
+                EMPTY_TOOLS_LIST_RETRIES
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(
+                EMPTY_TOOLS_LIST_RETRY_DELAY_MS,
+            ))
+            .await;
+            tools = list().await?;
+        }
+        Ok(tools)
+    }
+}
+
+/// Discover tools from SSE server with bidirectional transport
+async fn discover_tools_via_sse(
+    client: &reqwest::Client,
+    server_name: &str,
+    sse_url: &str,
+    _existing_session_id: &str, // Not used, we'll get a fresh one
+    init_request: Value,
+    id_type: RequestIdType,
+    stream_settings: SseStreamSettings,
+) -> anyhow::Result<Vec<Tool>> {
+    use futures::StreamExt;
+    use tokio::time::{timeout, Duration};
+
+    tracing::info!(target: LOG_DISCOVERY, server = %server_name, "Starting SSE tool discovery");
+
+    // Step 1: Open SSE connection and get session ID
+    let sse_response = client
+        .get(sse_url)
+        .header("Accept", "text/event-stream")
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to connect to SSE endpoint: {}", e))?;
+
+    let mut body = sse_response.bytes_stream();
+
+    // Wait for session data from SSE stream, reading multiple chunks if needed
+    let mut accumulated_data = String::new();
+    let session_id = loop {
+        match timeout(Duration::from_secs(10), body.next()).await {
+            Ok(Some(Ok(chunk))) => {
+                let chunk_str = String::from_utf8_lossy(&chunk);
+                accumulated_data.push_str(&chunk_str);
+
+                tracing::debug!(
+                    target: LOG_DISCOVERY,
+                    server = %server_name,
+                    "SSE handshake data: {}",
+                    chunk_str.trim()
+                );
+
+                // Try to parse session ID from accumulated data
+                if let Some(data_line) = accumulated_data
+                    .lines()
+                    .find(|line| line.starts_with("data: "))
+                {
+                    let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
+                    if let Some(session_param) = endpoint_path.split("sessionId=").nth(1) {
+                        break session_param.to_string();
+                    }
+                }
+
+                // If we have an "event: endpoint" but no data line yet, continue reading
+                if accumulated_data.contains("event: endpoint")
+                    && !accumulated_data
+                        .lines()
+                        .any(|line| line.starts_with("data: "))
+                {
+                    continue;
+                }
+
+                // If we've accumulated data but can't find session ID, something's wrong
+                if accumulated_data.len() > 1000 {
+                    return Err(anyhow::anyhow!(
+                        "Could not find sessionId in SSE data after reading {} chars",
+                        accumulated_data.len()
+                    ));
+                }
+            }
+            Ok(Some(Err(e))) => return Err(anyhow::anyhow!("SSE stream error: {}", e)),
+            Ok(None) => return Err(anyhow::anyhow!("SSE stream ended unexpectedly")),
+            Err(_) => return Err(anyhow::anyhow!("Timeout waiting for SSE session data")),
+        }
+    };
+
+    tracing::info!(
+        target: LOG_DISCOVERY,
+        server = %server_name,
+        "Got SSE session ID: {}",
+        session_id
+    );
+
+    // Step 2: Prepare message endpoint
+    let base_url = sse_url.trim_end_matches("/sse").trim_end_matches('/');
+    let message_url = format!("{}/message?sessionId={}", base_url, session_id);
+
+    // Step 3: Start listening for responses in background task
+    let responses = SseResponses::new(server_name);
+
+    // Spawn SSE response listener
+    tokio::spawn(forward_sse_messages(
+        body,
+        server_name.to_string(),
+        stream_settings,
+        responses.clone(),
+    ));
+
+    // Step 4: Send MCP handshake sequence
+
+    // 4a. Send initialize request
+    tracing::debug!(target: LOG_DISCOVERY, server = %server_name, "Sending initialize request");
+
+    let init_id = init_request["id"].clone();
+    let init_response = responses.expect(&init_id);
+    client
+        .post(&message_url)
+        .header("Content-Type", "application/json")
+        .json(&init_request)
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send initialize: {}", e))?;
+
+    // Wait for initialize response
+    responses
+        .wait(
+            &init_id,
+            init_response,
+            Duration::from_secs(10),
+            "initialize",
+        )
+        .await?;
+
+    tracing::debug!(target: LOG_DISCOVERY, server = %server_name, "Initialize response received");
+
+    // 4b. Send initialized notification
+    tracing::debug!(
+        target: LOG_DISCOVERY,
+        server = %server_name,
+        "Sending initialized notification"
+    );
+    let initialized_notification = json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/initialized"
+    });
+
+    client
+        .post(&message_url)
+        .header("Content-Type", "application/json")
+        .json(&initialized_notification)
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send initialized: {}", e))?;
+
+    // 4c. Send tools/list request
+    tracing::debug!(target: LOG_DISCOVERY, server = %server_name, "Sending tools/list request");
+    let tools_request = json!({
+        "jsonrpc": "2.0",
+        "id": id_type.id(2),
+        "method": "tools/list",
+        "params": {}
+    });
+    let tools_response = responses.expect(&tools_request["id"]);
+
+    client
+        .post(&message_url)
+        .header("Content-Type", "application/json")
+        .json(&tools_request)
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send tools/list: {}", e))?;
+
+    // Wait for tools/list response
+    let tools_response = responses
+        .wait(
+            &tools_request["id"],
+            tools_response,
+            Duration::from_secs(10),
+            "tools/list",
+        )
+        .await?;
+
+    tracing::debug!(target: LOG_DISCOVERY, server = %server_name, "tools/list response received");
+
+    // Step 5: Parse tools from response
+    let tools: Vec<Tool> = if let Some(result) = tools_response.get("result") {
+        if let Some(tools_array) = result.get("tools") {
+            if let Ok(tools_vec) =
+                serde_json::from_value::<Vec<serde_json::Value>>(tools_array.clone())
+            {
+                tools_vec
+                    .into_iter()
+                    .filter_map(|tool| {
+                        let name = tool.get("name")?.as_str()?.to_string();
+                        let description = tool
+                            .get("description")
+                            .and_then(|d| d.as_str())
+                            .unwrap_or("")
+                            .to_string();
+                        let input_schema = tool.get("inputSchema").cloned().unwrap_or_default();
+
+                        Some(Tool {
+                            name: name.clone(),
+                            description,
+                            input_schema,
+                            server_name: server_name.to_string(),
+                            // Preserve the original tool name for accurate forwarding
+                            original_tool_name: name,
+                            annotations: tool.get("annotations").cloned(),
+                            labels: Vec::new(),
+                        })
+                    })
+                    .collect()
+            } else {
+                return Err(anyhow::anyhow!("Failed to parse tools array"));
+            }
+        } else {
+            return Err(anyhow::anyhow!("No tools in response"));
+        }
+    } else {
+        return Err(anyhow::anyhow!("No result in tools response"));
+    };
+    let tools = dedupe_tools(server_name, tools);
+
+    tracing::info!(
+        target: LOG_DISCOVERY,
+        server = %server_name,
+        "Discovered {} tools via SSE",
+        tools.len()
+    );
+    Ok(tools)
+}
+
+impl BridgeState {
+    async fn handle_jsonrpc_request(
+        &self,
+        request: JsonRpcRequest,
+        headers: Option<&axum::http::HeaderMap>,
+    ) -> JsonRpcResponse {
+        tracing::debug!(target: LOG_SERVER, method = %request.method, "handle_jsonrpc_request");
+        match request.method.as_str() {
+            "initialize" => {
+                // Remember who is connecting in this session, for `client_info_passthrough`
+                let session_id = request_session_id(headers).to_string();
+                match request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("clientInfo"))
+                    .and_then(|info| serde_json::from_value::<ClientInfo>(info.clone()).ok())
+                {
+                    Some(client_info) => {
+                        self.client_infos
+                            .lock()
+                            .unwrap()
+                            .insert(session_id.clone(), client_info);
+                    }
+                    None => {
+                        self.client_infos.lock().unwrap().remove(&session_id);
+                    }
+                }
+                // Clients that only render text content ask for everything else as summaries
+                let text_only_content = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.pointer("/capabilities/experimental/textOnlyContent"))
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                if text_only_content {
+                    self.text_only_sessions.lock().unwrap().insert(session_id);
+                } else {
+                    self.text_only_sessions.lock().unwrap().remove(&session_id);
+                }
+
+                let capabilities = self.proxy_capabilities().await;
+
+                // Standard MCP initialization - simplified, no session complexity
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(json!({
+                        "protocolVersion": "2024-11-05",
+                        "capabilities": {
+                            "tools": {
+                                "listChanged": true
+                            }
+                        },
+                        "serverInfo": {
+                            "name": "toolman",
+                            "version": "1.0.0",
+                            "features": enabled_features(&capabilities),
+                            "tool_naming": capabilities["tool_naming"]
+                        }
+                    })),
+                    error: None,
+                }
+            }
+            "tools/list" => {
+                tracing::debug!(target: LOG_SERVER, "tools/list handler called");
+
+                // Tools the user enabled for this project, if they have a saved context
+                let context = self.request_context(headers).await;
+
+                // Get available tools, narrowed to the enabled set if one was posted
+                let available_tools = self.available_tools.read().await;
+                let enabled_tools = self.enabled_tools.read().await;
+                let config_manager = self.system_config_manager.read().await;
+                let include_tool_meta = config_manager.get_config().include_tool_meta;
+                let hide_builtin_tools = config_manager.get_config().hide_builtin_tools;
+                // Optional per-request scope: only list tools of these servers
+                let server_filter: Option<HashSet<&str>> = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("servers"))
+                    .and_then(|servers| servers.as_array())
+                    .map(|servers| servers.iter().filter_map(|s| s.as_str()).collect());
+                // Optional per-request scope: only list tools carrying all of these labels
+                let label_filter = request
+                    .params
+                    .as_ref()
+                    .and_then(|params| params.get("labels"))
+                    .map(requested_labels);
+                let mut all_tools = Vec::new();
+
+                tracing::debug!(
+                    target: LOG_SERVER,
+                    "Returning {} tools from all servers",
+                    available_tools.len()
+                );
+
+                // Add built-in toolman tools first (they stay callable even when hidden)
+                if !hide_builtin_tools {
+                    all_tools.push(json!({
+                        "name": "toolman_list_available_tools",
+                        "description": "Get available tools and MCP client config file structure for automated config generation.",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {}
+                        }
+                    }));
+                    all_tools.push(json!({
+                        "name": "toolman_preview_call",
+                        "description": "Show the arguments a tool call would be forwarded with, after defaults and injected parameters, without calling the tool.",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {
+                                "tool": { "type": "string", "description": "Prefixed tool name, e.g. memory_read_graph" },
+                                "arguments": { "type": "object", "description": "Arguments as the agent would send them" },
+                                "working_dir": { "type": "string", "description": "Working directory to resolve against (defaults to the request's X-Working-Directory)" }
+                            },
+                            "required": ["tool"]
+                        }
+                    }));
+                    all_tools.push(json!({
+                        "name": "toolman_context",
+                        "description": "Show the working directory Toolman resolved for this request, the project directory, and the context keys it injects into tool calls.",
+                        "inputSchema": {
+                            "type": "object",
+                            "properties": {}
+                        }
+                    }));
+                }
+
+                // Add tools from servers
+                for (prefixed_tool_name, tool) in available_tools.iter() {
+                    if let Some(enabled) = enabled_tools.as_ref() {
+                        if !enabled.contains(prefixed_tool_name) {
+                            continue;
+                        }
+                    }
+                    if let Some(servers) = &server_filter {
+                        if !servers.contains(tool.server_name.as_str()) {
+                            continue;
+                        }
+                    }
+                    if let Some(labels) = &label_filter {
+                        if !labels.iter().all(|label| tool.labels.contains(label)) {
+                            continue;
+                        }
+                    }
+                    if let Some(context) = &context {
+                        if context.is_tool_enabled(&tool.server_name, &tool.original_tool_name)
+                            != Some(true)
+                        {
+                            continue;
+                        }
+                    }
+                    tracing::debug!(target: LOG_SERVER, "Including tool: {}", prefixed_tool_name);
+                    let mut entry = json!({
+                        "name": prefixed_tool_name,
+                        "description": tool.description,
+                        "inputSchema": tool.input_schema
+                    });
+                    if include_tool_meta {
+                        let transport = config_manager
+                            .get_server(&tool.server_name)
+                            .map(|c| c.transport.as_str())
+                            .unwrap_or("stdio");
+                        entry["_meta"] = json!({
+                            "toolman": {
+                                "server": tool.server_name,
+                                "transport": transport
+                            }
+                        });
+                    }
+                    all_tools.push(entry);
+                }
+
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(json!({ "tools": all_tools })),
+                    error: None,
+                }
+            }
+            "notifications/cancelled" => {
+                // Abort the client's pending tools/call; its handler forwards the cancellation
+                let params = request.params.unwrap_or_default();
+                if let Some(request_id) = params.get("requestId") {
+                    let pending = self
+                        .pending_calls
+                        .lock()
+                        .unwrap()
+                        .remove(&pending_call_key(headers, request_id));
+                    if let Some(call) = pending {
+                        let reason = params
+                            .get("reason")
+                            .and_then(|r| r.as_str())
+                            .map(str::to_string);
+                        let _ = call.cancel.send(reason);
+                    }
+                }
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: Some(json!({})),
+                    error: None,
+                }
+            }
+            "tools/call" => {
+                // Follow-up call for the next page of an earlier paginated result
+                if let Some(cursor) = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("cursor"))
+                    .and_then(|c| c.as_str())
+                {
+                    return match self.next_result_page(cursor) {
+                        Some(page) => JsonRpcResponse {
+                            jsonrpc: "2.0".to_string(),
+                            id: request.id,
+                            result: Some(page),
+                            error: None,
+                        },
+                        None => JsonRpcResponse {
+                            jsonrpc: "2.0".to_string(),
+                            id: request.id,
+                            result: None,
+                            error: Some(JsonRpcError {
+                                code: -32602,
+                                message: format!("Unknown or expired cursor: {}", cursor),
+                                data: None,
+                            }),
+                        },
+                    };
+                }
+
+                if let Some(params) = request.params {
+                    if let Some(tool_name) = params.get("name").and_then(|v| v.as_str()) {
+                        let result = {
+                            // Handle built-in toolman tools first
+                            if tool_name == "toolman_list_available_tools" {
+                                // Generate config structure for agents
+                                let available_tools = self.available_tools.read().await;
+                                let config_manager = self.system_config_manager.read().await;
+                                let _servers = config_manager.get_servers();
+
+                                let mut tools_by_server = std::collections::HashMap::new();
+                                for tool in available_tools.values() {
+                                    tools_by_server
+                                        .entry(tool.server_name.clone())
+                                        .or_insert_with(Vec::new)
+                                        .push(json!({
+                                            "name": sanitize_name(&tool.name),
+                                            "description": tool.description
+                                        }));
+                                }
+
+                                let config_structure = json!({
+                                    "toolman_proxy_url": "http://toolman.mcp.svc.cluster.local:3000/mcp",
+                                    "available_tools_by_server": tools_by_server,
+                                    "config_instructions": "Use 'toolman_proxy_url' as the MCP server URL. All tools are prefixed with 'servername_toolname' format."
+                                });
+
+                                json!({
+                                    "content": [{
+                                        "type": "text",
+                                        "text": serde_json::to_string_pretty(&config_structure).unwrap_or_else(|_| "Error formatting config".to_string())
+                                    }]
+                                })
+                            } else if tool_name == "toolman_preview_call" {
+                                self.preview_call(
+                                    params.get("arguments").unwrap_or(&json!({})),
+                                    headers,
+                                )
+                                .await
+                            } else if tool_name == "toolman_context" {
+                                self.context_info(headers).await
+                            } else {
+                                // Parse prefixed tool name and forward to server
+                                let config_manager = self.system_config_manager.read().await;
+                                let prefixes = server_prefixes(config_manager.get_config());
+                                let available_servers: Vec<String> =
+                                    prefixes.keys().cloned().collect();
+                                let unknown_tool_fallback =
+                                    config_manager.get_config().unknown_tool_fallback.clone();
+                                let structured_errors =
+                                    config_manager.get_config().structured_tool_errors;
+                                let normalize_results =
+                                    config_manager.get_config().normalize_tool_results;
+                                drop(config_manager);
+
+                                // Get available tools for original name lookup
+                                let available_tools = self.available_tools.read().await;
+
+                                match parse_tool_name_with_servers(
+                                    tool_name,
+                                    &prefixes,
+                                    &available_tools,
+                                ) {
+                                    Ok(parsed_tool) => {
+                                        // Drop the available_tools lock early to prevent deadlocks
+                                        drop(available_tools);
+                                        // Get user working directory for context-aware server startup
+                                        let user_working_dir = self.request_working_dir(headers);
+
+                                        // Arguments as the backend will see them
+                                        let arguments = self
+                                            .resolve_call_arguments(
+                                                &parsed_tool,
+                                                params
+                                                    .get("arguments")
+                                                    .cloned()
+                                                    .unwrap_or(json!({})),
+                                                user_working_dir.as_deref(),
+                                            )
+                                            .await;
+
+                                        if self.validate_arguments {
+                                            let violations = self
+                                                .argument_violations(&parsed_tool, &arguments)
+                                                .await;
+                                            if !violations.is_empty() {
+                                                let messages: Vec<&str> = violations
+                                                    .iter()
+                                                    .filter_map(|v| v["message"].as_str())
+                                                    .collect();
+                                                self.audit_tool_call(
+                                                    &parsed_tool,
+                                                    params.get("arguments"),
+                                                    user_working_dir.as_deref(),
+                                                    Err(format!(
+                                                        "invalid arguments: {}",
+                                                        messages.join("; ")
+                                                    )),
+                                                );
+                                                return JsonRpcResponse {
+                                                    jsonrpc: "2.0".to_string(),
+                                                    id: request.id,
+                                                    result: None,
+                                                    error: Some(JsonRpcError {
+                                                        code: -32602,
+                                                        message: format!(
+                                                            "Invalid arguments for tool '{}': {}",
+                                                            tool_name,
+                                                            messages.join("; ")
+                                                        ),
+                                                        data: Some(
+                                                            json!({ "violations": violations }),
+                                                        ),
+                                                    }),
+                                                };
+                                            }
+                                        }
+
+                                        if is_dry_run(headers) {
+                                            return JsonRpcResponse {
+                                                jsonrpc: "2.0".to_string(),
+                                                id: request.id,
+                                                result: Some(
+                                                    self.dry_run_result(&parsed_tool, arguments)
+                                                        .await,
+                                                ),
+                                                error: None,
+                                            };
+                                        }
+
+                                        // Per-server/per-tool settings for this call. Keep a copy of the
+                                        // arguments if the call is shadowed to a candidate backend.
+                                        let (
+                                            shadow,
+                                            page_size,
+                                            retries,
+                                            retry_backoff,
+                                            fire_and_forget,
+                                        ) = {
+                                            let config_manager =
+                                                self.system_config_manager.read().await;
+                                            let server_config =
+                                                config_manager.get_server(&parsed_tool.server_name);
+                                            let retryable = server_config
+                                                .and_then(|c| c.tool_config(&parsed_tool.tool_name))
+                                                .is_some_and(|t| t.retryable);
+                                            let proxy = config_manager.get_config();
+                                            (
+                                                server_config
+                                                    .and_then(|c| {
+                                                        c.shadow_for(&parsed_tool.tool_name)
+                                                    })
+                                                    .map(|s| (s.to_string(), arguments.clone())),
+                                                server_config
+                                                    .and_then(|c| {
+                                                        c.tool_config(&parsed_tool.tool_name)
+                                                    })
+                                                    .and_then(|t| t.page_size),
+                                                if retryable {
+                                                    proxy.tool_call_retries
+                                                } else {
+                                                    0
+                                                },
+                                                tokio::time::Duration::from_millis(
+                                                    proxy.tool_call_retry_backoff_ms,
+                                                ),
+                                                server_config
+                                                    .and_then(|c| {
+                                                        c.tool_config(&parsed_tool.tool_name)
+                                                    })
+                                                    .is_some_and(|t| t.fire_and_forget),
+                                            )
+                                        };
+
+                                        // Who the call is for, should it connect a backend
+                                        let client_info = self.session_client_info(headers);
+
+                                        if fire_and_forget {
+                                            self.spawn_fire_and_forget(
+                                                parsed_tool,
+                                                arguments,
+                                                params.get("arguments").cloned(),
+                                                params.get("_meta").cloned(),
+                                                user_working_dir,
+                                                client_info,
+                                                retries,
+                                                retry_backoff,
+                                            );
+                                            return JsonRpcResponse {
+                                                jsonrpc: "2.0".to_string(),
+                                                id: request.id,
+                                                result: Some(json!({ "content": [] })),
+                                                error: None,
+                                            };
+                                        }
+
+                                        // Forward to the appropriate server with user context,
+                                        // unless the client cancels the call first
+                                        let (cancel_tx, cancel_rx) =
+                                            tokio::sync::oneshot::channel::<Option<String>>();
+                                        let pending = request.id.as_ref().and_then(|id| {
+                                            self.register_pending_call(headers, id, cancel_tx)
+                                        });
+                                        let backend_request_id = BackendRequestId::default();
+                                        let call_guard = PendingCallGuard {
+                                            state: self.clone(),
+                                            pending,
+                                            server_name: parsed_tool.server_name.clone(),
+                                            backend_request_id: backend_request_id.clone(),
+                                            finished: false,
+                                        };
+                                        let outcome = tokio::select! {
+                                            result = self.forward_with_retries(
+                                                &parsed_tool,
+                                                arguments,
+                                                params.get("_meta"),
+                                                user_working_dir.as_deref(),
+                                                client_info.as_ref(),
+                                                retries,
+                                                retry_backoff,
+                                                Some(&backend_request_id),
+                                            ) => Ok(result),
+                                            Ok(reason) = cancel_rx => Err(reason),
+                                        };
+                                        call_guard.finish();
+
+                                        self.audit_tool_call(
+                                            &parsed_tool,
+                                            params.get("arguments"),
+                                            user_working_dir.as_deref(),
+                                            match &outcome {
+                                                Err(reason) => Err(format!(
+                                                    "cancelled{}",
+                                                    reason
+                                                        .as_ref()
+                                                        .map(|r| format!(": {}", r))
+                                                        .unwrap_or_default()
+                                                )),
+                                                Ok(Err(e)) => Err(e.to_string()),
+                                                Ok(Ok(response)) => match response.get("error") {
+                                                    Some(error) => Err(error.to_string()),
+                                                    None if response["result"]["isError"]
+                                                        == true =>
+                                                    {
+                                                        Err("the tool reported an error"
+                                                            .to_string())
+                                                    }
+                                                    None => Ok(()),
+                                                },
+                                            },
+                                        );
+
+                                        match outcome {
+                                            Err(reason) => {
+                                                self.forward_cancellation(
+                                                    &parsed_tool.server_name,
+                                                    &backend_request_id,
+                                                    reason.clone(),
+                                                )
+                                                .await;
+                                                json!({
+                                                    "content": [tool_error_content(
+                                                        format!("Request cancelled{}", reason.as_ref().map(|r| format!(": {}", r)).unwrap_or_default()),
+                                                        "cancelled",
+                                                        Some(&parsed_tool.server_name),
+                                                        &parsed_tool.tool_name,
+                                                        reason.as_deref().unwrap_or_default(),
+                                                        structured_errors,
+                                                    )],
+                                                    "isError": true
+                                                })
+                                            }
+                                            Ok(Ok(response)) => {
+                                                if let Some((shadow_server, shadow_arguments)) =
+                                                    shadow
+                                                {
+                                                    self.spawn_shadow_call(
+                                                        &parsed_tool,
+                                                        shadow_server,
+                                                        shadow_arguments,
+                                                        response.clone(),
+                                                        user_working_dir.clone(),
+                                                        client_info.clone(),
+                                                    );
+                                                }
+
+                                                // Extract result from response or return the response directly
+                                                let result = match response.get("result") {
+                                                    Some(result) if normalize_results => {
+                                                        normalize_tool_result(result.clone())
+                                                    }
+                                                    Some(result) => result.clone(),
+                                                    None => response,
+                                                };
+                                                let result = if self
+                                                    .text_only_sessions
+                                                    .lock()
+                                                    .unwrap()
+                                                    .contains(request_session_id(headers))
+                                                {
+                                                    coerce_content_to_text(result)
+                                                } else {
+                                                    result
+                                                };
+
+                                                match page_size {
+                                                    Some(page_size) => {
+                                                        self.paginate_result(result, page_size)
+                                                    }
+                                                    None => result,
+                                                }
+                                            }
+                                            Ok(Err(e)) => {
+                                                self.record_server_error(
+                                                    &parsed_tool.server_name,
+                                                    "tool_call",
+                                                    format!("{}: {}", parsed_tool.tool_name, e),
+                                                );
+                                                json!({
+                                                    "content": [tool_error_content(
+                                                        format!("❌ Error calling tool '{}'\n\n🔍 **Debug Info:**\n- Original tool name: '{}'\n- Parsed as: server='{}', tool='{}'\n- Available servers: [{}]\n- Error: {}\n\n💡 Expected format: {{server_name}}_{{tool_name}}",
+                                                                tool_name,
+                                                                tool_name,
+                                                                parsed_tool.server_name,
+                                                                parsed_tool.tool_name,
+                                                                available_servers.join(", "),
+                                                                e),
+                                                        tool_error_kind(&e),
+                                                        Some(&parsed_tool.server_name),
+                                                        &parsed_tool.tool_name,
+                                                        &e.to_string(),
+                                                        structured_errors,
+                                                    )]
+                                                })
+                                            }
+                                        }
+                                    }
+                                    Err(e)
+                                        if unknown_tool_fallback
+                                            .as_deref()
+                                            .is_some_and(|fallback| fallback != tool_name) =>
+                                    {
+                                        drop(available_tools);
+                                        let fallback = unknown_tool_fallback.unwrap_or_default();
+                                        tracing::debug!(
+                                            target: LOG_SERVER,
+                                            "Unknown tool '{}' ({}), routing to fallback '{}'",
+                                            tool_name,
+                                            e,
+                                            fallback
+                                        );
+                                        self.call_unknown_tool_fallback(
+                                            &fallback,
+                                            tool_name,
+                                            params.get("arguments").cloned().unwrap_or(json!({})),
+                                            self.request_working_dir(headers),
+                                            self.session_client_info(headers).as_ref(),
+                                        )
+                                        .await
+                                        .unwrap_or_else(|e| {
+                                            json!({
+                                                "content": [tool_error_content(
+                                                    format!("❌ Unknown tool '{}' and fallback '{}' failed: {}", tool_name, fallback, e),
+                                                    "unknown_tool",
+                                                    None,
+                                                    tool_name,
+                                                    &format!("fallback '{}' failed: {}", fallback, e),
+                                                    structured_errors,
+                                                )],
+                                                "isError": true
+                                            })
+                                        })
+                                    }
+                                    Err(e) => {
+                                        // Drop the available_tools lock
+                                        drop(available_tools);
+                                        json!({
+                                            "content": [tool_error_content(
+                                                format!("❌ Invalid tool name format\n\n🔍 **Debug Info:**\n- Attempted tool name: '{}'\n- Parse error: {}\n- Available servers: [{}]\n- Expected format: {{server_name}}_{{tool_name}}\n\n📝 **Examples:**\n- memory_read_graph\n- git_git_status\n- task_master_ai_get_tasks",
+                                                        tool_name,
+                                                        e,
+                                                        available_servers.join(", ")),
+                                                "unknown_tool",
+                                                None,
+                                                tool_name,
+                                                &e.to_string(),
+                                                structured_errors,
+                                            )]
+                                        })
+                                    }
+                                }
+                            }
+                        };
+
+                        JsonRpcResponse {
+                            jsonrpc: "2.0".to_string(),
+                            id: request.id,
+                            result: Some(result),
+                            error: None,
+                        }
+                    } else {
+                        JsonRpcResponse {
+                            jsonrpc: "2.0".to_string(),
+                            id: request.id,
+                            result: None,
+                            error: Some(JsonRpcError {
+                                code: -32602,
+                                message: "Missing tool name".to_string(),
+                                data: None,
+                            }),
+                        }
+                    }
+                } else {
+                    JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id: request.id,
+                        result: None,
+                        error: Some(JsonRpcError {
+                            code: -32602,
+                            message: "Invalid params".to_string(),
+                            data: None,
+                        }),
+                    }
+                }
+            }
+            _ => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: -32601,
+                    message: "Method not found".to_string(),
+                    data: None,
+                }),
+            },
+        }
+    }
+
+    /// Return the first page of a result whose `content` exceeds `page_size` items, storing the
+    /// rest under a `nextCursor`. Results that fit in one page are returned unchanged.
+    fn paginate_result(&self, mut result: Value, page_size: usize) -> Value {
+        let page_size = page_size.max(1);
+        let content = match result.get_mut("content").and_then(|c| c.as_array_mut()) {
+            Some(content) if content.len() > page_size => std::mem::take(content),
+            _ => return result,
+        };
+
+        let mut envelope = result;
+        if let Some(obj) = envelope.as_object_mut() {
+            obj.remove("content");
+        }
+
+        let cursor = uuid::Uuid::new_v4().to_string();
+        let mut pages = self.result_pages.lock().unwrap();
+        let now = std::time::Instant::now();
+        pages.retain(|_, p| p.expires_at > now);
+        pages.insert(
+            cursor.clone(),
+            PendingPages {
+                envelope,
+                remaining: content,
+                page_size,
+                expires_at: now + RESULT_PAGE_TTL,
+            },
+        );
+        drop(pages);
+
+        self.next_result_page(&cursor)
+            .expect("page was stored just above")
+    }
+
+    /// Take the next page for `cursor`; the cursor is consumed and a new one issued if more remain
+    fn next_result_page(&self, cursor: &str) -> Option<Value> {
+        let mut pages = self.result_pages.lock().unwrap();
+        let mut pending = pages.remove(cursor)?;
+        if pending.expires_at <= std::time::Instant::now() {
+            return None;
+        }
+
+        let rest = pending
+            .remaining
+            .split_off(pending.page_size.min(pending.remaining.len()));
+        let mut page = pending.envelope.clone();
+        page["content"] = Value::Array(std::mem::replace(&mut pending.remaining, rest));
+
+        if !pending.remaining.is_empty() {
+            let next_cursor = uuid::Uuid::new_v4().to_string();
+            page["nextCursor"] = json!(next_cursor);
+            pending.expires_at = std::time::Instant::now() + RESULT_PAGE_TTL;
+            pages.insert(next_cursor, pending);
+        }
+
+        Some(page)
+    }
+
+    /// Forward a `fire_and_forget` tool's call in the background, once the client has
+    /// been answered. Failures are only logged and kept in the server's recent errors.
+    fn spawn_fire_and_forget(
+        &self,
+        parsed_tool: ParsedTool,
+        arguments: Value,
+        client_arguments: Option<Value>,
+        meta: Option<Value>,
+        user_working_dir: Option<std::path::PathBuf>,
+        client: Option<ClientInfo>,
+        retries: u32,
+        retry_backoff: tokio::time::Duration,
+    ) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let outcome = match state
+                .forward_with_retries(
+                    &parsed_tool,
+                    arguments,
+                    meta.as_ref(),
+                    user_working_dir.as_deref(),
+                    client.as_ref(),
+                    retries,
+                    retry_backoff,
+                    None,
+                )
+                .await
+            {
+                Ok(response) => match response.get("error") {
+                    Some(error) => Err(error.to_string()),
+                    None => Ok(()),
+                },
+                Err(e) => Err(e.to_string()),
+            };
+            if let Err(e) = &outcome {
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    server = %parsed_tool.server_name,
+                    "Fire-and-forget call to '{}' failed: {}",
+                    parsed_tool.tool_name,
+                    e
+                );
+                state.record_server_error(
+                    &parsed_tool.server_name,
+                    "tool_call",
+                    format!("{}: {}", parsed_tool.tool_name, e),
+                );
+            }
+            state.audit_tool_call(
+                &parsed_tool,
+                client_arguments.as_ref(),
+                user_working_dir.as_deref(),
+                outcome,
+            );
+        });
+    }
+
+    /// Record a tools/call forwarded to a server in the audit log, if there is one.
+    /// `arguments` are the client's own, with secrets redacted.
+    fn audit_tool_call(
+        &self,
+        parsed_tool: &ParsedTool,
+        arguments: Option<&Value>,
+        working_dir: Option<&std::path::Path>,
+        outcome: Result<(), String>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let context_id = self
+            .context_manager
+            .as_ref()
+            .and_then(|manager| manager.get_context())
+            .map(|context| context.context_id.clone());
+        audit_log.record(&json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "working_directory": working_dir,
+            "context_id": context_id,
+            "server": parsed_tool.server_name,
+            "tool": parsed_tool.tool_name,
+            "arguments": redact_arguments(arguments.unwrap_or(&json!({}))),
+            "success": outcome.is_ok(),
+            "error": outcome.err(),
+        }));
+    }
+
+    /// Schema violations in a call's resolved arguments, checked against the `inputSchema`
+    /// the tool was discovered with. Tools not discovered have none.
+    async fn argument_violations(&self, parsed_tool: &ParsedTool, arguments: &Value) -> Vec<Value> {
+        let schema = self
+            .available_tools
+            .read()
+            .await
+            .values()
+            .find(|tool| {
+                tool.server_name == parsed_tool.server_name
+                    && tool.original_tool_name == parsed_tool.tool_name
+            })
+            .map(|tool| tool.input_schema.clone());
+        let Some(schema) = schema else {
+            return Vec::new();
+        };
+        let injected: Vec<String> = self
+            .system_config_manager
+            .read()
+            .await
+            .get_server(&parsed_tool.server_name)
+            .map(|config| config.inject_params.keys().cloned().collect())
+            .unwrap_or_default();
+        argument_violations(&schema, arguments, &injected)
+    }
+
+    /// The arguments a call is forwarded with: configured `defaults` for any the caller
+    /// omitted, then the server's `inject_params` (except those the caller passed, when
+    /// `injection_precedence` is `client`)
+    async fn resolve_call_arguments(
+        &self,
+        parsed_tool: &ParsedTool,
+        mut arguments: Value,
+        working_dir: Option<&std::path::Path>,
+    ) -> Value {
+        if let Some(args_obj) = arguments.as_object_mut() {
+            let config_manager = self.system_config_manager.read().await;
+            let Some(server_config) = config_manager.get_server(&parsed_tool.server_name) else {
+                return arguments;
+            };
+            if let Some(tool_config) = server_config.tool_config(&parsed_tool.tool_name) {
+                for (name, value) in &tool_config.defaults {
+                    args_obj
+                        .entry(name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+
+            // ✨ AUTO-INJECT the parameters the server opted into (e.g. TaskMaster's projectRoot)
+            let client_wins =
+                config_manager.get_config().injection_precedence == InjectionPrecedence::Client;
+            let project_dir = config_manager
+                .get_config_path()
+                .parent()
+                .unwrap_or_else(|| std::path::Path::new("."));
+            for (name, value) in server_config.injected_params(working_dir, project_dir) {
+                if client_wins && args_obj.contains_key(&name) {
+                    tracing::debug!(
+                        target: LOG_SERVER,
+                        "Keeping client-supplied {} instead of {}",
+                        name,
+                        value
+                    );
+                } else {
+                    tracing::debug!(target: LOG_SERVER, "Auto-injected {}: {}", name, value);
+                    args_obj.insert(name, value);
+                }
+            }
+        }
+        arguments
+    }
+
+    /// The working directory of this request: the client's `X-Working-Directory`. Read per
+    /// request so concurrent clients each get their own.
+    fn request_working_dir(
+        &self,
+        headers: Option<&axum::http::HeaderMap>,
+    ) -> Option<std::path::PathBuf> {
+        headers
+            .and_then(|h| h.get("x-working-directory"))
+            .and_then(|v| v.to_str().ok())
+            .map(std::path::PathBuf::from)
+    }
+
+    /// Register a client's tools/call so a cancellation can abort it, returning its key and
+    /// token in `pending_calls`. A call whose key another call in progress holds (the same
+    /// id in the same session, or from clients without an `X-Session-ID`) isn't registered
+    /// and can't be cancelled; the earlier call keeps the key.
+    fn register_pending_call(
+        &self,
+        headers: Option<&axum::http::HeaderMap>,
+        request_id: &Value,
+        cancel: tokio::sync::oneshot::Sender<Option<String>>,
+    ) -> Option<(String, u64)> {
+        static NEXT_TOKEN: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let key = pending_call_key(headers, request_id);
+        match self.pending_calls.lock().unwrap().entry(key.clone()) {
+            std::collections::hash_map::Entry::Occupied(_) => {
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    "Request id {} is already in use by a call in progress in session '{}'; \
+                     this call can't be cancelled",
+                    request_id,
+                    request_session_id(headers)
+                );
+                None
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let token = NEXT_TOKEN.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                entry.insert(PendingCall { token, cancel });
+                Some((key, token))
+            }
+        }
+    }
+
+    /// The `clientInfo` the request's session sent in `initialize`, if any
+    fn session_client_info(&self, headers: Option<&axum::http::HeaderMap>) -> Option<ClientInfo> {
+        self.client_infos
+            .lock()
+            .unwrap()
+            .get(request_session_id(headers))
+            .cloned()
+    }
+
+    /// The saved context for the working directory of this request, if there is one
+    async fn request_context(
+        &self,
+        headers: Option<&axum::http::HeaderMap>,
+    ) -> Option<ContextConfig> {
+        let context_manager = self.context_manager.as_ref()?;
+        let working_dir = self
+            .request_working_dir(headers)?
+            .to_string_lossy()
+            .to_string();
+
+        match context_manager.find_context(&working_dir, None) {
+            Ok(context) => context,
+            Err(e) => {
+                tracing::warn!(
+                    target: LOG_SERVER,
+                    "Failed to load context for '{}', listing all tools: {}",
+                    working_dir,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// `toolman_context`: the working directory calls in this request context are resolved
+    /// against, the project directory, and which arguments each server gets injected
+    async fn context_info(&self, headers: Option<&axum::http::HeaderMap>) -> Value {
+        let working_dir = self.request_working_dir(headers);
+        let config_manager = self.system_config_manager.read().await;
+        let project_dir = config_manager
+            .get_config_path()
+            .parent()
+            .map(|dir| dir.to_path_buf());
+        // The header as the client sent it, alongside the directory resolved from it
+        let client_working_dir = headers
+            .and_then(|h| h.get("x-working-directory"))
+            .and_then(|v| v.to_str().ok());
+
+        let mut server_names: Vec<&String> = config_manager.get_servers().keys().collect();
+        server_names.sort();
+        let mut injected = Vec::new();
+        for server_name in server_names {
+            let server_config = &config_manager.get_servers()[server_name];
+            for (key, value) in server_config.injected_params(
+                working_dir.as_deref(),
+                project_dir
+                    .as_deref()
+                    .unwrap_or_else(|| std::path::Path::new(".")),
+            ) {
+                injected.push(json!({ "server": server_name, "key": key, "value": value }));
+            }
+        }
+        drop(config_manager);
+        let context = json!({
+            "working_directory": working_dir,
+            "client_working_directory": client_working_dir,
+            "project_directory": project_dir,
+            "injected_arguments": injected
+        });
+        json!({
+            "content": [{
+                "type": "text",
+                "text": serde_json::to_string_pretty(&context).unwrap_or_default()
+            }]
+        })
+    }
+
+    /// `toolman_preview_call`: resolve a call's arguments exactly as `tools/call` would,
+    /// without forwarding it, so agents can see what the backend will receive
+    async fn preview_call(
+        &self,
+        preview_args: &Value,
+        headers: Option<&axum::http::HeaderMap>,
+    ) -> Value {
+        let Some(tool_name) = preview_args.get("tool").and_then(|t| t.as_str()) else {
+            return json!({
+                "content": [{"type": "text", "text": "❌ Missing 'tool' argument"}],
+                "isError": true
+            });
+        };
+
+        let prefixes = server_prefixes(self.system_config_manager.read().await.get_config());
+        let parsed = {
+            let available_tools = self.available_tools.read().await;
+            parse_tool_name_with_servers(tool_name, &prefixes, &available_tools)
+        };
+        let parsed_tool = match parsed {
+            Ok(parsed_tool) => parsed_tool,
+            Err(e) => {
+                return json!({
+                    "content": [{"type": "text", "text": format!("❌ Cannot resolve tool '{}': {}", tool_name, e)}],
+                    "isError": true
+                })
+            }
+        };
+
+        let working_dir = match preview_args.get("working_dir").and_then(|w| w.as_str()) {
+            Some(dir) => Some(std::path::PathBuf::from(dir)),
+            None => self.request_working_dir(headers),
+        };
+        let arguments = self
+            .resolve_call_arguments(
+                &parsed_tool,
+                preview_args.get("arguments").cloned().unwrap_or(json!({})),
+                working_dir.as_deref(),
+            )
+            .await;
+
+        let preview = json!({
+            "server": parsed_tool.server_name,
+            "tool": parsed_tool.tool_name,
+            "arguments": arguments
+        });
+        json!({
+            "content": [{
+                "type": "text",
+                "text": serde_json::to_string_pretty(&preview).unwrap_or_default()
+            }]
+        })
+    }
+
+    /// The tool a `tools/call` is for and the arguments it will be forwarded with, if it
+    /// is a `streaming` tool of a stdio server. Dry runs and page requests aren't streamed.
+    async fn streaming_call(
+        &self,
+        request: &JsonRpcRequest,
+        headers: &axum::http::HeaderMap,
+    ) -> Option<(ParsedTool, Value, Option<std::path::PathBuf>)> {
+        let params = request.params.as_ref()?;
+        if request.method != "tools/call"
+            || params.get("cursor").is_some()
+            || is_dry_run(Some(headers))
+        {
+            return None;
+        }
+        let tool_name = params.get("name")?.as_str()?;
+        let parsed_tool = {
+            let prefixes = server_prefixes(self.system_config_manager.read().await.get_config());
+            let available_tools = self.available_tools.read().await;
+            parse_tool_name_with_servers(tool_name, &prefixes, &available_tools).ok()?
+        };
+        let streaming = self
+            .system_config_manager
+            .read()
+            .await
+            .get_server(&parsed_tool.server_name)
+            .filter(|config| config.transport == "stdio")
+            .and_then(|config| config.tool_config(&parsed_tool.tool_name))
+            .is_some_and(|tool| tool.streaming);
+        if !streaming {
+            return None;
+        }
+
+        let working_dir = self.request_working_dir(Some(headers));
+        let arguments = self
+            .resolve_call_arguments(
+                &parsed_tool,
+                params.get("arguments").cloned().unwrap_or(json!({})),
+                working_dir.as_deref(),
+            )
+            .await;
+        Some((parsed_tool, arguments, working_dir))
+    }
+
+    /// Answer a `tools/call` to a `streaming` tool with a body that carries the server's
+    /// response as it is read. A failure before any of it arrived is answered with a tool
+    /// error like any other call's; one after that aborts the body.
+    fn stream_tool_call(
+        &self,
+        parsed_tool: ParsedTool,
+        arguments: Value,
+        meta: Option<Value>,
+        working_dir: Option<std::path::PathBuf>,
+        client: Option<ClientInfo>,
+        client_id: Value,
+    ) -> axum::response::Response {
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let state = self.clone();
+        tokio::spawn(async move {
+            let params = tool_call_params(&parsed_tool.tool_name, arguments, meta.as_ref());
+            let backend_request_id = BackendRequestId::default();
+            let (result, started) = tokio::select! {
+                outcome = state.connection_pool.stream_tool_call(
+                    &parsed_tool.server_name,
+                    &parsed_tool.tool_name,
+                    params,
+                    working_dir.as_deref(),
+                    client.as_ref(),
+                    client_id.clone(),
+                    &tx,
+                    Some(&backend_request_id),
+                ) => outcome,
+                // The client went away. Stop waiting on the server, which releases its
+                // lock for the next call, and tell it to stop working on this one.
+                () = tx.closed() => {
+                    tracing::info!(
+                        target: LOG_SERVER,
+                        server = %parsed_tool.server_name,
+                        "Client disconnected during a streamed call to '{}'",
+                        parsed_tool.tool_name
+                    );
+                    state
+                        .forward_cancellation(
+                            &parsed_tool.server_name,
+                            &backend_request_id,
+                            Some("client disconnected".to_string()),
+                        )
+                        .await;
+                    return;
+                }
+            };
+            let Err(e) = result else {
+                return;
+            };
+            state.record_server_error(
+                &parsed_tool.server_name,
+                "tool_call",
+                format!("{}: {}", parsed_tool.tool_name, e),
+            );
+            if started {
+                tracing::error!(
+                    target: LOG_SERVER,
+                    server = %parsed_tool.server_name,
+                    "Streamed result of '{}' cut short: {}",
+                    parsed_tool.tool_name,
+                    e
+                );
+                let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                return;
+            }
+            let structured_errors = state
+                .system_config_manager
+                .read()
+                .await
+                .get_config()
+                .structured_tool_errors;
+            let response = JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: Some(client_id),
+                result: Some(json!({
+                    "content": [tool_error_content(
+                        format!("❌ Error calling tool '{}': {}", parsed_tool.tool_name, e),
+                        tool_error_kind(&e),
+                        Some(&parsed_tool.server_name),
+                        &parsed_tool.tool_name,
+                        &e.to_string(),
+                        structured_errors,
+                    )],
+                    "isError": true
+                })),
+                error: None,
+            };
+            let body = serde_json::to_vec(&response).unwrap_or_default();
+            let _ = tx.send(Ok(body.into())).await;
+        });
+
+        let body = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+        (
+            [(axum::http::header::CONTENT_TYPE, "application/json")],
+            axum::body::Body::from_stream(body),
+        )
+            .into_response()
+    }
+
+    /// Result of a `tools/call` made with `X-Toolman-Dry-Run: true`: where the call would
+    /// go and the arguments it would carry, in place of the backend's result
+    async fn dry_run_result(&self, parsed_tool: &ParsedTool, arguments: Value) -> Value {
+        let transport = self
+            .system_config_manager
+            .read()
+            .await
+            .get_server(&parsed_tool.server_name)
+            .map(|config| config.transport.clone());
+        let dry_run = json!({
+            "dry_run": true,
+            "server_name": parsed_tool.server_name,
+            "tool_name": parsed_tool.tool_name,
+            "arguments": arguments,
+            "transport": transport
+        });
+        json!({
+            "content": [{
+                "type": "text",
+                "text": serde_json::to_string_pretty(&dry_run).unwrap_or_default()
+            }]
+        })
+    }
+
+    /// Which optional features are enabled and the limits in effect, for `GET /capabilities`
+    async fn proxy_capabilities(&self) -> Value {
+        let config_manager = self.system_config_manager.read().await;
+        let config = config_manager.get_config();
+        let servers = config.servers.values();
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": {
+                "auth": false,
+                "batch": false,
+                "resources": false,
+                "prompts": false,
+                "tool_meta": config.include_tool_meta,
+                "builtin_tools_listed": !config.hide_builtin_tools,
+                "client_info_passthrough": config.client_info_passthrough,
+                "tool_catalog": self
+                    .catalog_writes_enabled
+                    .load(std::sync::atomic::Ordering::Relaxed),
+                "shadowing": servers.clone().any(|s| {
+                    s.shadow.is_some() || s.tools.values().any(|t| t.shadow.is_some())
+                }),
+                "pagination": servers.clone().any(|s| s.tools.values().any(|t| t.page_size.is_some())),
+                "retries": servers.clone().any(|s| s.tools.values().any(|t| t.retryable)),
+            },
+            "limits": {
+                "max_tools": null,
+                "tool_call_retries": config.tool_call_retries,
+                "reachability_timeout_ms": config.reachability_timeout_ms,
+                "reload_drain_timeout_secs": config.reload_drain_timeout_secs,
+                "result_page_ttl_secs": RESULT_PAGE_TTL.as_secs(),
+            },
+            "tool_name_separator": "_",
+            "tool_naming": tool_naming_rules(config),
+        })
+    }
+
+    /// Call the configured `unknown_tool_fallback` with the unresolvable request embedded
+    async fn call_unknown_tool_fallback(
+        &self,
+        fallback: &str,
+        requested_tool: &str,
+        requested_arguments: Value,
+        user_working_dir: Option<std::path::PathBuf>,
+        client: Option<&ClientInfo>,
+    ) -> anyhow::Result<Value> {
+        let prefixes = server_prefixes(self.system_config_manager.read().await.get_config());
+        let parsed_fallback = {
+            let available_tools = self.available_tools.read().await;
+            parse_tool_name_with_servers(fallback, &prefixes, &available_tools)?
+        };
+
+        let response = self
+            .connection_pool
+            .forward_tool_call_with_context(
+                &parsed_fallback.server_name,
+                &parsed_fallback.tool_name,
+                json!({ "tool": requested_tool, "arguments": requested_arguments }),
+                user_working_dir.as_deref(),
+                client,
+            )
+            .await?;
+        Ok(response.get("result").cloned().unwrap_or(response))
+    }
+
+    /// Pass a client's cancellation on to the backend that was handling the call, if it
+    /// got as far as sending the backend a request
+    async fn forward_cancellation(
+        &self,
+        server_name: &str,
+        backend_request_id: &BackendRequestId,
+        reason: Option<String>,
+    ) {
+        let Some(request_id) = backend_request_id.lock().unwrap().take() else {
+            return;
+        };
+        tracing::info!(
+            target: LOG_SERVER,
+            server = %server_name,
+            "Forwarding cancellation of request {}",
+            request_id
+        );
+        if let Err(e) = self
+            .connection_pool
+            .cancel_backend_request(server_name, request_id, reason)
+            .await
+        {
+            tracing::warn!(
+                target: LOG_SERVER,
+                server = %server_name,
+                "Failed to forward cancellation: {}",
+                e
+            );
+        }
+    }
+
+    /// Forward a tool call, retrying transport failures up to `retries` times with
+    /// exponential backoff. Callers pass 0 unless the tool is configured `retryable`.
+    async fn forward_with_retries(
+        &self,
+        parsed_tool: &ParsedTool,
+        arguments: Value,
+        meta: Option<&Value>,
+        user_working_dir: Option<&std::path::Path>,
+        client: Option<&ClientInfo>,
+        retries: u32,
+        backoff: tokio::time::Duration,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<Value> {
+        let sample_rate = self
+            .system_config_manager
+            .read()
+            .await
+            .get_server(&parsed_tool.server_name)
+            .and_then(|c| c.request_log_sample_rate)
+            .unwrap_or(0.0);
+        let sampled = sample_rate > 0.0 && fastrand::f64() < sample_rate;
+        let sampled_arguments = sampled.then(|| arguments.clone());
+        let started = std::time::Instant::now();
+
+        let (result, attempts) = retry_transport_failures(
+            &parsed_tool.server_name,
+            &format!("Call to '{}'", parsed_tool.tool_name),
+            retries,
+            backoff,
+            || {
+                self.connection_pool.forward_tool_call_tracked(
+                    &parsed_tool.server_name,
+                    &parsed_tool.tool_name,
+                    arguments.clone(),
+                    meta,
+                    user_working_dir,
+                    client,
+                    backend_request_id,
+                )
+            },
+        )
+        .await;
+
+        self.metrics.record_call(
+            &parsed_tool.server_name,
+            &parsed_tool.tool_name,
+            started.elapsed(),
+            result.is_err(),
+        );
+
+        if let Some(arguments) = sampled_arguments {
+            let outcome = match &result {
+                Ok(response) => response.to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            tracing::info!(
+                target: LOG_SERVER,
+                server = %parsed_tool.server_name,
+                tool = %parsed_tool.tool_name,
+                "Sampled call: arguments={} attempts={} duration={:?} result={}",
+                arguments,
+                attempts,
+                started.elapsed(),
+                outcome
+            );
+        }
+        result
+    }
+
+    /// Replay a tool call against a shadow server in the background and log how its
+    /// response differs from the primary's. The caller never waits on the shadow.
+    fn spawn_shadow_call(
+        &self,
+        primary: &ParsedTool,
+        shadow_server: String,
+        arguments: Value,
+        primary_response: Value,
+        user_working_dir: Option<std::path::PathBuf>,
+        client: Option<ClientInfo>,
+    ) {
+        let connection_pool = self.connection_pool.clone();
+        let primary_server = primary.server_name.clone();
+        let tool_name = primary.tool_name.clone();
+
+        tokio::spawn(async move {
+            let diff = match connection_pool
+                .forward_tool_call_with_context(
+                    &shadow_server,
+                    &tool_name,
+                    arguments,
+                    user_working_dir.as_deref(),
+                    client.as_ref(),
+                )
+                .await
+            {
+                Ok(shadow_response) => shadow_diff(&primary_response, &shadow_response),
+                Err(e) => Some(format!("shadow call failed: {}", e)),
+            };
+
+            match &diff {
+                Some(diff) => tracing::debug!(
+                    target: LOG_SERVER,
+                    "Shadow mismatch for '{}' ({} vs {}): {}",
+                    tool_name,
+                    primary_server,
+                    shadow_server,
+                    diff
+                ),
+                None => tracing::info!(
+                    target: LOG_SERVER,
+                    "Shadow match for '{}' ({} vs {})",
+                    tool_name,
+                    primary_server,
+                    shadow_server
+                ),
+            }
+        });
+    }
+}
+
+/// Compare a primary and a shadow response, ignoring the JSON-RPC envelope (ids differ per backend).
+/// Returns a description of the difference, or `None` when both backends agree.
+fn shadow_diff(primary: &Value, shadow: &Value) -> Option<String> {
+    let payload = |response: &Value| {
+        response
+            .get("result")
+            .or_else(|| response.get("error"))
+            .cloned()
+            .unwrap_or_else(|| response.clone())
+    };
+
+    let (primary, shadow) = (payload(primary), payload(shadow));
+    if primary == shadow {
+        None
+    } else {
+        Some(format!("primary={} shadow={}", primary, shadow))
+    }
+}
+
+// Liveness probe - checks the HTTP server is alive and the watchdog heartbeat is fresh,
+// so a deadlocked proxy gets restarted
+async fn health_check(State(state): State<BridgeState>) -> (StatusCode, Json<Value>) {
+    let heartbeat_age = state.heartbeat_age();
+    // Read without waiting: the config lock may be the one that is stuck
+    let timeout_secs = match state.system_config_manager.try_read() {
+        Ok(config_manager) => config_manager.get_config().health_heartbeat_timeout_secs,
+        Err(_) => ServersConfig::default().health_heartbeat_timeout_secs,
+    };
+
+    if timeout_secs > 0 && heartbeat_age.as_secs() >= timeout_secs {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "unhealthy",
+                "service": "toolman",
+                "reason": format!("watchdog heartbeat is {}s old", heartbeat_age.as_secs()),
+                "timestamp": Utc::now().to_rfc3339()
+            })),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "status": "ok",
+            "service": "toolman",
+            "timestamp": Utc::now().to_rfc3339()
+        })),
+    )
+}
+
+// Readiness probe - checks if MCP servers are available and ready
+async fn readiness_check(State(state): State<BridgeState>) -> Result<Json<Value>, StatusCode> {
+    let config_manager = state.system_config_manager.read().await;
+    let servers = config_manager.get_servers();
+
+    // Check if we have any servers configured
+    if servers.is_empty() {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // Servers are configured; report how each one fared in discovery and which are backed up
+    let discovery_status = state.discovery_status.read().await;
+    let queued_calls: HashMap<&String, usize> = servers
+        .keys()
+        .map(|name| (name, state.connection_pool.queued_calls(name)))
+        .filter(|(_, queued)| *queued > 0)
+        .collect();
+    Ok(Json(json!({
+        "status": "ready",
+        "service": "toolman",
+        "servers_configured": servers.len(),
+        "servers": *discovery_status,
+        "queued_calls": queued_calls,
+        "timestamp": Utc::now().to_rfc3339()
+    })))
+}
+
+// Session initialization endpoint
+async fn mcp_endpoint(
+    State(state): State<BridgeState>,
+    headers: axum::http::HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Json<JsonRpcResponse>, (StatusCode, Json<JsonRpcError>)> {
+    let hops = check_hops(&state, &headers).await?;
+
+    if let Ok(request) = serde_json::from_value::<JsonRpcRequest>(body) {
+        tracing::info!(target: LOG_SERVER, method = %request.method, "Processing request");
+        // Simple tool aggregation - no session complexity
+        let response = REQUEST_HOPS
+            .scope(hops, state.handle_jsonrpc_request(request, Some(&headers)))
+            .await;
+        Ok(Json(response))
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            Json(JsonRpcError {
+                code: -32700,
+                message: "Parse error".to_string(),
+                data: None,
+            }),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct McpQuery {
+    #[serde(default)]
+    labels: Option<String>,
+}
+
+// `/mcp`: calls to `streaming` tools get the server's response relayed as it is read;
+// everything else is answered whole by `mcp_endpoint`
+async fn mcp_route(
+    State(state): State<BridgeState>,
+    axum::extract::Query(query): axum::extract::Query<McpQuery>,
+    headers: axum::http::HeaderMap,
+    Json(mut body): Json<Value>,
+) -> axum::response::Response {
+    // `?labels=` scopes a tools/list, unless its params already do
+    if let Some(labels) = query.labels {
+        if body.get("method").and_then(Value::as_str) == Some("tools/list") {
+            if !body["params"].is_object() {
+                body["params"] = json!({});
+            }
+            body["params"]
+                .as_object_mut()
+                .unwrap()
+                .entry("labels")
+                .or_insert(json!(labels));
+        }
+    }
+    if body.get("method").and_then(Value::as_str) == Some("tools/call") {
+        if let Ok(request) = serde_json::from_value::<JsonRpcRequest>(body.clone()) {
+            if let Some((parsed_tool, arguments, working_dir)) =
+                state.streaming_call(&request, &headers).await
+            {
+                if let Err(rejection) = check_hops(&state, &headers).await {
+                    return rejection.into_response();
+                }
+                let meta = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("_meta"))
+                    .cloned();
+                let client_info = state.session_client_info(Some(&headers));
+                return state.stream_tool_call(
+                    parsed_tool,
+                    arguments,
+                    meta,
+                    working_dir,
+                    client_info,
+                    request.id.unwrap_or(Value::Null),
+                );
+            }
+        }
+    }
+    mcp_endpoint(State(state), headers, Json(body))
+        .await
+        .into_response()
+}
+
+/// The `X-Toolman-Hops` of a client request, refusing requests that have already been
+/// through `max_hops` proxies: a Toolman chain that forwards back to itself would otherwise
+/// recurse until resources run out
+async fn check_hops(
+    state: &BridgeState,
+    headers: &axum::http::HeaderMap,
+) -> Result<u32, (StatusCode, Json<JsonRpcError>)> {
+    let hops = headers
+        .get(HOPS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+    let max_hops = state
+        .system_config_manager
+        .read()
+        .await
+        .get_config()
+        .max_hops;
+    if max_hops > 0 && hops >= max_hops {
+        tracing::warn!(
+            target: LOG_SERVER,
+            "Rejecting request after {} proxy hops (max_hops = {})",
+            hops,
+            max_hops
+        );
+        return Err((
+            StatusCode::LOOP_DETECTED,
+            Json(JsonRpcError {
+                code: -32000,
+                message: format!(
+                    "Proxy loop detected: request already passed through {} Toolman proxies (max_hops = {}). Check that no server forwards back to this proxy.",
+                    hops, max_hops
+                ),
+                data: None,
+            }),
+        ));
+    }
+
+    Ok(hops)
+}
+
+// Recent tool-call and discovery errors of one server, oldest first
+async fn server_errors_endpoint(
+    State(state): State<BridgeState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    if state
+        .system_config_manager
+        .read()
+        .await
+        .get_server(&name)
+        .is_none()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let errors: Vec<ServerError> = state
+        .recent_errors
+        .lock()
+        .unwrap()
+        .get(&name)
+        .map(|errors| errors.iter().cloned().collect())
+        .unwrap_or_default();
+    Ok(Json(json!({ "server": name, "errors": errors })))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: Option<String>,
+}
+
+// Stream of server-initiated messages, such as `notifications/tools/list_changed`
+async fn mcp_notifications(
+    State(state): State<BridgeState>,
+) -> axum::response::sse::Sse<
+    impl futures::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>>,
+> {
+    let notifications =
+        futures::stream::unfold(state.notifications.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(message) => {
+                        let event = axum::response::sse::Event::default()
+                            .event("message")
+                            .data(message.to_string());
+                        return Some((Ok(event), rx));
+                    }
+                    // Tell the client about the latest state rather than every missed one
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+    axum::response::sse::Sse::new(notifications)
+        .keep_alive(axum::response::sse::KeepAlive::default())
+}
+
+/// The proxy's HTTP routes
+fn app(state: BridgeState) -> Router {
+    Router::new()
+        .route("/mcp", post(mcp_route).get(mcp_notifications))
+        .route(
+            "/client-config",
+            get(client_config_endpoint).post(update_client_config_endpoint),
+        )
+        .route("/health", get(health_check))
+        .route("/ready", get(readiness_check))
+        .route("/capabilities", get(capabilities_endpoint))
+        .route("/servers", get(servers_endpoint))
+        .route("/export", get(export_endpoint))
+        .route("/servers/{name}/errors", get(server_errors_endpoint))
+        .route("/metrics", get(metrics_endpoint))
+        .route("/reload", post(reload_endpoint))
+        .route("/suggest", post(suggest_endpoint))
+        .layer(axum::middleware::from_fn(pretty_json))
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+}
+
+/// Whether a query string asks for indented JSON: `pretty`, `pretty=true` or `pretty=1`
+fn wants_pretty_json(query: &str) -> bool {
+    query
+        .split('&')
+        .any(|pair| matches!(pair, "pretty" | "pretty=true" | "pretty=1"))
+}
+
+// Indent the JSON answering a GET with `?pretty=true`, for reading with curl. Other
+// responses are left compact.
+async fn pretty_json(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let pretty = request.method() == axum::http::Method::GET
+        && request.uri().query().is_some_and(wants_pretty_json);
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !pretty || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!(target: LOG_SERVER, "Failed to read a response to indent: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let body = match serde_json::from_slice::<Value>(&bytes)
+        .and_then(|value| serde_json::to_vec_pretty(&value))
+    {
+        Ok(pretty) => {
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            pretty.into()
+        }
+        Err(_) => bytes,
+    };
+    axum::response::Response::from_parts(parts, axum::body::Body::from(body))
+}
+
+// Re-read the config file and apply the difference; see `BridgeState::reload_config`
+async fn reload_endpoint(
+    State(state): State<BridgeState>,
+) -> Result<Json<ReloadSummary>, (StatusCode, Json<Value>)> {
+    state.reload_config().await.map(Json).map_err(|e| {
+        tracing::warn!(target: LOG_SERVER, "Reload failed: {}", e);
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e.to_string() })),
+        )
+    })
+}
+
+// Snapshot of the discovered tools in the `--export-tools` format, without rediscovering
+async fn export_endpoint(
+    State(state): State<BridgeState>,
+    axum::extract::Query(query): axum::extract::Query<ExportQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    if !matches!(query.format.as_deref(), None | Some("json")) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut tools_by_server: HashMap<String, Vec<Tool>> = HashMap::new();
+    for tool in state.available_tools.read().await.values() {
+        tools_by_server
+            .entry(tool.server_name.clone())
+            .or_default()
+            .push(tool.clone());
+    }
+    let config_manager = state.system_config_manager.read().await;
+    let servers = config_manager.get_servers();
+    for server_name in servers.keys() {
+        tools_by_server.entry(server_name.clone()).or_default();
+    }
+    tools_by_server.retain(|server_name, _| servers.contains_key(server_name));
+    Ok(Json(tools_export(servers, &tools_by_server)))
+}
+
+/// Words that say nothing about which tool fits a task
+const SUGGESTION_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "the", "to", "of", "for", "in", "on", "with", "from", "my", "some", "i",
+    "me", "want", "need",
+];
+
+/// The words of a text for matching tools: lowercase, with a plural `s` dropped
+/// (`files` matches `file`) and filler words left out
+fn suggestion_terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .map(|word| word.to_ascii_lowercase())
+        .filter(|word| !word.is_empty() && !SUGGESTION_STOP_WORDS.contains(&word.as_str()))
+        .map(|word| match word.strip_suffix('s') {
+            Some(singular) if singular.len() > 2 && !singular.ends_with('s') => {
+                singular.to_string()
+            }
+            _ => word,
+        })
+        .collect()
+}
+
+/// How well a tool fits a task's terms, from 0 to 1, and why. Each term counts for the
+/// best place it is found: the tool's name, then its category, use cases and description.
+fn suggestion_score(terms: &[String], prefixed_name: &str, info: &ToolInfo) -> (f64, String) {
+    if terms.is_empty() {
+        return (0.0, String::new());
+    }
+    let places = [
+        ("name", 1.0, suggestion_terms(prefixed_name)),
+        ("category", 0.6, suggestion_terms(&info.category)),
+        (
+            "use cases",
+            0.5,
+            suggestion_terms(&info.use_cases.join(" ")),
+        ),
+        ("description", 0.4, suggestion_terms(&info.description)),
+    ];
+    let mut total = 0.0;
+    let mut matches = Vec::new();
+    for term in terms {
+        if let Some((place, weight, _)) = places.iter().find(|(_, _, words)| words.contains(term)) {
+            total += weight;
+            matches.push(format!("'{}' in its {}", term, place));
+        }
+    }
+    let reason = if matches.is_empty() {
+        String::new()
+    } else {
+        format!("matches {}", matches.join(", "))
+    };
+    (total / terms.len() as f64, reason)
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestRequest {
+    query: String,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// How many suggestions `/suggest` returns unless the request asks for a different number
+const DEFAULT_SUGGESTION_LIMIT: usize = 10;
+
+// Tools that fit a task described in plain words, best first, so an agent can narrow a
+// large catalog down before calling tools/list
+async fn suggest_endpoint(
+    State(state): State<BridgeState>,
+    Json(request): Json<SuggestRequest>,
+) -> Json<Value> {
+    let suggestions = state
+        .suggest_tools(
+            &request.query,
+            request.limit.unwrap_or(DEFAULT_SUGGESTION_LIMIT),
+        )
+        .await;
+    Json(json!({ "suggestions": suggestions }))
+}
+
+// Every configured server, keyed by name, with whether it is connected and its tool count
+async fn servers_endpoint(State(state): State<BridgeState>) -> Json<Value> {
+    let mut tool_counts: HashMap<String, usize> = HashMap::new();
+    for tool in state.available_tools.read().await.values() {
+        *tool_counts.entry(tool.server_name.clone()).or_default() += 1;
+    }
+
+    let servers = state
+        .system_config_manager
+        .read()
+        .await
+        .get_servers()
+        .clone();
+    let discovery_timings = state.discovery_timings.read().await.clone();
+    let mut listing = serde_json::Map::new();
+    for (name, config) in servers {
+        let mut entry = json!({
+            "name": name,
+            "transport": config.transport,
+            "connected": state.connection_pool.is_connected(&name).await,
+            "tools": tool_counts.get(&name).copied().unwrap_or(0),
+            "queued_calls": state.connection_pool.queued_calls(&name),
+        });
+        match &config.url {
+            Some(url) => entry["url"] = json!(url),
+            None => entry["command"] = json!(config.command),
+        }
+        if let Some(timing) = discovery_timings.get(&name) {
+            entry["discovery"] = json!(timing);
+        }
+        listing.insert(name, entry);
+    }
+    Json(Value::Object(listing))
+}
+
+// Prometheus scrape endpoint
+async fn metrics_endpoint(State(state): State<BridgeState>) -> impl axum::response::IntoResponse {
+    state
+        .metrics
+        .pool_connections
+        .set(state.connection_pool.live_connections().await as i64);
+    state
+        .metrics
+        .discovered_tools
+        .set(state.available_tools.read().await.len() as i64);
+    // Only configured servers are reported, so removed ones don't linger
+    state.metrics.queued_calls.reset();
+    for name in state
+        .system_config_manager
+        .read()
+        .await
+        .get_servers()
+        .keys()
+    {
+        state
+            .metrics
+            .queued_calls
+            .with_label_values(&[name])
+            .set(state.connection_pool.queued_calls(name) as i64);
+    }
+    if let Some(audit_log) = &state.audit_log {
+        state
+            .metrics
+            .audit_log_dropped_lines
+            .set(audit_log.dropped_lines() as i64);
+    }
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        state.metrics.render(),
+    )
+}
+
+// Optional proxy features and limits, so clients can adapt without trial and error
+async fn capabilities_endpoint(State(state): State<BridgeState>) -> Json<Value> {
+    Json(state.proxy_capabilities().await)
+}
+
+// Client configuration endpoint - generates MCP client config with all tools disabled by default
+async fn client_config_endpoint(
+    State(state): State<BridgeState>,
+) -> Result<Json<Value>, StatusCode> {
+    let available_tools = state.available_tools.read().await;
+    let enabled_tools = state.enabled_tools.read().await;
+    let config_manager = state.system_config_manager.read().await;
+    let servers = config_manager.get_servers();
+
+    // Group tools by server
+    let mut tools_by_server: HashMap<String, Vec<Value>> = HashMap::new();
+
+    for (tool_key, tool) in available_tools.iter() {
+        let server_name = &tool.server_name;
+        let tool_config = json!({
+            "name": tool.name,
+            "description": tool.description,
+            // All tools disabled by default, until an enabled set is posted back
+            "enabled": enabled_tools.as_ref().is_some_and(|e| e.contains(tool_key)),
+            "inputSchema": tool.input_schema
+        });
+
+        tools_by_server
+            .entry(server_name.clone())
+            .or_default()
+            .push(tool_config);
+    }
+
+    // Build server configurations
+    let mut servers_config = json!({});
+
+    for (server_name, server_config) in servers.iter() {
+        let tools = tools_by_server
+            .get(server_name)
+            .cloned()
+            .unwrap_or_default();
+
+        servers_config[server_name] = json!({
+            "name": server_config.name,
+            "description": server_config.description,
+            "transport": server_config.transport,
+            "url": server_config.url,
+            "enabled": true,
+            "tools": tools
+        });
+    }
+
+    let client_config = json!({
+        "servers": servers_config,
+        "metadata": {
+            "generated_at": Utc::now().to_rfc3339(),
+            "generator": "toolman",
+            "version": env!("CARGO_PKG_VERSION"),
+            "total_tools": available_tools.len(),
+            "total_servers": servers.len(),
+            "note": "All tools are disabled by default. Enable specific tools and POST this document back to /client-config to apply them."
+        }
+    });
+
+    Ok(Json(client_config))
+}
+
+// Apply an edited client configuration (the GET /client-config structure) as the enabled tool set
+async fn update_client_config_endpoint(
+    State(state): State<BridgeState>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let servers = body.get("servers").and_then(|s| s.as_object()).ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": "Expected a 'servers' object as returned by GET /client-config" })),
+    ))?;
+
+    let available_tools = state.available_tools.read().await;
+    let mut enabled = HashSet::new();
+    let mut unknown = Vec::new();
+
+    for (server_name, server) in servers {
+        // A disabled server disables all of its tools
+        if server.get("enabled").and_then(|e| e.as_bool()) == Some(false) {
+            continue;
+        }
+        let tools = server.get("tools").and_then(|t| t.as_array());
+        for tool in tools.into_iter().flatten() {
+            if tool.get("enabled").and_then(|e| e.as_bool()) != Some(true) {
+                continue;
+            }
+            let Some(tool_name) = tool.get("name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            match available_tools
+                .iter()
+                .find(|(_, t)| &t.server_name == server_name && t.name == tool_name)
+            {
+                Some((key, _)) => {
+                    enabled.insert(key.clone());
+                }
+                None => unknown.push(format!("{}/{}", server_name, tool_name)),
+            }
+        }
+    }
+    drop(available_tools);
+
+    let mut enabled_list: Vec<String> = enabled.iter().cloned().collect();
+    enabled_list.sort();
+    tracing::debug!(target: LOG_SERVER, "Enabled tool set updated: {} tools", enabled_list.len());
+    *state.enabled_tools.write().await = Some(enabled);
+
+    Ok(Json(json!({
+        "enabled_tools": enabled_list,
+        "total_enabled": enabled_list.len(),
+        "unknown_tools": unknown
+    })))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    init_tracing();
+
+    // Default project_dir to current directory if not specified
+    let project_dir = args.project_dir.or_else(|| {
+        Some(std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from(".")))
+    });
+
+    // Handle export-tools flag - discover tools and export to file, then exit
+    if let Some(export_path) = args.export_tools {
+        tracing::debug!(
+            target: LOG_SERVER,
+            "Export mode: Discovering all tools from configured servers..."
+        );
+
+        let state = BridgeState::new(project_dir.clone())?;
+
+        // Discover all tools without enabling them
+        let config_manager = state.system_config_manager.read().await;
+        let servers = config_manager.get_servers();
+        let mut all_discovered_tools = std::collections::HashMap::new();
+
+        for (server_name, config) in servers.iter() {
+            tracing::debug!(target: LOG_SERVER, "Discovering tools from server: {}", server_name);
+            match state.discover_server_tools(server_name, config).await {
+                Ok(tools) => {
+                    tracing::info!(
+                        target: LOG_SERVER,
+                        "Discovered {} tools from server '{}'",
+                        tools.len(),
+                        server_name
+                    );
+                    all_discovered_tools.insert(server_name.clone(), tools);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        target: LOG_SERVER,
+                        "Failed to discover tools from server '{}': {}",
+                        server_name,
+                        e
+                    );
+                    all_discovered_tools.insert(server_name.clone(), Vec::new());
+                }
+            }
+        }
+
+        // Create export structure
+        let export_data = tools_export(servers, &all_discovered_tools);
+
+        // Write to file
+        std::fs::write(&export_path, serde_json::to_string_pretty(&export_data)?)?;
+
+        tracing::info!(
+            target: LOG_SERVER,
+            "Exported {} tools from {} servers to: {}",
+            export_data["total_tools_discovered"],
+            export_data["total_servers"],
+            export_path.display()
+        );
+
+        return Ok(());
+    }
+
+    tracing::info!(target: LOG_SERVER, "Starting MCP Tools HTTP Server on port {}", args.port);
+
+    // Print ALL environment variables for debugging
+    tracing::debug!(target: LOG_SERVER, "ALL Environment Variables Available:");
+    let mut env_vars: Vec<_> = std::env::vars().collect();
+    env_vars.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (key, value) in env_vars {
+        tracing::debug!(target: LOG_SERVER, "{}: {}", key, masked_env_value(&key, &value));
+    }
+    tracing::debug!(target: LOG_SERVER, "End Environment Variables");
+
+    let state = BridgeState::new(project_dir)?;
+    state.check_catalog_permissions().await;
+
+    // Discover all available tools and initialize servers at startup
+    tracing::debug!(target: LOG_SERVER, "Initializing all MCP servers...");
+    if let Err(e) = state.discover_all_tools().await {
+        tracing::error!(target: LOG_SERVER, "Failed to discover tools at startup: {}", e);
+        return Err(e);
+    }
+    tracing::info!(target: LOG_SERVER, "All MCP servers initialized and ready");
+    state.spawn_watchdog();
+    state.spawn_discovery_refresh().await;
+    state.spawn_catalog_refresh();
+
+    let app = app(state.clone());
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+
+    tracing::info!(target: LOG_SERVER, "HTTP server listening on http://{}", addr);
+    tracing::debug!(target: LOG_SERVER, "MCP endpoint: http://{}/mcp", addr);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // Don't leave stdio servers orphaned behind us
+    let shutdown_timeout = state
+        .system_config_manager
+        .read()
+        .await
+        .get_config()
+        .shutdown_timeout_secs;
+    state
+        .connection_pool
+        .stop_all_servers(std::time::Duration::from_secs(shutdown_timeout))
+        .await;
+
+    Ok(())
+}
+
+/// The export written by `--export-tools` and served by `/export`: each server's
+/// discovered tools, with totals
+fn tools_export(
+    servers: &HashMap<String, ServerConfig>,
+    tools_by_server: &HashMap<String, Vec<Tool>>,
+) -> Value {
+    serde_json::json!({
+        "export_timestamp": chrono::Utc::now().to_rfc3339(),
+        "total_servers": servers.len(),
+        "total_tools_discovered": tools_by_server.values().map(|tools| tools.len()).sum::<usize>(),
+        "servers": tools_by_server.iter().map(|(server_name, tools)| {
+            let config = servers.get(server_name).unwrap();
+            serde_json::json!({
+                "name": server_name,
+                "description": config.description.as_deref().unwrap_or("No description"),
+                "command": config.command,
+                "args": config.args,
+                "tools_count": tools.len(),
+                "tools": tools.iter().map(|tool| serde_json::json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": tool.input_schema
+                })).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>()
+    })
+}
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM (what Kubernetes sends to stop the pod)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!(target: LOG_SERVER, "Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!(target: LOG_SERVER, "Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!(target: LOG_SERVER, "Shutdown signal received, shutting down");
+}
+
+/// Params of a forwarded `tools/call`. The client's `_meta` (progress token, trace
+/// context, ...) is passed through as is.
+fn tool_call_params(tool_name: &str, arguments: Value, meta: Option<&Value>) -> Value {
+    let mut params = json!({
+        "name": tool_name,
+        "arguments": arguments
+    });
+    if let Some(meta) = meta {
+        params["_meta"] = meta.clone();
+    }
+    params
+}
+
+/// Replace the `result` of a tool call response with the value at `pointer` (a JSON
+/// pointer such as `/data`). Responses without a result, or whose result has nothing at
+/// the pointer, are returned unchanged.
+fn unwrap_result(server_name: &str, tool_name: &str, mut response: Value, pointer: &str) -> Value {
+    let Some(result) = response.get_mut("result") else {
+        return response;
+    };
+    match result.pointer_mut(pointer) {
+        Some(inner) => *result = inner.take(),
+        None => tracing::warn!(
+            target: LOG_SERVER,
+            server = %server_name,
+            "Result of '{}' has nothing at unwrap_path '{}', returning it unchanged",
+            tool_name,
+            pointer
+        ),
+    }
+    response
+}
+
+/// Where a `JsonRpcRelay` is within the current line of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayPhase {
+    /// Before the message's opening `{`
+    Start,
+    /// Before a member's key, or the closing `}`
+    Key,
+    /// Inside a member's key
+    InKey,
+    /// Between a key and its `:`
+    Colon,
+    /// Between a `:` and the value
+    ValueStart,
+    /// Inside a member's value
+    Value,
+    /// The line isn't the awaited response; ignore it up to the newline
+    Skip,
+}
+
+/// What a `JsonRpcRelay` does with the bytes of the current member's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayMember {
+    Drop,
+    /// The backend's `id`, collected to tell whether this is the awaited response
+    Id,
+    Pass,
+}
+
+/// Rewrites a stdio server's output, as it is read, into the response to the client's
+/// `tools/call`. Lines other than the response to `request_id` (notifications, late
+/// responses, status messages) are dropped. In the response, the backend's `id` is
+/// replaced with the client's and every other member is passed through byte for byte,
+/// so a large result is never held in memory whole. Members that arrive before the `id`
+/// are held back until it shows whether the line is the response.
+struct JsonRpcRelay {
+    request_id: Value,
+    client_id: Value,
+    phase: RelayPhase,
+    member: RelayMember,
+    in_string: bool,
+    escaped: bool,
+    // Nesting depth inside the current value
+    depth: usize,
+    key: Vec<u8>,
+    id: Vec<u8>,
+    // Whether this line's `id` has been read and is the awaited one
+    matched: bool,
+    held: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl JsonRpcRelay {
+    fn new(request_id: Value, client_id: Value) -> Self {
+        Self {
+            request_id,
+            client_id,
+            phase: RelayPhase::Start,
+            member: RelayMember::Drop,
+            in_string: false,
+            escaped: false,
+            depth: 0,
+            key: Vec::new(),
+            id: Vec::new(),
+            matched: false,
+            held: Vec::new(),
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Whether any of the response has been produced; after that, a failure can no longer
+    /// be reported as a response of its own
+    fn started(&self) -> bool {
+        self.started
+    }
+
+    /// Whether the whole response has been produced
+    fn done(&self) -> bool {
+        self.done
+    }
+
+    /// Feed the next bytes of output. Returns how many were consumed, which is fewer than
+    /// given only once the response is complete, and the bytes of the response they
+    /// completed.
+    fn push(&mut self, bytes: &[u8]) -> (usize, Vec<u8>) {
+        let mut out = Vec::new();
+        for (consumed, &byte) in bytes.iter().enumerate() {
+            if self.done {
+                return (consumed, out);
+            }
+            self.push_byte(byte, &mut out);
+        }
+        (bytes.len(), out)
+    }
+
+    fn push_byte(&mut self, byte: u8, out: &mut Vec<u8>) {
+        // JSON strings can't hold a raw newline, so one always ends the line
+        if byte == b'\n' {
+            self.next_line();
+            return;
+        }
+        match self.phase {
+            RelayPhase::Skip => {}
+            RelayPhase::Start => match byte {
+                b'{' => self.phase = RelayPhase::Key,
+                _ if byte.is_ascii_whitespace() => {}
+                _ => self.phase = RelayPhase::Skip,
+            },
+            RelayPhase::Key => match byte {
+                b'"' => {
+                    self.key = vec![byte];
+                    self.phase = RelayPhase::InKey;
+                }
+                b'}' => self.end_message(out),
+                _ if byte.is_ascii_whitespace() => {}
+                _ => self.phase = RelayPhase::Skip,
+            },
+            RelayPhase::InKey => {
+                self.key.push(byte);
+                if self.end_of_string(byte) {
+                    self.phase = RelayPhase::Colon;
+                }
+            }
+            RelayPhase::Colon => match byte {
+                b':' => self.start_member(out),
+                _ if byte.is_ascii_whitespace() => {}
+                _ => self.phase = RelayPhase::Skip,
+            },
+            RelayPhase::ValueStart if byte.is_ascii_whitespace() => {}
+            RelayPhase::ValueStart | RelayPhase::Value => {
+                self.phase = RelayPhase::Value;
+                if !self.in_string && self.depth == 0 && (byte == b',' || byte == b'}') {
+                    self.end_member(out);
+                    if byte == b'}' {
+                        self.end_message(out);
+                    }
+                    return;
+                }
+                if self.in_string {
+                    self.end_of_string(byte);
+                } else {
+                    match byte {
+                        b'"' => self.in_string = true,
+                        b'{' | b'[' => self.depth += 1,
+                        b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+                match self.member {
+                    RelayMember::Drop => {}
+                    RelayMember::Id => self.id.push(byte),
+                    RelayMember::Pass => self.emit(&[byte], out),
+                }
+            }
+        }
+    }
+
+    /// Track escapes inside a string, returning whether `byte` closes it
+    fn end_of_string(&mut self, byte: u8) -> bool {
+        if self.escaped {
+            self.escaped = false;
+        } else if byte == b'\\' {
+            self.escaped = true;
+        } else if byte == b'"' {
+            self.in_string = false;
+            return true;
+        }
+        false
+    }
+
+    fn start_member(&mut self, out: &mut Vec<u8>) {
+        self.member = match self.key.as_slice() {
+            b"\"id\"" => RelayMember::Id,
+            b"\"jsonrpc\"" => RelayMember::Drop,
+            // Notifications and server-to-client requests
+            b"\"method\"" => {
+                self.phase = RelayPhase::Skip;
+                return;
+            }
+            _ => {
+                let mut prefix = vec![b','];
+                prefix.append(&mut self.key);
+                prefix.push(b':');
+                self.emit(&prefix, out);
+                RelayMember::Pass
+            }
+        };
+        self.phase = RelayPhase::ValueStart;
+    }
+
+    fn end_member(&mut self, out: &mut Vec<u8>) {
+        self.phase = RelayPhase::Key;
+        if self.member != RelayMember::Id {
+            return;
+        }
+        let id = serde_json::from_slice::<Value>(&self.id).ok();
+        self.id.clear();
+        if id.as_ref() != Some(&self.request_id) {
+            self.phase = RelayPhase::Skip;
+            return;
+        }
+        self.matched = true;
+        self.started = true;
+        out.extend_from_slice(b"{\"jsonrpc\":\"2.0\",\"id\":");
+        out.extend_from_slice(self.client_id.to_string().as_bytes());
+        out.append(&mut self.held);
+    }
+
+    fn end_message(&mut self, out: &mut Vec<u8>) {
+        if self.matched {
+            out.push(b'}');
+            self.done = true;
+        } else {
+            // A message without an id isn't a response
+            self.phase = RelayPhase::Skip;
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8], out: &mut Vec<u8>) {
+        if self.matched {
+            out.extend_from_slice(bytes);
+        } else {
+            self.held.extend_from_slice(bytes);
+        }
+    }
+
+    fn next_line(&mut self) {
+        // The response can't be cut short by a newline and resumed on another line
+        if self.started {
+            self.phase = RelayPhase::Skip;
+            return;
+        }
+        *self = Self::new(self.request_id.take(), self.client_id.take());
+    }
+}
+
+/// How backend SSE streams are read, from the proxy-wide config
+#[derive(Debug, Clone, Copy)]
+struct SseStreamSettings {
+    /// Trace-log every chunk and line (`sse_chunk_logging`)
+    log_chunks: bool,
+    /// Largest message buffered before the stream is given up on (`max_sse_message_bytes`)
+    max_message_bytes: usize,
+}
+
+impl SseStreamSettings {
+    fn from_config(config: &ServersConfig) -> Self {
+        Self {
+            log_chunks: config.sse_chunk_logging,
+            max_message_bytes: config.max_sse_message_bytes,
+        }
+    }
+}
+
+/// An SSE message grew past `max_sse_message_bytes` without ending
+#[derive(Debug, thiserror::Error)]
+#[error("SSE message from server '{server}' exceeded {limit} bytes without ending (max_sse_message_bytes); closing the stream")]
+struct SseMessageTooLarge {
+    server: String,
+    limit: usize,
+}
+
+/// Reassembles JSON-RPC messages from the chunks of an SSE stream. Chunk-by-chunk trace
+/// logging is only formatted when `log_chunks` (`sse_chunk_logging`) is set.
+struct SseMessageParser {
+    server_name: String,
+    settings: SseStreamSettings,
+    accumulated_data: String,
+    in_data_section: bool,
+}
+
+impl SseMessageParser {
+    fn new(server_name: String, settings: SseStreamSettings) -> Self {
+        Self {
+            server_name,
+            settings,
+            accumulated_data: String::new(),
+            in_data_section: false,
+        }
+    }
+
+    fn trace(&self, message: impl FnOnce() -> String) {
+        if self.settings.log_chunks {
+            tracing::trace!(target: LOG_BACKEND, server = %self.server_name, "{}", message());
+        }
+    }
+
+    /// Feed the next chunk of the stream, returning the messages it completed. Fails once
+    /// the message being read is larger than `max_message_bytes`.
+    fn push_chunk(&mut self, chunk: &[u8]) -> Result<Vec<Value>, SseMessageTooLarge> {
+        let mut messages = Vec::new();
+        let chunk_str = String::from_utf8_lossy(chunk);
+        self.trace(|| format!("SSE chunk received ({} bytes):\n{}", chunk.len(), chunk_str));
+
+        for line in chunk_str.lines() {
+            if line == "event: message" {
+                self.trace(|| "Found 'event: message'".to_string());
+                self.in_data_section = true;
+                self.accumulated_data.clear();
+            } else if let (Some(json_part), true) =
+                (line.strip_prefix("data: "), self.in_data_section)
+            {
+                // Accumulate data from this line (multiple data lines should be joined with newlines)
+                if !self.accumulated_data.is_empty() {
+                    self.accumulated_data.push('\n');
+                }
+                self.accumulated_data.push_str(json_part);
+                self.trace(|| format!("Accumulated {} bytes of data", self.accumulated_data.len()));
+            } else if let Some(json_str) = line.strip_prefix("data: ") {
+                // Handle standalone data lines without event prefix
+                self.trace(|| format!("Found standalone data line: '{}'", line));
+                self.accumulated_data = json_str.to_string();
+            } else if line.trim().is_empty() && !self.accumulated_data.is_empty() {
+                // Empty line indicates end of SSE message, try to parse accumulated data
+                self.trace(|| format!("End of SSE message: {}", self.accumulated_data));
+                match serde_json::from_str::<Value>(&self.accumulated_data) {
+                    Ok(message) => {
+                        messages.push(message);
+                        self.accumulated_data.clear();
+                        self.in_data_section = false;
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            target: LOG_BACKEND,
+                            server = %self.server_name,
+                            "Failed to parse accumulated JSON: {} (error: {})",
+                            self.accumulated_data,
+                            e
+                        );
+                        self.trace(|| {
+                            format!("JSON bytes: {:?}", self.accumulated_data.as_bytes())
+                        });
+                    }
+                }
+            } else if self.in_data_section && !line.trim().is_empty() {
+                // This might be a continuation of the previous data line that got split across chunks
+                self.accumulated_data.push_str(line);
+                self.trace(|| {
+                    format!(
+                        "Accumulated {} bytes after continuation",
+                        self.accumulated_data.len()
+                    )
+                });
+            } else {
+                self.trace(|| format!("Skipping line: '{}'", line));
+            }
+
+            if self.accumulated_data.len() > self.settings.max_message_bytes {
+                return Err(SseMessageTooLarge {
+                    server: self.server_name.clone(),
+                    limit: self.settings.max_message_bytes,
+                });
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Parse whatever data is left once the stream has ended
+    fn finish(self) -> Option<Value> {
+        if self.accumulated_data.is_empty() {
+            return None;
+        }
+        match serde_json::from_str::<Value>(&self.accumulated_data) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                tracing::error!(
+                    target: LOG_BACKEND,
+                    server = %self.server_name,
+                    "Failed to parse final accumulated JSON: {} (error: {})",
+                    self.accumulated_data,
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Read an SSE stream to its end, sending each JSON-RPC message on it to `tx`
+async fn forward_sse_messages(
+    mut body: impl futures::Stream<Item = reqwest::Result<axum::body::Bytes>> + Unpin,
+    server_name: String,
+    settings: SseStreamSettings,
+    responses: SseResponses,
+) {
+    use futures::StreamExt;
+
+    let mut parser = SseMessageParser::new(server_name.clone(), settings);
+    while let Some(chunk_result) = body.next().await {
+        match chunk_result {
+            Ok(chunk) => match parser.push_chunk(&chunk) {
+                Ok(messages) => {
+                    for message in messages {
+                        responses.route(&server_name, message);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(target: LOG_BACKEND, server = %server_name, "{}", e);
+                    responses.fail(e.to_string());
+                    return;
+                }
+            },
+            Err(e) => {
+                tracing::error!(
+                    target: LOG_BACKEND,
+                    server = %parser.server_name,
+                    "SSE stream error: {}",
+                    e
+                );
+                break;
+            }
+        }
+    }
+
+    // Stream ended - try to parse any remaining accumulated data
+    if let Some(message) = parser.finish() {
+        responses.route(&server_name, message);
+    }
+    responses.close();
+}
+
+/// Requests waiting for their response on an SSE stream, keyed by JSON-RPC id. The
+/// stream's listener hands each response to the request with the same id, so
+/// notifications and out-of-order responses can't be mistaken for the one awaited.
+#[derive(Debug, Clone)]
+struct SseResponses {
+    server_name: String,
+    // None once the stream has ended
+    waiting: Arc<std::sync::Mutex<Option<SseWaiters>>>,
+    // Why the stream was given up on, if it didn't simply end
+    failure: Arc<std::sync::Mutex<Option<String>>>,
+}
+
+/// Senders for the responses still awaited, keyed by the request id's JSON text
+type SseWaiters = HashMap<String, tokio::sync::oneshot::Sender<Value>>;
+
+/// First id handed out for requests on an SSE session; the initialize request uses 1
+const FIRST_SESSION_REQUEST_ID: u64 = 2;
+
+/// Where SSE session request ids wrap around unless `max_request_id` says otherwise: the
+/// largest integer a JavaScript number holds exactly, so JS backends see the ids intact
+const DEFAULT_MAX_REQUEST_ID: u64 = (1 << 53) - 1;
+
+impl SseResponses {
+    fn new(server_name: &str) -> Self {
+        Self {
+            server_name: server_name.to_string(),
+            waiting: Arc::new(std::sync::Mutex::new(Some(HashMap::new()))),
+            failure: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Start waiting for the response to `id`. Call before sending the request, so a
+    /// fast response can't arrive before anyone is waiting for it.
+    fn expect(&self, id: &Value) -> tokio::sync::oneshot::Receiver<Value> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        if let Some(waiting) = self.waiting.lock().unwrap().as_mut() {
+            waiting.insert(id.to_string(), tx);
+        }
+        rx
+    }
+
+    /// Take the next request id from `next_id` and start waiting for its response, as
+    /// `expect` does. Ids count up to `max_id` and then wrap around to
+    /// `FIRST_SESSION_REQUEST_ID`. An id whose response is still awaited is skipped, so a
+    /// wrapped counter never hands out an id that is in flight. Fails when every id is.
+    fn expect_next(
+        &self,
+        next_id: &std::sync::Mutex<u64>,
+        max_id: u64,
+        id_type: RequestIdType,
+    ) -> anyhow::Result<(Value, tokio::sync::oneshot::Receiver<Value>)> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut waiting = self.waiting.lock().unwrap();
+        let mut next_id = next_id.lock().unwrap();
+        let max_id = max_id.max(FIRST_SESSION_REQUEST_ID);
+        let mut take_id = || {
+            let id = *next_id;
+            *next_id = if id >= max_id {
+                FIRST_SESSION_REQUEST_ID
+            } else {
+                id + 1
+            };
+            id_type.id(id)
+        };
+
+        let Some(waiting) = waiting.as_mut() else {
+            // The stream has ended; waiting on the id fails straight away
+            return Ok((take_id(), rx));
+        };
+        // One of the next `waiting.len() + 1` ids is free, unless the id space is smaller
+        let id_space = max_id - FIRST_SESSION_REQUEST_ID + 1;
+        for _ in 0..id_space.min(waiting.len() as u64 + 1) {
+            let id = take_id();
+            if let std::collections::hash_map::Entry::Vacant(entry) = waiting.entry(id.to_string())
+            {
+                entry.insert(tx);
+                return Ok((id, rx));
+            }
+        }
+        Err(anyhow::anyhow!(
+            "server '{}' has a request in flight for every id up to {} (max_request_id)",
+            self.server_name,
+            max_id
+        ))
+    }
+
+    /// Wait for a response registered with `expect`, giving up after `timeout`
+    async fn wait(
+        &self,
+        id: &Value,
+        response: tokio::sync::oneshot::Receiver<Value>,
+        timeout: tokio::time::Duration,
+        what: &str,
+    ) -> anyhow::Result<Value> {
+        match tokio::time::timeout(timeout, response).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => match self.failure.lock().unwrap().as_deref() {
+                Some(failure) => Err(anyhow::anyhow!(
+                    "SSE stream closed while waiting for {} response: {}",
+                    what,
+                    failure
+                )),
+                None => Err(anyhow::anyhow!(
+                    "SSE stream closed while waiting for {} response",
+                    what
+                )),
+            },
+            Err(_) => {
+                if let Some(waiting) = self.waiting.lock().unwrap().as_mut() {
+                    waiting.remove(&id.to_string());
+                }
+                Err(ResponseTimedOut {
+                    server: self.server_name.clone(),
+                    what: what.to_string(),
+                    secs: timeout.as_secs(),
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Hand a message read from the stream to the request it answers
+    fn route(&self, server_name: &str, message: Value) {
+        // Notifications and server-to-client requests carry a method
+        if message.get("method").is_some() {
+            tracing::debug!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Ignoring SSE message: {}",
+                message["method"]
+            );
+            return;
+        }
+        let waiter = message.get("id").and_then(|id| {
+            self.waiting
+                .lock()
+                .unwrap()
+                .as_mut()?
+                .remove(&id.to_string())
+        });
+        match waiter {
+            Some(waiter) => {
+                let _ = waiter.send(message);
+            }
+            None => tracing::warn!(
+                target: LOG_BACKEND,
+                server = %server_name,
+                "Dropping SSE response no request is waiting for: {}",
+                message
+            ),
+        }
+    }
+
+    /// The stream ended: fail every current and future wait
+    fn close(&self) {
+        *self.waiting.lock().unwrap() = None;
+    }
+
+    /// The stream was given up on: fail every current and future wait with `reason`
+    fn fail(&self, reason: String) {
+        *self.failure.lock().unwrap() = Some(reason);
+        self.close();
+    }
+}
+
+/// An initialized session with an SSE backend, kept open and reused across tool calls
+/// instead of reconnecting and re-running the handshake for every call
+#[derive(Debug)]
+struct SseSession {
+    message_url: String,
+    // Responses read from the SSE stream, routed to their requests by id
+    responses: SseResponses,
+    // Held for the length of a call, so calls on one session run one at a time
+    in_use: Mutex<()>,
+    next_request_id: std::sync::Mutex<u64>,
+    // Where `next_request_id` wraps around
+    max_request_id: u64,
+    // Task reading the SSE stream, stopped when the session is dropped
+    listener: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for SseSession {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl SseSession {
+    /// Open the SSE stream and complete the MCP handshake over it
+    async fn open(
+        client: &reqwest::Client,
+        server_name: &str,
+        sse_url: &str,
+        init_request: Value,
+        stream_settings: SseStreamSettings,
+        max_request_id: u64,
+    ) -> anyhow::Result<Self> {
+        use futures::StreamExt;
+        use tokio::time::{timeout, Duration};
+
+        tracing::info!(target: LOG_BACKEND, server = %server_name, "Opening SSE session");
+
+        // Step 1: Open SSE connection and get session ID
+        let sse_response = client
+            .get(sse_url)
+            .header("Accept", "text/event-stream")
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to SSE endpoint: {}", e))?;
+
+        let mut body = sse_response.bytes_stream();
+
+        // Wait for session data from SSE stream, reading multiple chunks if needed
+        let mut accumulated_data = String::new();
+        let session_id = loop {
+            match timeout(Duration::from_secs(10), body.next()).await {
+                Ok(Some(Ok(chunk))) => {
+                    let chunk_str = String::from_utf8_lossy(&chunk);
+                    accumulated_data.push_str(&chunk_str);
+
+                    tracing::debug!(
+                        target: LOG_BACKEND,
+                        server = %server_name,
+                        "SSE handshake data: {}",
+                        chunk_str.trim()
+                    );
+
+                    // Try to parse session ID from accumulated data
+                    if let Some(data_line) = accumulated_data
+                        .lines()
+                        .find(|line| line.starts_with("data: "))
+                    {
+                        let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
+                        if let Some(session_param) = endpoint_path.split("sessionId=").nth(1) {
+                            break session_param.to_string();
+                        }
+                    }
+
+                    // If we have an "event: endpoint" but no data line yet, continue reading
+                    if accumulated_data.contains("event: endpoint")
+                        && !accumulated_data
+                            .lines()
+                            .any(|line| line.starts_with("data: "))
+                    {
+                        continue;
+                    }
+
+                    // If we've accumulated data but can't find session ID, something's wrong
+                    if accumulated_data.len() > 1000 {
+                        return Err(anyhow::anyhow!(
+                            "Could not find sessionId in SSE data after reading {} chars",
+                            accumulated_data.len()
+                        ));
+                    }
+                }
+                Ok(Some(Err(e))) => return Err(anyhow::anyhow!("SSE stream error: {}", e)),
+                Ok(None) => return Err(anyhow::anyhow!("SSE stream ended unexpectedly")),
+                Err(_) => return Err(anyhow::anyhow!("Timeout waiting for SSE session data")),
+            }
+        };
+
+        tracing::info!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Got SSE session ID: {}",
+            session_id
+        );
+
+        // Step 2: Prepare message endpoint
+        let base_url = sse_url.trim_end_matches("/sse").trim_end_matches('/');
+        let message_url = format!("{}/message?sessionId={}", base_url, session_id);
+
+        // Step 3: Start listening for responses in background task
+        let responses = SseResponses::new(server_name);
+
+        // Spawn SSE response listener
+        let listener = tokio::spawn(forward_sse_messages(
+            body,
+            server_name.to_string(),
+            stream_settings,
+            responses.clone(),
+        ));
+
+        // From here on, dropping the session on an error stops the listener
+        let session = Self {
+            message_url,
+            responses,
+            in_use: Mutex::new(()),
+            next_request_id: std::sync::Mutex::new(FIRST_SESSION_REQUEST_ID),
+            max_request_id,
+            listener,
+        };
+
+        // Step 4: Send MCP handshake sequence
+
+        // 4a. Send initialize request
+        let init_id = init_request["id"].clone();
+        let init_resp = session.responses.expect(&init_id);
+        let init_response = client
+            .post(&session.message_url)
+            .json(&init_request)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send initialize request: {}", e))?;
+
+        if !init_response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Initialize request failed with status: {}",
+                init_response.status()
+            ));
+        }
+
+        // Wait for initialize response
+        session
+            .responses
+            .wait(&init_id, init_resp, Duration::from_secs(10), "initialize")
+            .await?;
+
+        tracing::debug!(target: LOG_BACKEND, server = %server_name, "MCP initialize completed");
+
+        // 4b. Send initialized notification
+        let initialized_notification = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+            "params": {}
+        });
+
+        let notif_response = client
+            .post(&session.message_url)
+            .json(&initialized_notification)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send initialized notification: {}", e))?;
+
+        if !notif_response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Initialized notification failed with status: {}",
+                notif_response.status()
+            ));
+        }
+
+        tracing::info!(target: LOG_BACKEND, server = %server_name, "MCP session established");
+
+        Ok(session)
+    }
+
+    /// Whether no call is using the session right now
+    fn is_idle(&self) -> bool {
+        self.in_use.try_lock().is_ok()
+    }
+
+    /// Call a tool on this session, waiting for the response with the matching id
+    async fn call_tool(
+        &self,
+        client: &reqwest::Client,
+        server_name: &str,
+        tool_name: &str,
+        params: Value,
+        id_type: RequestIdType,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<Value> {
+        let _in_use = self.in_use.lock().await;
+        let (request_id, response) =
+            self.responses
+                .expect_next(&self.next_request_id, self.max_request_id, id_type)?;
+        let tool_call_request = json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": "tools/call",
+            "params": params
+        });
+
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Sending tool call: {}",
+            tool_name
+        );
+
+        let call_response = client
+            .post(&self.message_url)
+            .json(&tool_call_request)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send tool call request: {}", e))?;
+
+        if !call_response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Tool call request failed with status: {}",
+                call_response.status()
+            ));
+        }
+
+        // Wait for the tool call response via SSE
+        let response = self
+            .responses
+            .wait(&request_id, response, timeout, "tools/call")
+            .await?;
+        tracing::debug!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Tool call completed: {}",
+            tool_name
+        );
+        Ok(response)
+    }
+}
+
+/// Socket of a WebSocket backend connection
+type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// The socket to a WebSocket backend was closed or broke; it has to be reopened
+#[derive(Debug, thiserror::Error)]
+#[error("WebSocket connection closed: {0}")]
+struct WebSocketClosed(String);
+
+/// An initialized connection to a WebSocket backend, kept in the pool and reused across
+/// calls like a stdio server's pipes
+#[derive(Debug)]
+struct WebSocketConnection {
+    socket: WebSocketStream,
+    next_request_id: u64,
+    id_type: RequestIdType,
+}
+
+impl WebSocketConnection {
+    /// Connect to `url` and complete the MCP handshake, returning the initialize response too
+    async fn open(
+        server_name: &str,
+        url: &str,
+        init_request: Value,
+        id_type: RequestIdType,
+    ) -> anyhow::Result<(Self, Value)> {
+        use tokio::time::{timeout, Duration};
+
+        tracing::info!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "Opening WebSocket connection: {}",
+            url
+        );
+        let (socket, _) = timeout(
+            Duration::from_secs(10),
+            tokio_tungstenite::connect_async(url),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Timeout connecting to WebSocket {}", url))?
+        .map_err(|e| anyhow::anyhow!("Failed to connect to WebSocket {}: {}", url, e))?;
+        let mut connection = Self {
+            socket,
+            // The initialize request uses id 1
+            next_request_id: 2,
+            id_type,
+        };
+
+        connection.send(&init_request).await?;
+        let init_response = connection
+            .read_response(server_name, &init_request["id"])
+            .await?;
+        connection
+            .send(&json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/initialized"
+            }))
+            .await?;
+
+        tracing::info!(
+            target: LOG_BACKEND,
+            server = %server_name,
+            "WebSocket connection initialized"
+        );
+        Ok((connection, init_response))
+    }
+
+    /// Send a request and wait for the response with its id
+    async fn request(
+        &mut self,
+        server_name: &str,
+        method: &str,
+        params: Value,
+        backend_request_id: Option<&BackendRequestId>,
+    ) -> anyhow::Result<Value> {
+        let request_id = self.id_type.id(self.next_request_id);
+        self.next_request_id += 1;
+        if let Some(slot) = backend_request_id {
+            *slot.lock().unwrap() = Some(request_id.clone());
+        }
+
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": method,
+            "params": params
+        }))
+        .await?;
+        self.read_response(server_name, &request_id).await
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<(), WebSocketClosed> {
+        use futures::SinkExt;
+
+        self.socket
+            .send(tokio_tungstenite::tungstenite::Message::text(
+                message.to_string(),
+            ))
+            .await
+            .map_err(|e| WebSocketClosed(e.to_string()))
+    }
+
+    /// Read frames until the response to `request_id` arrives, skipping notifications and
+    /// server requests that come in ahead of it
+    async fn read_response(
+        &mut self,
+        server_name: &str,
+        request_id: &Value,
+    ) -> anyhow::Result<Value> {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let timeout_secs = 120; // Generous timeout for all tool calls
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(timeout_secs);
+        loop {
+            let frame = tokio::time::timeout_at(deadline, self.socket.next())
+                .await
+                .map_err(|_| {
+                    anyhow::anyhow!(
+                        "Timeout waiting for WebSocket response (waited {}s)",
+                        timeout_secs
+                    )
+                })?;
+            let text = match frame {
+                Some(Ok(Message::Text(text))) => text.as_str().to_string(),
+                Some(Ok(Message::Binary(data))) => String::from_utf8_lossy(&data).into_owned(),
+                Some(Ok(Message::Close(frame))) => {
+                    return Err(WebSocketClosed(format!("closed by server ({:?})", frame)).into())
+                }
+                // Pings are answered by the socket itself
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(WebSocketClosed(e.to_string()).into()),
+                None => return Err(WebSocketClosed("stream ended".to_string()).into()),
+            };
+
+            match serde_json::from_str::<Value>(&text) {
+                Ok(message)
+                    if message.get("id") == Some(request_id) && message.get("method").is_none() =>
+                {
+                    return Ok(message)
+                }
+                Ok(message) => tracing::debug!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Skipping WebSocket message while waiting for {}: {}",
+                    request_id,
+                    message.get("method").and_then(|m| m.as_str()).unwrap_or("response")
+                ),
+                Err(_) => tracing::warn!(
+                    target: LOG_BACKEND,
+                    server = %server_name,
+                    "Skipping non-JSON WebSocket message: {}",
+                    text
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line-oriented MCP server written in sh. It answers `initialize`, `tools/list`
+    /// (with `$MOCK_TOOLS_LIST`) and `tools/call` (with `$MOCK_TOOLS_CALL`, after sleeping
+    /// `$MOCK_CALL_DELAY` seconds), echoing the request id.
+    const MOCK_STDIO_SERVER: &str = r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) result='{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"mock","version":"0.1.0"}}' ;;
+    *'"method":"tools/list"'*) result="${MOCK_FIRST_TOOLS_LIST:-$MOCK_TOOLS_LIST}"; MOCK_FIRST_TOOLS_LIST= ;;
+    *'"method":"tools/call"'*) sleep "${MOCK_CALL_DELAY:-0}"; result="$MOCK_TOOLS_CALL" ;;
+    *) continue ;;
+  esac
+  printf '{"jsonrpc":"2.0","id":%s,"result":%s}\n' "$id" "$result"
+done"#;
+
+    /// Server config for a mock stdio backend exposing `tool`, whose calls return `text`
+    fn mock_stdio_server(tool: &str, text: &str) -> Value {
+        mock_stdio_server_returning(tool, json!({ "content": [{"type": "text", "text": text}] }))
+    }
+
+    /// Server config for a mock stdio backend exposing `tool`, whose calls return `call_result`
+    fn mock_stdio_server_returning(tool: &str, call_result: Value) -> Value {
+        json!({
+            "command": "sh",
+            "args": ["-c", MOCK_STDIO_SERVER],
+            "env": {
+                "MOCK_TOOLS_LIST": json!({
+                    "tools": [{"name": tool, "description": "mock tool", "inputSchema": {"type": "object"}}]
+                }).to_string(),
+                "MOCK_TOOLS_CALL": call_result.to_string()
+            }
+        })
+    }
+
+    /// Build a BridgeState backed by a servers-config.json written to a temp directory
+    fn state_with_servers(servers: Value) -> (tempfile::TempDir, BridgeState) {
+        state_with_config(json!({ "servers": servers }))
+    }
+
+    /// Build a BridgeState from a complete servers-config.json document
+    fn state_with_config(config: Value) -> (tempfile::TempDir, BridgeState) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("servers-config.json"), config.to_string()).unwrap();
+        let state = BridgeState::new(Some(dir.path().to_path_buf())).unwrap();
+        (dir, state)
+    }
+
+    /// Request headers seen by a mock HTTP backend, in arrival order
+    type SeenHeaders = Arc<std::sync::Mutex<Vec<axum::http::HeaderMap>>>;
+
+    /// A streamable-HTTP MCP backend on `/mcp` exposing `tool`, whose calls return `call_result`
+    fn mock_http_backend(tool: &str, call_result: Value, seen: SeenHeaders) -> Router {
+        let tools = json!({
+            "tools": [{"name": tool, "description": "mock tool", "inputSchema": {"type": "object"}}]
+        });
+        Router::new().route(
+            "/mcp",
+            post(
+                move |headers: axum::http::HeaderMap, Json(request): Json<Value>| async move {
+                    seen.lock().unwrap().push(headers);
+                    let result = match request["method"].as_str() {
+                        Some("initialize") => json!({
+                            "protocolVersion": "2024-11-05",
+                            "capabilities": {"tools": {}},
+                            "serverInfo": {"name": "mock-http", "version": "0.1.0"}
+                        }),
+                        Some("tools/list") => tools,
+                        Some("tools/call") => call_result,
+                        _ => return Json(json!({})),
+                    };
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+                },
+            ),
+        )
+    }
 
-                println!(
-                    "✅ [{}] Discovered {} tools via existing connection",
-                    server_name,
-                    parsed_tools.len(),
-                );
+    /// Serve `router` on an ephemeral local port and return its base URL
+    async fn spawn_http_backend(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}", addr)
+    }
 
-                return Ok(parsed_tools);
+    /// A WebSocket MCP backend exposing an `echo` tool. It sends a log notification ahead of
+    /// every response and hangs up after each tool call. Returns its URL and a count of
+    /// the connections it accepted.
+    async fn spawn_websocket_backend() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = socket.next().await {
+                        let request: Value = serde_json::from_str(text.as_str()).unwrap();
+                        if request.get("id").is_none() {
+                            continue;
+                        }
+                        let result = match request["method"].as_str() {
+                            Some("initialize") => json!({"protocolVersion": "2024-11-05",
+                                "capabilities": {"tools": {}}, "serverInfo": {"name": "ws", "version": "3.1.0"}}),
+                            Some("tools/list") => json!({"tools": [{"name": "echo",
+                                "description": "Echo", "inputSchema": {"type": "object"}}]}),
+                            _ => json!({"content": [{"type": "text",
+                                "text": request["params"]["arguments"]["text"]}]}),
+                        };
+                        let notice = json!({"jsonrpc": "2.0", "method": "notifications/message",
+                            "params": {"level": "info", "data": "working"}});
+                        let response =
+                            json!({"jsonrpc": "2.0", "id": request["id"], "result": result});
+                        socket
+                            .send(Message::text(notice.to_string()))
+                            .await
+                            .unwrap();
+                        socket
+                            .send(Message::text(response.to_string()))
+                            .await
+                            .unwrap();
+                        if request["method"] == "tools/call" {
+                            let _ = socket.close(None).await;
+                            break;
+                        }
+                    }
+                });
             }
+        });
+        (format!("ws://{}", addr), accepted)
+    }
+
+    /// Register a tool as if it had been discovered, without starting its server
+    async fn register_tool(state: &BridgeState, server: &str, tool: &str) {
+        state.available_tools.write().await.insert(
+            prefixed_tool_name(&sanitize_name(server), tool),
+            Tool {
+                name: tool.to_string(),
+                description: format!("{} tool", tool),
+                input_schema: json!({"type": "object"}),
+                server_name: server.to_string(),
+                original_tool_name: tool.to_string(),
+                annotations: None,
+                labels: Vec::new(),
+            },
+        );
+    }
+
+    /// Prefixed names returned by tools/list, excluding toolman's built-in tools
+    async fn listed_tools(state: &BridgeState) -> Vec<String> {
+        listed_tools_with_params(state, None).await
+    }
+
+    /// Like `listed_tools`, for a tools/list request with the given params
+    async fn listed_tools_with_params(state: &BridgeState, params: Option<Value>) -> Vec<String> {
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params,
+        };
+        let result = state
+            .handle_jsonrpc_request(request, None)
+            .await
+            .result
+            .unwrap();
+        let mut names: Vec<String> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap().to_string())
+            .filter(|n| !n.starts_with("toolman_"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn tool_call(name: &str, arguments: Value) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": name, "arguments": arguments })),
         }
+    }
 
-        // If no tools found or parsing failed
-        println!("⚠️ [{}] No tools found in response", server_name);
-        Ok(Vec::new())
+    /// Headers of a request from a client working in `dir`
+    fn working_dir_headers(dir: impl AsRef<std::path::Path>) -> axum::http::HeaderMap {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            "x-working-directory",
+            dir.as_ref().to_str().unwrap().parse().unwrap(),
+        );
+        headers
     }
 
-    // Discover tools from a single server (without "starting" it permanently)
-    async fn discover_server_tools(
-        &self,
-        server_name: &str,
-        config: &ServerConfig,
-    ) -> anyhow::Result<Vec<Tool>> {
-        let start_time = std::time::Instant::now();
-        println!(
-            "🔍 [{}] Starting tool discovery at {:?}",
-            server_name,
-            chrono::Utc::now().format("%H:%M:%S")
+    #[tokio::test]
+    async fn test_shadow_returns_primary_and_reports_diff() {
+        let mut primary = mock_stdio_server("lookup", "from primary");
+        primary["shadow"] = json!("candidate");
+        let (_dir, state) = state_with_servers(json!({
+            "primary": primary,
+            "candidate": mock_stdio_server("lookup", "from candidate"),
+        }));
+
+        // The shadow call runs on this (single-threaded) runtime, so its log lands here too
+        let captured = CapturedLogs::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(log_filter(Some("debug")))
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("primary_lookup", json!({})), None)
+            .await;
+        assert_eq!(
+            response.result.unwrap()["content"][0]["text"],
+            "from primary"
         );
 
-        // For stdio servers, check if we already have a connection (reuse it to avoid deadlock)
-        if config.transport == "stdio" {
-            // CRITICAL: Minimize read lock duration to prevent deadlock with write locks
-            let connection = {
-                let connections = self.connection_pool.connections.read().await;
-                if let Some(connection) = connections.get(server_name) {
-                    println!(
-                        "🔄 [{}] Reusing existing stdio connection for tool discovery",
-                        server_name
-                    );
-                    Some(connection.clone()) // Clone before dropping the lock
-                } else {
-                    None
-                }
-            }; // Read lock automatically dropped here
+        // The handler doesn't wait for the shadow, which reports its diff once it answers
+        let mut mismatch = None;
+        for _ in 0..100 {
+            let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+            mismatch = logs
+                .lines()
+                .find(|line| line.contains("Shadow mismatch"))
+                .map(str::to_string);
+            if mismatch.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let mismatch = mismatch.expect("disagreeing backends should log a diff");
+        assert!(
+            mismatch.contains("from primary") && mismatch.contains("from candidate"),
+            "{}",
+            mismatch
+        );
+        state.connection_pool.stop_server("primary").await.unwrap();
+        state
+            .connection_pool
+            .stop_server("candidate")
+            .await
+            .unwrap();
+    }
 
-            if let Some(connection) = connection {
-                // Send tools/list request using existing connection
-                let tools_request = json!({
-                    "jsonrpc": "2.0",
-                    "id": 2,
-                    "method": "tools/list"
-                });
+    #[test]
+    fn test_catalog_permission_failure_is_actionable() {
+        let forbidden = kube::Error::Api(kube::core::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "configmaps \"toolman-tool-catalog\" is forbidden: User \"system:serviceaccount:mcp:toolman\" cannot patch resource \"configmaps\" in API group \"\" in the namespace \"mcp\"".to_string(),
+            reason: "Forbidden".to_string(),
+            code: 403,
+        });
 
-                if let Err(e) = self
-                    .connection_pool
-                    .send_request(connection.clone(), tools_request)
-                    .await
-                {
-                    return Err(anyhow::anyhow!("Failed to send tools/list request: {}", e));
-                }
+        let message = catalog_permission_message(&forbidden, "mcp");
+        assert!(message.contains("missing RBAC verb 'patch'"));
+        assert!(message.contains("configmaps in namespace 'mcp'"));
 
-                // Read response
-                match self.connection_pool.read_response(connection.clone()).await {
-                    Ok(response) => {
-                        return self.parse_tools_response(server_name, response);
-                    }
-                    Err(e) => {
-                        return Err(anyhow::anyhow!("Failed to read tools/list response: {}", e));
-                    }
-                }
-            } else {
-                println!(
-                    "🔄 [{}] No existing connection found, will spawn temporary process",
-                    server_name
-                );
+        let unavailable = kube::Error::Api(kube::core::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "etcd unavailable".to_string(),
+            reason: "ServiceUnavailable".to_string(),
+            code: 503,
+        });
+        assert!(!catalog_permission_message(&unavailable, "mcp").contains("RBAC"));
+    }
+
+    #[tokio::test]
+    async fn test_paginated_result_pages_through_cursor() {
+        let items: Vec<Value> = (0..25)
+            .map(|i| json!({"type": "text", "text": format!("file{}", i)}))
+            .collect();
+        let mut server = mock_stdio_server_returning("list_files", json!({ "content": items }));
+        server["tools"] = json!({ "list_files": { "page_size": 10 } });
+        let (_dir, state) = state_with_servers(json!({ "fs": server }));
+
+        let first = state
+            .handle_jsonrpc_request(tool_call("fs_list_files", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        assert_eq!(first["content"].as_array().unwrap().len(), 10);
+        assert_eq!(first["content"][0]["text"], "file0");
+
+        let mut cursor = first["nextCursor"].as_str().unwrap().to_string();
+        let mut fetched = vec![first];
+        loop {
+            let request = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(2)),
+                method: "tools/call".to_string(),
+                params: Some(json!({ "name": "fs_list_files", "cursor": cursor })),
+            };
+            let page = state
+                .handle_jsonrpc_request(request, None)
+                .await
+                .result
+                .unwrap();
+            let next = page
+                .get("nextCursor")
+                .and_then(|c| c.as_str())
+                .map(String::from);
+            fetched.push(page);
+            match next {
+                Some(next) => cursor = next,
+                None => break,
             }
         }
 
-        // Handle HTTP and SSE transports
-        if config.transport == "http" || config.transport == "sse" {
-            if let Some(url) = &config.url {
-                println!(
-                    "🌐 [{}] Discovering tools from HTTP server: {}",
-                    server_name, url
-                );
-
-                let client = reqwest::Client::new();
+        let texts: Vec<&str> = fetched
+            .iter()
+            .flat_map(|p| p["content"].as_array().unwrap())
+            .map(|c| c["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(fetched.len(), 3);
+        assert_eq!(texts.len(), 25);
+        assert_eq!(texts[24], "file24");
+
+        // Cursors are single-use
+        let replay = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(3)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": "fs_list_files", "cursor": cursor })),
+        };
+        assert!(state
+            .handle_jsonrpc_request(replay, None)
+            .await
+            .error
+            .is_some());
+    }
 
-                // Use transport type to determine communication method
-                println!(
-                    "🔍 [{}] URL: {}, transport: {}",
-                    server_name, url, config.transport
-                );
-                let (message_url, session_id) = if config.transport == "sse" {
-                    println!(
-                        "🔄 [{}] Detected SSE endpoint, starting SSE handshake",
-                        server_name
-                    );
-                    let sse_response = client
-                        .get(url)
-                        .header("Accept", "text/event-stream")
-                        .send()
-                        .await;
+    #[tokio::test]
+    async fn test_http_user_agent_default_and_override() {
+        let seen = SeenHeaders::default();
+        let base = spawn_http_backend(mock_http_backend("ping", json!({}), seen.clone())).await;
+        let (_dir, state) = state_with_servers(json!({
+            "plain": { "transport": "http", "url": format!("{}/mcp", base) },
+            "custom": { "transport": "http", "url": format!("{}/mcp", base), "user_agent": "acme-agent/2.0" },
+        }));
+
+        let user_agents = |seen: &SeenHeaders| -> Vec<String> {
+            seen.lock()
+                .unwrap()
+                .drain(..)
+                .map(|h| h["user-agent"].to_str().unwrap().to_string())
+                .collect()
+        };
 
-                    if let Ok(response) = sse_response {
-                        let content_type = response
-                            .headers()
-                            .get("content-type")
-                            .and_then(|v| v.to_str().ok())
-                            .unwrap_or("");
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+        state
+            .discover_server_tools("plain", &servers["plain"])
+            .await
+            .unwrap();
+        let agents = user_agents(&seen);
+        assert!(!agents.is_empty());
+        assert!(agents
+            .iter()
+            .all(|ua| ua == concat!("toolman/", env!("CARGO_PKG_VERSION"))));
+
+        state
+            .handle_jsonrpc_request(tool_call("custom_ping", json!({})), None)
+            .await;
+        assert_eq!(user_agents(&seen), vec!["acme-agent/2.0".to_string()]);
+    }
 
-                        if content_type.contains("text/event-stream") {
-                            // This is an SSE endpoint, parse the session info
-                            // For SSE, we only need to read the initial chunk with session ID
-                            let mut body = response.bytes_stream();
-                            use futures::StreamExt;
+    #[tokio::test]
+    async fn test_client_config_round_trip_sets_enabled_tools() {
+        let (_dir, state) = state_with_servers(json!({
+            "memory": { "command": "true" },
+            "git-hub": { "command": "true" },
+        }));
+        for (server, tool) in [
+            ("memory", "read_graph"),
+            ("memory", "delete_entities"),
+            ("git-hub", "create-issue"),
+            ("git-hub", "list_prs"),
+        ] {
+            register_tool(&state, server, tool).await;
+        }
+        assert_eq!(listed_tools(&state).await.len(), 4);
+
+        let Json(mut config) = client_config_endpoint(State(state.clone())).await.unwrap();
+        for server in config["servers"].as_object_mut().unwrap().values_mut() {
+            for tool in server["tools"].as_array_mut().unwrap() {
+                assert_eq!(tool["enabled"], false);
+                if tool["name"] == "read_graph" || tool["name"] == "create-issue" {
+                    tool["enabled"] = json!(true);
+                }
+            }
+        }
 
-                            let first_chunk = match tokio::time::timeout(
-                                tokio::time::Duration::from_secs(10),
-                                body.next(),
-                            )
-                            .await
-                            {
-                                Ok(Some(Ok(chunk))) => String::from_utf8_lossy(&chunk).to_string(),
-                                Ok(Some(Err(e))) => {
-                                    return Err(anyhow::anyhow!("Failed to read SSE chunk: {}", e))
-                                }
-                                Ok(None) => {
-                                    return Err(anyhow::anyhow!(
-                                        "No data received from SSE endpoint"
-                                    ))
-                                }
-                                Err(_) => {
-                                    return Err(anyhow::anyhow!(
-                                        "Timeout waiting for SSE session data (waited 10s)"
-                                    ))
-                                }
-                            };
+        let Json(summary) = update_client_config_endpoint(State(state.clone()), Json(config))
+            .await
+            .unwrap();
+        assert_eq!(summary["total_enabled"], 2);
+        assert_eq!(
+            listed_tools(&state).await,
+            vec![
+                "git_hub_create_issue".to_string(),
+                "memory_read_graph".to_string()
+            ]
+        );
 
-                            // Parse SSE format: "event: endpoint\ndata: /message?sessionId=xxx"
-                            let session_id = if let Some(data_line) =
-                                first_chunk.lines().find(|line| line.starts_with("data: "))
-                            {
-                                let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
-                                if let Some(session_param) =
-                                    endpoint_path.split("sessionId=").nth(1)
-                                {
-                                    session_param.to_string()
-                                } else {
-                                    return Err(anyhow::anyhow!(
-                                        "No sessionId found in SSE response"
-                                    ));
-                                }
-                            } else {
-                                return Err(anyhow::anyhow!("No data line found in SSE response"));
-                            };
+        // GET now reflects the posted set
+        let Json(config) = client_config_endpoint(State(state.clone())).await.unwrap();
+        let enabled: Vec<&Value> = config["servers"]["memory"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|t| t["enabled"] == true)
+            .collect();
+        assert_eq!(enabled.len(), 1);
+        assert_eq!(enabled[0]["name"], "read_graph");
+    }
 
-                            // Construct the message URL
-                            let base_url = url.trim_end_matches("/sse").trim_end_matches('/');
-                            let message_url =
-                                format!("{}/message?sessionId={}", base_url, session_id);
-                            println!("🔗 [{}] SSE session ID: {}", server_name, session_id);
-                            println!("🎯 [{}] SSE message URL: {}", server_name, message_url);
-                            (message_url, session_id)
-                        } else {
-                            // Not SSE, use original direct HTTP approach
-                            (url.to_string(), String::new())
+    #[tokio::test]
+    async fn test_configured_capabilities_satisfy_strict_backend() {
+        // A backend that refuses to serve tools unless the handshake declared `roots`
+        let initialized = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let initialized = initialized.clone();
+                async move {
+                    use std::sync::atomic::Ordering;
+                    let id = request["id"].clone();
+                    match request["method"].as_str() {
+                        Some("initialize") => {
+                            if request["params"]["capabilities"].get("roots").is_none() {
+                                initialized.store(false, Ordering::SeqCst);
+                                return Json(json!({"jsonrpc": "2.0", "id": id,
+                                    "error": {"code": -32602, "message": "capabilities.roots is required"}}));
+                            }
+                            initialized.store(true, Ordering::SeqCst);
+                            Json(json!({"jsonrpc": "2.0", "id": id, "result": {
+                                "protocolVersion": "2024-11-05", "capabilities": {"tools": {}},
+                                "serverInfo": {"name": "strict", "version": "1.0.0"}}}))
                         }
-                    } else {
-                        // Failed to GET, try original direct HTTP approach
-                        (url.to_string(), String::new())
+                        Some("tools/list") if initialized.load(Ordering::SeqCst) => {
+                            Json(json!({"jsonrpc": "2.0", "id": id, "result": {"tools": [
+                                {"name": "search", "description": "Search", "inputSchema": {"type": "object"}}
+                            ]}}))
+                        }
+                        _ => Json(json!({"jsonrpc": "2.0", "id": id,
+                            "error": {"code": -32002, "message": "not initialized"}})),
                     }
-                } else {
-                    // URL doesn't end with /sse, use direct HTTP approach
-                    println!("🔗 [{}] Using direct HTTP approach", server_name);
-                    (url.to_string(), String::new())
-                };
+                }
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "default": { "transport": "http", "url": url },
+            "configured": {
+                "transport": "http",
+                "url": url,
+                "capabilities": { "roots": { "listChanged": true } }
+            },
+        }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
 
-                println!("🎯 [{}] Final message_url: {}", server_name, message_url);
+        let tools = state
+            .discover_server_tools("default", &servers["default"])
+            .await
+            .unwrap();
+        assert!(tools.is_empty());
 
-                // Handle SSE vs HTTP endpoints differently
-                if config.transport == "sse" {
-                    println!(
-                        "🔄 [{}] SSE endpoint detected - using SSE transport",
-                        server_name
-                    );
+        let tools = state
+            .discover_server_tools("configured", &servers["configured"])
+            .await
+            .unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "search");
+    }
 
-                    // For SSE endpoints, we need to handle the full MCP handshake
-                    // with responses coming through the SSE stream
-                    return discover_tools_via_sse(&client, server_name, url, &session_id).await;
+    #[tokio::test]
+    async fn test_backend_handshake_passes_client_identity_through() {
+        // A backend that records the clientInfo of every handshake
+        let seen_client_info = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen_client_info.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let recorded = recorded.clone();
+                async move {
+                    let id = request["id"].clone();
+                    let result = if request["method"] == "initialize" {
+                        recorded
+                            .lock()
+                            .unwrap()
+                            .push(request["params"]["clientInfo"].clone());
+                        json!({"protocolVersion": "2024-11-05", "capabilities": {"tools": {}},
+                            "serverInfo": {"name": "recorder", "version": "1.0.0"}})
+                    } else {
+                        json!({"tools": []})
+                    };
+                    Json(json!({"jsonrpc": "2.0", "id": id, "result": result}))
                 }
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        // Answers each tools/call with the clientInfo name of its handshake
+        const WHOAMI_SERVER: &str = r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      client=$(printf '%s' "$line" | sed -n 's/.*"clientInfo":{"name":"\([^"]*\)".*/\1/p')
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"mock","version":"0.1.0"}}}\n' "$id" ;;
+    *'"method":"tools/call"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"%s"}]}}\n' "$id" "$client" ;;
+  esac
+done"#;
+        let whoami = json!({ "command": "sh", "args": ["-c", WHOAMI_SERVER] });
+        let (_dir, state) = state_with_config(json!({
+            "client_info_passthrough": "via",
+            "servers": {
+                "recorder": { "transport": "http", "url": url },
+                "first": whoami.clone(),
+                "second": whoami,
+            },
+        }));
+        register_tool(&state, "first", "whoami").await;
+        register_tool(&state, "second", "whoami").await;
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
 
-                // Non-SSE HTTP endpoint handling
-                println!(
-                    "🔄 [{}] HTTP endpoint - sending initialize first",
-                    server_name
-                );
+        // Two client sessions initialize with different identities
+        let session = |id: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert("x-session-id", id.parse().unwrap());
+            headers
+        };
+        let (cursor, zed) = (session("session-a"), session("session-b"));
+        for (headers, name) in [(&cursor, "cursor"), (&zed, "zed")] {
+            let initialize = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: Some(json!({
+                    "protocolVersion": "2024-11-05",
+                    "clientInfo": { "name": name, "version": "1.2.3" }
+                })),
+            };
+            state
+                .handle_jsonrpc_request(initialize, Some(headers))
+                .await;
+        }
 
-                // Initialize the server for non-SSE endpoints
-                let init_request = json!({
-                    "jsonrpc": "2.0",
-                    "id": 1,
-                    "method": "initialize",
-                    "params": {
-                        "protocolVersion": "2024-11-05",
-                        "capabilities": {},
-                        "clientInfo": {
-                            "name": "toolman",
-                            "version": "1.0.0"
-                        }
-                    }
-                });
+        // Discovery isn't made for any client session, so backends see toolman itself
+        state
+            .discover_server_tools("recorder", &servers["recorder"])
+            .await
+            .unwrap();
+        let seen = seen_client_info.lock().unwrap().clone();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0]["name"], "toolman");
+
+        // A backend started for a session's call handshakes with that session's identity
+        let whoami = |tool: &'static str, headers: &axum::http::HeaderMap| {
+            let state = state.clone();
+            let headers = headers.clone();
+            async move {
+                let response = state
+                    .handle_jsonrpc_request(tool_call(tool, json!({})), Some(&headers))
+                    .await;
+                response.result.unwrap()["content"][0]["text"].clone()
+            }
+        };
+        assert_eq!(whoami("first_whoami", &zed).await, "toolman-via-zed");
+        assert_eq!(whoami("second_whoami", &cursor).await, "toolman-via-cursor");
+        // A running backend keeps the identity it was started with
+        assert_eq!(whoami("first_whoami", &cursor).await, "toolman-via-zed");
 
-                println!(
-                    "📤 [{}] Sending initialize request to: {}",
-                    server_name, message_url
-                );
-                let init_response = client
-                    .post(&message_url)
-                    .header("Accept", "application/json,text/event-stream")
-                    .json(&init_request)
-                    .send()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("HTTP init request failed: {}", e))?;
+        let client: ClientInfo =
+            serde_json::from_value(json!({"name": "cursor", "version": "1.2.3"})).unwrap();
+        assert_eq!(
+            backend_client_info(ClientInfoPassthrough::Forward, Some(&client)),
+            json!({"name": "cursor", "version": "1.2.3"})
+        );
+        assert_eq!(
+            backend_client_info(ClientInfoPassthrough::Via, Some(&client)),
+            json!({"name": "toolman-via-cursor", "version": env!("CARGO_PKG_VERSION")})
+        );
+        assert_eq!(
+            backend_client_info(ClientInfoPassthrough::Off, Some(&client))["name"],
+            "toolman"
+        );
+    }
 
-                println!(
-                    "📥 [{}] Initialize response status: {}",
-                    server_name,
-                    init_response.status()
-                );
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_spawn_limits_apply_to_child() {
+        let limits = SpawnLimits {
+            nice: Some(5),
+            memory_mb: Some(512),
+            cpu_seconds: None,
+        };
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "ulimit -v; ulimit -t; nice"])
+            .stdout(Stdio::piped());
+        apply_spawn_limits(&mut cmd, "limited", &limits);
+
+        let output = cmd.output().await.unwrap();
+        let lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        // `ulimit -v` reports kilobytes
+        assert_eq!(lines, ["524288", "unlimited", "5"]);
+    }
 
-                // Get tools list
-                let tools_request = json!({
-                    "jsonrpc": "2.0",
-                    "id": 2,
-                    "method": "tools/list",
-                    "params": {}
-                });
+    #[tokio::test]
+    async fn test_client_ids_echoed_whatever_the_backend_id_type() {
+        // A backend that records the ids it is sent
+        let seen_ids = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen_ids.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let recorded = recorded.clone();
+                async move {
+                    recorded.lock().unwrap().push(request["id"].clone());
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {
+                        "content": [{"type": "text", "text": "ok"}]}}))
+                }
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "strict": { "transport": "http", "url": url, "request_id_type": "string" },
+        }));
+        register_tool(&state, "strict", "lookup").await;
+
+        for client_id in [json!("req-abc"), json!(42)] {
+            let mut request = tool_call("strict_lookup", json!({}));
+            request.id = Some(client_id.clone());
+            let response = state.handle_jsonrpc_request(request, None).await;
+            assert_eq!(response.id, Some(client_id));
+            assert_eq!(response.result.unwrap()["content"][0]["text"], "ok");
+        }
+
+        let seen = seen_ids.lock().unwrap().clone();
+        assert_eq!(seen.len(), 2);
+        assert!(seen.iter().all(Value::is_string), "{:?}", seen);
+
+        assert_eq!(RequestIdType::default().id(7), json!(7));
+        assert_eq!(RequestIdType::String.id(7), json!("7"));
+    }
+
+    #[test]
+    fn test_initialize_request_is_never_empty() {
+        let config: ServerConfig = serde_json::from_value(json!({"command": "true"})).unwrap();
+        let request = initialize_request(&config, backend_client_info(Default::default(), None));
+        assert_eq!(
+            request["params"]["capabilities"],
+            default_handshake_capabilities()
+        );
+        assert_ne!(request["params"]["capabilities"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_http_backend_fails_fast() {
+        // A port nothing listens on any more
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (_dir, state) = state_with_config(json!({
+            "reachability_timeout_ms": 300,
+            "servers": {
+                "dead": { "transport": "http", "url": format!("http://127.0.0.1:{}/mcp", port) }
+            }
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["dead"].clone();
+
+        let started = std::time::Instant::now();
+        let tools = state.discover_and_record("dead", &config).await;
+        assert!(tools.is_empty());
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+
+        let status = state.discovery_status.read().await["dead"].clone();
+        assert!(matches!(status, DiscoveryStatus::Unreachable { .. }));
+
+        let Json(ready) = readiness_check(State(state.clone())).await.unwrap();
+        assert_eq!(ready["servers"]["dead"]["status"], "unreachable");
+    }
+
+    /// Log output captured in memory
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 
-                println!(
-                    "📤 [{}] Sending tools/list request to: {}",
-                    server_name, message_url
-                );
-                let tools_response = client
-                    .post(&message_url)
-                    .header("Accept", "application/json,text/event-stream")
-                    .json(&tools_request)
-                    .send()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("HTTP tools request failed: {}", e))?;
+    /// Emit one event per log target under `directives` and return what was written
+    fn logs_with_filter(directives: &str) -> String {
+        let captured = CapturedLogs::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(log_filter(Some(directives)))
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: LOG_SERVER, "server event");
+            tracing::info!(target: LOG_DISCOVERY, "discovery event");
+            backend_span("memory")
+                .in_scope(|| tracing::debug!(target: LOG_BACKEND, "memory stderr"));
+            backend_span("git").in_scope(|| tracing::debug!(target: LOG_BACKEND, "git stderr"));
+        });
 
-                println!(
-                    "📥 [{}] Tools response status: {}",
-                    server_name,
-                    tools_response.status()
-                );
-                let response_text = tools_response
-                    .text()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to get response text: {}", e))?;
-                println!("🔍 [{}] Raw tools response: {}", server_name, response_text);
+        let bytes = captured.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
 
-                // Handle SSE format responses (both direct HTTP endpoints like Solana and SSE endpoints like rustdocs)
-                let json_content = if response_text.contains("data: ")
-                    && (response_text.starts_with("event:") || response_text.starts_with("data:"))
-                {
-                    println!(
-                        "🔄 [{}] Detected SSE format response, extracting JSON data",
-                        server_name
-                    );
-                    // Extract JSON from SSE format - handle multiple possible formats:
-                    // 1. "event: message\ndata: {json}\n\n"
-                    // 2. "data: {json}\n\n"
-                    // 3. Multiple data lines that need to be concatenated
-                    let data_lines: Vec<&str> = response_text
-                        .lines()
-                        .filter(|line| line.starts_with("data: "))
-                        .collect();
-
-                    if !data_lines.is_empty() {
-                        // Concatenate all data lines and remove "data: " prefix
-                        let combined_data = data_lines
-                            .iter()
-                            .map(|line| line.strip_prefix("data: ").unwrap_or(line))
-                            .collect::<Vec<_>>()
-                            .join("");
-                        println!("🔍 [{}] Extracted SSE data: {}", server_name, combined_data);
-                        combined_data
-                    } else {
-                        println!(
-                            "⚠️ [{}] SSE format detected but no data lines found",
-                            server_name
-                        );
-                        response_text
-                    }
+    #[tokio::test]
+    async fn test_duplicate_tools_registered_once_with_warning() {
+        let router = Router::new().route(
+            "/mcp",
+            post(|Json(request): Json<Value>| async move {
+                let result = if request["method"] == "tools/list" {
+                    json!({"tools": [
+                        {"name": "search", "description": "First", "inputSchema": {"type": "object"}},
+                        {"name": "fetch", "description": "Fetch", "inputSchema": {"type": "object"}},
+                        {"name": "search", "description": "Second", "inputSchema": {"type": "object"}}
+                    ]})
                 } else {
-                    response_text
+                    json!({"protocolVersion": "2024-11-05", "capabilities": {"tools": {}},
+                        "serverInfo": {"name": "dupes", "version": "1.0.0"}})
                 };
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "dupes": { "transport": "http", "url": url },
+        }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+
+        let captured = CapturedLogs::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_env_filter(log_filter(Some("warn")))
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let tools = {
+            let _guard = tracing::subscriber::set_default(subscriber);
+            state
+                .discover_server_tools("dupes", &servers["dupes"])
+                .await
+                .unwrap()
+        };
 
-                let response_json: Value = serde_json::from_str(&json_content)
-                    .map_err(|e| anyhow::anyhow!("Failed to parse tools response: {}", e))?;
+        let names: Vec<&str> = tools.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["search", "fetch"]);
+        assert_eq!(tools[0].description, "First");
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains("Ignoring duplicate tool 'search'"),
+            "{}",
+            logs
+        );
+    }
 
-                println!(
-                    "🔍 [{}] Tools response JSON: {}",
-                    server_name, response_json
-                );
+    #[test]
+    fn test_log_directive_silences_only_its_target() {
+        let logs = logs_with_filter("debug,toolman::backend=off");
+        assert!(logs.contains("server event"));
+        assert!(logs.contains("discovery event"));
+        assert!(!logs.contains("stderr"));
+
+        let logs = logs_with_filter("info,toolman::discovery=off,toolman::backend=debug");
+        assert!(logs.contains("server event"));
+        assert!(!logs.contains("discovery event"));
+        assert!(logs.contains("memory stderr") && logs.contains("git stderr"));
+
+        // Backend output can be narrowed to a single server by its span's `server` field
+        let logs = logs_with_filter("info,toolman::backend[{server=memory}]=debug");
+        assert!(logs.contains("memory stderr"));
+        assert!(!logs.contains("git stderr"));
+    }
 
-                // Parse tools from response
-                if let Some(result) = response_json.get("result") {
-                    if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
-                        let parsed_tools: Vec<Tool> = tools_array
-                            .iter()
-                            .filter_map(|tool| {
-                                if let (Some(name), Some(description)) = (
-                                    tool.get("name").and_then(|n| n.as_str()),
-                                    tool.get("description").and_then(|d| d.as_str()),
-                                ) {
-                                    Some(Tool {
-                                        name: name.to_string(),
-                                        description: description.to_string(),
-                                        input_schema: tool
-                                            .get("inputSchema")
-                                            .cloned()
-                                            .unwrap_or(json!({})),
-                                        server_name: server_name.to_string(),
-                                        // Preserve the original tool name for accurate forwarding
-                                        original_tool_name: name.to_string(),
-                                    })
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect();
+    #[tokio::test]
+    async fn test_stop_lets_in_flight_call_finish() {
+        let mut server = mock_stdio_server("slow_task", "finished");
+        server["env"]["MOCK_CALL_DELAY"] = json!("1");
+        let (_dir, state) = state_with_servers(json!({ "worker": server }));
+        let pool = state.connection_pool.clone();
+        pool.start_server("worker").await.unwrap();
+
+        let call = {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.forward_tool_call_with_context("worker", "slow_task", json!({}), None, None)
+                    .await
+            })
+        };
+        while pool.in_flight_calls("worker") == 0 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
 
-                        println!(
-                            "✅ [{}] Discovered {} tools via HTTP (elapsed: {:?})",
-                            server_name,
-                            parsed_tools.len(),
-                            start_time.elapsed()
-                        );
+        // As on reload, the server is stopped only once its in-flight call is answered
+        pool.stop_server("worker").await.unwrap();
+        assert!(call.is_finished(), "stop must wait for the in-flight call");
 
-                        return Ok(parsed_tools);
-                    }
-                }
+        let response = call.await.unwrap().unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], "finished");
+        assert_eq!(pool.in_flight_calls("worker"), 0);
+        assert!(!pool.connections.read().await.contains_key("worker"));
+    }
 
-                return Ok(Vec::new());
-            } else {
-                return Err(anyhow::anyhow!("HTTP transport requires 'url' field"));
-            }
+    #[tokio::test]
+    async fn test_calls_past_queue_depth_are_rejected_as_busy() {
+        let mut server = mock_stdio_server("slow_task", "finished");
+        server["env"]["MOCK_CALL_DELAY"] = json!("1");
+        server["max_queued_calls"] = json!(1);
+        let (_dir, state) = state_with_servers(json!({ "worker": server }));
+        let pool = state.connection_pool.clone();
+        pool.start_server("worker").await.unwrap();
+
+        // One call is served, one waits in the queue, the third finds the queue full
+        let call =
+            || pool.forward_tool_call_with_context("worker", "slow_task", json!({}), None, None);
+        let (first, second, third) = tokio::join!(call(), call(), call());
+
+        for response in [first.unwrap(), second.unwrap()] {
+            assert_eq!(response["result"]["content"][0]["text"], "finished");
         }
+        let error = third.unwrap_err();
+        assert!(error.is::<ServerBusy>(), "unexpected error: {}", error);
+        assert!(error.to_string().contains("busy"));
+        assert_eq!(pool.queued_calls("worker"), 0);
+    }
 
-        // Original stdio discovery logic
-        use std::process::Stdio;
-        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-        use tokio::process::Command;
+    #[tokio::test]
+    async fn test_queued_calls_are_reported_in_servers_and_metrics() {
+        let mut server = mock_stdio_server("slow_task", "finished");
+        server["env"]["MOCK_CALL_DELAY"] = json!("1");
+        let (_dir, state) = state_with_servers(json!({ "worker": server }));
+        let pool = state.connection_pool.clone();
+        pool.start_server("worker").await.unwrap();
+
+        // One call is served while two wait their turn
+        let calls: Vec<_> = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    pool.forward_tool_call_with_context(
+                        "worker",
+                        "slow_task",
+                        json!({}),
+                        None,
+                        None,
+                    )
+                    .await
+                })
+            })
+            .collect();
+        while pool.in_flight_calls("worker") < 3 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
 
-        println!(
-            "🔍 [{}] Command: {} {}",
-            server_name,
-            config.command,
-            config.args.join(" ")
+        let Json(servers) = servers_endpoint(State(state.clone())).await;
+        assert_eq!(servers["worker"]["queued_calls"], 2);
+        let response = metrics_endpoint(State(state.clone())).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let metrics = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            metrics.contains("toolman_queued_calls{server=\"worker\"} 2"),
+            "{}",
+            metrics
         );
-        println!(
-            "🔍 [{}] Environment variables: {:?}",
-            server_name, config.env
+
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+        let Json(servers) = servers_endpoint(State(state.clone())).await;
+        assert_eq!(servers["worker"]["queued_calls"], 0);
+        pool.stop_server("worker").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_only_retryable_tools_are_retried() {
+        // A backend whose first tools/call fails in transport (an unparseable body)
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let counter = counter.clone();
+                async move {
+                    use std::sync::atomic::Ordering;
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return "<html>502 Bad Gateway</html>".to_string();
+                    }
+                    json!({"jsonrpc": "2.0", "id": request["id"], "result": {
+                        "content": [{"type": "text", "text": "recovered"}]}})
+                    .to_string()
+                }
+            }),
         );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_config(json!({
+            "tool_call_retry_backoff_ms": 10,
+            "servers": {
+                "flaky": {
+                    "transport": "http",
+                    "url": url,
+                    "tools": { "read": { "retryable": true } }
+                }
+            },
+        }));
+        register_tool(&state, "flaky", "read").await;
+        register_tool(&state, "flaky", "write").await;
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("flaky_read", json!({})), None)
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "recovered");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Not retryable: the transient failure reaches the agent after a single attempt
+        calls.store(0, std::sync::atomic::Ordering::SeqCst);
+        let response = state
+            .handle_jsonrpc_request(tool_call("flaky_write", json!({})), None)
+            .await;
+        let text = response.result.unwrap()["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(text.contains("Error calling tool"), "{}", text);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
 
-        // Debug: Check if command exists
-        let command_check = std::process::Command::new("which")
-            .arg(&config.command)
-            .output();
+    #[tokio::test]
+    async fn test_capabilities_reflect_config() {
+        let mut server = mock_stdio_server("lookup", "ok");
+        server["tools"] = json!({ "lookup": { "page_size": 10 } });
+        let (_dir, state) = state_with_config(json!({
+            "include_tool_meta": true,
+            "client_info_passthrough": "via",
+            "tool_call_retries": 5,
+            "servers": { "memory": server },
+        }));
+
+        let Json(capabilities) = capabilities_endpoint(State(state.clone())).await;
+        let features = &capabilities["features"];
+        assert_eq!(features["tool_meta"], true);
+        assert_eq!(features["builtin_tools_listed"], true);
+        assert_eq!(features["client_info_passthrough"], "via");
+        assert_eq!(features["pagination"], true);
+        assert_eq!(features["shadowing"], false);
+        assert_eq!(features["retries"], false);
+        assert_eq!(features["batch"], false);
+        assert_eq!(capabilities["limits"]["tool_call_retries"], 5);
+        assert_eq!(capabilities["tool_name_separator"], "_");
+
+        let initialize = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: None,
+        };
+        let result = state
+            .handle_jsonrpc_request(initialize, None)
+            .await
+            .result
+            .unwrap();
+        let summary = &result["serverInfo"]["features"];
+        assert!(summary.as_array().unwrap().contains(&json!("tool_meta")));
+        assert!(!summary.as_array().unwrap().contains(&json!("shadowing")));
+    }
 
-        match command_check {
-            Ok(output) => {
-                if output.status.success() {
-                    let path = String::from_utf8_lossy(&output.stdout);
-                    println!("🔍 [{}] Command found at: {}", server_name, path.trim());
+    #[tokio::test]
+    async fn test_discovery_timeout_override_per_server() {
+        // A backend that takes 1.5s to list its tools
+        let router = Router::new().route(
+            "/mcp",
+            post(|Json(request): Json<Value>| async move {
+                let result = if request["method"] == "tools/list" {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(1500)).await;
+                    json!({"tools": [{"name": "index", "description": "Index", "inputSchema": {"type": "object"}}]})
                 } else {
-                    println!(
-                        "⚠️ [{}] Command '{}' not found in PATH",
-                        server_name, config.command
-                    );
-                    println!(
-                        "⚠️ [{}] Current PATH: {}",
-                        server_name,
-                        std::env::var("PATH").unwrap_or_default()
-                    );
-                }
-            }
-            Err(e) => {
-                println!(
-                    "⚠️ [{}] Failed to check command existence: {}",
-                    server_name, e
-                );
+                    json!({"protocolVersion": "2024-11-05", "capabilities": {"tools": {}},
+                        "serverInfo": {"name": "slow", "version": "1.0.0"}})
+                };
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "impatient": { "transport": "http", "url": url, "discovery_timeout_secs": 1 },
+            "patient": { "transport": "http", "url": url, "discovery_timeout_secs": 10 },
+        }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+
+        let tools = state
+            .discover_and_record("impatient", &servers["impatient"])
+            .await;
+        assert!(tools.is_empty());
+        let tools = state
+            .discover_and_record("patient", &servers["patient"])
+            .await;
+        assert_eq!(tools.len(), 1);
+
+        let status = state.discovery_status.read().await;
+        assert_eq!(
+            status["impatient"],
+            DiscoveryStatus::TimedOut { after_secs: 1 }
+        );
+        assert_eq!(
+            status["patient"],
+            DiscoveryStatus::Ready {
+                tools: 1,
+                server_version: Some("1.0.0".to_string())
             }
-        }
+        );
+        assert_eq!(
+            serde_json::to_value(&status["impatient"]).unwrap(),
+            json!({"status": "timed_out", "after_secs": 1})
+        );
+    }
 
-        // Check if this is a Docker-based server and ensure Docker is ready
-        if config.command == "docker" {
-            println!(
-                "🐳 [{}] Detected Docker-based server, checking Docker readiness...",
-                server_name
-            );
-            if let Err(e) = self.connection_pool.wait_for_docker(30).await {
-                println!("⚠️ [{}] Docker readiness check failed: {}", server_name, e);
-                // Continue anyway, but warn that it might not work
+    #[tokio::test]
+    async fn test_preview_call_shows_resolved_arguments() {
+        let mut server = mock_stdio_server("search", "unused");
+        server["tools"] = json!({ "search": { "defaults": { "limit": 10, "query": "ignored" } } });
+        server["inject_params"] = json!({ "projectRoot": "working_dir" });
+        let (_dir, state) = state_with_servers(json!({ "docs": server }));
+        register_tool(&state, "docs", "search").await;
+
+        let preview_with_headers = |arguments: Value, headers: Option<axum::http::HeaderMap>| {
+            let state = state.clone();
+            async move {
+                let result = state
+                    .handle_jsonrpc_request(
+                        tool_call("toolman_preview_call", arguments),
+                        headers.as_ref(),
+                    )
+                    .await
+                    .result
+                    .unwrap();
+                serde_json::from_str::<Value>(result["content"][0]["text"].as_str().unwrap())
+                    .unwrap()
             }
-        }
+        };
+        let preview = |arguments: Value| preview_with_headers(arguments, None);
+
+        let resolved = preview(json!({
+            "tool": "docs_search",
+            "arguments": { "query": "rust" },
+            "working_dir": "/work/project"
+        }))
+        .await;
+        assert_eq!(resolved["server"], "docs");
+        assert_eq!(resolved["tool"], "search");
+        assert_eq!(
+            resolved["arguments"],
+            json!({ "query": "rust", "limit": 10, "projectRoot": "/work/project" })
+        );
 
-        // Spawn the server process for tool discovery only
-        let mut cmd = Command::new(&config.command);
-        cmd.args(&config.args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        // Without one, the request's working directory is injected as the real call would
+        let resolved = preview_with_headers(
+            json!({ "tool": "docs_search" }),
+            Some(working_dir_headers("/work/client")),
+        )
+        .await;
+        assert_eq!(
+            resolved["arguments"],
+            json!({ "query": "ignored", "limit": 10, "projectRoot": "/work/client" })
+        );
 
-        // Set working directory (default to project directory if not specified)
-        let config_manager = self.system_config_manager.read().await;
-        let project_dir = config_manager
-            .get_config_path()
-            .parent()
-            .unwrap_or_else(|| std::path::Path::new("."));
-        let working_dir = config
-            .working_directory
-            .as_ref()
-            .map(|wd| resolve_working_directory(wd, project_dir))
-            .unwrap_or_else(|| project_dir.to_path_buf());
-        cmd.current_dir(&working_dir);
-        println!(
-            "🔍 [{}] Setting working directory: {}",
-            server_name,
-            working_dir.display()
+        // Without any working directory nothing is injected
+        let resolved = preview(json!({ "tool": "docs_search" })).await;
+        assert_eq!(
+            resolved["arguments"],
+            json!({ "query": "ignored", "limit": 10 })
         );
 
-        // Inherit all environment variables from parent process
-        cmd.envs(std::env::vars());
+        // The preview never reaches the backend, which was never started
+        assert!(state.connection_pool.connections.read().await.is_empty());
+    }
 
-        // Process environment variables with template substitution
-        let template_context = TemplateContext::new(
-            project_dir.to_path_buf(),
-            working_dir.clone(),
-            server_name.to_string(),
+    #[tokio::test]
+    async fn test_discovery_timing_is_recorded_per_server() {
+        let (_dir, state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("read_graph", "ok") }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+        state
+            .discover_and_record("memory", &servers["memory"])
+            .await;
+        state.connection_pool.stop_server("memory").await.unwrap();
+
+        let timing = state.discovery_timings.read().await["memory"].clone();
+        assert!(timing.init_secs.unwrap() > 0.0);
+        assert!(timing.tools_list_secs.unwrap() > 0.0);
+
+        let Json(listing) = servers_endpoint(State(state.clone())).await;
+        assert_eq!(listing["memory"]["discovery"], json!(timing));
+
+        let metrics = state.metrics.render();
+        assert!(metrics
+            .contains("toolman_discovery_duration_seconds{phase=\"init\",server=\"memory\"}"));
+        assert!(metrics.contains(
+            "toolman_discovery_duration_seconds{phase=\"tools_list\",server=\"memory\"}"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_backend_version_reported_in_status_and_catalog() {
+        // The mock stdio server identifies itself as version 0.1.0
+        let (_dir, state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("read_graph", "ok") }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+
+        let tools = state
+            .discover_and_record("memory", &servers["memory"])
+            .await;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&state.discovery_status.read().await["memory"]).unwrap(),
+            json!({"status": "ready", "tools": 1, "server_version": "0.1.0"})
         );
-        let processed_env = process_env_templates(&config.env, &template_context);
 
-        // Add/override with server-specific environment variables
-        for (key, value) in &processed_env {
-            cmd.env(key, value);
-            if !value.is_empty() {
-                println!("🔧 [{}] Setting env {}={}", server_name, key, value);
-            }
+        let tools: HashMap<String, Tool> = tools
+            .into_iter()
+            .map(|tool| (prefixed_tool_name("memory", &tool.name), tool))
+            .collect();
+        let catalog = state.build_tool_catalog(&tools, &servers, &HashMap::new());
+        let catalog = serde_json::to_value(&catalog).unwrap();
+        assert_eq!(catalog["remote"]["memory"]["server_version"], "0.1.0");
+    }
+
+    /// A backend whose tools/call never finishes in time, recording every request it gets
+    fn recording_slow_backend(seen: Arc<std::sync::Mutex<Vec<Value>>>) -> Router {
+        Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let seen = seen.clone();
+                async move {
+                    seen.lock().unwrap().push(request.clone());
+                    if request["method"] == "tools/call" {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+                    }
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {}}))
+                }
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_aborts_call_and_reaches_owning_backend() {
+        let busy_seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let idle_seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let busy_url = spawn_http_backend(recording_slow_backend(busy_seen.clone())).await;
+        let idle_url = spawn_http_backend(recording_slow_backend(idle_seen.clone())).await;
+        let (_dir, state) = state_with_servers(json!({
+            "busy": { "transport": "http", "url": format!("{}/mcp", busy_url) },
+            "idle": { "transport": "http", "url": format!("{}/mcp", idle_url) },
+        }));
+        register_tool(&state, "busy", "crawl").await;
+
+        let mut request = tool_call("busy_crawl", json!({}));
+        request.id = Some(json!("call-7"));
+        let call = {
+            let state = state.clone();
+            tokio::spawn(async move { state.handle_jsonrpc_request(request, None).await })
+        };
+        while busy_seen.lock().unwrap().is_empty() {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         }
 
-        println!(
-            "🔍 [{}] Spawning process... (elapsed: {:?})",
-            server_name,
-            start_time.elapsed()
+        let cancel = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": "call-7", "reason": "user pressed stop" })),
+        };
+        state.handle_jsonrpc_request(cancel, None).await;
+
+        // The local wait ends long before the backend would have answered
+        let response = tokio::time::timeout(tokio::time::Duration::from_secs(5), call)
+            .await
+            .expect("cancelled call should stop waiting")
+            .unwrap();
+        assert_eq!(response.id, Some(json!("call-7")));
+        let result = response.result.unwrap();
+        assert_eq!(result["isError"], true);
+        assert_eq!(
+            result["content"][0]["text"],
+            "Request cancelled: user pressed stop"
         );
-        let mut process = match cmd.spawn() {
-            Ok(p) => {
-                println!(
-                    "✅ [{}] Process spawned successfully (elapsed: {:?})",
-                    server_name,
-                    start_time.elapsed()
-                );
-                p
-            }
-            Err(e) => {
-                println!(
-                    "❌ [{}] Failed to spawn process: {} (elapsed: {:?})",
-                    server_name,
-                    e,
-                    start_time.elapsed()
-                );
-                return Err(e.into());
-            }
+
+        let busy_seen = busy_seen.lock().unwrap().clone();
+        let cancelled = busy_seen
+            .iter()
+            .find(|r| r["method"] == "notifications/cancelled")
+            .expect("backend should be told about the cancellation");
+        assert_eq!(cancelled["params"]["requestId"], 1);
+        assert_eq!(cancelled["params"]["reason"], "user pressed stop");
+        assert!(idle_seen.lock().unwrap().is_empty());
+        assert!(state.pending_calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_only_reaches_the_calling_session() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = spawn_http_backend(recording_slow_backend(seen.clone())).await;
+        let (_dir, state) = state_with_servers(json!({
+            "busy": { "transport": "http", "url": format!("{}/mcp", url) },
+        }));
+        register_tool(&state, "busy", "crawl").await;
+        let session = |id: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert("x-session-id", id.parse().unwrap());
+            headers
         };
 
-        let mut stdin = process.stdin.take().unwrap();
-        let stdout = process.stdout.take().unwrap();
-        let stderr = process.stderr.take().unwrap();
+        // Two clients whose calls both have id 3
+        let calls: Vec<_> = ["session-a", "session-b"]
+            .into_iter()
+            .map(|id| {
+                let state = state.clone();
+                let headers = session(id);
+                tokio::spawn(async move {
+                    let mut request = tool_call("busy_crawl", json!({}));
+                    request.id = Some(json!(3));
+                    state.handle_jsonrpc_request(request, Some(&headers)).await
+                })
+            })
+            .collect();
+        while seen.lock().unwrap().len() < 2 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.pending_calls.lock().unwrap().len(), 2);
 
-        // Spawn a task to consume stderr to prevent blocking
-        let server_name_clone = server_name.to_string();
-        tokio::spawn(async move {
-            use tokio::io::{AsyncBufReadExt, BufReader};
-            let mut stderr_reader = BufReader::new(stderr);
-            let mut line = String::new();
-            loop {
-                line.clear();
-                match stderr_reader.read_line(&mut line).await {
-                    Ok(0) => break, // EOF
-                    Ok(_) => {
-                        if !line.trim().is_empty() {
-                            println!("🔍 [{}] stderr: {}", server_name_clone, line.trim());
-                        }
-                    }
-                    Err(e) => {
-                        println!("❌ [{}] Error reading stderr: {}", server_name_clone, e);
-                        break;
-                    }
-                }
-            }
-        });
+        let cancel = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": 3, "reason": "user pressed stop" })),
+        };
+        state
+            .handle_jsonrpc_request(cancel, Some(&session("session-a")))
+            .await;
 
-        // Initialize the MCP server
-        let init_request = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "initialize",
-            "params": {
-                "protocolVersion": "2024-11-05",
-                "capabilities": {},
-                "clientInfo": {
-                                        "name": "toolman",
-                    "version": "1.0.0"
-                }
-            }
-        });
+        let [first, second]: [_; 2] = calls.try_into().unwrap();
+        let response = tokio::time::timeout(tokio::time::Duration::from_secs(5), first)
+            .await
+            .expect("cancelled call should stop waiting")
+            .unwrap();
+        assert_eq!(response.result.unwrap()["isError"], true);
 
-        let init_msg = format!("{}\n", serde_json::to_string(&init_request)?);
-        println!(
-            "🔍 [{}] Sending init request (elapsed: {:?}): {}",
-            server_name,
-            start_time.elapsed(),
-            init_msg.trim()
+        // The other session's call with the same id is still running and cancellable
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        assert!(!second.is_finished());
+        assert_eq!(
+            state
+                .pending_calls
+                .lock()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["session-b/3"]
         );
+        second.abort();
+    }
 
-        if let Err(e) = stdin.write_all(init_msg.as_bytes()).await {
-            println!(
-                "❌ [{}] Failed to write init request: {} (elapsed: {:?})",
-                server_name,
-                e,
-                start_time.elapsed()
-            );
-            return Err(e.into());
+    #[tokio::test]
+    async fn test_colliding_call_ids_keep_the_first_call_cancellable() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let url = spawn_http_backend(recording_slow_backend(seen.clone())).await;
+        let (_dir, state) = state_with_servers(json!({
+            "busy": { "transport": "http", "url": format!("{}/mcp", url) },
+        }));
+        register_tool(&state, "busy", "crawl").await;
+
+        // Two clients without an X-Session-ID both use id 3
+        let mut calls = Vec::new();
+        for started in 1..=2 {
+            let state = state.clone();
+            calls.push(tokio::spawn(async move {
+                let mut request = tool_call("busy_crawl", json!({}));
+                request.id = Some(json!(3));
+                state.handle_jsonrpc_request(request, None).await
+            }));
+            while seen.lock().unwrap().len() < started {
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
         }
+        // The second call didn't take over the first one's entry
+        let token = {
+            let pending_calls = state.pending_calls.lock().unwrap();
+            assert_eq!(pending_calls.len(), 1);
+            pending_calls["/3"].token
+        };
 
-        // Read initialization response
-        let mut reader = BufReader::new(stdout);
-        let mut line = String::new();
+        let cancel = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": 3 })),
+        };
+        state.handle_jsonrpc_request(cancel, None).await;
+        let [first, second]: [_; 2] = calls.try_into().unwrap();
+        let response = tokio::time::timeout(tokio::time::Duration::from_secs(5), first)
+            .await
+            .expect("the first call should be the one cancelled")
+            .unwrap();
+        assert_eq!(response.result.unwrap()["isError"], true);
+        assert!(!second.is_finished());
+        second.abort();
+
+        // A guard only removes the entry it registered, not a newer call's under its key
+        let (cancel, _cancelled) = tokio::sync::oneshot::channel();
+        let newer = state
+            .register_pending_call(None, &json!(3), cancel)
+            .unwrap();
+        assert_ne!(newer.1, token);
+        drop(PendingCallGuard {
+            state: state.clone(),
+            pending: Some(("/3".to_string(), token)),
+            server_name: "busy".to_string(),
+            backend_request_id: BackendRequestId::default(),
+            finished: true,
+        });
+        assert_eq!(state.pending_calls.lock().unwrap()["/3"].token, newer.1);
+    }
 
-        println!(
-            "🔍 [{}] Reading init response... (elapsed: {:?})",
-            server_name,
-            start_time.elapsed()
+    #[tokio::test]
+    async fn test_tools_sharing_a_lock_key_run_one_at_a_time() {
+        // A backend whose calls take a while, tracking how many overlap
+        let running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let max_running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (counter, max) = (running.clone(), max_running.clone());
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let (counter, max) = (counter.clone(), max.clone());
+                async move {
+                    use std::sync::atomic::Ordering;
+                    let now = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+                    counter.fetch_sub(1, Ordering::SeqCst);
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {}}))
+                }
+            }),
         );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "files": {
+                "transport": "http",
+                "url": url,
+                "tools": {
+                    "write_file": { "lock_key": "workspace" },
+                    "delete_file": { "lock_key": "workspace" }
+                }
+            },
+        }));
+        let pool = state.connection_pool.clone();
+        let call = |tool| pool.forward_tool_call_with_context("files", tool, json!({}), None, None);
+
+        let (write, delete) = tokio::join!(call("write_file"), call("delete_file"));
+        write.unwrap();
+        delete.unwrap();
+        assert_eq!(max_running.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let (first, second) = tokio::join!(call("read_file"), call("list_files"));
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(max_running.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 
-        // Use configurable timeout or default
-        // Increased to 120s to allow for first-time uvx installations from git repos
-        // which need to clone + pip install before the MCP server can respond
-        let timeout_secs = 120; // Default timeout for all servers
-        println!(
-            "🔍 [{}] Using timeout of {} seconds for initialization",
-            server_name, timeout_secs
+    #[tokio::test]
+    async fn test_unknown_tool_routes_to_configured_fallback() {
+        // The fallback echoes the request it was given
+        let router = Router::new().route(
+            "/mcp",
+            post(|Json(request): Json<Value>| async move {
+                let text = request["params"]["arguments"].to_string();
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {
+                    "content": [{"type": "text", "text": text}]}}))
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let servers = json!({ "docs": { "transport": "http", "url": url } });
+
+        let (_dir, state) = state_with_config(json!({
+            "unknown_tool_fallback": "docs_search",
+            "servers": servers.clone(),
+        }));
+        register_tool(&state, "docs", "search").await;
+        let result = state
+            .handle_jsonrpc_request(tool_call("frobnicate", json!({"level": 3})), None)
+            .await
+            .result
+            .unwrap();
+        let forwarded: Value =
+            serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(
+            forwarded,
+            json!({"tool": "frobnicate", "arguments": {"level": 3}})
         );
 
-        // Some servers print status messages before JSON responses
-        // Keep reading lines until we get valid JSON or EOF
-        let mut init_attempts = 0;
-        let max_init_attempts = 10; // Increased from 5
+        // Without a fallback the unknown name is still an error
+        let (_dir, state) = state_with_servers(servers);
+        register_tool(&state, "docs", "search").await;
+        let result = state
+            .handle_jsonrpc_request(tool_call("frobnicate", json!({"level": 3})), None)
+            .await
+            .result
+            .unwrap();
+        assert!(result["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid tool name format"));
+    }
 
-        loop {
-            line.clear();
-            println!(
-                "🔍 [{}] Waiting for init response line {} (elapsed: {:?})",
-                server_name,
-                init_attempts + 1,
-                start_time.elapsed()
-            );
+    #[tokio::test]
+    async fn test_tools_list_meta_follows_config_flag() {
+        let tools_list = |state: BridgeState| async move {
+            let request = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "tools/list".to_string(),
+                params: None,
+            };
+            let result = state
+                .handle_jsonrpc_request(request, None)
+                .await
+                .result
+                .unwrap();
+            result["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|t| t["name"] == "docs_search")
+                .cloned()
+                .unwrap()
+        };
+        let servers = json!({ "docs": { "transport": "sse", "url": "http://localhost:1/sse" } });
 
-            match tokio::time::timeout(
-                tokio::time::Duration::from_secs(timeout_secs),
-                reader.read_line(&mut line),
-            )
-            .await
-            {
-                Ok(read_result) => match read_result {
-                    Ok(bytes_read) => {
-                        if bytes_read == 0 {
-                            println!("❌ [{}] No more lines to read for init response (EOF) (elapsed: {:?})", server_name, start_time.elapsed());
-                            return Ok(Vec::new());
-                        }
+        let (_dir, state) =
+            state_with_config(json!({ "include_tool_meta": true, "servers": servers }));
+        register_tool(&state, "docs", "search").await;
+        let tool = tools_list(state).await;
+        assert_eq!(
+            tool["_meta"]["toolman"],
+            json!({"server": "docs", "transport": "sse"})
+        );
 
-                        println!(
-                            "🔍 [{}] Read init line {} ({} bytes, elapsed: {:?}): {}",
-                            server_name,
-                            init_attempts + 1,
-                            bytes_read,
-                            start_time.elapsed(),
-                            line.trim()
-                        );
+        let (_dir, state) = state_with_servers(servers);
+        register_tool(&state, "docs", "search").await;
+        let tool = tools_list(state).await;
+        assert!(tool.get("_meta").is_none());
+    }
 
-                        // Try to parse as JSON
-                        if let Ok(_) = serde_json::from_str::<Value>(&line) {
-                            println!(
-                                "✅ [{}] Found valid JSON init response (elapsed: {:?})",
-                                server_name,
-                                start_time.elapsed()
-                            );
-                            break;
-                        } else {
-                            println!(
-                                "🔍 [{}] Init line is not JSON, continuing... (elapsed: {:?})",
-                                server_name,
-                                start_time.elapsed()
-                            );
-                            init_attempts += 1;
-                            if init_attempts >= max_init_attempts {
-                                println!("⚠️ [{}] No JSON init response found after {} attempts, but continuing anyway... (elapsed: {:?})", server_name, max_init_attempts, start_time.elapsed());
-                                break; // Continue without valid init response
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!(
-                            "❌ [{}] Failed to read init line: {} (elapsed: {:?})",
-                            server_name,
-                            e,
-                            start_time.elapsed()
-                        );
-                        return Err(e.into());
-                    }
+    #[tokio::test]
+    async fn test_sanitized_names_resolve_to_original_tool() {
+        let (_dir, state) = state_with_servers(json!({ "ctx-7": { "command": "true" } }));
+        for tool in ["resolve-library-id", "docs.search", "list files"] {
+            register_tool(&state, "ctx-7", tool).await;
+        }
+        let servers = HashMap::from([("ctx-7".to_string(), "ctx_7".to_string())]);
+        let tools = state.available_tools.read().await;
+
+        for (incoming, original) in [
+            // Cursor-style hyphen sanitization
+            ("ctx_7_resolve_library_id", "resolve-library-id"),
+            // Clients that also rewrite dots and spaces
+            ("ctx_7_docs_search", "docs.search"),
+            ("ctx_7_list_files", "list files"),
+            // Clients that don't sanitize at all
+            ("ctx-7_docs.search", "docs.search"),
+        ] {
+            let parsed = parse_tool_name_with_servers(incoming, &servers, &tools).unwrap();
+            assert_eq!(parsed.server_name, "ctx-7");
+            assert_eq!(parsed.tool_name, original, "incoming name {}", incoming);
+        }
+    }
+
+    #[test]
+    fn test_local_tools_config_reports_rejected_entries() {
+        let config_json = json!({
+            "servers": {
+                "filesystem": {
+                    "command": "npx",
+                    "args": ["-y", "@modelcontextprotocol/server-filesystem", "/workspace"]
                 },
-                Err(_) => {
-                    println!(
-                        "❌ [{}] Timeout reading init line after {} seconds (elapsed: {:?})",
-                        server_name,
-                        timeout_secs,
-                        start_time.elapsed()
-                    );
-                    return Ok(Vec::new());
-                }
+                "broken": { "command": "npx", "args": "-y @oops/not-a-list" },
+                "remote": { "transport": "http" }
             }
-        }
+        })
+        .to_string();
+
+        let (servers, rejected) = parse_local_tools_config(&config_json).unwrap();
+        assert_eq!(servers.keys().collect::<Vec<_>>(), vec!["filesystem"]);
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0].0, "broken");
+        assert!(rejected[0].1.contains("invalid type"), "{}", rejected[0].1);
+        assert_eq!(
+            rejected[1],
+            (
+                "remote".to_string(),
+                "http server requires a 'url'".to_string()
+            )
+        );
 
-        // Init response parsing is now handled in the loop above
+        assert!(parse_local_tools_config("{\"servers\": 3}").is_err());
+    }
 
-        // Send initialized notification (required by MCP protocol)
-        let initialized_notification = json!({
-            "jsonrpc": "2.0",
-            "method": "notifications/initialized"
-        });
+    #[tokio::test]
+    async fn test_hidden_builtin_tools_remain_callable() {
+        let (_dir, state) = state_with_config(json!({
+            "hide_builtin_tools": true,
+            "servers": { "memory": { "command": "true" } }
+        }));
+        register_tool(&state, "memory", "read_graph").await;
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+        let result = state
+            .handle_jsonrpc_request(request, None)
+            .await
+            .result
+            .unwrap();
+        let names: Vec<&str> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["memory_read_graph"]);
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("toolman_list_available_tools", json!({})), None)
+            .await;
+        let text = response.result.unwrap()["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(text.contains("available_tools_by_server"));
+    }
 
-        let initialized_msg = format!("{}\n", serde_json::to_string(&initialized_notification)?);
-        println!(
-            "🔍 [{}] Sending initialized notification (elapsed: {:?}): {}",
-            server_name,
-            start_time.elapsed(),
-            initialized_msg.trim()
-        );
+    #[test]
+    fn test_parse_http_jsonrpc_body() {
+        let direct =
+            parse_http_jsonrpc_body("{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{\"tools\":[]}}\n")
+                .unwrap();
+        assert_eq!(direct["id"], 2);
+
+        // SSE framing, with and without an event line or the space after `data:`
+        let sse = "event: message\r\ndata: {\"jsonrpc\":\"2.0\",\"id\":3,\"result\":{}}\r\n\r\n";
+        assert_eq!(parse_http_jsonrpc_body(sse).unwrap()["id"], 3);
+        let sse = ": keep-alive\n\ndata:{\"jsonrpc\":\"2.0\",\n\ndata: {\"jsonrpc\":\"2.0\",\"id\":4,\"result\":{}}\n";
+        assert_eq!(parse_http_jsonrpc_body(sse).unwrap()["id"], 4);
+
+        let garbage = format!("<html>502 Bad Gateway</html>{}", "x".repeat(500));
+        let error = parse_http_jsonrpc_body(&garbage).unwrap_err().to_string();
+        assert!(error.contains("neither JSON nor SSE-framed JSON"));
+        assert!(error.contains("<html>502 Bad Gateway</html>"));
+        assert!(error.len() < 300, "snippet should be truncated: {}", error);
+    }
 
-        if let Err(e) = stdin.write_all(initialized_msg.as_bytes()).await {
-            println!(
-                "❌ [{}] Failed to write initialized notification: {} (elapsed: {:?})",
-                server_name,
-                e,
-                start_time.elapsed()
-            );
-            return Err(e.into());
-        }
+    #[test]
+    fn test_shadow_diff_ignores_envelope() {
+        let primary = json!({"jsonrpc": "2.0", "id": 1, "result": {"ok": true}});
+        let shadow = json!({"jsonrpc": "2.0", "id": 42, "result": {"ok": true}});
+        assert_eq!(shadow_diff(&primary, &shadow), None);
 
-        // Give server time to initialize (especially important for document-scanning servers)
-        println!(
-            "🔍 [{}] Waiting for server initialization (3 seconds)... (elapsed: {:?})",
-            server_name,
-            start_time.elapsed()
+        let shadow = json!({"jsonrpc": "2.0", "id": 42, "error": {"code": -1, "message": "boom"}});
+        assert!(shadow_diff(&primary, &shadow).is_some());
+    }
+
+    #[test]
+    fn test_parse_tool_name_valid() {
+        // Simple case
+        let result = parse_tool_name("memory_delete_entities").unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: "memory".to_string(),
+                tool_name: "delete_entities".to_string(),
+            }
         );
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-        println!(
-            "🔍 [{}] Server initialization wait complete (elapsed: {:?})",
-            server_name,
-            start_time.elapsed()
+
+        // Single underscore in tool name
+        let result = parse_tool_name("filesystem_read_file").unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: "filesystem".to_string(),
+                tool_name: "read_file".to_string(),
+            }
         );
 
-        // Get tools list
-        let tools_request = json!({
-            "jsonrpc": "2.0",
-            "id": 2,
-            "method": "tools/list",
-            "params": {}
-        });
+        // Multiple underscores in tool name - should split at first underscore only
+        let result = parse_tool_name("memory_delete_all_entities").unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: "memory".to_string(),
+                tool_name: "delete_all_entities".to_string(),
+            }
+        );
 
-        let tools_msg = format!("{}\n", serde_json::to_string(&tools_request)?);
-        println!(
-            "🔍 [{}] Sending tools/list request (elapsed: {:?}): {}",
-            server_name,
-            start_time.elapsed(),
-            tools_msg.trim()
+        // Numbers and hyphens
+        let result = parse_tool_name("server123_tool-name").unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: "server123".to_string(),
+                tool_name: "tool-name".to_string(),
+            }
         );
+    }
 
-        if let Err(e) = stdin.write_all(tools_msg.as_bytes()).await {
-            println!(
-                "❌ [{}] Failed to write tools request: {} (elapsed: {:?})",
-                server_name,
-                e,
-                start_time.elapsed()
-            );
-            return Err(e.into());
-        }
+    #[test]
+    fn test_parse_tool_name_invalid() {
+        // Empty string
+        assert!(matches!(
+            parse_tool_name(""),
+            Err(ToolParseError::EmptyToolName)
+        ));
 
-        // Read tools response (may also have status messages before JSON)
-        line.clear();
-        println!(
-            "🔍 [{}] Reading tools response... (elapsed: {:?})",
-            server_name,
-            start_time.elapsed()
-        );
+        // No underscore
+        assert!(matches!(
+            parse_tool_name("memorydel"),
+            Err(ToolParseError::InvalidFormat(_))
+        ));
 
-        // Keep reading lines until we get valid JSON or EOF
-        let mut tools_attempts = 0;
-        let max_tools_attempts = 10; // Increased from 5
+        // Missing server name (starts with underscore)
+        assert!(matches!(
+            parse_tool_name("_delete_entities"),
+            Err(ToolParseError::InvalidFormat(_))
+        ));
 
-        loop {
-            line.clear();
-            println!(
-                "🔍 [{}] Waiting for tools response line {} (elapsed: {:?})",
-                server_name,
-                tools_attempts + 1,
-                start_time.elapsed()
-            );
+        // Missing tool name (ends with underscore)
+        assert!(matches!(
+            parse_tool_name("memory_"),
+            Err(ToolParseError::InvalidFormat(_))
+        ));
 
-            match tokio::time::timeout(
-                tokio::time::Duration::from_secs(timeout_secs),
-                reader.read_line(&mut line),
-            )
-            .await
-            {
-                Ok(read_result) => match read_result {
-                    Ok(bytes_read) => {
-                        if bytes_read == 0 {
-                            println!("❌ [{}] No more lines to read for tools response (EOF) (elapsed: {:?})", server_name, start_time.elapsed());
-                            return Ok(Vec::new());
-                        }
+        // Only underscore
+        assert!(matches!(
+            parse_tool_name("_"),
+            Err(ToolParseError::InvalidFormat(_))
+        ));
+    }
 
-                        println!(
-                            "🔍 [{}] Read tools line {} ({} bytes, elapsed: {:?}): {}",
-                            server_name,
-                            tools_attempts + 1,
-                            bytes_read,
-                            start_time.elapsed(),
-                            line.trim()
-                        );
+    #[test]
+    fn test_parse_tool_name_edge_cases() {
+        // Multiple consecutive underscores
+        let result = parse_tool_name("server__tool").unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: "server".to_string(),
+                tool_name: "_tool".to_string(),
+            }
+        );
 
-                        // Try to parse as JSON
-                        if let Ok(_) = serde_json::from_str::<Value>(&line) {
-                            println!(
-                                "✅ [{}] Found valid JSON tools response (elapsed: {:?})",
-                                server_name,
-                                start_time.elapsed()
-                            );
-                            break;
-                        } else {
-                            println!(
-                                "🔍 [{}] Tools line is not JSON, continuing... (elapsed: {:?})",
-                                server_name,
-                                start_time.elapsed()
-                            );
-                            tools_attempts += 1;
-                            if tools_attempts >= max_tools_attempts {
-                                println!("❌ [{}] Too many non-JSON tools lines after {} attempts, giving up (elapsed: {:?})", server_name, max_tools_attempts, start_time.elapsed());
-                                return Ok(Vec::new());
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!(
-                            "❌ [{}] Failed to read tools line: {} (elapsed: {:?})",
-                            server_name,
-                            e,
-                            start_time.elapsed()
-                        );
-                        return Err(e.into());
-                    }
-                },
-                Err(_) => {
-                    println!(
-                        "❌ [{}] Timeout reading tools line after {} seconds (elapsed: {:?})",
-                        server_name,
-                        timeout_secs,
-                        start_time.elapsed()
-                    );
-                    return Ok(Vec::new());
-                }
+        // Very long names
+        let long_server = "a".repeat(100);
+        let long_tool = "b".repeat(100);
+        let long_name = format!("{}_{}", long_server, long_tool);
+        let result = parse_tool_name(&long_name).unwrap();
+        assert_eq!(
+            result,
+            ParsedTool {
+                server_name: long_server,
+                tool_name: long_tool,
             }
-        }
+        );
+    }
 
-        let tools = if let Ok(response) = serde_json::from_str::<Value>(&line) {
-            println!(
-                "🔍 [{}] Parsed tools response JSON successfully",
-                server_name
-            );
-            if let Some(result) = response.get("result") {
-                println!("🔍 [{}] Found 'result' field in response", server_name);
-                if let Some(tools_array) = result.get("tools").and_then(|t| t.as_array()) {
-                    println!(
-                        "🔍 [{}] Found 'tools' array with {} items",
-                        server_name,
-                        tools_array.len()
-                    );
-                    let parsed_tools: Vec<Tool> = tools_array
-                        .iter()
-                        .filter_map(|tool| {
-                            if let (Some(name), Some(description)) = (
-                                tool.get("name").and_then(|n| n.as_str()),
-                                tool.get("description").and_then(|d| d.as_str()),
-                            ) {
-                                println!(
-                                    "🔍 [{}] Found tool: {} - {}",
-                                    server_name, name, description
-                                );
-                                Some(Tool {
-                                    name: name.to_string(),
-                                    description: description.to_string(),
-                                    input_schema: tool
-                                        .get("inputSchema")
-                                        .cloned()
-                                        .unwrap_or(json!({})),
-                                    server_name: server_name.to_string(),
-                                    // Preserve the original tool name for accurate forwarding
-                                    original_tool_name: name.to_string(),
+    #[tokio::test]
+    async fn test_sequential_sse_calls_reuse_one_session() {
+        type Sessions =
+            Arc<std::sync::Mutex<HashMap<String, tokio::sync::mpsc::UnboundedSender<Value>>>>;
+        let sessions: Sessions = Arc::default();
+        let streams = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let handshakes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        // An SSE MCP backend: GET /sse opens a session stream, POST /message answers on it
+        let (open_sessions, opened) = (sessions.clone(), streams.clone());
+        let (answer_sessions, initialized) = (sessions.clone(), handshakes.clone());
+        let router = Router::new()
+            .route(
+                "/sse",
+                get(move || async move {
+                    let id = opened.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Value>();
+                    open_sessions.lock().unwrap().insert(id.to_string(), tx);
+                    let endpoint = futures::stream::once(async move {
+                        format!("event: endpoint\ndata: /message?sessionId={}\n\n", id)
+                    });
+                    let messages = futures::stream::unfold(rx, |mut rx| async move {
+                        let message = rx.recv().await?;
+                        Some((format!("event: message\ndata: {}\n\n", message), rx))
+                    });
+                    use futures::StreamExt;
+                    let body = endpoint
+                        .chain(messages)
+                        .map(Ok::<_, std::convert::Infallible>);
+                    (
+                        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                        axum::body::Body::from_stream(body),
+                    )
+                }),
+            )
+            .route(
+                "/message",
+                post(
+                    move |axum::extract::Query(query): axum::extract::Query<
+                        HashMap<String, String>,
+                    >,
+                          Json(request): Json<Value>| async move {
+                        let result = match request["method"].as_str() {
+                            Some("initialize") => {
+                                initialized.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                json!({
+                                    "protocolVersion": "2024-11-05",
+                                    "capabilities": {"tools": {}},
+                                    "serverInfo": {"name": "mock-sse", "version": "0.1.0"}
                                 })
-                            } else {
-                                println!(
-                                    "❌ [{}] Skipping malformed tool: {:?}",
-                                    server_name, tool
-                                );
-                                None
                             }
-                        })
-                        .collect();
-                    println!(
-                        "🔍 [{}] Successfully parsed {} tools",
-                        server_name,
-                        parsed_tools.len()
-                    );
-                    parsed_tools
-                } else {
-                    println!("❌ [{}] No 'tools' array found in result", server_name);
-                    println!("🔍 [{}] Result content: {:?}", server_name, result);
-                    Vec::new()
-                }
-            } else {
-                println!("❌ [{}] No 'result' field found in response", server_name);
-                println!("🔍 [{}] Response content: {:?}", server_name, response);
-                Vec::new()
-            }
-        } else {
-            println!(
-                "❌ [{}] Failed to parse tools response as JSON",
-                server_name
+                            Some("tools/call") => {
+                                json!({"content": [{"type": "text", "text": "pong"}]})
+                            }
+                            _ => return axum::http::StatusCode::ACCEPTED,
+                        };
+                        let sessions = answer_sessions.lock().unwrap();
+                        let session = &sessions[&query["sessionId"]];
+                        let _ = session
+                            .send(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}));
+                        axum::http::StatusCode::ACCEPTED
+                    },
+                ),
             );
-            println!("🔍 [{}] Raw response: {}", server_name, line);
-            Vec::new()
-        };
-
-        // Kill the discovery process - we only needed it for tool discovery
-        println!("🔍 [{}] Killing discovery process", server_name);
-        let _ = process.kill().await;
+        let url = spawn_http_backend(router).await;
+        let (_dir, state) = state_with_servers(json!({
+            "remote": { "transport": "sse", "url": format!("{}/sse", url) }
+        }));
+        register_tool(&state, "remote", "ping").await;
+
+        for _ in 0..2 {
+            let response = state
+                .handle_jsonrpc_request(tool_call("remote_ping", json!({})), None)
+                .await;
+            assert!(response.error.is_none(), "{:?}", response.error);
+            assert_eq!(response.result.unwrap()["content"][0]["text"], "pong");
+        }
 
-        println!(
-            "🔍 [{}] Tool discovery complete. Found {} tools",
-            server_name,
-            tools.len()
-        );
-        Ok(tools)
+        assert_eq!(handshakes.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(streams.load(std::sync::atomic::Ordering::SeqCst), 1);
     }
-}
-
-/// Discover tools from SSE server with bidirectional transport
-async fn discover_tools_via_sse(
-    client: &reqwest::Client,
-    server_name: &str,
-    sse_url: &str,
-    _existing_session_id: &str, // Not used, we'll get a fresh one
-) -> anyhow::Result<Vec<Tool>> {
-    use futures::StreamExt;
-    use tokio::time::{timeout, Duration};
-
-    println!("🚀 [{}] Starting SSE tool discovery", server_name);
-
-    // Step 1: Open SSE connection and get session ID
-    let sse_response = client
-        .get(sse_url)
-        .header("Accept", "text/event-stream")
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to connect to SSE endpoint: {}", e))?;
 
-    let mut body = sse_response.bytes_stream();
+    #[tokio::test]
+    async fn test_unwrap_path_extracts_payload_from_wrapped_result() {
+        let wrapped = json!({
+            "data": { "content": [{"type": "text", "text": "inner"}] },
+            "meta": { "backend": "acme" }
+        });
+        let seen = SeenHeaders::default();
+        let url = spawn_http_backend(mock_http_backend("lookup", wrapped.clone(), seen)).await;
+        let (_dir, state) = state_with_servers(json!({
+            "acme": {
+                "transport": "http",
+                "url": format!("{}/mcp", url),
+                "tools": { "lookup": { "unwrap_path": "/data" } }
+            },
+        }));
+        let pool = state.connection_pool.clone();
 
-    // Wait for session data from SSE stream, reading multiple chunks if needed
-    let mut accumulated_data = String::new();
-    let session_id = loop {
-        match timeout(Duration::from_secs(10), body.next()).await {
-            Ok(Some(Ok(chunk))) => {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                accumulated_data.push_str(&chunk_str);
+        let unwrapped = pool
+            .forward_tool_call_with_context("acme", "lookup", json!({}), None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            unwrapped["result"],
+            json!({ "content": [{"type": "text", "text": "inner"}] })
+        );
 
-                println!(
-                    "🔗 [{}] SSE handshake data: {}",
-                    server_name,
-                    chunk_str.trim()
-                );
+        // Tools without an unwrap_path keep the backend's result as it is
+        let untouched = pool
+            .forward_tool_call_with_context("acme", "search", json!({}), None, None)
+            .await
+            .unwrap();
+        assert_eq!(untouched["result"], wrapped);
+    }
 
-                // Try to parse session ID from accumulated data
-                if let Some(data_line) = accumulated_data
-                    .lines()
-                    .find(|line| line.starts_with("data: "))
-                {
-                    let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
-                    if let Some(session_param) = endpoint_path.split("sessionId=").nth(1) {
-                        break session_param.to_string();
-                    }
-                }
+    #[tokio::test]
+    async fn test_global_env_reaches_servers_unless_they_override_it() {
+        // The mock answers calls with $MOCK_TOOLS_CALL, so the result shows which value won
+        let global_result = json!({ "content": [{"type": "text", "text": "from global_env"}] });
+        let mut inherits = mock_stdio_server("ping", "unused");
+        inherits["env"]
+            .as_object_mut()
+            .unwrap()
+            .remove("MOCK_TOOLS_CALL");
+        let overrides = mock_stdio_server("ping", "from server env");
+        let (_dir, state) = state_with_config(json!({
+            "global_env": { "MOCK_TOOLS_CALL": global_result.to_string() },
+            "servers": { "inherits": inherits, "overrides": overrides },
+        }));
+        let pool = state.connection_pool.clone();
+
+        let response = pool
+            .forward_tool_call_with_context("inherits", "ping", json!({}), None, None)
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], "from global_env");
 
-                // If we have an "event: endpoint" but no data line yet, continue reading
-                if accumulated_data.contains("event: endpoint")
-                    && !accumulated_data
-                        .lines()
-                        .any(|line| line.starts_with("data: "))
-                {
-                    continue;
-                }
+        let response = pool
+            .forward_tool_call_with_context("overrides", "ping", json!({}), None, None)
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["content"][0]["text"], "from server env");
+    }
 
-                // If we've accumulated data but can't find session ID, something's wrong
-                if accumulated_data.len() > 1000 {
-                    return Err(anyhow::anyhow!(
-                        "Could not find sessionId in SSE data after reading {} chars",
-                        accumulated_data.len()
-                    ));
-                }
-            }
-            Ok(Some(Err(e))) => return Err(anyhow::anyhow!("SSE stream error: {}", e)),
-            Ok(None) => return Err(anyhow::anyhow!("SSE stream ended unexpectedly")),
-            Err(_) => return Err(anyhow::anyhow!("Timeout waiting for SSE session data")),
+    #[tokio::test]
+    async fn test_stale_heartbeat_makes_health_unhealthy() {
+        let (_dir, state) = state_with_config(json!({
+            "servers": {},
+            "health_heartbeat_timeout_secs": 30
+        }));
+
+        let (status, Json(health)) = health_check(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["status"], "ok");
+
+        // As if the watchdog had been stuck behind a lock for a minute
+        *state.heartbeat.lock().unwrap() = std::time::Instant::now()
+            .checked_sub(std::time::Duration::from_secs(60))
+            .unwrap();
+        let (status, Json(health)) = health_check(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health["status"], "unhealthy");
+
+        // A watchdog round brings it back
+        let watchdog = state.spawn_watchdog();
+        while state.heartbeat_age() > std::time::Duration::from_secs(1) {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
         }
-    };
+        watchdog.abort();
+        let (status, _) = health_check(State(state)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
 
-    println!("✅ [{}] Got SSE session ID: {}", server_name, session_id);
+    /// State with one registered tool on each of `memory`, `git` and `github`
+    async fn state_with_three_servers() -> (tempfile::TempDir, BridgeState) {
+        let (dir, state) = state_with_servers(json!({}));
+        register_tool(&state, "memory", "read_graph").await;
+        register_tool(&state, "git", "status").await;
+        register_tool(&state, "github", "create_issue").await;
+        (dir, state)
+    }
 
-    // Step 2: Prepare message endpoint
-    let base_url = sse_url.trim_end_matches("/sse").trim_end_matches('/');
-    let message_url = format!("{}/message?sessionId={}", base_url, session_id);
+    #[tokio::test]
+    async fn test_tools_list_filtered_to_one_server() {
+        let (_dir, state) = state_with_three_servers().await;
+        let params = json!({ "servers": ["git"] });
+        assert_eq!(
+            listed_tools_with_params(&state, Some(params)).await,
+            vec!["git_status"]
+        );
+        // The filter applies to that request only
+        assert_eq!(listed_tools(&state).await.len(), 3);
+    }
 
-    // Step 3: Start listening for responses in background task
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    #[tokio::test]
+    async fn test_tools_list_filtered_to_several_servers() {
+        let (_dir, state) = state_with_three_servers().await;
+        let params = json!({ "servers": ["memory", "github"] });
+        assert_eq!(
+            listed_tools_with_params(&state, Some(params)).await,
+            vec!["github_create_issue", "memory_read_graph"]
+        );
+    }
 
-    // Spawn SSE response listener
-    let tx_clone = tx.clone();
-    let server_name_clone = server_name.to_string();
-    tokio::spawn(async move {
-        let mut accumulated_data = String::new();
-        let mut in_data_section = false;
+    #[tokio::test]
+    async fn test_tools_list_filter_ignores_unknown_servers() {
+        let (_dir, state) = state_with_three_servers().await;
+        let params = json!({ "servers": ["memory", "nonexistent"] });
+        assert_eq!(
+            listed_tools_with_params(&state, Some(params)).await,
+            vec!["memory_read_graph"]
+        );
+    }
 
-        while let Some(chunk_result) = body.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    println!(
-                        "🔍 [{}] SSE chunk received ({} bytes):\n{}",
-                        server_name_clone,
-                        chunk.len(),
-                        chunk_str
-                    );
+    #[tokio::test]
+    async fn test_client_meta_is_forwarded_to_backend() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| async move {
+                recorded.lock().unwrap().push(request.clone());
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {"content": []}}))
+            }),
+        );
+        let url = spawn_http_backend(router).await;
+        let (_dir, state) = state_with_servers(json!({
+            "remote": { "transport": "http", "url": format!("{}/mcp", url) },
+        }));
+        register_tool(&state, "remote", "crawl").await;
+
+        let mut request = tool_call("remote_crawl", json!({ "url": "https://example.com" }));
+        request.params.as_mut().unwrap()["_meta"] =
+            json!({ "progressToken": "progress-42", "traceparent": "00-abc-def-01" });
+        let response = state.handle_jsonrpc_request(request, None).await;
+        assert!(response.error.is_none());
+
+        let seen = seen.lock().unwrap();
+        let call = seen
+            .iter()
+            .find(|r| r["method"] == "tools/call")
+            .expect("backend should receive the call");
+        assert_eq!(call["params"]["name"], "crawl");
+        assert_eq!(call["params"]["arguments"]["url"], "https://example.com");
+        assert_eq!(call["params"]["_meta"]["progressToken"], "progress-42");
+        assert_eq!(call["params"]["_meta"]["traceparent"], "00-abc-def-01");
+    }
 
-                    let lines: Vec<&str> = chunk_str.lines().collect();
-                    println!("🔍 [{}] SSE lines parsed: {:?}", server_name_clone, lines);
-
-                    for line in lines {
-                        if line == "event: message" {
-                            println!("✅ [{}] Found 'event: message'", server_name_clone);
-                            in_data_section = true;
-                            accumulated_data.clear();
-                        } else if line.starts_with("data: ") && in_data_section {
-                            // Accumulate data from this line (multiple data lines should be joined with newlines)
-                            if let Some(json_part) = line.strip_prefix("data: ") {
-                                if !accumulated_data.is_empty() {
-                                    accumulated_data.push('\n');
-                                }
-                                accumulated_data.push_str(json_part);
-                                println!(
-                                    "🔍 [{}] Accumulated {} bytes of data",
-                                    server_name_clone,
-                                    accumulated_data.len()
-                                );
-                            }
-                        } else if line.starts_with("data: ") {
-                            // Handle standalone data lines without event prefix
-                            println!(
-                                "🔍 [{}] Found standalone data line: '{}'",
-                                server_name_clone, line
-                            );
-                            if let Some(json_str) = line.strip_prefix("data: ") {
-                                accumulated_data = json_str.to_string();
-                                println!(
-                                    "🔍 [{}] Set accumulated data from standalone: {}",
-                                    server_name_clone, accumulated_data
-                                );
-                            }
-                        } else if line.trim().is_empty() && !accumulated_data.is_empty() {
-                            // Empty line indicates end of SSE message, try to parse accumulated data
-                            println!(
-                                "🔍 [{}] End of SSE message, attempting to parse {} bytes of JSON",
-                                server_name_clone,
-                                accumulated_data.len()
-                            );
-                            println!(
-                                "🔍 [{}] Accumulated JSON: {}",
-                                server_name_clone, accumulated_data
-                            );
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_backend_handling_sigterm_exits_before_sigkill() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("cleaned-up");
+        let grace = tokio::time::Duration::from_secs(5);
+
+        // Cleans up and exits on SIGTERM
+        let mut graceful = Command::new("sh")
+            .args([
+                "-c",
+                r#"trap 'echo done > "$MARKER"; exit 0' TERM; while :; do sleep 0.1; done"#,
+            ])
+            .env("MARKER", &marker)
+            .spawn()
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        let started = std::time::Instant::now();
+        let status = terminate_gracefully("graceful", &mut graceful, grace)
+            .await
+            .unwrap();
+        assert!(status.success(), "{:?}", status);
+        assert_eq!(status.signal(), None);
+        assert!(started.elapsed() < grace);
+        assert!(marker.exists(), "SIGTERM handler should have run");
+
+        // Ignores SIGTERM, so it is killed once the grace period is over
+        let mut stubborn = Command::new("sh")
+            .args(["-c", "trap '' TERM; while :; do sleep 0.1; done"])
+            .spawn()
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        let status = terminate_gracefully(
+            "stubborn",
+            &mut stubborn,
+            tokio::time::Duration::from_millis(500),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
 
-                            match serde_json::from_str::<serde_json::Value>(&accumulated_data) {
-                                Ok(response) => {
-                                    println!(
-                                        "📨 [{}] SSE response parsed successfully from accumulated data",
-                                        server_name_clone
-                                    );
-                                    let _ = tx_clone.send(response);
-                                    accumulated_data.clear();
-                                    in_data_section = false;
-                                }
-                                Err(e) => {
-                                    println!(
-                                        "❌ [{}] Failed to parse accumulated JSON: {} (error: {})",
-                                        server_name_clone, accumulated_data, e
-                                    );
-                                    println!(
-                                        "🔍 [{}] JSON bytes: {:?}",
-                                        server_name_clone,
-                                        accumulated_data.as_bytes()
-                                    );
-                                }
-                            }
-                        } else if in_data_section && !line.trim().is_empty() {
-                            // This might be a continuation of the previous data line that got split across chunks
-                            println!(
-                                "🔍 [{}] Found continuation line in data section: '{}'",
-                                server_name_clone, line
-                            );
-                            accumulated_data.push_str(line);
-                            println!(
-                                "🔍 [{}] Accumulated {} bytes after continuation",
-                                server_name_clone,
-                                accumulated_data.len()
-                            );
-                        } else {
-                            println!("🔍 [{}] Skipping line: '{}'", server_name_clone, line);
-                        }
+    #[tokio::test]
+    async fn test_configured_category_overrides_inferred_one() {
+        let (_dir, state) = state_with_servers(json!({
+            "github": {
+                "transport": "http",
+                "url": "http://localhost:1/mcp",
+                "tools": {
+                    "search_code": {
+                        "category": "version-control",
+                        "use_cases": ["finding code across repositories"]
                     }
                 }
-                Err(e) => {
-                    println!("❌ [{}] SSE stream error: {}", server_name_clone, e);
-                    break;
-                }
             }
-        }
+        }));
+        register_tool(&state, "github", "search_code").await;
+        register_tool(&state, "github", "search_issues").await;
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+
+        let tools = state.available_tools.read().await.clone();
+        let catalog = state.build_tool_catalog(&tools, &servers, &HashMap::new());
+        let catalog = serde_json::to_value(&catalog).unwrap();
+        let catalog_tool = |name: &str| {
+            catalog["remote"]["github"]["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|t| t["name"] == name)
+                .unwrap()
+                .clone()
+        };
 
-        // Stream ended - try to parse any remaining accumulated data
-        if !accumulated_data.is_empty() {
-            println!(
-                "🔍 [{}] Stream ended with {} bytes of accumulated data, attempting final parse",
-                server_name_clone,
-                accumulated_data.len()
+        // "search" in the name would otherwise make this a search tool
+        let curated = catalog_tool("search_code");
+        assert_eq!(curated["category"], "version-control");
+        assert_eq!(
+            curated["use_cases"],
+            json!(["finding code across repositories"])
+        );
+
+        let inferred = catalog_tool("search_issues");
+        assert_eq!(inferred["category"], "search");
+        assert_eq!(inferred["use_cases"], json!(["finding information"]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_docker_servers_discovered_within_their_own_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        // A "docker" command that is really sh, so no daemon is needed
+        let docker = dir.path().join("docker");
+        std::os::unix::fs::symlink("/bin/sh", &docker).unwrap();
+
+        // Each process marks its startup (+ ... -) in a log before serving MCP
+        let mock_logging_to = |command: &str, log: &std::path::Path| {
+            let mut server = mock_stdio_server("ping", "pong");
+            server["command"] = json!(command);
+            server["args"] = json!([
+                "-c",
+                format!(
+                    "echo + >> \"$STARTUP_LOG\"; sleep 0.3; echo - >> \"$STARTUP_LOG\"\n{}",
+                    MOCK_STDIO_SERVER
+                )
+            ]);
+            server["env"]["STARTUP_LOG"] = json!(log);
+            server
+        };
+        let docker_log = dir.path().join("docker.log");
+        let other_log = dir.path().join("other.log");
+        let mut servers = serde_json::Map::new();
+        for i in 0..3 {
+            servers.insert(
+                format!("docker{}", i),
+                mock_logging_to(docker.to_str().unwrap(), &docker_log),
             );
-            match serde_json::from_str::<serde_json::Value>(&accumulated_data) {
-                Ok(response) => {
-                    println!(
-                        "📨 [{}] Final SSE response parsed successfully from accumulated data",
-                        server_name_clone
-                    );
-                    let _ = tx_clone.send(response);
-                }
-                Err(e) => {
-                    println!(
-                        "❌ [{}] Failed to parse final accumulated JSON: {} (error: {})",
-                        server_name_clone, accumulated_data, e
-                    );
-                }
-            }
+            servers.insert(format!("plain{}", i), mock_logging_to("sh", &other_log));
         }
-    });
+        let (_dir, state) = state_with_config(json!({
+            "docker_discovery_concurrency": 1,
+            "servers": servers,
+        }));
+        let server_list: Vec<_> = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
 
-    // Step 4: Send MCP handshake sequence
+        let tools = state.discover_servers(server_list).await;
+        assert_eq!(tools.len(), 6);
 
-    // 4a. Send initialize request
-    println!("📤 [{}] Sending initialize request", server_name);
-    let init_request = json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "initialize",
-        "params": {
-            "protocolVersion": "2024-11-05",
-            "capabilities": {},
-            "clientInfo": {
-                "name": "toolman",
-                "version": "1.0.0"
+        // Most processes that were starting up at the same moment
+        let max_overlap = |log: &std::path::Path| {
+            let mut running = 0i32;
+            let mut max = 0;
+            for line in std::fs::read_to_string(log).unwrap().lines() {
+                running += if line == "+" { 1 } else { -1 };
+                max = max.max(running);
             }
-        }
-    });
-
-    client
-        .post(&message_url)
-        .header("Content-Type", "application/json")
-        .json(&init_request)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send initialize: {}", e))?;
+            max
+        };
+        assert_eq!(max_overlap(&docker_log), 1);
+        assert_eq!(max_overlap(&other_log), 3);
+    }
 
-    // Wait for initialize response
-    let _init_response = timeout(Duration::from_secs(10), rx.recv())
-        .await
-        .map_err(|_| anyhow::anyhow!("Timeout waiting for initialize response"))?
-        .ok_or_else(|| anyhow::anyhow!("Initialize response channel closed"))?;
+    #[tokio::test]
+    async fn test_context_tool_reports_resolved_working_directory() {
+        let (dir, state) = state_with_servers(json!({
+            "tasks": { "command": "task-master-mcp", "inject_params": { "projectRoot": "working_dir" } },
+            "memory": { "command": "memory-mcp" },
+        }));
+        let context = |headers: Option<axum::http::HeaderMap>| {
+            let state = state.clone();
+            async move {
+                let result = state
+                    .handle_jsonrpc_request(
+                        tool_call("toolman_context", json!({})),
+                        headers.as_ref(),
+                    )
+                    .await
+                    .result
+                    .unwrap();
+                serde_json::from_str::<Value>(result["content"][0]["text"].as_str().unwrap())
+                    .unwrap()
+            }
+        };
 
-    println!("✅ [{}] Initialize response received", server_name);
+        // Nothing resolved yet: nothing is injected
+        let unresolved = context(None).await;
+        assert_eq!(unresolved["working_directory"], Value::Null);
+        assert_eq!(unresolved["injected_arguments"], json!([]));
+        assert_eq!(
+            unresolved["project_directory"],
+            json!(dir.path().to_string_lossy())
+        );
 
-    // 4b. Send initialized notification
-    println!("📤 [{}] Sending initialized notification", server_name);
-    let initialized_notification = json!({
-        "jsonrpc": "2.0",
-        "method": "notifications/initialized"
-    });
+        // Calls are resolved against the directory the client reports
+        let resolved = context(Some(working_dir_headers("/home/dev/checkout"))).await;
+        assert_eq!(resolved["working_directory"], "/home/dev/checkout");
+        assert_eq!(resolved["client_working_directory"], "/home/dev/checkout");
+        assert_eq!(
+            resolved["injected_arguments"],
+            json!([{ "server": "tasks", "key": "projectRoot", "value": "/home/dev/checkout" }])
+        );
+    }
 
-    client
-        .post(&message_url)
-        .header("Content-Type", "application/json")
-        .json(&initialized_notification)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send initialized: {}", e))?;
+    #[test]
+    fn test_sse_chunk_logging_is_skipped_unless_enabled() {
+        let chunks: [&[u8]; 3] = [
+            b"event: message\ndata: {\"jsonrpc\":\"2.0\",",
+            b"\"id\":1,\"result\":{}}\n\n",
+            b"event: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{}}\n\n",
+        ];
+        // Parse the chunks under a subscriber that would show every trace event
+        let parse = |log_chunks: bool| {
+            let captured = CapturedLogs::default();
+            let writer = captured.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .with_env_filter(log_filter(Some("trace")))
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish();
+            let messages = tracing::subscriber::with_default(subscriber, || {
+                let settings = SseStreamSettings {
+                    log_chunks,
+                    ..SseStreamSettings::from_config(&ServersConfig::default())
+                };
+                let mut parser = SseMessageParser::new("remote".to_string(), settings);
+                chunks
+                    .iter()
+                    .flat_map(|chunk| parser.push_chunk(chunk).unwrap())
+                    .collect::<Vec<_>>()
+            });
+            let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+            (messages, logs)
+        };
 
-    // 4c. Send tools/list request
-    println!("📤 [{}] Sending tools/list request", server_name);
-    let tools_request = json!({
-        "jsonrpc": "2.0",
-        "id": 2,
-        "method": "tools/list",
-        "params": {}
-    });
+        let (messages, logs) = parse(false);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["id"], 1);
+        assert_eq!(messages[1]["id"], 2);
+        assert!(logs.is_empty(), "{}", logs);
 
-    client
-        .post(&message_url)
-        .header("Content-Type", "application/json")
-        .json(&tools_request)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send tools/list: {}", e))?;
+        let (messages, logs) = parse(true);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(logs.matches("SSE chunk received").count(), chunks.len());
+    }
 
-    // Wait for tools/list response
-    let tools_response = timeout(Duration::from_secs(10), rx.recv())
-        .await
-        .map_err(|_| anyhow::anyhow!("Timeout waiting for tools/list response"))?
-        .ok_or_else(|| anyhow::anyhow!("Tools/list response channel closed"))?;
+    #[tokio::test]
+    async fn test_tools_list_follows_saved_context_for_working_directory() {
+        let (_dir, mut state) = state_with_servers(json!({}));
+        let contexts_dir = tempfile::tempdir().unwrap();
+        let mut context_manager =
+            ContextManager::with_contexts_dir(contexts_dir.path().to_path_buf());
+        context_manager
+            .load_context("/work/project".to_string(), None, None)
+            .unwrap();
+        context_manager.get_context_mut().unwrap().enabled_tools = HashMap::from([
+            (
+                "memory".to_string(),
+                vec!["read_graph".to_string(), "create_entities".to_string()],
+            ),
+            ("git".to_string(), vec!["status".to_string()]),
+        ]);
+        context_manager.save_context().unwrap();
+        state.context_manager = Some(context_manager);
+
+        register_tool(&state, "memory", "read_graph").await;
+        register_tool(&state, "memory", "create_entities").await;
+        register_tool(&state, "memory", "delete_entities").await;
+        register_tool(&state, "github", "create_issue").await;
+
+        let list = |working_dir: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert("x-working-directory", working_dir.parse().unwrap());
+            let state = state.clone();
+            async move {
+                let request = JsonRpcRequest {
+                    jsonrpc: "2.0".to_string(),
+                    id: Some(json!(1)),
+                    method: "tools/list".to_string(),
+                    params: None,
+                };
+                let result = state
+                    .handle_jsonrpc_request(request, Some(&headers))
+                    .await
+                    .result
+                    .unwrap();
+                let mut names: Vec<String> = result["tools"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|t| t["name"].as_str().unwrap().to_string())
+                    .collect();
+                names.sort();
+                names
+            }
+        };
 
-    println!("✅ [{}] Tools/list response received", server_name);
+        let names = list("/work/project").await;
+        assert!(names.contains(&"toolman_list_available_tools".to_string()));
+        let backend_tools: Vec<_> = names
+            .iter()
+            .filter(|n| !n.starts_with("toolman_"))
+            .collect();
+        assert_eq!(
+            backend_tools,
+            vec!["memory_create_entities", "memory_read_graph"]
+        );
 
-    // Step 5: Parse tools from response
-    let tools: Vec<Tool> = if let Some(result) = tools_response.get("result") {
-        if let Some(tools_array) = result.get("tools") {
-            if let Ok(tools_vec) =
-                serde_json::from_value::<Vec<serde_json::Value>>(tools_array.clone())
-            {
-                tools_vec
-                    .into_iter()
-                    .filter_map(|tool| {
-                        let name = tool.get("name")?.as_str()?.to_string();
-                        let description = tool
-                            .get("description")
-                            .and_then(|d| d.as_str())
-                            .unwrap_or("")
-                            .to_string();
-                        let input_schema = tool.get("inputSchema").cloned().unwrap_or(json!({}));
+        // No saved context for this directory: everything is listed
+        let names = list("/work/other").await;
+        assert_eq!(
+            names.iter().filter(|n| !n.starts_with("toolman_")).count(),
+            4
+        );
+    }
 
-                        Some(Tool {
-                            name: name.clone(),
-                            description,
-                            input_schema,
-                            server_name: server_name.to_string(),
-                            // Preserve the original tool name for accurate forwarding
-                            original_tool_name: name,
-                        })
-                    })
-                    .collect()
-            } else {
-                return Err(anyhow::anyhow!("Failed to parse tools array"));
-            }
-        } else {
-            return Err(anyhow::anyhow!("No tools in response"));
+    #[tokio::test]
+    async fn test_discovery_spawns_one_process_per_stdio_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("spawns.log");
+        let mut server = mock_stdio_server("ping", "pong");
+        server["args"] = json!([
+            "-c",
+            format!("echo spawned >> \"$SPAWN_LOG\"\n{}", MOCK_STDIO_SERVER)
+        ]);
+        server["env"]["SPAWN_LOG"] = json!(log);
+        let (_dir, state) = state_with_servers(json!({ "worker": server }));
+        let config = state.system_config_manager.read().await.get_servers()["worker"].clone();
+
+        // Concurrent discoveries of a server nobody has started yet
+        let (first, second, third) = tokio::join!(
+            state.discover_server_tools("worker", &config),
+            state.discover_server_tools("worker", &config),
+            state.discover_server_tools("worker", &config),
+        );
+        for tools in [first.unwrap(), second.unwrap(), third.unwrap()] {
+            assert_eq!(tools.len(), 1);
         }
-    } else {
-        return Err(anyhow::anyhow!("No result in tools response"));
-    };
+        // And again once it's warm
+        state
+            .discover_server_tools("worker", &config)
+            .await
+            .unwrap();
 
-    println!(
-        "✅ [{}] Discovered {} tools via SSE",
-        server_name,
-        tools.len()
-    );
-    Ok(tools)
-}
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+        assert!(state
+            .connection_pool
+            .connections
+            .read()
+            .await
+            .contains_key("worker"));
+    }
 
-impl BridgeState {
-    async fn handle_jsonrpc_request(
-        &self,
-        request: JsonRpcRequest,
-        _headers: Option<&axum::http::HeaderMap>,
-    ) -> JsonRpcResponse {
-        println!(
-            "🔍 DEBUG: handle_jsonrpc_request called with method: {}",
-            request.method
-        );
-        match request.method.as_str() {
-            "initialize" => {
-                // Standard MCP initialization - simplified, no session complexity
-                JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: request.id,
-                    result: Some(json!({
-                        "protocolVersion": "2024-11-05",
-                        "capabilities": {
-                            "tools": {
-                                "listChanged": true
-                            }
-                        },
-                        "serverInfo": {
-                            "name": "toolman",
-                            "version": "1.0.0"
-                        }
-                    })),
-                    error: None,
-                }
-            }
-            "tools/list" => {
-                println!(
-                    "🔍 DEBUG: tools/list handler called - returning ALL tools without filtering"
-                );
+    #[tokio::test]
+    async fn test_idle_reaper_stops_stale_connections() {
+        let (_dir, state) = state_with_servers(json!({
+            "stale": mock_stdio_server("ping", "pong"),
+            "fresh": mock_stdio_server("ping", "pong"),
+        }));
+        let pool = state.connection_pool.clone();
+        pool.start_server("stale").await.unwrap();
+        pool.start_server("fresh").await.unwrap();
+
+        let idle_timeout = std::time::Duration::from_secs(60);
+        {
+            let connections = pool.connections.read().await;
+            let mut stale = connections["stale"].lock().await;
+            stale.last_used -= idle_timeout;
+        }
 
-                // Get ALL available tools without any filtering
-                let available_tools = self.available_tools.read().await;
-                let mut all_tools = Vec::new();
+        assert_eq!(pool.reap_idle_connections(idle_timeout).await, ["stale"]);
+        let connections = pool.connections.read().await;
+        assert!(!connections.contains_key("stale"));
+        assert!(connections.contains_key("fresh"));
+    }
 
-                println!(
-                    "🔍 Returning {} tools from all servers",
-                    available_tools.len()
-                );
+    #[tokio::test]
+    async fn test_text_only_clients_get_non_text_content_as_text() {
+        let image = json!({
+            "type": "image",
+            "data": "A".repeat(16384),
+            "mimeType": "image/png"
+        });
+        let result = json!({ "content": [{"type": "text", "text": "Screenshot:"}, image] });
+        let (_dir, state) = state_with_servers(json!({
+            "browser": mock_stdio_server_returning("screenshot", result.clone()),
+        }));
+        let initialize = |capabilities: Value| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: Some(json!({ "capabilities": capabilities })),
+        };
+        let session = |id: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert("x-session-id", id.parse().unwrap());
+            headers
+        };
+        let (text_only, rich) = (session("session-a"), session("session-b"));
+        let call = |headers: &axum::http::HeaderMap| {
+            let state = state.clone();
+            let headers = headers.clone();
+            async move {
+                state
+                    .handle_jsonrpc_request(
+                        tool_call("browser_screenshot", json!({})),
+                        Some(&headers),
+                    )
+                    .await
+                    .result
+                    .unwrap()
+            }
+        };
 
-                // Add built-in toolman tools first
-                all_tools.push(json!({
-                    "name": "toolman_list_available_tools",
-                    "description": "Get available tools and MCP client config file structure for automated config generation.",
-                    "inputSchema": {
-                        "type": "object",
-                        "properties": {}
-                    }
-                }));
+        // A client that renders everything initializing later doesn't undo the other's choice
+        state
+            .handle_jsonrpc_request(
+                initialize(json!({ "experimental": { "textOnlyContent": true } })),
+                Some(&text_only),
+            )
+            .await;
+        state
+            .handle_jsonrpc_request(initialize(json!({})), Some(&rich))
+            .await;
+        assert_eq!(
+            call(&text_only).await["content"],
+            json!([
+                {"type": "text", "text": "Screenshot:"},
+                {"type": "text", "text": "[image: 12KB png]"}
+            ])
+        );
+        assert_eq!(call(&rich).await, result);
 
-                // Add ALL tools from servers - no filtering
-                for (prefixed_tool_name, tool) in available_tools.iter() {
-                    println!("✅ Including tool: {}", prefixed_tool_name);
-                    all_tools.push(json!({
-                        "name": prefixed_tool_name,
-                        "description": tool.description,
-                        "inputSchema": tool.input_schema
-                    }));
-                }
+        state
+            .handle_jsonrpc_request(initialize(json!({})), Some(&text_only))
+            .await;
+        assert_eq!(call(&text_only).await, result);
+    }
 
-                JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: request.id,
-                    result: Some(json!({ "tools": all_tools })),
-                    error: None,
-                }
-            }
-            "tools/call" => {
-                if let Some(params) = request.params {
-                    if let Some(tool_name) = params.get("name").and_then(|v| v.as_str()) {
-                        let result = {
-                            // Handle built-in toolman tools first
-                            if tool_name == "toolman_list_available_tools" {
-                                // Generate config structure for agents
-                                let available_tools = self.available_tools.read().await;
-                                let config_manager = self.system_config_manager.read().await;
-                                let _servers = config_manager.get_servers();
+    #[tokio::test]
+    async fn test_websocket_server_discovery_and_calls() {
+        let (url, accepted) = spawn_websocket_backend().await;
+        let (_dir, state) = state_with_servers(json!({
+            "ws": { "transport": "websocket", "url": url },
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["ws"].clone();
+
+        let tools = state.discover_server_tools("ws", &config).await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+
+        // The first call reuses the discovery connection, the second finds it closed and reconnects
+        let pool = state.connection_pool.clone();
+        for text in ["first", "second"] {
+            let response = pool
+                .forward_tool_call_with_context("ws", "echo", json!({ "text": text }), None, None)
+                .await
+                .unwrap();
+            assert_eq!(response["result"]["content"][0]["text"], text);
+        }
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(
+            pool.server_versions
+                .lock()
+                .unwrap()
+                .get("ws")
+                .map(String::as_str),
+            Some("3.1.0")
+        );
+    }
 
-                                let mut tools_by_server = std::collections::HashMap::new();
-                                for (prefixed_name, tool) in available_tools.iter() {
-                                    if let Some((server_name, tool_name)) =
-                                        prefixed_name.split_once('_')
-                                    {
-                                        tools_by_server
-                                            .entry(server_name.to_string())
-                                            .or_insert_with(Vec::new)
-                                            .push(json!({
-                                                "name": tool_name,
-                                                "description": tool.description
-                                            }));
-                                    }
-                                }
+    #[tokio::test]
+    async fn test_requests_at_max_hops_are_rejected_as_loops() {
+        // A downstream Toolman recording the hop count of what it's sent
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(
+                |headers: axum::http::HeaderMap, Json(request): Json<Value>| async move {
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push(headers[HOPS_HEADER].to_str().unwrap().to_string());
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"],
+                        "result": {"content": [{"type": "text", "text": "ok"}]}}))
+                },
+            ),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_config(json!({
+            "max_hops": 3,
+            "servers": { "downstream": { "transport": "http", "url": url } },
+        }));
+        let call = |hops: u32| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(HOPS_HEADER, hops.into());
+            let body = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+                "params": {"name": "downstream_ping", "arguments": {}}});
+            mcp_endpoint(State(state.clone()), headers, Json(body))
+        };
 
-                                let config_structure = json!({
-                                    "toolman_proxy_url": "http://toolman.mcp.svc.cluster.local:3000/mcp",
-                                    "available_tools_by_server": tools_by_server,
-                                    "config_instructions": "Use 'toolman_proxy_url' as the MCP server URL. All tools are prefixed with 'servername_toolname' format."
-                                });
+        let Json(response) = call(2).await.unwrap();
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "ok");
+        assert_eq!(*seen.lock().unwrap(), ["3"]);
 
-                                json!({
-                                    "content": [{
-                                        "type": "text",
-                                        "text": serde_json::to_string_pretty(&config_structure).unwrap_or_else(|_| "Error formatting config".to_string())
-                                    }]
-                                })
-                            } else {
-                                // Parse prefixed tool name and forward to server
-                                let config_manager = self.system_config_manager.read().await;
-                                let available_servers: Vec<String> =
-                                    config_manager.get_servers().keys().cloned().collect();
-                                drop(config_manager);
+        let (status, Json(error)) = call(3).await.unwrap_err();
+        assert_eq!(status, StatusCode::LOOP_DETECTED);
+        assert!(error.message.contains("loop"), "{}", error.message);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
 
-                                // Get available tools for original name lookup
-                                let available_tools = self.available_tools.read().await;
+    #[tokio::test]
+    async fn test_failed_calls_are_listed_in_server_errors() {
+        // Nothing listens on port 1
+        let (_dir, state) = state_with_servers(json!({
+            "broken": { "transport": "http", "url": "http://127.0.0.1:1/mcp" },
+        }));
+        let errors = |name: &str| server_errors_endpoint(State(state.clone()), Path(name.into()));
+
+        let Json(before) = errors("broken").await.unwrap();
+        assert_eq!(before["errors"], json!([]));
+
+        state
+            .handle_jsonrpc_request(tool_call("broken_fetch", json!({})), None)
+            .await;
+
+        let Json(after) = errors("broken").await.unwrap();
+        let recorded = after["errors"].as_array().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0]["source"], "tool_call");
+        assert!(recorded[0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("fetch: "));
+        assert!(recorded[0]["timestamp"].as_str().is_some());
+
+        assert_eq!(errors("missing").await.unwrap_err(), StatusCode::NOT_FOUND);
+    }
 
-                                match parse_tool_name_with_servers(
-                                    tool_name,
-                                    &available_servers,
-                                    &available_tools,
-                                ) {
-                                    Ok(parsed_tool) => {
-                                        // Drop the available_tools lock early to prevent deadlocks
-                                        drop(available_tools);
-                                        // Get arguments for the tool call
-                                        let mut arguments =
-                                            params.get("arguments").cloned().unwrap_or(json!({}));
-
-                                        // ✨ AUTO-INJECT parameters based on working directory
-                                        if let Some(working_dir) =
-                                            self.current_working_dir.read().await.as_ref()
-                                        {
-                                            if let Some(args_obj) = arguments.as_object_mut() {
-                                                // 🎯 Universal projectRoot injection (for TaskMaster, etc.)
-                                                args_obj.insert(
-                                                    "projectRoot".to_string(),
-                                                    json!(working_dir.to_string_lossy()),
-                                                );
-                                                println!(
-                                                    "🔧 Auto-injected projectRoot: {}",
-                                                    working_dir.display()
-                                                );
+    #[tokio::test]
+    async fn test_sse_responses_are_matched_to_requests_by_id() {
+        let responses = SseResponses::new("mock");
+        let tools_list = responses.expect(&json!(2));
+        let other = responses.expect(&json!("2"));
+
+        // A notification and a response to another request arrive first
+        let chunks: Vec<reqwest::Result<axum::body::Bytes>> = vec![
+            Ok(axum::body::Bytes::from(
+                "event: message\ndata: {\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"progress\":1}}\n\n",
+            )),
+            Ok(axum::body::Bytes::from(
+                "event: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":\"2\",\"result\":{\"other\":true}}\n\n",
+            )),
+            Ok(axum::body::Bytes::from(
+                "event: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":2,\"result\":{\"tools\":[]}}\n\n",
+            )),
+        ];
+        forward_sse_messages(
+            futures::stream::iter(chunks),
+            "mock".to_string(),
+            SseStreamSettings::from_config(&ServersConfig::default()),
+            responses.clone(),
+        )
+        .await;
 
-                                                // 🎯 Server-specific parameter injection
-                                                // Note: Memory server uses environment variables, not parameters
-                                                // Other servers may need specific parameter injection here
-                                                match parsed_tool.server_name.as_str() {
-                                                    _ => {
-                                                        // No additional parameter injection needed yet
-                                                        // Future servers that need working directory as parameters can be added here
-                                                    }
-                                                }
-                                            }
-                                        }
+        let timeout = tokio::time::Duration::from_secs(1);
+        let response = responses
+            .wait(&json!(2), tools_list, timeout, "tools/list")
+            .await
+            .unwrap();
+        assert_eq!(response["result"], json!({"tools": []}));
+        let response = responses
+            .wait(&json!("2"), other, timeout, "other")
+            .await
+            .unwrap();
+        assert_eq!(response["result"], json!({"other": true}));
 
-                                        // Get user working directory for context-aware server startup
-                                        let user_working_dir = {
-                                            let wd = self.current_working_dir.read().await;
-                                            wd.clone()
-                                        };
+        // The stream has ended, so later waits fail instead of hanging
+        let late = responses.expect(&json!(3));
+        let error = responses
+            .wait(&json!(3), late, timeout, "late")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("closed"), "{}", error);
+    }
 
-                                        // Forward to the appropriate server with user context
-                                        match self
-                                            .connection_pool
-                                            .forward_tool_call_with_context(
-                                                &parsed_tool.server_name,
-                                                &parsed_tool.tool_name,
-                                                arguments,
-                                                user_working_dir.as_deref(),
-                                            )
-                                            .await
-                                        {
-                                            Ok(response) => {
-                                                // Extract result from response or return the response directly
-                                                if let Some(result) = response.get("result") {
-                                                    result.clone()
-                                                } else {
-                                                    response
-                                                }
-                                            }
-                                            Err(e) => {
-                                                json!({
-                                                    "content": [{
-                                                        "type": "text",
-                                                        "text": format!("❌ Error calling tool '{}'\n\n🔍 **Debug Info:**\n- Original tool name: '{}'\n- Parsed as: server='{}', tool='{}'\n- Available servers: [{}]\n- Error: {}\n\n💡 Expected format: {{server_name}}_{{tool_name}}",
-                                                                       tool_name,
-                                                                       tool_name,
-                                                                       parsed_tool.server_name,
-                                                                       parsed_tool.tool_name,
-                                                                       available_servers.join(", "),
-                                                                       e)
-                                                    }]
-                                                })
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        // Drop the available_tools lock
-                                        drop(available_tools);
-                                        json!({
-                                            "content": [{
-                                                "type": "text",
-                                                "text": format!("❌ Invalid tool name format\n\n🔍 **Debug Info:**\n- Attempted tool name: '{}'\n- Parse error: {}\n- Available servers: [{}]\n- Expected format: {{server_name}}_{{tool_name}}\n\n📝 **Examples:**\n- memory_read_graph\n- git_git_status\n- task_master_ai_get_tasks",
-                                                               tool_name,
-                                                               e,
-                                                               available_servers.join(", "))
-                                            }]
-                                        })
-                                    }
-                                }
-                            }
-                        };
+    #[tokio::test]
+    async fn test_warn_level_keeps_discovery_failures_but_not_progress() {
+        let (_dir, state) = state_with_servers(json!({
+            "memory": mock_stdio_server("read_graph", "{}"),
+            "broken": { "command": "/nonexistent/mcp-server" },
+        }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+
+        let discover = |directives: &str| {
+            let captured = CapturedLogs::default();
+            let writer = captured.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .with_env_filter(log_filter(Some(directives)))
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish();
+            let state = state.clone();
+            let servers = servers.clone();
+            async move {
+                let _guard = tracing::subscriber::set_default(subscriber);
+                for name in ["memory", "broken"] {
+                    state.discover_and_record(name, &servers[name]).await;
+                }
+                drop(_guard);
+                state.connection_pool.stop_server("memory").await.unwrap();
+                String::from_utf8(captured.0.lock().unwrap().clone()).unwrap()
+            }
+        };
 
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id: request.id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    } else {
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id: request.id,
-                            result: None,
-                            error: Some(JsonRpcError {
-                                code: -32602,
-                                message: "Missing tool name".to_string(),
-                            }),
-                        }
-                    }
-                } else {
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: request.id,
-                        result: None,
-                        error: Some(JsonRpcError {
-                            code: -32602,
-                            message: "Invalid params".to_string(),
-                        }),
-                    }
+        let chatty = discover("toolman=debug").await;
+        assert!(
+            chatty.contains("Discovered 1 tools via stdio"),
+            "{}",
+            chatty
+        );
+        assert!(chatty.contains("discover{server=memory transport=stdio}"));
+
+        let quiet = discover("toolman=warn").await;
+        assert!(!quiet.contains("Discovered"), "{}", quiet);
+        assert!(!quiet.contains("Starting MCP server"), "{}", quiet);
+        assert!(quiet.contains("Failed to initialize server"), "{}", quiet);
+        assert!(quiet.contains("server=broken"), "{}", quiet);
+    }
+
+    #[tokio::test]
+    async fn test_server_name_normalization_sets_prefix_and_routes_back() {
+        for (normalization, map, advertised) in [
+            ("sanitize", json!({}), "task_master_get_tasks"),
+            ("keep", json!({}), "task-master_get_tasks"),
+            ("keep", json!({"task-master": "tm"}), "tm_get_tasks"),
+        ] {
+            let (_dir, state) = state_with_config(json!({
+                "server_name_normalization": normalization,
+                "server_name_map": map,
+                "servers": { "task-master": mock_stdio_server("get_tasks", "done") },
+            }));
+            let (server_list, prefixes) = {
+                let config_manager = state.system_config_manager.read().await;
+                let config = config_manager.get_config();
+                (
+                    config.servers.clone().into_iter().collect(),
+                    server_prefixes(config),
+                )
+            };
+
+            let tools = state.discover_servers(server_list).await;
+            state
+                .connection_pool
+                .stop_server("task-master")
+                .await
+                .unwrap();
+            assert_eq!(
+                tools.keys().collect::<Vec<_>>(),
+                vec![advertised],
+                "{}",
+                normalization
+            );
+
+            // The advertised name, and what a sanitizing client makes of it, route to the server
+            for incoming in [advertised.to_string(), sanitize_name(advertised)] {
+                let parsed = parse_tool_name_with_servers(&incoming, &prefixes, &tools).unwrap();
+                assert_eq!(parsed.server_name, "task-master");
+                assert_eq!(parsed.tool_name, "get_tasks");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_structured_tool_errors_carry_error_metadata() {
+        for structured in [true, false] {
+            // Nothing listens on port 1
+            let (_dir, state) = state_with_config(json!({
+                "structured_tool_errors": structured,
+                "servers": {
+                    "broken": { "transport": "http", "url": "http://127.0.0.1:1/mcp" },
+                },
+            }));
+            let call = |name: &'static str| {
+                let state = state.clone();
+                async move {
+                    let result = state
+                        .handle_jsonrpc_request(tool_call(name, json!({})), None)
+                        .await
+                        .result
+                        .unwrap();
+                    result["content"][0].clone()
                 }
+            };
+
+            let failed = call("broken_fetch").await;
+            let unknown = call("nosuch_tool").await;
+            // The human-readable text is kept either way
+            assert!(failed["text"]
+                .as_str()
+                .unwrap()
+                .contains("Error calling tool"));
+            assert!(unknown["text"]
+                .as_str()
+                .unwrap()
+                .contains("Invalid tool name"));
+            if !structured {
+                assert!(failed.get("_meta").is_none());
+                assert!(unknown.get("_meta").is_none());
+                continue;
             }
-            _ => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: request.id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32601,
-                    message: "Method not found".to_string(),
-                }),
-            },
+
+            let error = &failed["_meta"]["toolman_error"];
+            assert_eq!(error["kind"], "call_failed");
+            assert_eq!(error["server"], "broken");
+            assert_eq!(error["tool"], "fetch");
+            assert!(!error["detail"].as_str().unwrap().is_empty());
+            let error = &unknown["_meta"]["toolman_error"];
+            assert_eq!(error["kind"], "unknown_tool");
+            assert_eq!(error["server"], Value::Null);
+            assert_eq!(error["tool"], "nosuch_tool");
+            assert!(error["detail"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid tool name format"));
         }
     }
-}
 
-// Liveness probe - just checks if HTTP server is alive
-async fn health_check() -> Json<Value> {
-    Json(json!({
-        "status": "ok",
-        "service": "toolman",
-        "timestamp": Utc::now().to_rfc3339()
-    }))
-}
+    #[tokio::test]
+    async fn test_http_auth_headers_sent_on_every_request() {
+        std::env::set_var("TOOLMAN_TEST_GATEWAY_TOKEN", "secret-token");
+        let seen = SeenHeaders::default();
+        let base = spawn_http_backend(mock_http_backend("ping", json!({}), seen.clone())).await;
+        let url = format!("{}/mcp", base);
+        let (_dir, state) = state_with_servers(json!({
+            "gated": {
+                "transport": "http",
+                "url": url,
+                "auth": { "type": "bearer", "token_env": "TOOLMAN_TEST_GATEWAY_TOKEN" },
+            },
+            "keyed": {
+                "transport": "http",
+                "url": url,
+                "auth": { "type": "header", "name": "X-Api-Key", "value": "k-123" },
+            },
+            "open": { "transport": "http", "url": url },
+        }));
+        let servers = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone();
+        let header = |seen: &SeenHeaders, name: &str| -> Vec<Option<String>> {
+            seen.lock()
+                .unwrap()
+                .drain(..)
+                .map(|h| h.get(name).map(|v| v.to_str().unwrap().to_string()))
+                .collect()
+        };
 
-// Readiness probe - checks if MCP servers are available and ready
-async fn readiness_check(State(state): State<BridgeState>) -> Result<Json<Value>, StatusCode> {
-    let config_manager = state.system_config_manager.read().await;
-    let servers = config_manager.get_servers();
+        state
+            .discover_server_tools("gated", &servers["gated"])
+            .await
+            .unwrap();
+        state
+            .handle_jsonrpc_request(tool_call("gated_ping", json!({})), None)
+            .await;
+        let sent = header(&seen, "authorization");
+        assert!(sent.len() >= 2, "{:?}", sent);
+        assert!(sent
+            .iter()
+            .all(|v| v.as_deref() == Some("Bearer secret-token")));
+
+        state
+            .discover_server_tools("keyed", &servers["keyed"])
+            .await
+            .unwrap();
+        let sent = header(&seen, "x-api-key");
+        assert!(!sent.is_empty());
+        assert!(sent.iter().all(|v| v.as_deref() == Some("k-123")));
 
-    // Check if we have any servers configured
-    if servers.is_empty() {
-        return Err(StatusCode::SERVICE_UNAVAILABLE);
+        state
+            .discover_server_tools("open", &servers["open"])
+            .await
+            .unwrap();
+        assert!(header(&seen, "authorization").iter().all(Option::is_none));
     }
 
-    // For now, just check if servers are configured
-    // TODO: In the future, we could ping each server to check actual availability
-    Ok(Json(json!({
-        "status": "ready",
-        "service": "toolman",
-        "servers_configured": servers.len(),
-        "timestamp": Utc::now().to_rfc3339()
-    })))
-}
+    #[tokio::test]
+    async fn test_stdio_call_timeout_is_configurable_per_server() {
+        let mut slow = mock_stdio_server("build", "built");
+        slow["env"]["MOCK_CALL_DELAY"] = json!("3");
+        slow["call_timeout_secs"] = json!(1);
+        let (_dir, state) = state_with_servers(json!({ "slow": slow }));
+        state.connection_pool.start_server("slow").await.unwrap();
+
+        let started = std::time::Instant::now();
+        let result = state
+            .handle_jsonrpc_request(tool_call("slow_build", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(3));
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(
+            text.contains("server 'slow' did not answer tools/call within 1s"),
+            "{}",
+            text
+        );
 
-// Session initialization endpoint
-async fn mcp_endpoint(
-    State(state): State<BridgeState>,
-    headers: axum::http::HeaderMap,
-    Json(body): Json<Value>,
-) -> Result<Json<JsonRpcResponse>, (StatusCode, Json<JsonRpcError>)> {
-    if let Ok(request) = serde_json::from_value::<JsonRpcRequest>(body) {
-        eprintln!("📨 Processing request: {}", request.method);
-        // Simple tool aggregation - no session complexity
-        let response = state.handle_jsonrpc_request(request, Some(&headers)).await;
-        Ok(Json(response))
-    } else {
-        Err((
-            StatusCode::BAD_REQUEST,
-            Json(JsonRpcError {
-                code: -32700,
-                message: "Parse error".to_string(),
-            }),
-        ))
+        // The late answer to the timed-out call isn't mistaken for the next call's
+        state
+            .system_config_manager
+            .write()
+            .await
+            .get_config_mut()
+            .servers
+            .get_mut("slow")
+            .unwrap()
+            .call_timeout_secs = Some(10);
+        let result = state
+            .handle_jsonrpc_request(tool_call("slow_build", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        assert_eq!(result["content"][0]["text"], "built");
+        state.connection_pool.stop_server("slow").await.unwrap();
     }
-}
 
-// Client configuration endpoint - generates MCP client config with all tools disabled by default
-async fn client_config_endpoint(
-    State(state): State<BridgeState>,
-) -> Result<Json<Value>, StatusCode> {
-    let available_tools = state.available_tools.read().await;
-    let config_manager = state.system_config_manager.read().await;
-    let servers = config_manager.get_servers();
+    #[tokio::test]
+    async fn test_http_session_id_reused_and_renewed_after_invalidation() {
+        use axum::response::IntoResponse;
+
+        // Requests as (method, Mcp-Session-Id sent), and the session the backend accepts
+        type Requests = Arc<std::sync::Mutex<Vec<(String, Option<String>)>>>;
+        let requests = Requests::default();
+        let valid = Arc::new(std::sync::Mutex::new(None::<String>));
+        let issued = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let router = {
+            let (requests, valid) = (requests.clone(), valid.clone());
+            Router::new().route(
+                "/mcp",
+                post(
+                    move |headers: axum::http::HeaderMap, Json(request): Json<Value>| async move {
+                        let method = request["method"].as_str().unwrap_or_default().to_string();
+                        let session = headers
+                            .get(MCP_SESSION_HEADER)
+                            .map(|v| v.to_str().unwrap().to_string());
+                        requests.lock().unwrap().push((method.clone(), session.clone()));
+                        let result = match method.as_str() {
+                            "initialize" => {
+                                let n = issued.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                let session = format!("s{}", n + 1);
+                                *valid.lock().unwrap() = Some(session.clone());
+                                let body = json!({"jsonrpc": "2.0", "id": request["id"],
+                                    "result": {"protocolVersion": "2024-11-05", "capabilities": {}}});
+                                return ([(MCP_SESSION_HEADER, session)], Json(body))
+                                    .into_response();
+                            }
+                            _ if session != *valid.lock().unwrap() => {
+                                return StatusCode::NOT_FOUND.into_response()
+                            }
+                            "tools/list" => json!({"tools": [{"name": "ping",
+                                "description": "mock tool", "inputSchema": {"type": "object"}}]}),
+                            "tools/call" => json!({"content": [{"type": "text", "text": "pong"}]}),
+                            _ => return StatusCode::ACCEPTED.into_response(),
+                        };
+                        Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+                            .into_response()
+                    },
+                ),
+            )
+        };
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "remote": { "transport": "http", "url": url },
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["remote"].clone();
+        let sent = |requests: &Requests| -> Vec<(String, Option<String>)> {
+            requests.lock().unwrap().drain(..).collect()
+        };
+        let entry =
+            |method: &str, session: Option<&str>| (method.to_string(), session.map(str::to_string));
+        let call = || async {
+            let result = state
+                .handle_jsonrpc_request(tool_call("remote_ping", json!({})), None)
+                .await
+                .result
+                .unwrap();
+            assert_eq!(result["content"][0]["text"], "pong");
+        };
 
-    // Group tools by server
-    let mut tools_by_server: HashMap<String, Vec<Value>> = HashMap::new();
+        state
+            .discover_server_tools("remote", &config)
+            .await
+            .unwrap();
+        assert_eq!(
+            sent(&requests),
+            [
+                entry("initialize", None),
+                entry("notifications/initialized", Some("s1")),
+                entry("tools/list", Some("s1")),
+            ]
+        );
 
-    for (_tool_key, tool) in available_tools.iter() {
-        let server_name = &tool.server_name;
-        let tool_config = json!({
-            "name": tool.name,
-            "description": tool.description,
-            "enabled": false, // All tools disabled by default
-            "inputSchema": tool.input_schema
-        });
+        // Calls reuse the session without initializing again
+        call().await;
+        call().await;
+        assert_eq!(
+            sent(&requests),
+            [
+                entry("tools/call", Some("s1")),
+                entry("tools/call", Some("s1"))
+            ]
+        );
 
-        tools_by_server
-            .entry(server_name.clone())
-            .or_default()
-            .push(tool_config);
+        // The backend forgets the session: the next call starts a new one and goes through
+        *valid.lock().unwrap() = None;
+        call().await;
+        assert_eq!(
+            sent(&requests),
+            [
+                entry("tools/call", Some("s1")),
+                entry("initialize", None),
+                entry("notifications/initialized", Some("s2")),
+                entry("tools/call", Some("s2")),
+            ]
+        );
     }
 
-    // Build server configurations
-    let mut servers_config = json!({});
+    #[tokio::test]
+    async fn test_request_log_sample_rate_controls_detailed_call_logs() {
+        for (rate, expected) in [(0.0, 0), (1.0, 3)] {
+            let mut server = mock_stdio_server("lookup", "found");
+            server["request_log_sample_rate"] = json!(rate);
+            let (_dir, state) = state_with_servers(json!({ "docs": server }));
+
+            let captured = CapturedLogs::default();
+            let writer = captured.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .with_env_filter(log_filter(Some("info")))
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish();
+            let guard = tracing::subscriber::set_default(subscriber);
+            for i in 0..3 {
+                state
+                    .handle_jsonrpc_request(tool_call("docs_lookup", json!({ "query": i })), None)
+                    .await;
+            }
+            drop(guard);
+            state.connection_pool.stop_server("docs").await.unwrap();
+
+            let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+            let sampled: Vec<&str> = logs
+                .lines()
+                .filter(|l| l.contains("Sampled call"))
+                .collect();
+            assert_eq!(sampled.len(), expected, "rate {}: {}", rate, logs);
+            for (i, line) in sampled.iter().enumerate() {
+                assert!(line.contains("server=docs"), "{}", line);
+                assert!(line.contains("tool=lookup"), "{}", line);
+                assert!(
+                    line.contains(&format!(r#"arguments={{"query":{}}}"#, i)),
+                    "{}",
+                    line
+                );
+                assert!(line.contains("found"), "{}", line);
+            }
+        }
+    }
 
-    for (server_name, server_config) in servers.iter() {
-        let tools = tools_by_server
-            .get(server_name)
-            .cloned()
-            .unwrap_or_default();
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_stdio_calls_each_get_their_own_response() {
+        // Ahead of each call's answer, which echoes the call's `tag`, writes a response
+        // carrying another id, as an abandoned call's late response would be
+        const STRAY_RESPONSE_SERVER: &str = r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"mock","version":"0.1.0"}}}\n' "$id" ;;
+    *'"method":"tools/call"'*)
+      tag=$(printf '%s' "$line" | sed -n 's/.*"tag":\([0-9]*\).*/\1/p')
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"stray"}]}}\n' "$((id + 1000))"
+      printf '{"jsonrpc":"2.0","id":%s,"result":{"content":[{"type":"text","text":"call %s"}]}}\n' "$id" "$tag" ;;
+  esac
+done"#;
+        let (_dir, state) = state_with_servers(json!({
+            "echo": { "command": "sh", "args": ["-c", STRAY_RESPONSE_SERVER] },
+        }));
+        state.connection_pool.start_server("echo").await.unwrap();
+
+        let call = |tag: usize| {
+            let state = state.clone();
+            async move {
+                let result = state
+                    .handle_jsonrpc_request(tool_call("echo_run", json!({ "tag": tag })), None)
+                    .await
+                    .result
+                    .unwrap();
+                result["content"][0]["text"].as_str().unwrap().to_string()
+            }
+        };
+        let texts = future::join_all((0..6).map(|tag| tokio::spawn(call(tag)))).await;
+        state.connection_pool.stop_server("echo").await.unwrap();
 
-        servers_config[server_name] = json!({
-            "name": server_config.name,
-            "description": server_config.description,
-            "transport": server_config.transport,
-            "url": server_config.url,
-            "enabled": true,
-            "tools": tools
-        });
+        for (tag, text) in texts.into_iter().enumerate() {
+            assert_eq!(text.unwrap(), format!("call {}", tag));
+        }
     }
 
-    let client_config = json!({
-        "servers": servers_config,
-        "metadata": {
-            "generated_at": Utc::now().to_rfc3339(),
-            "generator": "toolman",
-            "version": env!("CARGO_PKG_VERSION"),
-            "total_tools": available_tools.len(),
-            "total_servers": servers.len(),
-            "note": "All tools are disabled by default. Enable specific tools as needed."
+    #[tokio::test]
+    async fn test_metrics_count_tool_calls_per_tool() {
+        use axum::response::IntoResponse;
+
+        let (_dir, state) = state_with_servers(json!({
+            "docs": mock_stdio_server("lookup", "found"),
+        }));
+        let scrape = || async {
+            let response = metrics_endpoint(State(state.clone())).await.into_response();
+            assert_eq!(
+                response.headers()[axum::http::header::CONTENT_TYPE],
+                "text/plain; version=0.0.4"
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+        let calls = r#"toolman_tool_calls_total{server="docs",tool="lookup"}"#;
+        assert!(!scrape().await.contains(calls));
+
+        for _ in 0..2 {
+            state
+                .handle_jsonrpc_request(tool_call("docs_lookup", json!({})), None)
+                .await;
         }
-    });
+        let metrics = scrape().await;
+        state.connection_pool.stop_server("docs").await.unwrap();
+
+        assert!(metrics.contains(&format!("{} 2", calls)), "{}", metrics);
+        assert!(metrics.contains(r#"toolman_tool_call_duration_seconds_count{server="docs"} 2"#));
+        assert!(
+            metrics.contains("toolman_pool_connections 1"),
+            "{}",
+            metrics
+        );
+        assert!(
+            metrics.contains("toolman_discovered_tools 0"),
+            "{}",
+            metrics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_call_timeout_ladder_follows_tool_annotations_and_category() {
+        let mut jobs = mock_stdio_server("lookup", "done");
+        jobs["env"]["MOCK_TOOLS_LIST"] = json!(json!({
+            "tools": [
+                {"name": "lookup", "description": "read", "inputSchema": {"type": "object"},
+                 "annotations": {"readOnlyHint": true}},
+                {"name": "build", "description": "build", "inputSchema": {"type": "object"}}
+            ]
+        })
+        .to_string());
+        jobs["env"]["MOCK_CALL_DELAY"] = json!("2");
+        jobs["tools"] = json!({ "build": {"category": "long_running"} });
+        let (_dir, state) = state_with_config(json!({
+            "servers": { "jobs": jobs },
+            "call_timeout_ladder": {"read_only": 1, "long_running": 10, "default": 1}
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["jobs"].clone();
+        assert_eq!(state.discover_and_record("jobs", &config).await.len(), 2);
+
+        // The read-only tool gets the short rung and gives up before the server answers
+        let result = state
+            .handle_jsonrpc_request(tool_call("jobs_lookup", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(
+            text.contains("did not answer tools/call within 1s"),
+            "{}",
+            text
+        );
+
+        // The long-running one waits long enough for its answer
+        let result = state
+            .handle_jsonrpc_request(tool_call("jobs_build", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        assert_eq!(result["content"][0]["text"], "done");
+
+        // An explicit per-tool timeout beats the ladder
+        state
+            .system_config_manager
+            .write()
+            .await
+            .get_config_mut()
+            .servers
+            .get_mut("jobs")
+            .unwrap()
+            .tools
+            .insert(
+                "lookup".to_string(),
+                serde_json::from_value(json!({"call_timeout_secs": 10})).unwrap(),
+            );
+        let result = state
+            .handle_jsonrpc_request(tool_call("jobs_lookup", json!({})), None)
+            .await
+            .result
+            .unwrap();
+        assert_eq!(result["content"][0]["text"], "done");
+        state.connection_pool.stop_server("jobs").await.unwrap();
+    }
 
-    Ok(Json(client_config))
-}
+    #[tokio::test]
+    async fn test_empty_tools_list_is_retried_when_tools_capability_declared() {
+        let mut slow_start = mock_stdio_server("search", "found");
+        slow_start["env"]["MOCK_FIRST_TOOLS_LIST"] = json!(r#"{"tools":[]}"#);
+        let (_dir, state) = state_with_servers(json!({ "slow-start": slow_start }));
+        let config = state.system_config_manager.read().await.get_servers()["slow-start"].clone();
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+        let tools = state
+            .discover_server_tools("slow-start", &config)
+            .await
+            .unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "search");
+        state
+            .connection_pool
+            .stop_server("slow-start")
+            .await
+            .unwrap();
+    }
 
-    // Default project_dir to current directory if not specified
-    let project_dir = args.project_dir.or_else(|| {
-        Some(std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from(".")))
-    });
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_stops_every_stdio_server() {
+        let (_dir, state) = state_with_servers(json!({
+            "first": mock_stdio_server("ping", "pong"),
+            "second": mock_stdio_server("ping", "pong")
+        }));
+        let pool = &state.connection_pool;
+        pool.start_server("first").await.unwrap();
+        pool.start_server("second").await.unwrap();
+        let pids: Vec<u32> = pool.process_ids.lock().unwrap().values().copied().collect();
+        assert_eq!(pids.len(), 2);
+
+        pool.stop_all_servers(std::time::Duration::from_secs(10))
+            .await;
+        assert!(pool.connections.read().await.is_empty());
+        for pid in pids {
+            // The child has exited and been reaped, so the pid is gone
+            assert_eq!(
+                unsafe { libc::kill(pid as libc::pid_t, 0) },
+                -1,
+                "pid {}",
+                pid
+            );
+        }
+    }
 
-    // Handle export-tools flag - discover tools and export to file, then exit
-    if let Some(export_path) = args.export_tools {
-        println!("🔍 Export mode: Discovering all tools from configured servers...");
+    #[tokio::test]
+    async fn test_servers_endpoint_reports_connection_and_tool_count() {
+        let (_dir, state) = state_with_servers(json!({
+            "local": mock_stdio_server("ping", "pong"),
+            "remote": {"transport": "http", "url": "http://127.0.0.1:9/mcp"}
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["local"].clone();
+        let tools = state.discover_and_record("local", &config).await;
+        for tool in tools {
+            state
+                .available_tools
+                .write()
+                .await
+                .insert(prefixed_tool_name("local", &tool.name), tool);
+        }
 
-        let state = BridgeState::new(project_dir.clone())?;
+        let Json(servers) = servers_endpoint(State(state.clone())).await;
+        assert_eq!(servers["local"]["connected"], true);
+        assert_eq!(servers["local"]["tools"], 1);
+        assert_eq!(servers["local"]["transport"], "stdio");
+        assert_eq!(servers["local"]["command"], "sh");
+        assert_eq!(servers["remote"]["connected"], false);
+        assert_eq!(servers["remote"]["tools"], 0);
+        assert_eq!(servers["remote"]["url"], "http://127.0.0.1:9/mcp");
+
+        state.connection_pool.stop_server("local").await.unwrap();
+        let Json(servers) = servers_endpoint(State(state.clone())).await;
+        assert_eq!(servers["local"]["connected"], false);
+    }
 
-        // Discover all tools without enabling them
-        let config_manager = state.system_config_manager.read().await;
-        let servers = config_manager.get_servers();
-        let mut all_discovered_tools = std::collections::HashMap::new();
+    #[tokio::test]
+    async fn test_export_endpoint_returns_discovered_tools_with_totals() {
+        let (_dir, state) = state_with_servers(json!({
+            "git": {"command": "git-mcp"},
+            "docs": {"command": "docs-mcp"},
+            "idle": {"command": "idle-mcp"}
+        }));
+        register_tool(&state, "git", "status").await;
+        register_tool(&state, "git", "log").await;
+        register_tool(&state, "docs", "search").await;
+
+        let query = |format: Option<&str>| ExportQuery {
+            format: format.map(str::to_string),
+        };
+        let Json(export) = export_endpoint(State(state.clone()), axum::extract::Query(query(None)))
+            .await
+            .unwrap();
+        assert_eq!(export["total_servers"], 3);
+        assert_eq!(export["total_tools_discovered"], 3);
+        let servers = export["servers"].as_array().unwrap();
+        let server = |name: &str| servers.iter().find(|s| s["name"] == name).unwrap().clone();
+        assert_eq!(server("git")["tools_count"], 2);
+        assert_eq!(server("git")["command"], "git-mcp");
+        assert_eq!(server("docs")["tools"][0]["name"], "search");
+        assert_eq!(server("idle")["tools_count"], 0);
+
+        let yaml = export_endpoint(
+            State(state.clone()),
+            axum::extract::Query(query(Some("yaml"))),
+        );
+        assert_eq!(yaml.await.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
 
-        for (server_name, config) in servers.iter() {
-            println!("🔍 Discovering tools from server: {}", server_name);
-            match state.discover_server_tools(server_name, config).await {
-                Ok(tools) => {
-                    println!(
-                        "✅ Discovered {} tools from server '{}'",
-                        tools.len(),
-                        server_name
-                    );
-                    all_discovered_tools.insert(server_name.clone(), tools);
+    #[tokio::test]
+    async fn test_injection_precedence_decides_client_supplied_project_root() {
+        let mut tasks = mock_stdio_server("list", "unused");
+        tasks["inject_params"] = json!({ "projectRoot": "working_dir" });
+        for (precedence, expected) in [("proxy", "/work/injected"), ("client", "/work/from-client")]
+        {
+            let (_dir, state) = state_with_config(json!({
+                "servers": { "tasks": tasks.clone() },
+                "injection_precedence": precedence
+            }));
+            register_tool(&state, "tasks", "list").await;
+
+            let result = state
+                .handle_jsonrpc_request(
+                    tool_call(
+                        "toolman_preview_call",
+                        json!({
+                            "tool": "tasks_list",
+                            "arguments": { "projectRoot": "/work/from-client" },
+                            "working_dir": "/work/injected"
+                        }),
+                    ),
+                    None,
+                )
+                .await
+                .result
+                .unwrap();
+            let resolved: Value =
+                serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+            assert_eq!(
+                resolved["arguments"]["projectRoot"], expected,
+                "{}",
+                precedence
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retryable_calls_retry_5xx_but_not_jsonrpc_errors() {
+        use axum::response::IntoResponse;
+
+        // Every tool call fails with a 503 twice before the backend recovers; `reject`
+        // is always answered with a JSON-RPC error
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let counter = counter.clone();
+                async move {
+                    use std::sync::atomic::Ordering;
+                    if request["params"]["name"] == "reject" {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        return Json(json!({"jsonrpc": "2.0", "id": request["id"],
+                            "error": {"code": -32602, "message": "bad arguments"}}))
+                        .into_response();
+                    }
+                    if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                        return (StatusCode::SERVICE_UNAVAILABLE, "upstream overloaded")
+                            .into_response();
+                    }
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": {
+                        "content": [{"type": "text", "text": "recovered"}]}}))
+                    .into_response()
                 }
-                Err(e) => {
-                    println!(
-                        "❌ Failed to discover tools from server '{}': {}",
-                        server_name, e
-                    );
-                    all_discovered_tools.insert(server_name.clone(), Vec::new());
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_config(json!({
+            "tool_call_retry_backoff_ms": 10,
+            "servers": {
+                "flaky": {
+                    "transport": "http",
+                    "url": url,
+                    "tools": { "fetch": { "retryable": true }, "reject": { "retryable": true } }
                 }
-            }
-        }
+            },
+        }));
+        register_tool(&state, "flaky", "fetch").await;
+        register_tool(&state, "flaky", "reject").await;
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("flaky_fetch", json!({})), None)
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "recovered");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        // The backend's JSON-RPC error is its answer, so it is passed on after one attempt
+        calls.store(0, std::sync::atomic::Ordering::SeqCst);
+        state
+            .handle_jsonrpc_request(tool_call("flaky_reject", json!({})), None)
+            .await;
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
 
-        // Create export structure
-        let export_data = serde_json::json!({
-            "export_timestamp": chrono::Utc::now().to_rfc3339(),
-            "total_servers": servers.len(),
-            "total_tools_discovered": all_discovered_tools.values().map(|tools| tools.len()).sum::<usize>(),
-            "servers": all_discovered_tools.iter().map(|(server_name, tools)| {
-                let config = servers.get(server_name).unwrap();
-                serde_json::json!({
-                    "name": server_name,
-                    "description": config.description.as_deref().unwrap_or("No description"),
-                    "command": config.command,
-                    "args": config.args,
-                    "tools_count": tools.len(),
-                    "tools": tools.iter().map(|tool| serde_json::json!({
-                        "name": tool.name,
-                        "description": tool.description,
-                        "inputSchema": tool.input_schema
-                    })).collect::<Vec<_>>()
-                })
-            }).collect::<Vec<_>>()
-        });
+    #[tokio::test]
+    async fn test_concurrent_session_renewals_run_one_handshake() {
+        use axum::response::IntoResponse;
+
+        // A session-issuing backend that counts its initialize handshakes
+        let handshakes = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let valid = Arc::new(std::sync::Mutex::new(None::<String>));
+        let router = {
+            let (handshakes, valid) = (handshakes.clone(), valid.clone());
+            Router::new().route(
+                "/mcp",
+                post(
+                    move |headers: axum::http::HeaderMap, Json(request): Json<Value>| async move {
+                        let session = headers
+                            .get(MCP_SESSION_HEADER)
+                            .map(|v| v.to_str().unwrap().to_string());
+                        let result = match request["method"].as_str().unwrap_or_default() {
+                            "initialize" => {
+                                use std::sync::atomic::Ordering;
+                                let n = handshakes.fetch_add(1, Ordering::SeqCst) + 1;
+                                // Slow enough for the other requests to pile up behind it
+                                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                                let session = format!("s{}", n);
+                                *valid.lock().unwrap() = Some(session.clone());
+                                let body = json!({"jsonrpc": "2.0", "id": request["id"],
+                                    "result": {"protocolVersion": "2024-11-05", "capabilities": {}}});
+                                return ([(MCP_SESSION_HEADER, session)], Json(body))
+                                    .into_response();
+                            }
+                            _ if session != *valid.lock().unwrap() => {
+                                return StatusCode::NOT_FOUND.into_response()
+                            }
+                            "tools/list" => json!({"tools": [{"name": "ping",
+                                "description": "mock tool", "inputSchema": {"type": "object"}}]}),
+                            "tools/call" => json!({"content": [{"type": "text", "text": "pong"}]}),
+                            _ => return StatusCode::ACCEPTED.into_response(),
+                        };
+                        Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+                            .into_response()
+                    },
+                ),
+            )
+        };
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "remote": { "transport": "http", "url": url },
+        }));
+        let config = state.system_config_manager.read().await.get_servers()["remote"].clone();
+        state
+            .discover_server_tools("remote", &config)
+            .await
+            .unwrap();
+        assert_eq!(handshakes.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // The backend forgets the session while a rediscovery and two calls are under way:
+        // all of them find it expired, and exactly one of them starts the new one
+        *valid.lock().unwrap() = None;
+        let call = || async {
+            let result = state
+                .handle_jsonrpc_request(tool_call("remote_ping", json!({})), None)
+                .await
+                .result
+                .unwrap();
+            assert_eq!(result["content"][0]["text"], "pong");
+        };
+        let (tools, _, _) = tokio::join!(
+            state.discover_server_tools("remote", &config),
+            call(),
+            call()
+        );
+        assert_eq!(tools.unwrap().len(), 1);
+        assert_eq!(handshakes.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 
-        // Write to file
-        std::fs::write(&export_path, serde_json::to_string_pretty(&export_data)?)?;
+    #[tokio::test]
+    async fn test_discovery_ttl_refreshes_tools_and_keeps_them_when_unreachable() {
+        use axum::response::IntoResponse;
+
+        // A backend whose tool list can change, and which can start failing
+        let listed = Arc::new(std::sync::Mutex::new(vec!["search"]));
+        let down = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let router = {
+            let (listed, down) = (listed.clone(), down.clone());
+            Router::new().route(
+                "/mcp",
+                post(move |Json(request): Json<Value>| async move {
+                    if down.load(std::sync::atomic::Ordering::SeqCst) {
+                        return StatusCode::BAD_GATEWAY.into_response();
+                    }
+                    let result = match request["method"].as_str() {
+                        Some("initialize") => json!({"protocolVersion": "2024-11-05",
+                            "capabilities": {}}),
+                        Some("tools/list") => json!({"tools": listed.lock().unwrap().iter()
+                            .map(|name| json!({"name": name, "description": "mock tool",
+                                "inputSchema": {"type": "object"}}))
+                            .collect::<Vec<_>>()}),
+                        _ => return StatusCode::ACCEPTED.into_response(),
+                    };
+                    Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+                        .into_response()
+                }),
+            )
+        };
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_config(json!({
+            "tool_call_retries": 0,
+            "servers": {
+                "remote-docs": { "transport": "http", "url": url, "discovery_ttl_secs": 1 }
+            },
+        }));
+        register_tool(&state, "other", "keep").await;
+        assert!(state.refresh_server_tools("remote-docs").await);
+        let names = || async {
+            let mut names: Vec<String> =
+                state.available_tools.read().await.keys().cloned().collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names().await, ["other_keep", "remote_docs_search"]);
 
-        println!(
-            "✅ Exported {} tools from {} servers to: {}",
-            export_data["total_tools_discovered"],
-            export_data["total_servers"],
-            export_path.display()
+        // The backend's tools change, and the refresh picks that up after the TTL
+        *listed.lock().unwrap() = vec!["search", "fetch"];
+        state.spawn_discovery_refresh().await;
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        assert_eq!(
+            names().await,
+            ["other_keep", "remote_docs_fetch", "remote_docs_search"]
         );
 
-        return Ok(());
+        // An unreachable server keeps the tools it was last seen with
+        down.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(!state.refresh_server_tools("remote-docs").await);
+        assert_eq!(
+            names().await,
+            ["other_keep", "remote_docs_fetch", "remote_docs_search"]
+        );
     }
 
-    println!("🚀 Starting MCP Tools HTTP Server on port {}", args.port);
+    #[tokio::test]
+    async fn test_advertised_tool_naming_matches_config() {
+        let (_dir, state) = state_with_config(json!({
+            "server_name_normalization": "lowercase",
+            "server_name_map": { "My-Memory": "mem" },
+            "servers": {
+                "My-Memory": mock_stdio_server("lookup", "ok"),
+                "Git-Hub": mock_stdio_server("search", "ok"),
+            },
+        }));
+
+        let Json(capabilities) = capabilities_endpoint(State(state.clone())).await;
+        let naming = &capabilities["tool_naming"];
+        assert_eq!(naming["separator"], capabilities["tool_name_separator"]);
+        assert_eq!(naming["server_name_normalization"], "lowercase");
+        assert_eq!(naming["server_prefixes"]["My-Memory"], "mem");
+        assert_eq!(naming["server_prefixes"]["Git-Hub"], "git_hub");
+        assert_eq!(naming["builtin_prefix"], "toolman_");
+
+        // Names built from the advertised rules are the names discovery registers
+        let server_list = {
+            let config_manager = state.system_config_manager.read().await;
+            config_manager
+                .get_config()
+                .servers
+                .clone()
+                .into_iter()
+                .collect()
+        };
+        let tools = state.discover_servers(server_list).await;
+        for server in ["My-Memory", "Git-Hub"] {
+            state.connection_pool.stop_server(server).await.unwrap();
+        }
+        let separator = naming["separator"].as_str().unwrap();
+        let mut expected: Vec<String> = [("My-Memory", "lookup"), ("Git-Hub", "search")]
+            .iter()
+            .map(|(server, tool)| {
+                let prefix = naming["server_prefixes"][server].as_str().unwrap();
+                format!("{prefix}{separator}{tool}")
+            })
+            .collect();
+        expected.sort();
+        let mut discovered: Vec<String> = tools.keys().cloned().collect();
+        discovered.sort();
+        assert_eq!(discovered, expected);
+
+        let initialize = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "initialize".to_string(),
+            params: None,
+        };
+        let result = state
+            .handle_jsonrpc_request(initialize, None)
+            .await
+            .result
+            .unwrap();
+        assert_eq!(&result["serverInfo"]["tool_naming"], naming);
+    }
 
-    // Print ALL environment variables for debugging
-    println!("🔍 ALL Environment Variables Available:");
-    let mut env_vars: Vec<_> = std::env::vars().collect();
-    env_vars.sort_by(|a, b| a.0.cmp(&b.0));
+    #[test]
+    fn test_invalid_config_refuses_to_start() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("servers-config.json"),
+            json!({
+                "servers": {
+                    "remote": { "transport": "http" },
+                    "local": { "command": "" },
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let error = BridgeState::new(Some(dir.path().to_path_buf()))
+            .err()
+            .expect("an invalid config must not start")
+            .to_string();
+        assert!(error.contains("servers-config.json"), "{}", error);
+        assert!(
+            error.contains("server 'remote': http server requires a 'url'"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("server 'local': stdio server requires a non-empty 'command'"),
+            "{}",
+            error
+        );
+    }
 
-    for (key, value) in env_vars {
-        // Mask potentially sensitive values but show everything
-        let masked_value = if key.contains("API_KEY")
-            || key.contains("TOKEN")
-            || key.contains("PASSWORD")
-            || key.contains("SECRET")
-        {
-            if value.len() > 8 {
-                format!("{}...{}", &value[..4], &value[value.len() - 4..])
-            } else {
-                "***".to_string()
-            }
-        } else {
-            value
+    #[tokio::test]
+    async fn test_unbounded_sse_message_closes_stream_with_error() {
+        use futures::StreamExt;
+
+        let responses = SseResponses::new("mock");
+        let pending = responses.expect(&json!(1));
+
+        // A message whose data line never ends
+        let body = futures::stream::iter([Ok(axum::body::Bytes::from(
+            "event: message\ndata: {\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"",
+        ))])
+        .chain(futures::stream::repeat_with(|| {
+            Ok(axum::body::Bytes::from("x".repeat(4096)))
+        }));
+        let settings = SseStreamSettings {
+            log_chunks: false,
+            max_message_bytes: 64 * 1024,
         };
-        println!("  {}: {}", key, masked_value);
+        tokio::time::timeout(
+            tokio::time::Duration::from_secs(5),
+            forward_sse_messages(
+                Box::pin(body),
+                "mock".to_string(),
+                settings,
+                responses.clone(),
+            ),
+        )
+        .await
+        .expect("the listener must give up on the stream");
+
+        let error = responses
+            .wait(
+                &json!(1),
+                pending,
+                tokio::time::Duration::from_secs(1),
+                "tools/call",
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("exceeded 65536 bytes"), "{}", error);
+        assert!(error.contains("max_sse_message_bytes"), "{}", error);
     }
-    println!("🔍 End Environment Variables\n");
 
-    let state = BridgeState::new(project_dir)?;
+    #[tokio::test]
+    async fn test_dry_run_header_describes_call_without_forwarding() {
+        // The server's command doesn't exist, so a forwarded call would fail
+        let (_dir, state) = state_with_servers(json!({
+            "context7": {
+                "command": "/nonexistent/context7-mcp",
+                "tools": { "get-library-docs": { "defaults": { "tokens": 5000 } } },
+                "inject_params": { "projectRoot": "working_dir" },
+            },
+        }));
+        register_tool(&state, "context7", "get-library-docs").await;
+
+        let mut headers = working_dir_headers("/work/app");
+        headers.insert(DRY_RUN_HEADER, "true".parse().unwrap());
+        let response = state
+            .handle_jsonrpc_request(
+                tool_call("context7_get_library_docs", json!({ "topic": "routing" })),
+                Some(&headers),
+            )
+            .await;
+        let result = response.result.unwrap();
+        assert!(result.get("isError").is_none(), "{}", result);
+        let echoed: Value =
+            serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(echoed["dry_run"], true);
+        assert_eq!(echoed["server_name"], "context7");
+        assert_eq!(echoed["tool_name"], "get-library-docs");
+        assert_eq!(echoed["transport"], "stdio");
+        assert_eq!(
+            echoed["arguments"],
+            json!({ "topic": "routing", "tokens": 5000, "projectRoot": "/work/app" })
+        );
+        assert!(!state.connection_pool.is_connected("context7").await);
+    }
 
-    // Discover all available tools and initialize servers at startup
-    println!("🔄 Initializing all MCP servers...");
-    if let Err(e) = state.discover_all_tools().await {
-        eprintln!("❌ Failed to discover tools at startup: {}", e);
-        return Err(e);
+    #[test]
+    fn test_json_rpc_relay_rewrites_id_and_skips_other_lines() {
+        let output = concat!(
+            "starting up\n",
+            "{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"id\":7}}\n",
+            "{\"jsonrpc\":\"2.0\",\"id\":6,\"result\":{\"late\":true}}\n",
+            // The awaited response, with its id after the result
+            "{\"result\": {\"content\": [{\"type\":\"text\",\"text\":\"a \\\"quoted\\\" }\"}]}, \"jsonrpc\":\"2.0\", \"id\":7}\n",
+            "{\"jsonrpc\":\"2.0\",\"id\":8,\"result\":{}}\n",
+        );
+        // Feed it a few bytes at a time, as reads from a pipe would
+        let mut relay = JsonRpcRelay::new(json!(7), json!("client-1"));
+        let mut relayed = Vec::new();
+        let mut rest = output.as_bytes();
+        while !relay.done() {
+            let (consumed, out) = relay.push(&rest[..rest.len().min(5)]);
+            relayed.extend(out);
+            rest = &rest[consumed..];
+        }
+        let response: Value = serde_json::from_slice(&relayed).unwrap();
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": "client-1",
+                "result": {"content": [{"type": "text", "text": "a \"quoted\" }"}]}})
+        );
+        // The next message is left for the next read
+        assert!(String::from_utf8_lossy(rest).starts_with("\n{\"jsonrpc\":\"2.0\",\"id\":8"));
     }
-    println!("✅ All MCP servers initialized and ready");
 
-    let app = Router::new()
-        .route("/mcp", post(mcp_endpoint))
-        .route("/client-config", get(client_config_endpoint))
-        .route("/health", get(health_check))
-        .route("/ready", get(readiness_check))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+    #[tokio::test]
+    async fn test_streaming_tool_result_reaches_client_incrementally() {
+        use futures::StreamExt;
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+        // Two 100KB content items; the server writes the second one a second after the first
+        let part = |n: usize| json!({"type": "text", "text": n.to_string().repeat(100_000)});
+        let first = format!(
+            r#"{{"jsonrpc":"2.0","id":ID,"result":{{"content":[{},"#,
+            part(1)
+        );
+        let second = format!("{}]}}}}", part(2));
+        let script = r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*) printf '{"jsonrpc":"2.0","id":%s,"result":{"protocolVersion":"2024-11-05","capabilities":{},"serverInfo":{"name":"dump","version":"0.1.0"}}}\n' "$id" ;;
+    *'"method":"tools/call"'*)
+      printf '{"jsonrpc":"2.0","method":"notifications/progress","params":{}}\n'
+      printf '%s' "$FIRST" | sed "s/ID/$id/"
+      sleep 1
+      printf '%s\n' "$SECOND" ;;
+  esac
+done"#;
+        let (_dir, state) = state_with_servers(json!({
+            "dump": {
+                "command": "sh",
+                "args": ["-c", script],
+                "env": { "FIRST": first, "SECOND": second },
+                "tools": { "read_file": { "streaming": true } },
+            },
+        }));
+        register_tool(&state, "dump", "read_file").await;
+        let app = Router::new()
+            .route("/mcp", post(mcp_route))
+            .with_state(state.clone());
+        let url = format!("{}/mcp", spawn_http_backend(app).await);
+
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&json!({"jsonrpc": "2.0", "id": 42, "method": "tools/call",
+                "params": {"name": "dump_read_file", "arguments": {}}}))
+            .send()
+            .await
+            .unwrap();
+        let mut chunks = response.bytes_stream();
+        let mut body = Vec::new();
+        let mut first_chunk_at = None;
+        while let Some(chunk) = chunks.next().await {
+            first_chunk_at.get_or_insert_with(std::time::Instant::now);
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        state.connection_pool.stop_server("dump").await.unwrap();
 
-    println!("✅ HTTP server listening on http://{}", addr);
-    println!("🔗 MCP endpoint: http://{}/mcp", addr);
+        // The first item arrived while the server was still writing the second
+        assert!(
+            first_chunk_at.unwrap().elapsed() >= std::time::Duration::from_millis(700),
+            "the result was buffered before being sent"
+        );
+        let response: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], 42);
+        assert_eq!(response["result"]["content"], json!([part(1), part(2)]));
+    }
 
-    axum::serve(listener, app).await?;
+    #[tokio::test]
+    async fn test_missing_input_schema_gets_configured_default() {
+        let mut server = mock_stdio_server("lookup", "ok");
+        server["env"]["MOCK_TOOLS_LIST"] = json!({
+            "tools": [
+                {"name": "lookup", "description": "no schema"},
+                {"name": "search", "description": "own schema", "inputSchema": {"type": "object", "required": ["q"]}},
+            ]
+        })
+        .to_string()
+        .into();
+
+        for (setting, expected) in [
+            (None, json!({})),
+            (Some("empty"), json!({})),
+            (
+                Some("permissive"),
+                json!({"type": "object", "additionalProperties": true}),
+            ),
+        ] {
+            let mut config = json!({ "servers": { "memory": server.clone() } });
+            if let Some(setting) = setting {
+                config["missing_input_schema"] = json!(setting);
+            }
+            let (_dir, state) = state_with_config(config);
+            let server_config = state
+                .system_config_manager
+                .read()
+                .await
+                .get_server("memory")
+                .cloned()
+                .unwrap();
+            let tools = state
+                .discover_server_tools("memory", &server_config)
+                .await
+                .unwrap();
+            state.connection_pool.stop_server("memory").await.unwrap();
 
-    Ok(())
-}
+            let schema = |name: &str| {
+                tools
+                    .iter()
+                    .find(|tool| tool.name == name)
+                    .unwrap()
+                    .input_schema
+                    .clone()
+            };
+            assert_eq!(schema("lookup"), expected, "{:?}", setting);
+            assert_eq!(
+                schema("search"),
+                json!({"type": "object", "required": ["q"]}),
+                "{:?}",
+                setting
+            );
+        }
+    }
 
-/// Call a tool via SSE transport with bidirectional communication
-async fn call_tool_via_sse(
-    client: &reqwest::Client,
-    server_name: &str,
-    sse_url: &str,
-    tool_name: &str,
-    arguments: Value,
-) -> anyhow::Result<Value> {
-    use futures::StreamExt;
-    use tokio::time::{timeout, Duration};
+    #[tokio::test]
+    async fn test_tools_list_filters_by_configured_labels() {
+        let mut server = mock_stdio_server("lookup", "ok");
+        server["env"]["MOCK_TOOLS_LIST"] = json!({
+            "tools": [
+                {"name": "lookup", "description": "read", "inputSchema": {"type": "object"}},
+                {"name": "delete", "description": "write", "inputSchema": {"type": "object"}},
+            ]
+        })
+        .to_string()
+        .into();
+        server["labels"] = json!(["team-data"]);
+        server["tools"] = json!({
+            "lookup": { "labels": ["safe", "read"] },
+            "delete": { "labels": ["destructive"] },
+        });
+        let (_dir, state) = state_with_servers(json!({ "memory": server }));
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        let tools = state.discover_servers(server_list).await;
+        state.connection_pool.stop_server("memory").await.unwrap();
+        assert_eq!(tools["memory_lookup"].labels, ["team-data", "safe", "read"]);
+        *state.available_tools.write().await = tools;
+
+        // Labels are carried into the catalog
+        let catalog = state.tool_info(
+            &state.available_tools.read().await["memory_delete"],
+            &state.system_config_manager.read().await.get_servers()["memory"],
+        );
+        assert_eq!(catalog.labels, ["team-data", "destructive"]);
 
-    println!("🚀 [{}] Starting SSE tool call: {}", server_name, tool_name);
+        let with_labels =
+            |labels: Value| listed_tools_with_params(&state, Some(json!({ "labels": labels })));
+        assert_eq!(with_labels(json!(["safe"])).await, ["memory_lookup"]);
+        assert_eq!(with_labels(json!("safe,read")).await, ["memory_lookup"]);
+        assert_eq!(
+            with_labels(json!(["team-data"])).await,
+            ["memory_delete", "memory_lookup"]
+        );
+        assert!(with_labels(json!(["safe", "destructive"])).await.is_empty());
+
+        // `/mcp?labels=` scopes the list the same way
+        let app = Router::new()
+            .route("/mcp", post(mcp_route))
+            .with_state(state.clone());
+        let url = spawn_http_backend(app).await;
+        let listed: Value = reqwest::Client::new()
+            .post(format!("{}/mcp?labels=destructive", url))
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let names: Vec<&str> = listed["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|tool| tool["name"].as_str())
+            .filter(|name| !name.starts_with("toolman_"))
+            .collect();
+        assert_eq!(names, ["memory_delete"]);
+    }
 
-    // Step 1: Open SSE connection and get session ID
-    let sse_response = client
-        .get(sse_url)
-        .header("Accept", "text/event-stream")
-        .send()
+    #[tokio::test]
+    async fn test_reload_discovers_added_servers_and_drops_removed_ones() {
+        let (dir, state) =
+            state_with_servers(json!({ "alpha": mock_stdio_server("ping", "pong") }));
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        *state.available_tools.write().await = state.discover_servers(server_list).await;
+        let config_path = dir.path().join("servers-config.json");
+
+        std::fs::write(
+            &config_path,
+            json!({ "servers": {
+                "alpha": mock_stdio_server("ping", "pong"),
+                "beta": mock_stdio_server("echo", "hello"),
+            }})
+            .to_string(),
+        )
+        .unwrap();
+        let Json(summary) = reload_endpoint(State(state.clone())).await.unwrap();
+        assert_eq!(summary.added, vec!["beta"]);
+        assert_eq!(summary.unchanged, vec!["alpha"]);
+        assert!(summary.removed.is_empty() && summary.changed.is_empty());
+        {
+            let available_tools = state.available_tools.read().await;
+            assert!(available_tools.contains_key("alpha_ping"));
+            assert_eq!(available_tools["beta_echo"].server_name, "beta");
+        }
+
+        std::fs::write(
+            &config_path,
+            json!({ "servers": { "beta": mock_stdio_server("echo", "hello") } }).to_string(),
+        )
+        .unwrap();
+        let Json(summary) = reload_endpoint(State(state.clone())).await.unwrap();
+        assert_eq!(summary.removed, vec!["alpha"]);
+        let available_tools = state.available_tools.read().await;
+        assert!(!available_tools.contains_key("alpha_ping"));
+        assert!(available_tools.contains_key("beta_echo"));
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_config_and_tools_together() {
+        // Lists its tools only after a second, keeping the reload's discovery in progress
+        let router = Router::new().route(
+            "/mcp",
+            post(|Json(request): Json<Value>| async move {
+                let result = match request["method"].as_str() {
+                    Some("initialize") => json!({
+                        "protocolVersion": "2024-11-05",
+                        "capabilities": {"tools": {}},
+                        "serverInfo": {"name": "slow", "version": "0.1.0"}
+                    }),
+                    Some("tools/list") => {
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        json!({"tools": [{"name": "echo", "description": "slow tool", "inputSchema": {"type": "object"}}]})
+                    }
+                    _ => return Json(json!({})),
+                };
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (dir, state) = state_with_servers(json!({
+            "alpha": mock_stdio_server("ping", "pong"),
+            "gamma": mock_stdio_server("lookup", "found"),
+        }));
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        *state.available_tools.write().await = state.discover_servers(server_list).await;
+
+        // alpha is removed, beta added, and gamma changed to a command that can't start
+        std::fs::write(
+            dir.path().join("servers-config.json"),
+            json!({ "servers": {
+                "beta": { "transport": "http", "url": url, "discovery_ttl_secs": 60 },
+                "gamma": { "command": "/nonexistent/mcp-server", "discovery_ttl_secs": 60 },
+            }})
+            .to_string(),
+        )
+        .unwrap();
+        let reload = {
+            let state = state.clone();
+            tokio::spawn(async move { state.reload_config().await })
+        };
+
+        // While beta is still being discovered, the removed alpha is listed and answers
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(!reload.is_finished());
+        assert!(state
+            .available_tools
+            .read()
+            .await
+            .contains_key("alpha_ping"));
+        let response = state
+            .handle_jsonrpc_request(tool_call("alpha_ping", json!({})), None)
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "pong");
+
+        let summary = reload.await.unwrap().unwrap();
+        assert_eq!(summary.added, vec!["beta"]);
+        assert_eq!(summary.removed, vec!["alpha"]);
+        assert_eq!(summary.changed, vec!["gamma"]);
+        {
+            let available_tools = state.available_tools.read().await;
+            assert!(!available_tools.contains_key("alpha_ping"));
+            assert!(available_tools.contains_key("beta_echo"));
+            // gamma failed rediscovery, so its known tools are kept
+            assert!(available_tools.contains_key("gamma_lookup"));
+        }
+        assert!(state
+            .system_config_manager
+            .read()
+            .await
+            .get_server("alpha")
+            .is_none());
+        assert!(!state
+            .connection_pool
+            .connections
+            .read()
+            .await
+            .contains_key("alpha"));
+
+        // Both servers setting discovery_ttl_secs now refresh, each with one loop
+        let mut refreshing: Vec<String> = state
+            .refresh_tasks
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        refreshing.sort();
+        assert_eq!(refreshing, vec!["beta", "gamma"]);
+    }
+
+    #[tokio::test]
+    async fn test_reload_does_not_wait_for_in_flight_calls() {
+        let mut slow = mock_stdio_server("ping", "pong");
+        slow["env"]["MOCK_CALL_DELAY"] = json!("3");
+        let (dir, state) = state_with_servers(json!({ "slow": slow.clone() }));
+        register_tool(&state, "slow", "ping").await;
+
+        let call = {
+            let state = state.clone();
+            tokio::spawn(async move {
+                state
+                    .handle_jsonrpc_request(tool_call("slow_ping", json!({})), None)
+                    .await
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        // Reloading while the call is in flight neither deadlocks nor waits for the call
+        std::fs::write(
+            dir.path().join("servers-config.json"),
+            json!({ "servers": {
+                "slow": slow,
+                "beta": mock_stdio_server("echo", "hello"),
+            }})
+            .to_string(),
+        )
+        .unwrap();
+        let summary = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            reload_endpoint(State(state.clone())),
+        )
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to connect to SSE endpoint: {}", e))?;
+        .expect("reload hung behind the in-flight call")
+        .unwrap();
+        assert_eq!(summary.added, vec!["beta"]);
+        assert!(!call.is_finished());
 
-    let mut body = sse_response.bytes_stream();
+        let response = tokio::time::timeout(std::time::Duration::from_secs(10), call)
+            .await
+            .expect("call hung behind the reload")
+            .unwrap();
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "pong");
+    }
 
-    // Wait for session data from SSE stream, reading multiple chunks if needed
-    let mut accumulated_data = String::new();
-    let session_id = loop {
-        match timeout(Duration::from_secs(10), body.next()).await {
-            Ok(Some(Ok(chunk))) => {
-                let chunk_str = String::from_utf8_lossy(&chunk);
-                accumulated_data.push_str(&chunk_str);
+    #[tokio::test]
+    async fn test_env_file_is_loaded_under_explicit_env() {
+        let mut server = mock_stdio_server("lookup", "from env");
+        let tools_list = server["env"]
+            .as_object_mut()
+            .unwrap()
+            .remove("MOCK_TOOLS_LIST")
+            .unwrap();
+        server["env_file"] = json!("secrets.env");
+        let (dir, state) = state_with_servers(json!({ "memory": server }));
+        std::fs::write(
+            dir.path().join("secrets.env"),
+            format!(
+                "# tools come from here\n\nexport MOCK_TOOLS_LIST={}\nMOCK_TOOLS_CALL='{}'\n",
+                tools_list.as_str().unwrap(),
+                json!({ "content": [{"type": "text", "text": "from env_file"}] })
+            ),
+        )
+        .unwrap();
 
-                println!(
-                    "🔗 [{}] SSE handshake data: {}",
-                    server_name,
-                    chunk_str.trim()
-                );
+        let server_config = state
+            .system_config_manager
+            .read()
+            .await
+            .get_server("memory")
+            .cloned()
+            .unwrap();
+        let tools = state
+            .discover_server_tools("memory", &server_config)
+            .await
+            .unwrap();
+        assert_eq!(tools[0].name, "lookup");
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("memory_lookup", json!({})), None)
+            .await;
+        state.connection_pool.stop_server("memory").await.unwrap();
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "from env");
+    }
 
-                // Try to parse session ID from accumulated data
-                if let Some(data_line) = accumulated_data
-                    .lines()
-                    .find(|line| line.starts_with("data: "))
-                {
-                    let endpoint_path = data_line.strip_prefix("data: ").unwrap_or("");
-                    if let Some(session_param) = endpoint_path.split("sessionId=").nth(1) {
-                        break session_param.to_string();
-                    }
-                }
+    #[tokio::test]
+    async fn test_crashed_stdio_server_is_restarted_for_the_next_call() {
+        let (_dir, state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("lookup", "found") }));
+        let call = || state.handle_jsonrpc_request(tool_call("memory_lookup", json!({})), None);
+
+        let response = call().await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "found");
+        let first_pid = state.connection_pool.process_ids.lock().unwrap()["memory"];
+        std::process::Command::new("kill")
+            .args(["-9", &first_pid.to_string()])
+            .status()
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+        let response = call().await;
+        let result = response.result.unwrap();
+        assert_eq!(result["content"][0]["text"], "found", "{result}");
+        let second_pid = state.connection_pool.process_ids.lock().unwrap()["memory"];
+        assert_ne!(first_pid, second_pid);
+        state.connection_pool.stop_server("memory").await.unwrap();
+    }
 
-                // If we have an "event: endpoint" but no data line yet, continue reading
-                if accumulated_data.contains("event: endpoint")
-                    && !accumulated_data
-                        .lines()
-                        .any(|line| line.starts_with("data: "))
-                {
-                    continue;
+    #[tokio::test]
+    async fn test_arguments_are_validated_against_input_schema() {
+        let mut server = mock_stdio_server("search", "found");
+        server["env"]["MOCK_TOOLS_LIST"] = json!({
+            "tools": [{
+                "name": "search",
+                "description": "search",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {"query": {"type": "string"}},
+                    "required": ["query"],
+                    "additionalProperties": false
                 }
+            }]
+        })
+        .to_string()
+        .into();
+        server["inject_params"] = json!({ "projectRoot": "working_dir" });
+        let (dir, mut state) = state_with_servers(json!({ "memory": server }));
+        state.validate_arguments = true;
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        *state.available_tools.write().await = state.discover_servers(server_list).await;
+        // The injected projectRoot mustn't trip additionalProperties
+        let headers = working_dir_headers(dir.path());
+
+        let response = state
+            .handle_jsonrpc_request(tool_call("memory_search", json!({})), Some(&headers))
+            .await;
+        let error = response.error.expect("missing argument should be rejected");
+        assert_eq!(error.code, -32602);
+        assert!(
+            error.message.contains("\"query\" is a required property"),
+            "{}",
+            error.message
+        );
+        assert_eq!(
+            error.data.unwrap()["violations"].as_array().unwrap().len(),
+            1
+        );
+
+        let response = state
+            .handle_jsonrpc_request(
+                tool_call("memory_search", json!({"query": 5})),
+                Some(&headers),
+            )
+            .await;
+        let error = response.error.expect("wrong type should be rejected");
+        assert_eq!(error.data.unwrap()["violations"][0]["path"], "/query");
+
+        let response = state
+            .handle_jsonrpc_request(
+                tool_call("memory_search", json!({"query": "rust"})),
+                Some(&headers),
+            )
+            .await;
+        state.connection_pool.stop_server("memory").await.unwrap();
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "found");
+    }
 
-                // If we've accumulated data but can't find session ID, something's wrong
-                if accumulated_data.len() > 1000 {
-                    return Err(anyhow::anyhow!(
-                        "Could not find sessionId in SSE data after reading {} chars",
-                        accumulated_data.len()
-                    ));
+    #[tokio::test]
+    async fn test_results_without_content_are_wrapped_when_normalizing() {
+        let servers = json!({
+            "bare": mock_stdio_server_returning("lookup", json!("plain answer")),
+            "proper": mock_stdio_server("lookup", "found"),
+        });
+        for normalize in [false, true] {
+            let (_dir, state) = state_with_config(json!({
+                "servers": servers.clone(),
+                "normalize_tool_results": normalize,
+            }));
+            let call = |name: &'static str| {
+                let state = state.clone();
+                async move {
+                    state
+                        .handle_jsonrpc_request(tool_call(name, json!({})), None)
+                        .await
+                        .result
+                        .unwrap()
                 }
+            };
+
+            let bare = call("bare_lookup").await;
+            if normalize {
+                assert_eq!(
+                    bare,
+                    json!({"content": [{"type": "text", "text": "plain answer"}]})
+                );
+            } else {
+                assert_eq!(bare, json!("plain answer"));
             }
-            Ok(Some(Err(e))) => return Err(anyhow::anyhow!("SSE stream error: {}", e)),
-            Ok(None) => return Err(anyhow::anyhow!("SSE stream ended unexpectedly")),
-            Err(_) => return Err(anyhow::anyhow!("Timeout waiting for SSE session data")),
-        }
-    };
+            let proper = call("proper_lookup").await;
+            assert_eq!(
+                proper,
+                json!({"content": [{"type": "text", "text": "found"}]})
+            );
 
-    println!("✅ [{}] Got SSE session ID: {}", server_name, session_id);
+            state.connection_pool.stop_server("bare").await.unwrap();
+            state.connection_pool.stop_server("proper").await.unwrap();
+        }
+    }
 
-    // Step 2: Prepare message endpoint
-    let base_url = sse_url.trim_end_matches("/sse").trim_end_matches('/');
-    let message_url = format!("{}/message?sessionId={}", base_url, session_id);
+    #[tokio::test]
+    async fn test_catalog_refresh_picks_up_changed_tools() {
+        let mut server = mock_stdio_server("beta", "ok");
+        server["env"]["MOCK_FIRST_TOOLS_LIST"] = json!({
+            "tools": [{"name": "alpha", "description": "mock tool", "inputSchema": {"type": "object"}}]
+        })
+        .to_string()
+        .into();
+        let (_dir, state) = state_with_config(json!({
+            "servers": { "memory": server },
+            "catalog_refresh_interval_secs": 1,
+        }));
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        *state.available_tools.write().await = state.discover_servers(server_list).await;
+        assert!(state
+            .available_tools
+            .read()
+            .await
+            .contains_key("memory_alpha"));
 
-    // Step 3: Start listening for responses in background task
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut notifications = state.notifications.subscribe();
+        state.spawn_catalog_refresh();
+        let notification =
+            tokio::time::timeout(tokio::time::Duration::from_secs(10), notifications.recv())
+                .await
+                .expect("a changed tool list should be announced")
+                .unwrap();
+        assert_eq!(notification["method"], "notifications/tools/list_changed");
+        {
+            let available_tools = state.available_tools.read().await;
+            assert!(available_tools.contains_key("memory_beta"));
+            assert!(!available_tools.contains_key("memory_alpha"));
+        }
 
-    // Spawn SSE response listener
-    let tx_clone = tx.clone();
-    let server_name_clone = server_name.to_string();
-    tokio::spawn(async move {
-        let mut accumulated_data = String::new();
-        let mut in_data_section = false;
+        // Nothing changes on the next refresh, so the catalog isn't republished
+        assert!(!state.refresh_catalog().await);
+        state.connection_pool.stop_server("memory").await.unwrap();
+    }
 
-        while let Some(chunk_result) = body.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    println!(
-                        "🔍 [{}] SSE chunk received ({} bytes):\n{}",
-                        server_name_clone,
-                        chunk.len(),
-                        chunk_str
-                    );
+    #[tokio::test]
+    async fn test_only_configured_servers_get_injected_params() {
+        let mut workspace = mock_stdio_server("build", "unused");
+        workspace["inject_params"] = json!({
+            "workspaceRoot": "working_dir",
+            "configDir": "project_dir",
+            "mode": { "literal": "ci" },
+        });
+        let (dir, state) = state_with_servers(json!({
+            "plain": mock_stdio_server("build", "unused"),
+            "workspace": workspace,
+        }));
+        register_tool(&state, "plain", "build").await;
+        register_tool(&state, "workspace", "build").await;
+
+        let preview = |tool: &'static str| {
+            let state = state.clone();
+            async move {
+                let result = state
+                    .handle_jsonrpc_request(
+                        tool_call(
+                            "toolman_preview_call",
+                            json!({
+                                "tool": tool,
+                                "arguments": { "target": "all" },
+                                "working_dir": "/work/app"
+                            }),
+                        ),
+                        None,
+                    )
+                    .await
+                    .result
+                    .unwrap();
+                serde_json::from_str::<Value>(result["content"][0]["text"].as_str().unwrap())
+                    .unwrap()
+            }
+        };
 
-                    let lines: Vec<&str> = chunk_str.lines().collect();
-                    println!("🔍 [{}] SSE lines parsed: {:?}", server_name_clone, lines);
-
-                    for line in lines {
-                        if line == "event: message" {
-                            println!("✅ [{}] Found 'event: message'", server_name_clone);
-                            in_data_section = true;
-                            accumulated_data.clear();
-                        } else if line.starts_with("data: ") && in_data_section {
-                            // Accumulate data from this line (multiple data lines should be joined with newlines)
-                            if let Some(json_part) = line.strip_prefix("data: ") {
-                                if !accumulated_data.is_empty() {
-                                    accumulated_data.push('\n');
-                                }
-                                accumulated_data.push_str(json_part);
-                                println!(
-                                    "🔍 [{}] Accumulated {} bytes of data",
-                                    server_name_clone,
-                                    accumulated_data.len()
-                                );
-                            }
-                        } else if line.starts_with("data: ") {
-                            // Handle standalone data lines without event prefix
-                            println!(
-                                "🔍 [{}] Found standalone data line: '{}'",
-                                server_name_clone, line
-                            );
-                            if let Some(json_str) = line.strip_prefix("data: ") {
-                                accumulated_data = json_str.to_string();
-                                println!(
-                                    "🔍 [{}] Set accumulated data from standalone: {}",
-                                    server_name_clone, accumulated_data
-                                );
-                            }
-                        } else if line.trim().is_empty() && !accumulated_data.is_empty() {
-                            // Empty line indicates end of SSE message, try to parse accumulated data
-                            println!(
-                                "🔍 [{}] End of SSE message, attempting to parse {} bytes of JSON",
-                                server_name_clone,
-                                accumulated_data.len()
-                            );
-                            println!(
-                                "🔍 [{}] Accumulated JSON: {}",
-                                server_name_clone, accumulated_data
-                            );
+        assert_eq!(
+            preview("plain_build").await["arguments"],
+            json!({ "target": "all" })
+        );
+        assert_eq!(
+            preview("workspace_build").await["arguments"],
+            json!({
+                "target": "all",
+                "workspaceRoot": "/work/app",
+                "configDir": dir.path().to_string_lossy(),
+                "mode": "ci",
+            })
+        );
+    }
 
-                            match serde_json::from_str::<serde_json::Value>(&accumulated_data) {
-                                Ok(response) => {
-                                    println!(
-                                        "📨 [{}] SSE response parsed successfully from accumulated data",
-                                        server_name_clone
-                                    );
-                                    let _ = tx_clone.send(response);
-                                    accumulated_data.clear();
-                                    in_data_section = false;
-                                }
-                                Err(e) => {
-                                    println!(
-                                        "❌ [{}] Failed to parse accumulated JSON: {} (error: {})",
-                                        server_name_clone, accumulated_data, e
-                                    );
-                                    println!(
-                                        "🔍 [{}] JSON bytes: {:?}",
-                                        server_name_clone,
-                                        accumulated_data.as_bytes()
-                                    );
-                                }
-                            }
-                        } else if in_data_section && !line.trim().is_empty() {
-                            // This might be a continuation of the previous data line that got split across chunks
-                            println!(
-                                "🔍 [{}] Found continuation line in data section: '{}'",
-                                server_name_clone, line
-                            );
-                            accumulated_data.push_str(line);
-                            println!(
-                                "🔍 [{}] Accumulated {} bytes after continuation",
-                                server_name_clone,
-                                accumulated_data.len()
-                            );
-                        } else {
-                            println!("🔍 [{}] Skipping line: '{}'", server_name_clone, line);
-                        }
+    #[tokio::test]
+    async fn test_max_concurrent_calls_serializes_calls_beyond_the_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // A slow backend that records how many tool calls it was running at once
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let (counter, peak) = (running.clone(), most_running.clone());
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let (counter, peak) = (counter.clone(), peak.clone());
+                async move {
+                    if request["method"] == "tools/call" {
+                        let now = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                        counter.fetch_sub(1, Ordering::SeqCst);
                     }
+                    Json(
+                        json!({"jsonrpc": "2.0", "id": request["id"].clone(), "result": {
+                        "content": [{"type": "text", "text": "done"}]}}),
+                    )
                 }
-                Err(e) => {
-                    println!("❌ [{}] SSE stream error: {}", server_name_clone, e);
-                    break;
-                }
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "limited": { "transport": "http", "url": url, "max_concurrent_calls": 1 },
+            "unlimited": { "transport": "http", "url": url },
+        }));
+        register_tool(&state, "limited", "work").await;
+        register_tool(&state, "unlimited", "work").await;
+
+        let started = std::time::Instant::now();
+        let (first, second) = tokio::join!(
+            state.handle_jsonrpc_request(tool_call("limited_work", json!({})), None),
+            state.handle_jsonrpc_request(tool_call("limited_work", json!({})), None),
+        );
+        assert!(first.error.is_none() && second.error.is_none());
+        assert_eq!(most_running.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() >= std::time::Duration::from_millis(600));
+
+        // Without a limit the same two calls overlap
+        most_running.store(0, Ordering::SeqCst);
+        let (first, second) = tokio::join!(
+            state.handle_jsonrpc_request(tool_call("unlimited_work", json!({})), None),
+            state.handle_jsonrpc_request(tool_call("unlimited_work", json!({})), None),
+        );
+        assert!(first.error.is_none() && second.error.is_none());
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_call_during_initialization_waits_for_the_handshake() {
+        let mut rejecting = mock_stdio_server("lookup", "found");
+        rejecting["while_initializing"] = json!("reject");
+        let (_dir, state) = state_with_servers(json!({
+            "waiting": mock_stdio_server("lookup", "found"),
+            "rejecting": rejecting,
+        }));
+        let pool = state.connection_pool.clone();
+
+        for server in ["waiting", "rejecting"] {
+            let start = {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.start_server(server).await })
+            };
+            // The handshake is still under way when the call arrives
+            tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+            assert!(!pool.connections.read().await.contains_key(server));
+
+            let response = pool
+                .forward_tool_call_with_context(server, "lookup", json!({}), None, None)
+                .await;
+            if server == "waiting" {
+                assert_eq!(response.unwrap()["result"]["content"][0]["text"], "found");
+            } else {
+                assert_eq!(
+                    tool_error_kind(&response.unwrap_err()),
+                    "server_initializing"
+                );
             }
+            start.await.unwrap().unwrap();
+            pool.stop_server(server).await.unwrap();
         }
+    }
 
-        // Stream ended - try to parse any remaining accumulated data
-        if !accumulated_data.is_empty() {
-            println!(
-                "🔍 [{}] Stream ended with {} bytes of accumulated data, attempting final parse",
-                server_name_clone,
-                accumulated_data.len()
-            );
-            match serde_json::from_str::<serde_json::Value>(&accumulated_data) {
-                Ok(response) => {
-                    println!(
-                        "📨 [{}] Final SSE response parsed successfully from accumulated data",
-                        server_name_clone
-                    );
-                    let _ = tx_clone.send(response);
-                }
-                Err(e) => {
-                    println!(
-                        "❌ [{}] Failed to parse final accumulated JSON: {} (error: {})",
-                        server_name_clone, accumulated_data, e
-                    );
-                }
+    #[tokio::test]
+    async fn test_tool_calls_are_written_to_the_audit_log() {
+        let (dir, mut state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("lookup", "found") }));
+        state.audit_log = Some(AuditLog::open(&dir.path().join("audit.log")).unwrap());
+
+        let arguments = json!({ "query": "rust", "auth": { "apiKey": "sk-12345", "user": "me" } });
+        let response = state
+            .handle_jsonrpc_request(
+                tool_call("memory_lookup", arguments),
+                Some(&working_dir_headers(dir.path())),
+            )
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "found");
+        state.connection_pool.stop_server("memory").await.unwrap();
+
+        // The line is written in the background; the file is named for today
+        let audit_file = dir
+            .path()
+            .join(format!("audit.log.{}", Utc::now().format("%Y-%m-%d")));
+        let mut contents = String::new();
+        for _ in 0..50 {
+            contents = std::fs::read_to_string(&audit_file).unwrap_or_default();
+            if !contents.is_empty() {
+                break;
             }
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
         }
-    });
-
-    // Step 4: Send MCP handshake sequence
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1, "{contents}");
+        let entry = &lines[0];
+        assert!(entry["timestamp"].as_str().is_some());
+        assert_eq!(entry["working_directory"], json!(dir.path()));
+        assert_eq!(entry["server"], "memory");
+        assert_eq!(entry["tool"], "lookup");
+        assert_eq!(
+            entry["arguments"],
+            json!({ "query": "rust", "auth": { "apiKey": "[REDACTED]", "user": "me" } })
+        );
+        assert_eq!(entry["success"], true);
+        assert!(entry["error"].is_null());
+    }
 
-    // 4a. Send initialize request
-    let initialize_request = json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "initialize",
-        "params": {
-            "protocolVersion": "2024-11-05",
-            "capabilities": {},
-            "clientInfo": {
-                "name": "toolman",
-                "version": "1.0.0"
+    #[tokio::test]
+    async fn test_audit_log_drops_and_counts_lines_when_the_disk_falls_behind() {
+        // A disk that takes a while with each write
+        struct SlowDisk;
+        impl std::io::Write for SlowDisk {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
             }
         }
-    });
-
-    let init_response = client
-        .post(&message_url)
-        .json(&initialize_request)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send initialize request: {}", e))?;
+        let (_dir, mut state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("lookup", "found") }));
+        let audit_log = AuditLog::with_writer(SlowDisk, 1);
+
+        // Recording never waits for the disk; what doesn't fit in the queue is dropped
+        let started = std::time::Instant::now();
+        for call in 0..10 {
+            audit_log.record(&json!({ "call": call }));
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(200));
+        let dropped = audit_log.dropped_lines();
+        assert!(dropped >= 8, "{dropped}");
 
-    if !init_response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Initialize request failed with status: {}",
-            init_response.status()
-        ));
+        state.audit_log = Some(audit_log);
+        let response = metrics_endpoint(State(state)).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let metrics = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            metrics.contains(&format!("toolman_audit_log_dropped_lines {}", dropped)),
+            "{}",
+            metrics
+        );
     }
 
-    // Wait for initialize response
-    let _init_resp = match timeout(Duration::from_secs(10), rx.recv()).await {
-        Ok(Some(response)) => response,
-        Ok(None) => return Err(anyhow::anyhow!("SSE channel closed during initialize")),
-        Err(_) => return Err(anyhow::anyhow!("Timeout waiting for initialize response")),
-    };
-
-    println!("✅ [{}] MCP initialize completed", server_name);
-
-    // 4b. Send initialized notification
-    let initialized_notification = json!({
-        "jsonrpc": "2.0",
-        "method": "notifications/initialized",
-        "params": {}
-    });
+    #[tokio::test]
+    async fn test_pretty_query_param_indents_json_responses() {
+        let (_dir, state) =
+            state_with_servers(json!({ "memory": mock_stdio_server("lookup", "found") }));
+        let base = spawn_http_backend(app(state)).await;
+        let get = |path: &str| {
+            let url = format!("{}{}", base, path);
+            async move { reqwest::get(url).await.unwrap().text().await.unwrap() }
+        };
 
-    let notif_response = client
-        .post(&message_url)
-        .json(&initialized_notification)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send initialized notification: {}", e))?;
+        let compact = get("/servers").await;
+        assert!(!compact.contains('\n'), "{compact}");
 
-    if !notif_response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Initialized notification failed with status: {}",
-            notif_response.status()
-        ));
+        let pretty = get("/servers?pretty=true").await;
+        assert!(pretty.contains("\n  \"memory\": {"), "{pretty}");
+        assert_eq!(
+            serde_json::from_str::<Value>(&pretty).unwrap(),
+            serde_json::from_str::<Value>(&compact).unwrap()
+        );
     }
 
-    println!("✅ [{}] MCP session established", server_name);
+    #[tokio::test]
+    async fn test_request_ids_wrap_around_skipping_ids_in_flight() {
+        let responses = SseResponses::new("mock");
+        let next_id = std::sync::Mutex::new(FIRST_SESSION_REQUEST_ID);
+        let next = || responses.expect_next(&next_id, 4, RequestIdType::Integer);
+
+        // Ids 2 to 4 are the whole space; with all three in flight there is none to give
+        let (_, _two) = next().unwrap();
+        let (three_id, three) = next().unwrap();
+        let (four_id, _four) = next().unwrap();
+        assert_eq!((three_id, four_id), (json!(3), json!(4)));
+        assert!(next().is_err());
+
+        // Once 3 is answered, the counter wraps around past 2, which is still in flight
+        responses.route("mock", json!({"jsonrpc": "2.0", "id": 3, "result": {}}));
+        assert!(three.await.is_ok());
+        let (id, _) = next().unwrap();
+        assert_eq!(id, json!(3));
+    }
 
-    // Step 5: Send the actual tool call request
-    let tool_call_request = json!({
-        "jsonrpc": "2.0",
-        "id": 2,
-        "method": "tools/call",
-        "params": {
-            "name": tool_name,
-            "arguments": arguments
+    #[tokio::test]
+    async fn test_client_disconnect_releases_the_server_for_the_next_call() {
+        let mut server = mock_stdio_server("lookup", "found");
+        server["env"]["MOCK_CALL_DELAY"] = json!("2");
+        server["tools"] = json!({ "lookup": { "streaming": true } });
+        let (_dir, state) = state_with_servers(json!({ "memory": server }));
+        register_tool(&state, "memory", "lookup").await;
+        let pool = state.connection_pool.clone();
+        pool.start_server("memory").await.unwrap();
+        let server_is_free = || pool.exchange_lock("memory").try_lock().is_ok();
+        // Whether the server is free for another call within half a second
+        async fn released(server_is_free: impl Fn() -> bool) -> bool {
+            for _ in 0..50 {
+                if server_is_free() {
+                    return true;
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
+            false
         }
-    });
-
-    println!("🔧 [{}] Sending tool call: {}", server_name, tool_name);
 
-    let call_response = client
-        .post(&message_url)
-        .json(&tool_call_request)
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send tool call request: {}", e))?;
+        // A streamed call whose client hangs up while the server is still working
+        let app = Router::new()
+            .route("/mcp", post(mcp_route))
+            .with_state(state.clone());
+        let url = format!("{}/mcp", spawn_http_backend(app).await);
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+                "params": {"name": "memory_lookup", "arguments": {}}}))
+            .send()
+            .await
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        assert!(!server_is_free());
+        drop(response);
+        assert!(
+            released(server_is_free).await,
+            "streamed call kept the server"
+        );
 
-    if !call_response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Tool call request failed with status: {}",
-            call_response.status()
-        ));
+        // A plain call whose request is dropped mid-call
+        let call = {
+            let state = state.clone();
+            tokio::spawn(async move {
+                let mut request = tool_call("memory_lookup", json!({}));
+                request.id = Some(json!("abandoned"));
+                state.handle_jsonrpc_request(request, None).await
+            })
+        };
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        assert!(!server_is_free());
+        call.abort();
+        assert!(
+            released(server_is_free).await,
+            "dropped call kept the server"
+        );
+        assert!(state.pending_calls.lock().unwrap().is_empty());
+
+        // The late responses to both calls don't get mixed up with the next one
+        let response = state
+            .handle_jsonrpc_request(tool_call("memory_lookup", json!({})), None)
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "found");
+        pool.stop_server("memory").await.unwrap();
     }
 
-    // Step 6: Wait for tool call response via SSE
-    let timeout_secs = 120; // Generous timeout for all tool calls
-    let tool_response = match timeout(Duration::from_secs(timeout_secs), rx.recv()).await {
-        Ok(Some(response)) => response,
-        Ok(None) => return Err(anyhow::anyhow!("SSE channel closed during tool call")),
-        Err(_) => return Err(anyhow::anyhow!("Timeout waiting for tool call response")),
-    };
-
-    println!("✅ [{}] Tool call completed: {}", server_name, tool_name);
-
-    Ok(tool_response)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_suggest_ranks_tools_for_a_task() {
+        let (_dir, state) = state_with_servers(json!({
+            "filesystem": { "command": "fs" },
+            "memory": { "command": "memory" },
+            "git": { "command": "git" },
+        }));
+        for (server, tool, description) in [
+            (
+                "filesystem",
+                "read_file",
+                "Read the complete contents of a file from disk",
+            ),
+            (
+                "filesystem",
+                "write_file",
+                "Create a new file or overwrite an existing one",
+            ),
+            ("memory", "read_graph", "Read the entire knowledge graph"),
+            ("git", "git_status", "Shows the working tree status"),
+        ] {
+            register_tool(&state, server, tool).await;
+            state
+                .available_tools
+                .write()
+                .await
+                .get_mut(&prefixed_tool_name(server, tool))
+                .unwrap()
+                .description = description.to_string();
+        }
 
-    #[test]
-    fn test_parse_tool_name_valid() {
-        // Simple case
-        let result = parse_tool_name("memory_delete_entities").unwrap();
+        let Json(response) = suggest_endpoint(
+            State(state.clone()),
+            Json(SuggestRequest {
+                query: "Read a file".to_string(),
+                limit: None,
+            }),
+        )
+        .await;
+        let suggestions = response["suggestions"].as_array().unwrap();
+        assert_eq!(suggestions[0]["tool"], "filesystem_read_file");
+        assert_eq!(suggestions[0]["score"], 1.0);
+        assert_eq!(suggestions[0]["category"], "file-operations");
         assert_eq!(
-            result,
-            ParsedTool {
-                server_name: "memory".to_string(),
-                tool_name: "delete_entities".to_string(),
-            }
+            suggestions[0]["reason"],
+            "matches 'read' in its name, 'file' in its name"
         );
+        let ranked: Vec<&str> = suggestions
+            .iter()
+            .map(|s| s["tool"].as_str().unwrap())
+            .collect();
+        assert!(!ranked.contains(&"git_git_status"), "{ranked:?}");
 
-        // Single underscore in tool name
-        let result = parse_tool_name("filesystem_read_file").unwrap();
-        assert_eq!(
-            result,
-            ParsedTool {
-                server_name: "filesystem".to_string(),
-                tool_name: "read_file".to_string(),
-            }
-        );
+        let Json(response) = suggest_endpoint(
+            State(state),
+            Json(SuggestRequest {
+                query: "read files".to_string(),
+                limit: Some(1),
+            }),
+        )
+        .await;
+        assert_eq!(response["suggestions"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_servers_start_after_their_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = dir.path().join("started");
+        // Each server notes when its process starts; `upstream` is slow to get going
+        let server = |name: &str, delay: &str| {
+            let mut server = mock_stdio_server("ping", name);
+            server["args"][1] = json!(format!(
+                "sleep {}; echo {} >> {}; {}",
+                delay,
+                name,
+                started.display(),
+                MOCK_STDIO_SERVER
+            ));
+            server
+        };
+        let mut gateway = server("gateway", "0");
+        gateway["depends_on"] = json!(["upstream"]);
+        let mut broken = server("broken", "0");
+        broken["command"] = json!("/nonexistent/command");
+        let mut stranded = server("stranded", "0");
+        stranded["depends_on"] = json!(["broken"]);
+        let (_config_dir, state) = state_with_servers(json!({
+            "gateway": gateway,
+            "upstream": server("upstream", "0.5"),
+            "broken": broken,
+            "stranded": stranded,
+        }));
+
+        let server_list = state
+            .system_config_manager
+            .read()
+            .await
+            .get_servers()
+            .clone()
+            .into_iter()
+            .collect();
+        let tools = state.discover_servers(server_list).await;
+        state.connection_pool.stop_server("gateway").await.unwrap();
+        state.connection_pool.stop_server("upstream").await.unwrap();
 
-        // Multiple underscores in tool name - should split at first underscore only
-        let result = parse_tool_name("memory_delete_all_entities").unwrap();
         assert_eq!(
-            result,
-            ParsedTool {
-                server_name: "memory".to_string(),
-                tool_name: "delete_all_entities".to_string(),
-            }
+            std::fs::read_to_string(&started).unwrap(),
+            "upstream\ngateway\n"
         );
-
-        // Numbers and hyphens
-        let result = parse_tool_name("server123_tool-name").unwrap();
+        assert!(tools.contains_key("gateway_ping") && tools.contains_key("upstream_ping"));
+        // A server whose dependency failed isn't started, and says why
         assert_eq!(
-            result,
-            ParsedTool {
-                server_name: "server123".to_string(),
-                tool_name: "tool-name".to_string(),
+            state.discovery_status.read().await["stranded"],
+            DiscoveryStatus::DependencyNotReady {
+                dependencies: vec!["broken".to_string()]
             }
         );
     }
 
-    #[test]
-    fn test_parse_tool_name_invalid() {
-        // Empty string
-        assert!(matches!(
-            parse_tool_name(""),
-            Err(ToolParseError::EmptyToolName)
-        ));
-
-        // No underscore
-        assert!(matches!(
-            parse_tool_name("memorydel"),
-            Err(ToolParseError::InvalidFormat(_))
-        ));
-
-        // Missing server name (starts with underscore)
-        assert!(matches!(
-            parse_tool_name("_delete_entities"),
-            Err(ToolParseError::InvalidFormat(_))
-        ));
-
-        // Missing tool name (ends with underscore)
-        assert!(matches!(
-            parse_tool_name("memory_"),
-            Err(ToolParseError::InvalidFormat(_))
-        ));
-
-        // Only underscore
-        assert!(matches!(
-            parse_tool_name("_"),
-            Err(ToolParseError::InvalidFormat(_))
-        ));
+    #[tokio::test]
+    async fn test_fire_and_forget_call_is_acked_before_the_backend_finishes() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // A slow backend that notes when it has finished a call
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        let router = Router::new().route(
+            "/mcp",
+            post(move |Json(request): Json<Value>| {
+                let flag = flag.clone();
+                async move {
+                    if request["method"] == "tools/call" {
+                        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                        flag.store(true, Ordering::SeqCst);
+                    }
+                    Json(
+                        json!({"jsonrpc": "2.0", "id": request["id"].clone(), "result": {
+                        "content": [{"type": "text", "text": "a large result"}]}}),
+                    )
+                }
+            }),
+        );
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "events": {
+                "transport": "http",
+                "url": url,
+                "tools": { "publish": { "fire_and_forget": true } }
+            },
+        }));
+        register_tool(&state, "events", "publish").await;
+
+        let started = std::time::Instant::now();
+        let response = state
+            .handle_jsonrpc_request(tool_call("events_publish", json!({})), None)
+            .await;
+        assert_eq!(response.result.unwrap(), json!({ "content": [] }));
+        assert!(started.elapsed() < std::time::Duration::from_millis(300));
+        assert!(!finished.load(Ordering::SeqCst));
+
+        // The call still reaches the backend and runs to completion
+        for _ in 0..100 {
+            if finished.load(Ordering::SeqCst) {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        }
+        assert!(finished.load(Ordering::SeqCst));
     }
 
-    #[test]
-    fn test_parse_tool_name_edge_cases() {
-        // Multiple consecutive underscores
-        let result = parse_tool_name("server__tool").unwrap();
-        assert_eq!(
-            result,
-            ParsedTool {
-                server_name: "server".to_string(),
-                tool_name: "_tool".to_string(),
-            }
+    #[tokio::test]
+    async fn test_concurrent_calls_inject_their_own_working_directory() {
+        // A slow backend that answers with the projectRoot it was given, so the two calls
+        // are in flight together
+        let router = Router::new().route(
+            "/mcp",
+            post(|Json(request): Json<Value>| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                let root = request["params"]["arguments"]["projectRoot"].clone();
+                Json(
+                    json!({"jsonrpc": "2.0", "id": request["id"].clone(), "result": {
+                    "content": [{"type": "text", "text": root.as_str().unwrap_or("")}]}}),
+                )
+            }),
         );
-
-        // Very long names
-        let long_server = "a".repeat(100);
-        let long_tool = "b".repeat(100);
-        let long_name = format!("{}_{}", long_server, long_tool);
-        let result = parse_tool_name(&long_name).unwrap();
-        assert_eq!(
-            result,
-            ParsedTool {
-                server_name: long_server,
-                tool_name: long_tool,
+        let url = format!("{}/mcp", spawn_http_backend(router).await);
+        let (_dir, state) = state_with_servers(json!({
+            "tasks": {
+                "transport": "http",
+                "url": url,
+                "inject_params": { "projectRoot": "working_dir" }
             }
+        }));
+        register_tool(&state, "tasks", "next").await;
+
+        let (alpha, beta) = (
+            working_dir_headers("/work/alpha"),
+            working_dir_headers("/work/beta"),
+        );
+        let (first, second, unset) = tokio::join!(
+            state.handle_jsonrpc_request(tool_call("tasks_next", json!({})), Some(&alpha)),
+            state.handle_jsonrpc_request(tool_call("tasks_next", json!({})), Some(&beta)),
+            state.handle_jsonrpc_request(tool_call("tasks_next", json!({})), None),
         );
+        assert_eq!(first.result.unwrap()["content"][0]["text"], "/work/alpha");
+        assert_eq!(second.result.unwrap()["content"][0]["text"], "/work/beta");
+        // Without the header there is no working directory to inject
+        assert_eq!(unset.result.unwrap()["content"][0]["text"], "");
     }
 }
@@ -72,11 +72,11 @@ impl McpTestClient {
             let mut stdin = stdin;
             while let Some(line) = stdin_receiver.recv().await {
                 if let Err(e) = stdin.write_all(line.as_bytes()).await {
-                    eprintln!("Failed to write to stdin: {e}");
+                    tracing::error!("Failed to write to stdin: {e}");
                     break;
                 }
                 if let Err(e) = stdin.flush().await {
-                    eprintln!("Failed to flush stdin: {e}");
+                    tracing::error!("Failed to flush stdin: {e}");
                     break;
                 }
             }
@@ -9,7 +9,7 @@ pub struct ProtocolValidator;
 
 impl ProtocolValidator {
     pub async fn validate_initialization(server: &mut TestServer) -> Result<Value> {
-        println!(
+        tracing::info!(
             "Validating initialization for server: {}",
             server.config.name
         );
@@ -21,7 +21,7 @@ impl ProtocolValidator {
 
         // Validate basic response structure
         if let Some(protocol_version) = response.get("protocolVersion") {
-            println!("Protocol version: {}", protocol_version);
+            tracing::info!("Protocol version: {}", protocol_version);
         } else {
             return Err(anyhow::anyhow!(
                 "Missing protocolVersion in initialize response"
@@ -29,7 +29,7 @@ impl ProtocolValidator {
         }
 
         if let Some(capabilities) = response.get("capabilities") {
-            println!("Server capabilities: {}", capabilities);
+            tracing::info!("Server capabilities: {}", capabilities);
         } else {
             return Err(anyhow::anyhow!(
                 "Missing capabilities in initialize response"
@@ -37,14 +37,14 @@ impl ProtocolValidator {
         }
 
         if let Some(server_info) = response.get("serverInfo") {
-            println!("Server info: {}", server_info);
+            tracing::info!("Server info: {}", server_info);
         }
 
         Ok(response)
     }
 
     pub async fn validate_tool_listing(server: &mut TestServer) -> Result<Vec<String>> {
-        println!("Validating tool listing for server: {}", server.config.name);
+        tracing::info!("Validating tool listing for server: {}", server.config.name);
 
         let response = server.list_tools().await.context("Failed to list tools")?;
 
@@ -57,14 +57,14 @@ impl ProtocolValidator {
         for tool in tools {
             if let Some(name) = tool.get("name").and_then(|n| n.as_str()) {
                 tool_names.push(name.to_string());
-                println!("Found tool: {}", name);
+                tracing::info!("Found tool: {}", name);
 
                 // Validate tool structure
                 if tool.get("description").is_none() {
-                    println!("Warning: Tool {} missing description", name);
+                    tracing::warn!("Tool {} missing description", name);
                 }
                 if tool.get("inputSchema").is_none() {
-                    println!("Warning: Tool {} missing inputSchema", name);
+                    tracing::warn!("Tool {} missing inputSchema", name);
                 }
             }
         }
@@ -81,7 +81,7 @@ impl ProtocolValidator {
         tool_name: &str,
         args: Value,
     ) -> Result<Value> {
-        println!("Validating tool call: {} with args: {}", tool_name, args);
+        tracing::info!("Validating tool call: {} with args: {}", tool_name, args);
 
         let response = server
             .call_tool(tool_name, args)
@@ -93,7 +93,7 @@ impl ProtocolValidator {
             return Err(anyhow::anyhow!("Tool response missing content field"));
         }
 
-        println!(
+        tracing::info!(
             "Tool call successful: {}",
             serde_json::to_string_pretty(&response)?
         );
@@ -101,7 +101,7 @@ impl ProtocolValidator {
     }
 
     pub async fn validate_error_handling(server: &mut TestServer) -> Result<()> {
-        println!(
+        tracing::info!(
             "Validating error handling for server: {}",
             server.config.name
         );
@@ -109,9 +109,9 @@ impl ProtocolValidator {
         // Test invalid tool call
         let result = server.call_tool("non_existent_tool", json!({})).await;
         if result.is_ok() {
-            println!("Warning: Server should have failed for non-existent tool");
+            tracing::warn!("Server should have failed for non-existent tool");
         } else {
-            println!("Correct: Server properly handled invalid tool call");
+            tracing::info!("Server properly handled invalid tool call");
         }
 
         // Test invalid method
@@ -121,12 +121,9 @@ impl ProtocolValidator {
             .await;
         if let Ok(response) = result {
             if let Some(error) = response.error {
-                println!(
-                    "Correct: Server returned error for invalid method: {}",
-                    error
-                );
+                tracing::info!("Server returned error for invalid method: {}", error);
             } else {
-                println!("Warning: Server should have returned error for invalid method");
+                tracing::warn!("Server should have returned error for invalid method");
             }
         }
 
@@ -180,7 +177,7 @@ impl ProtocolValidator {
             }
         }
 
-        println!("Validation complete for server: {}", server.config.name);
+        tracing::info!("Validation complete for server: {}", server.config.name);
         report.print_summary();
 
         Ok(report)
@@ -226,8 +223,8 @@ impl ValidationReport {
     }
 
     pub fn print_summary(&self) {
-        println!("\n=== Validation Report for {} ===", self.server_name);
-        println!(
+        tracing::info!("=== Validation Report for {} ===", self.server_name);
+        tracing::info!(
             "Initialization: {}",
             if self.initialization_passed {
                 "✅ PASS"
@@ -235,7 +232,7 @@ impl ValidationReport {
                 "❌ FAIL"
             }
         );
-        println!(
+        tracing::info!(
             "Tools List: {}",
             if self.tools_list_passed {
                 "✅ PASS"
@@ -243,7 +240,7 @@ impl ValidationReport {
                 "❌ FAIL"
             }
         );
-        println!(
+        tracing::info!(
             "Error Handling: {}",
             if self.error_handling_passed {
                 "✅ PASS"
@@ -251,24 +248,22 @@ impl ValidationReport {
                 "❌ FAIL"
             }
         );
-        println!("Protocol Version: {}", self.protocol_version);
-        println!("Available Tools: {}", self.available_tools.join(", "));
+        tracing::info!("Protocol Version: {}", self.protocol_version);
+        tracing::info!("Available Tools: {}", self.available_tools.join(", "));
 
         if !self.errors.is_empty() {
-            println!("Errors:");
             for error in &self.errors {
-                println!("  ❌ {}", error);
+                tracing::error!("{}", error);
             }
         }
 
         if !self.warnings.is_empty() {
-            println!("Warnings:");
             for warning in &self.warnings {
-                println!("  ⚠️  {}", warning);
+                tracing::warn!("{}", warning);
             }
         }
 
-        println!(
+        tracing::info!(
             "Overall Status: {}",
             if self.is_healthy() {
                 "✅ HEALTHY"
@@ -276,7 +271,7 @@ impl ValidationReport {
                 "❌ UNHEALTHY"
             }
         );
-        println!("====================================\n");
+        tracing::info!("====================================");
     }
 }
 
@@ -285,7 +280,7 @@ pub struct CommonTestScenarios;
 
 impl CommonTestScenarios {
     pub async fn test_filesystem_server(server: &mut TestServer, test_dir: &str) -> Result<()> {
-        println!("Running filesystem server tests...");
+        tracing::info!("Running filesystem server tests...");
 
         // Test reading a file
         let read_result = server
@@ -298,8 +293,8 @@ impl CommonTestScenarios {
             .await;
 
         match read_result {
-            Ok(response) => println!("File read successful: {}", response),
-            Err(e) => println!("File read failed (expected if file doesn't exist): {}", e),
+            Ok(response) => tracing::info!("File read successful: {}", response),
+            Err(e) => tracing::info!("File read failed (expected if file doesn't exist): {}", e),
         }
 
         // Test listing directory
@@ -313,15 +308,15 @@ impl CommonTestScenarios {
             .await;
 
         match list_result {
-            Ok(response) => println!("Directory listing successful: {}", response),
-            Err(e) => println!("Directory listing failed: {}", e),
+            Ok(response) => tracing::info!("Directory listing successful: {}", response),
+            Err(e) => tracing::warn!("Directory listing failed: {}", e),
         }
 
         Ok(())
     }
 
     pub async fn test_fetch_server(server: &mut TestServer) -> Result<()> {
-        println!("Running fetch server tests...");
+        tracing::info!("Running fetch server tests...");
 
         // Test fetching a simple URL
         let fetch_result = server
@@ -335,8 +330,8 @@ impl CommonTestScenarios {
             .await;
 
         match fetch_result {
-            Ok(response) => println!("Fetch successful: {}", response),
-            Err(e) => println!("Fetch failed: {}", e),
+            Ok(response) => tracing::info!("Fetch successful: {}", response),
+            Err(e) => tracing::warn!("Fetch failed: {}", e),
         }
 
         Ok(())
@@ -60,9 +60,11 @@ pub struct TestServer {
 
 impl TestServer {
     pub async fn start(config: ServerConfig) -> Result<Self> {
-        println!(
+        tracing::info!(
             "Starting server: {} with command: {} {:?}",
-            config.name, config.command, config.args
+            config.name,
+            config.command,
+            config.args
         );
 
         let client = timeout(
@@ -88,7 +90,7 @@ impl TestServer {
             .await
             .context("Server health check failed after startup")?;
 
-        println!("Server {} started successfully", server.config.name);
+        tracing::info!("Server {} started successfully", server.config.name);
         Ok(server)
     }
 
@@ -127,7 +129,7 @@ impl TestServer {
     }
 
     pub async fn shutdown(mut self) -> Result<()> {
-        println!("Shutting down server: {}", self.config.name);
+        tracing::info!("Shutting down server: {}", self.config.name);
         self.client.shutdown().await
     }
 }
@@ -205,10 +207,10 @@ mod tests {
         // These tests will only pass if the runtimes are installed
         // In CI, we'll ensure these are available
         if check_runtime_available("node").await {
-            println!("Node.js runtime is available");
+            tracing::info!("Node.js runtime is available");
         }
         if check_runtime_available("docker").await {
-            println!("Docker runtime is available");
+            tracing::info!("Docker runtime is available");
         }
     }
 }
@@ -15,7 +15,7 @@ impl TestEnvironment {
     pub fn new() -> Result<Self> {
         // Check if we're running in Docker test environment
         if let Ok(docker_test_dir) = env::var("MCP_TEST_DATA_DIR") {
-            println!("Using Docker test environment: {}", docker_test_dir);
+            tracing::info!("Using Docker test environment: {}", docker_test_dir);
 
             // Verify the test data directory exists
             if !Path::new(&docker_test_dir).exists() {
@@ -146,9 +146,9 @@ macro_rules! integration_test {
 
             if runtime != "unknown" {
                 match $crate::common::server_lifecycle::check_runtime_available(runtime).await {
-                    true => println!("Runtime {} is available", runtime),
+                    true => tracing::info!("Runtime {} is available", runtime),
                     false => {
-                        println!(
+                        tracing::warn!(
                             "Skipping test {}: {} runtime not available",
                             stringify!($test_name),
                             runtime
@@ -162,7 +162,7 @@ macro_rules! integration_test {
             {
                 Ok(server) => server,
                 Err(e) => {
-                    println!(
+                    tracing::warn!(
                         "Failed to start server for test {}: {}",
                         stringify!($test_name),
                         e
@@ -216,26 +216,24 @@ impl TestResults {
     }
 
     pub fn print_summary(&self) {
-        println!("\n=== Integration Test Summary ===");
-        println!("Passed: {}", self.passed.len());
-        println!("Failed: {}", self.failed.len());
-        println!("Skipped: {}", self.skipped.len());
+        tracing::info!("=== Integration Test Summary ===");
+        tracing::info!("Passed: {}", self.passed.len());
+        tracing::info!("Failed: {}", self.failed.len());
+        tracing::info!("Skipped: {}", self.skipped.len());
 
         if !self.failed.is_empty() {
-            println!("\nFailed tests:");
             for (test, error) in &self.failed {
-                println!("  ❌ {}: {}", test, error);
+                tracing::error!("Failed: {}: {}", test, error);
             }
         }
 
         if !self.skipped.is_empty() {
-            println!("\nSkipped tests:");
             for test in &self.skipped {
-                println!("  ⏭️  {}", test);
+                tracing::warn!("Skipped: {}", test);
             }
         }
 
-        println!("=================================\n");
+        tracing::info!("=================================");
     }
 }
 
@@ -258,9 +256,9 @@ mod tests {
         assert!(timeout > 0);
 
         let is_ci = is_ci_environment();
-        println!("Running in CI: {}", is_ci);
+        tracing::info!("Running in CI: {}", is_ci);
 
         let remote_url = get_remote_server_url();
-        println!("Remote server URL: {}", remote_url);
+        tracing::info!("Remote server URL: {}", remote_url);
     }
 }
@@ -15,12 +15,16 @@ static INIT: Once = Once::new();
 
 pub fn setup_integration_tests() {
     INIT.call_once(|| {
-        // Set up logging for integration tests
-        env_logger::Builder::from_default_env()
-            .filter_level(log::LevelFilter::Info)
-            .init();
-
-        println!("Integration test environment initialized");
+        // Route the helpers' tracing events to the test output, honoring RUST_LOG
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+            )
+            .with_test_writer()
+            .try_init();
+
+        tracing::info!("Integration test environment initialized");
     });
 }
 